@@ -0,0 +1,6 @@
+//! `redis-check-aof`, built with `cargo build --example redis-check-aof`. It lives here rather
+//! than in `src/bin`, where a second binary would keep `cargo run` from picking the server.
+
+fn main() -> anyhow::Result<()> {
+    redis_starter_rust::check_aof(std::env::args().skip(1))
+}
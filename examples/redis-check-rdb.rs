@@ -0,0 +1,6 @@
+//! `redis-check-rdb`, built with `cargo build --example redis-check-rdb`. It lives here rather
+//! than in `src/bin`, where a second binary would keep `cargo run` from picking the server.
+
+fn main() -> anyhow::Result<()> {
+    redis_starter_rust::check_rdb(std::env::args().skip(1))
+}
@@ -0,0 +1,191 @@
+//! The AOF checker, which is this binary run as `redis-check-aof` like in Redis. It checks an
+//! AOF file, or every file a manifest lists, and can cut off what follows the last command that
+//! could be read, as a crash leaves it.
+
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::aof::manifest::{FileKind, Manifest};
+use crate::aof::{load, scan};
+use crate::rdb;
+use crate::storage::Storage;
+
+const USAGE: &str = "Usage: redis-check-aof [--fix] [--dump] <file.manifest|file.aof>";
+
+/// Checks the AOF in `args`. With `--fix`, an AOF file that isn't valid is truncated to its
+/// last valid command. Only the last file of a manifest can be, since the others were complete
+/// when the next one was started. With `--dump`, the keys are written to stdout as JSON lines,
+/// and the report goes to stderr instead.
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut path = None;
+    let mut fix = false;
+    let mut dump = false;
+    for arg in args {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            "--dump" => dump = true,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => bail!(USAGE),
+        }
+    }
+    let path = path.context(USAGE)?;
+    let report = |line: String| {
+        if dump {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+
+    let files = if path
+        .extension()
+        .is_some_and(|extension| extension == "manifest")
+    {
+        report(format!("Start checking Multi Part AOF {}", path.display()));
+        let manifest = fs::read_to_string(&path)
+            .with_context(|| format!("Failed opening {}", path.display()))?;
+        let manifest = Manifest::parse(&manifest)
+            .with_context(|| format!("Failed loading {}", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let last = manifest
+            .files()
+            .count()
+            .checked_sub(1)
+            .with_context(|| format!("No files in manifest {}", path.display()))?;
+        manifest
+            .files()
+            .enumerate()
+            .map(|(i, file)| {
+                (
+                    dir.join(&file.name),
+                    i == last && file.kind == FileKind::Incr,
+                )
+            })
+            .collect()
+    } else {
+        vec![(path, true)]
+    };
+
+    let mut storage = Storage::default();
+    for (path, last) in files {
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed opening {}", path.display()))?;
+
+        if contents.starts_with(b"REDIS") {
+            report(format!("Checking RDB preamble {}", path.display()));
+            rdb::load(&contents, &mut storage)
+                .with_context(|| format!("RDB preamble of {} is not valid", path.display()))?;
+            report(format!("RDB preamble of {} is valid", path.display()));
+            continue;
+        }
+
+        let valid = check_commands(&path, &contents, fix && last, report)?;
+        if dump {
+            load(&contents[..valid], &mut storage)?;
+        }
+    }
+
+    if dump {
+        for (key, value, expiry) in storage.snapshot().entries() {
+            let key = key.plain_string().unwrap_or_default();
+            println!("{}", rdb::check::dump_line(key, value, expiry));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the commands of an AOF file, and returns how many of its bytes are valid, after
+/// truncating it to them if `fix` is set.
+fn check_commands(path: &Path, aof: &[u8], fix: bool, report: impl Fn(String)) -> Result<usize> {
+    let scan = scan(aof, |_| {});
+    report(format!(
+        "AOF analyzed: filename={}, size={}, ok_up_to={}, commands={}, diff={}",
+        path.display(),
+        aof.len(),
+        scan.valid,
+        scan.commands,
+        aof.len() - scan.valid
+    ));
+    if scan.valid == aof.len() {
+        report(format!("AOF {} is valid", path.display()));
+        return Ok(scan.valid);
+    }
+
+    match &scan.error {
+        Some(e) => report(format!("AOF {} format error: {:#}", path.display(), e)),
+        None => report(format!(
+            "AOF {} ends with an unfinished command or transaction at offset {}",
+            path.display(),
+            scan.valid
+        )),
+    }
+    ensure!(
+        fix,
+        "AOF {} is not valid. Use the --fix option to try fixing it.",
+        path.display()
+    );
+
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(scan.valid as u64))
+        .with_context(|| format!("Failed truncating {}", path.display()))?;
+    report(format!(
+        "Successfully truncated AOF {} to {} bytes",
+        path.display(),
+        scan.valid
+    ));
+
+    Ok(scan.valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_commands() {
+        let path = std::env::temp_dir().join(format!("test-check-{}.aof", std::process::id()));
+        let complete = "*2\r\n$3\r\nDEL\r\n$1\r\nk\r\n*1\r\n$5\r\nMULTI\r\n*1\r\n$4\r\nEXEC\r\n";
+
+        fs::write(&path, format!("{complete}*2\r\n$3\r\nDEL\r\n$1")).unwrap();
+        let aof = fs::read(&path).unwrap();
+        assert!(check_commands(&path, &aof, false, |_| {}).is_err());
+        assert_eq!(
+            check_commands(&path, &aof, true, |_| {}).unwrap(),
+            complete.len()
+        );
+        assert_eq!(fs::read(&path).unwrap(), complete.as_bytes());
+
+        // what follows a format error is cut off too
+        let aof = format!("{complete}*1\r\n$4\r\nEXEC\r\n{complete}");
+        let scan = scan(aof.as_bytes(), |_| {});
+        assert_eq!(scan.valid, complete.len());
+        assert_eq!(scan.commands, 1);
+        assert_eq!(
+            scan.error.unwrap().to_string(),
+            format!("EXEC without MULTI at offset {}", complete.len())
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_empty_manifest() {
+        let path =
+            std::env::temp_dir().join(format!("test-check-{}.aof.manifest", std::process::id()));
+        fs::write(&path, "# no files yet\n").unwrap();
+
+        let error = run([path.display().to_string()].into_iter()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("No files in manifest {}", path.display())
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
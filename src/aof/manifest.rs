@@ -0,0 +1,201 @@
+//! The manifest that lists the files the AOF is made of, in the format of Redis 7: a base file
+//! with the dataset as it was when the AOF was last rewritten, and incremental files with the
+//! writes since.
+
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, ensure, Context, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Base,
+    Incr,
+    /// A file a rewrite replaced, which is only listed until it is deleted.
+    History,
+}
+
+impl FileKind {
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "b" => Ok(FileKind::Base),
+            "i" => Ok(FileKind::Incr),
+            "h" => Ok(FileKind::History),
+            _ => bail!("Unknown AOF file type {}", s),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FileKind::Base => "b",
+            FileKind::Incr => "i",
+            FileKind::History => "h",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AofFile {
+    pub name: String,
+    pub seq: u64,
+    pub kind: FileKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub base: Option<AofFile>,
+    /// Ordered by sequence number, which is the order they are loaded in.
+    pub incrs: Vec<AofFile>,
+}
+
+impl Manifest {
+    pub fn file_name(filename: &str) -> String {
+        format!("{}.manifest", filename)
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut manifest = Manifest::default();
+
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let tokens = line.split(' ').collect::<Vec<_>>();
+            ensure!(
+                tokens.len() % 2 == 0,
+                "Invalid AOF manifest file format: {}",
+                line
+            );
+
+            let (mut name, mut seq, mut kind) = (None, None, None);
+            for pair in tokens.chunks(2) {
+                match pair[0] {
+                    "file" => name = Some(pair[1].to_string()),
+                    "seq" => seq = Some(pair[1].parse().context("Invalid AOF file sequence")?),
+                    "type" => kind = Some(FileKind::parse(pair[1])?),
+                    // unknown keys are left for later versions
+                    _ => {}
+                }
+            }
+
+            let (Some(name), Some(seq), Some(kind)) = (name, seq, kind) else {
+                bail!("Invalid AOF manifest file format: {}", line);
+            };
+            ensure!(
+                !name.contains('/'),
+                "File can't be a path, just a filename: {}",
+                name
+            );
+
+            let file = AofFile { name, seq, kind };
+            match kind {
+                FileKind::Base => {
+                    ensure!(
+                        manifest.base.is_none(),
+                        "Found duplicate base file information"
+                    );
+                    manifest.base = Some(file);
+                }
+                FileKind::Incr => {
+                    if let Some(last) = manifest.incrs.last() {
+                        ensure!(file.seq > last.seq, "Found a non-monotonic sequence number");
+                    }
+                    manifest.incrs.push(file);
+                }
+                FileKind::History => {}
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Adds an incremental file after the last one, and returns it.
+    pub fn add_incr(&mut self, filename: &str) -> &AofFile {
+        let seq = self.incrs.last().map_or(1, |incr| incr.seq + 1);
+        self.incrs.push(AofFile {
+            name: format!("{}.{}.incr.aof", filename, seq),
+            seq,
+            kind: FileKind::Incr,
+        });
+
+        self.incrs.last().unwrap()
+    }
+
+    /// The base file that replaces the current one, in RDB format if `rdb` is set.
+    pub fn next_base(&self, filename: &str, rdb: bool) -> AofFile {
+        let seq = self.base.as_ref().map_or(1, |base| base.seq + 1);
+        let extension = if rdb { "rdb" } else { "aof" };
+
+        AofFile {
+            name: format!("{}.{}.base.{}", filename, seq, extension),
+            seq,
+            kind: FileKind::Base,
+        }
+    }
+
+    /// Every file, in the order they are loaded in.
+    pub fn files(&self) -> impl Iterator<Item = &AofFile> {
+        self.base.iter().chain(&self.incrs)
+    }
+}
+
+impl Display for Manifest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for file in self.files() {
+            writeln!(
+                f,
+                "file {} seq {} type {}",
+                file.name,
+                file.seq,
+                file.kind.as_str()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let mut manifest = Manifest::parse(
+            "file appendonly.aof.1.base.rdb seq 1 type b\n\
+             # comment\n\
+             file appendonly.aof.1.incr.aof seq 1 type i\n\
+             file appendonly.aof.2.incr.aof type i seq 2\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.base.as_ref().unwrap().name,
+            "appendonly.aof.1.base.rdb"
+        );
+        assert_eq!(manifest.incrs.len(), 2);
+        assert_eq!(
+            manifest.add_incr("appendonly.aof").name,
+            "appendonly.aof.3.incr.aof"
+        );
+        assert_eq!(
+            manifest.next_base("appendonly.aof", false).name,
+            "appendonly.aof.2.base.aof"
+        );
+        assert_eq!(
+            manifest.to_string(),
+            "file appendonly.aof.1.base.rdb seq 1 type b\n\
+             file appendonly.aof.1.incr.aof seq 1 type i\n\
+             file appendonly.aof.2.incr.aof seq 2 type i\n\
+             file appendonly.aof.3.incr.aof seq 3 type i\n"
+        );
+        assert_eq!(Manifest::parse(&manifest.to_string()).unwrap(), manifest);
+
+        assert!(Manifest::parse("file a seq 1").is_err());
+        assert!(Manifest::parse("file a seq 1 type x").is_err());
+        assert!(Manifest::parse("file ../a seq 1 type b").is_err());
+        assert!(Manifest::parse("file a seq 2 type i\nfile b seq 1 type i").is_err());
+        assert!(Manifest::parse("file a seq 1 type b\nfile b seq 2 type b").is_err());
+    }
+}
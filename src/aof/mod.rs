@@ -0,0 +1,694 @@
+//! The append-only file, which logs every write as the command that made it, so that replaying
+//! the commands at startup rebuilds the dataset.
+
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::config::Config;
+use crate::rdb;
+use crate::resp::{Array, BulkString, Integer, Resp, SimpleString};
+use crate::storage::Storage;
+
+use manifest::{AofFile, FileKind, Manifest};
+
+pub use reader::read_command;
+pub use rewrite::start_rewrite;
+
+pub mod check;
+mod manifest;
+mod reader;
+mod rewrite;
+
+/// The prefix of the names of the AOF files.
+pub const DEFAULT_FILENAME: &str = "appendonly.aof";
+/// Where the AOF files are, relative to `dir`.
+pub const DEFAULT_DIRNAME: &str = "appendonlydir";
+
+tokio::task_local! {
+    /// The write command running on this task, which is logged once it modifies the storage.
+    static CURRENT_COMMAND: RefCell<Propagation>;
+}
+
+/// When the AOF is flushed to the disk, from `appendfsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fsync {
+    /// After every write, before the client gets its reply.
+    Always,
+    /// Once a second, so that a crash loses a second of writes at most.
+    EverySec,
+    /// Whenever the OS decides to.
+    No,
+}
+
+impl Fsync {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "always" => Ok(Fsync::Always),
+            "everysec" => Ok(Fsync::EverySec),
+            "no" => Ok(Fsync::No),
+            _ => bail!("argument must be one of the following: always, everysec, no"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Propagation {
+    /// The command hasn't modified the storage yet.
+    Pending(Resp),
+    /// The command is in the log at this index.
+    Logged(usize),
+}
+
+#[derive(Debug)]
+pub struct Aof {
+    fsync: Fsync,
+    dir: PathBuf,
+    filename: String,
+    use_rdb_preamble: bool,
+    log: Mutex<Log>,
+    rewrite_in_progress: AtomicBool,
+    last_rewrite_ok: AtomicBool,
+    last_rewrite_try: Mutex<Option<Instant>>,
+    /// The size of the AOF after it was last rewritten, which automatic rewrites compare its
+    /// size to.
+    base_size: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Log {
+    /// The last incremental file, which writes are appended to.
+    file: File,
+    /// The size of the file, which a failed write is truncated back to.
+    size: u64,
+    /// The size of the other files of the AOF.
+    other_size: u64,
+    manifest: Manifest,
+    /// The commands logged since the last write. They are written before the replies of their
+    /// commands are sent, until then a command may still change what it is logged as.
+    pending: Vec<Resp>,
+    /// How many commands were written, which gives the index of the pending ones.
+    written: usize,
+    /// Whether there were writes since the last fsync.
+    unsynced: bool,
+    last_write_ok: bool,
+}
+
+impl Aof {
+    /// Loads the AOF in the `appenddirname` directory, and opens its last incremental file to
+    /// log writes to. An AOF from before there were several files is moved there as the base.
+    pub fn load(config: &Config, storage: &mut Storage) -> Result<Self> {
+        let dir = config.aof_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Can't create the AOF directory {}", dir.display()))?;
+
+        let manifest_path = dir.join(Manifest::file_name(&config.appendfilename));
+        let legacy_path = config.dir.join(&config.appendfilename);
+        let mut manifest = if manifest_path.exists() {
+            let manifest = fs::read_to_string(&manifest_path)
+                .with_context(|| format!("Failed opening {}", manifest_path.display()))?;
+            Manifest::parse(&manifest)
+                .with_context(|| format!("Failed loading {}", manifest_path.display()))?
+        } else if legacy_path.exists() {
+            fs::rename(&legacy_path, dir.join(&config.appendfilename))
+                .with_context(|| format!("Failed moving {}", legacy_path.display()))?;
+            Manifest {
+                base: Some(AofFile {
+                    name: config.appendfilename.clone(),
+                    seq: 1,
+                    kind: FileKind::Base,
+                }),
+                incrs: Vec::new(),
+            }
+        } else {
+            Manifest::default()
+        };
+
+        if let Some(base) = &manifest.base {
+            let path = dir.join(&base.name);
+            ensure!(
+                path.exists(),
+                "The AOF base file {} doesn't exist",
+                path.display()
+            );
+            if fs::read(&path)?.starts_with(b"REDIS") {
+                rdb::load_file(&path, storage)?;
+            } else {
+                load_file(&path, storage, false)?;
+            }
+        }
+        for (i, incr) in manifest.incrs.iter().enumerate() {
+            let path = dir.join(&incr.name);
+            ensure!(
+                path.exists(),
+                "The AOF file {} doesn't exist",
+                path.display()
+            );
+            // only the file written last may have been cut short by a crash
+            let is_last = i == manifest.incrs.len() - 1;
+            load_file(&path, storage, config.aof_load_truncated && is_last)?;
+        }
+
+        if manifest.incrs.is_empty() {
+            manifest.add_incr(&config.appendfilename);
+        }
+        write_manifest(&dir, &config.appendfilename, &manifest)?;
+
+        let incr = manifest.incrs.last().unwrap();
+        let file = open_incr(&dir.join(&incr.name))?;
+        let size = file.metadata()?.len();
+        let other_size = files_size(&dir, manifest.files())? - size;
+
+        Ok(Aof {
+            fsync: config.appendfsync,
+            dir,
+            filename: config.appendfilename.clone(),
+            use_rdb_preamble: config.aof_use_rdb_preamble,
+            log: Mutex::new(Log {
+                file,
+                size,
+                other_size,
+                manifest,
+                pending: Vec::new(),
+                written: 0,
+                unsynced: false,
+                last_write_ok: true,
+            }),
+            rewrite_in_progress: AtomicBool::new(false),
+            last_rewrite_ok: AtomicBool::new(true),
+            last_rewrite_try: Mutex::new(None),
+            base_size: AtomicU64::new(other_size + size),
+        })
+    }
+
+    pub fn fsync_policy(&self) -> Fsync {
+        self.fsync
+    }
+
+    pub fn last_write_ok(&self) -> bool {
+        self.log.lock().unwrap().last_write_ok
+    }
+
+    pub fn rewrite_in_progress(&self) -> bool {
+        self.rewrite_in_progress.load(Ordering::SeqCst)
+    }
+
+    pub fn last_rewrite_ok(&self) -> bool {
+        self.last_rewrite_ok.load(Ordering::SeqCst)
+    }
+
+    /// The size of all the files of the AOF.
+    pub fn current_size(&self) -> u64 {
+        let log = self.log.lock().unwrap();
+        log.other_size + log.size
+    }
+
+    pub fn base_size(&self) -> u64 {
+        self.base_size.load(Ordering::SeqCst)
+    }
+
+    /// Logs `command`, and returns its index in the log.
+    fn log(&self, command: Resp) -> usize {
+        let mut log = self.log.lock().unwrap();
+        log.pending.push(as_bulk_strings(command));
+
+        log.written + log.pending.len() - 1
+    }
+
+    /// Changes the command at `index` in the log, if it wasn't written yet. An empty command
+    /// isn't written at all.
+    fn edit(&self, index: usize, f: impl FnOnce(&mut Vec<Resp>)) {
+        let mut log = self.log.lock().unwrap();
+        let Some(index) = index.checked_sub(log.written) else {
+            return;
+        };
+
+        if let Some(Resp::Array(Array(elements))) = log.pending.get_mut(index) {
+            f(elements);
+        }
+    }
+
+    /// The index the next command logged gets.
+    pub fn position(&self) -> usize {
+        let log = self.log.lock().unwrap();
+        log.written + log.pending.len()
+    }
+
+    /// Wraps the commands logged since `start` in `MULTI` and `EXEC`, so that replaying them is
+    /// as atomic as the transaction or script that ran them.
+    pub fn wrap_in_transaction(&self, start: usize) {
+        let mut log = self.log.lock().unwrap();
+        let Some(start) = start.checked_sub(log.written) else {
+            return;
+        };
+
+        let logged = log.pending[start..]
+            .iter()
+            .filter(|command| !is_empty(command))
+            .count();
+        if logged > 1 {
+            log.pending.insert(start, command(["MULTI"]));
+            log.pending.push(command(["EXEC"]));
+        }
+    }
+
+    /// Writes the commands logged so far to the file, and flushes it to the disk if the policy
+    /// is to always do so.
+    pub fn write(&self) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        self.write_pending(&mut log)
+    }
+
+    fn write_pending(&self, log: &mut Log) -> Result<()> {
+        if log.pending.is_empty() {
+            return Ok(());
+        }
+
+        let commands = log
+            .pending
+            .iter()
+            .filter(|command| !is_empty(command))
+            .map(Resp::to_string)
+            .collect::<String>();
+
+        if let Err(e) = log.file.write_all(commands.as_bytes()) {
+            // don't leave half a command behind, the whole write is retried with the next one
+            let size = log.size;
+            let _ = log.file.set_len(size);
+            log.last_write_ok = false;
+            return Err(e).context("Error writing to the AOF file");
+        }
+
+        log.size += commands.len() as u64;
+        log.written += log.pending.len();
+        log.pending.clear();
+        log.last_write_ok = true;
+
+        if self.fsync == Fsync::Always {
+            log.file.sync_data().context("Can't fsync the AOF file")?;
+        } else {
+            log.unsynced = true;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes what was written since the last time to the disk, without holding the log while
+    /// the disk is slow.
+    pub fn fsync(&self) -> Result<()> {
+        let file = {
+            let mut log = self.log.lock().unwrap();
+            if !log.unsynced {
+                return Ok(());
+            }
+            log.unsynced = false;
+            log.file.try_clone()?
+        };
+
+        file.sync_data().context("Can't fsync the AOF file")
+    }
+}
+
+/// Runs `future` with `command` as the write command of the task, so that it is logged if it
+/// modifies the storage.
+pub async fn propagating<F: Future>(command: Resp, future: F) -> F::Output {
+    CURRENT_COMMAND
+        .scope(RefCell::new(Propagation::Pending(command)), future)
+        .await
+}
+
+/// Logs the write command running on this task, the first time it modifies the storage, so
+/// that the log follows the order writes happen in.
+pub fn log_current(aof: &Aof) {
+    let _ = CURRENT_COMMAND.try_with(|current| {
+        let mut current = current.borrow_mut();
+        if let Propagation::Pending(command) = &*current {
+            *current = Propagation::Logged(aof.log(command.clone()));
+        }
+    });
+}
+
+/// Changes what the write command running on this task is logged as.
+pub fn rewrite_current(aof: &Aof, f: impl FnOnce(&mut Vec<Resp>)) {
+    let _ = CURRENT_COMMAND.try_with(|current| match &mut *current.borrow_mut() {
+        Propagation::Pending(Resp::Array(Array(elements))) => f(elements),
+        Propagation::Pending(_) => {}
+        Propagation::Logged(index) => aof.edit(*index, f),
+    });
+}
+
+/// Logs a write that isn't made by a command, such as a key expiring.
+pub fn log(aof: &Aof, args: impl IntoIterator<Item = impl Into<String>>) {
+    aof.log(command(args));
+}
+
+/// Replays the AOF at `path`, if there is one. A command cut short at the end of the file, as a
+/// crash leaves it, is cut off the file if `load_truncated` is set, and fails loading otherwise.
+pub fn load_file(path: &Path, storage: &mut Storage, load_truncated: bool) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let aof = fs::read(path).with_context(|| format!("Failed opening {}", path.display()))?;
+    let valid =
+        load(&aof, storage).with_context(|| format!("Failed loading {}", path.display()))?;
+
+    if valid < aof.len() {
+        ensure!(
+            load_truncated,
+            "Unexpected end of file reading the append only file {}. You can: 1) Make a backup \
+             of your AOF file, then use ./redis-check-aof --fix <filename>. 2) Alternatively \
+             you can set the 'aof-load-truncated' configuration option to yes and restart the \
+             server.",
+            path.display()
+        );
+
+        eprintln!(
+            "!!! Warning: short read while loading the AOF file {}!!!",
+            path.display()
+        );
+        eprintln!(
+            "AOF loaded anyway because aof-load-truncated is enabled, truncating it to {} bytes",
+            valid
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(valid as u64))
+            .with_context(|| format!("Failed truncating {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Replays the commands in `aof` through the normal command dispatch, and returns how many of
+/// its bytes they take. The rest is a command cut short, or a transaction that never ended.
+///
+/// Keys don't expire while loading, since the AOF has the deletion of the ones that did.
+pub fn load(aof: &[u8], storage: &mut Storage) -> Result<usize> {
+    storage.loading = true;
+    let result = storage.run_exclusive(|storage| replay(aof, storage));
+    storage.loading = false;
+
+    result
+}
+
+fn replay(aof: &[u8], storage: &RwLock<Storage>) -> Result<usize> {
+    // the commands failed the same way when they were logged
+    let scan = scan(aof, |command| {
+        command.run_now(storage);
+    });
+
+    match scan.error {
+        Some(e) => Err(e),
+        None => Ok(scan.valid),
+    }
+}
+
+/// How far the commands of an AOF could be read.
+#[derive(Debug)]
+pub struct Scan {
+    /// How many bytes the commands take, without a transaction that never ended.
+    pub valid: usize,
+    pub commands: usize,
+    /// Why the commands after `valid` can't be read, unless they were just cut short.
+    pub error: Option<anyhow::Error>,
+}
+
+/// Reads the commands of `aof` in order, and passes them to `f`. The commands of a transaction
+/// are only passed once it ends.
+pub fn scan(aof: &[u8], mut f: impl FnMut(Resp)) -> Scan {
+    let mut scan = Scan {
+        valid: 0,
+        commands: 0,
+        error: None,
+    };
+    let mut pos = 0;
+    let mut transaction: Option<Vec<Resp>> = None;
+
+    loop {
+        let (command, end) = match read_command(aof, pos) {
+            Ok(Some(read)) => read,
+            Ok(None) => break,
+            Err(e) => {
+                scan.error = Some(e);
+                break;
+            }
+        };
+
+        match command.command_name().as_deref() {
+            Some("MULTI") => {
+                if transaction.is_some() {
+                    scan.error = Some(anyhow!("Nested MULTI at offset {}", pos));
+                    break;
+                }
+                transaction = Some(Vec::new());
+            }
+            Some("EXEC") => {
+                let Some(commands) = transaction.take() else {
+                    scan.error = Some(anyhow!("EXEC without MULTI at offset {}", pos));
+                    break;
+                };
+                scan.commands += commands.len();
+                commands.into_iter().for_each(&mut f);
+            }
+            _ => match &mut transaction {
+                Some(commands) => commands.push(command),
+                None => {
+                    scan.commands += 1;
+                    f(command);
+                }
+            },
+        }
+
+        pos = end;
+        if transaction.is_none() {
+            scan.valid = pos;
+        }
+    }
+
+    scan
+}
+
+fn open_incr(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Can't open the append-only file {}", path.display()))
+}
+
+fn files_size<'a>(dir: &Path, files: impl Iterator<Item = &'a AofFile>) -> Result<u64> {
+    let mut size = 0;
+    for file in files {
+        size += fs::metadata(dir.join(&file.name))?.len();
+    }
+
+    Ok(size)
+}
+
+/// Replaces the manifest, so that it lists either the old files or the new ones if this fails
+/// midway.
+fn write_manifest(dir: &Path, filename: &str, manifest: &Manifest) -> Result<()> {
+    let name = Manifest::file_name(filename);
+    let temp_path = dir.join(format!("temp-{}", name));
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(manifest.to_string().as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, dir.join(&name))
+        .with_context(|| format!("Failed writing the AOF manifest {}", name))
+}
+
+fn command(args: impl IntoIterator<Item = impl Into<String>>) -> Resp {
+    Resp::Array(Array(
+        args.into_iter()
+            .map(|arg| Resp::BulkString(BulkString(Some(arg.into()))))
+            .collect(),
+    ))
+}
+
+/// Clients may send integers and simple strings as arguments, which are logged as bulk strings
+/// like the rest.
+fn as_bulk_strings(command: Resp) -> Resp {
+    let Resp::Array(Array(elements)) = command else {
+        return command;
+    };
+
+    let elements = elements
+        .into_iter()
+        .map(|element| match element {
+            Resp::Integer(Integer(i)) => Resp::BulkString(BulkString(Some(i.to_string()))),
+            Resp::SimpleString(SimpleString(s)) => Resp::BulkString(BulkString(Some(s))),
+            element => element,
+        })
+        .collect();
+
+    Resp::Array(Array(elements))
+}
+
+fn is_empty(command: &Resp) -> bool {
+    matches!(command, Resp::Array(Array(elements)) if elements.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::resp::bulk;
+
+    /// A config with an empty `dir` of its own.
+    pub fn test_config(name: &str) -> Config {
+        let dir = std::env::temp_dir().join(format!("test-aof-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        Config {
+            dir,
+            appendonly: true,
+            appendfsync: Fsync::No,
+            ..Default::default()
+        }
+    }
+
+    pub fn run(storage: &RwLock<Storage>, args: &[&str]) -> Resp {
+        let reply = command(args.iter().copied()).run_now(storage);
+        storage.read().unwrap().write_aof();
+        reply
+    }
+
+    #[test]
+    fn test_log_and_load() {
+        let config = test_config("log");
+        let path = config.aof_dir().join("appendonly.aof.1.incr.aof");
+
+        let storage: RwLock<Storage> = Default::default();
+        let aof = Aof::load(&config, &mut storage.write().unwrap()).unwrap();
+        storage.write().unwrap().aof = Some(Arc::new(aof));
+
+        run(&storage, &["SET", "string", "value", "PX", "100000"]);
+        run(&storage, &["SADD", "set", "a", "b", "c"]);
+        run(&storage, &["SPOP", "set"]);
+        // failed commands and commands that change nothing aren't logged
+        run(&storage, &["SADD", "string", "a"]);
+        run(&storage, &["SREM", "set", "nope"]);
+        run(&storage, &["ZADD", "zset", "XX", "1", "m"]);
+        run(&storage, &["XADD", "stream", "0-0", "f", "v"]);
+        run(&storage, &["XADD", "stream", "MAXLEN", "2", "*", "f", "v"]);
+        run(&storage, &["GET", "string"]);
+        storage.write().unwrap().run_exclusive(|storage| {
+            command(["SADD", "other", "x"]).run_now(storage);
+            command(["SMOVE", "other", "set", "x"]).run_now(storage);
+        });
+        storage.read().unwrap().write_aof();
+
+        let aof = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+        let commands = aof
+            .split("\r\n")
+            .filter(|line| !line.starts_with(['*', '$']))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let members = match run(&storage, &["SMEMBERS", "set"]) {
+            Resp::Array(Array(members)) => members,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        let popped = ["a", "b", "c"]
+            .into_iter()
+            .find(|member| {
+                !members.contains(&Resp::BulkString(BulkString(Some(member.to_string()))))
+            })
+            .unwrap();
+        let id = match run(&storage, &["XRANGE", "stream", "-", "+"]) {
+            Resp::Array(Array(entries)) => match &entries[0] {
+                Resp::Array(Array(entry)) => entry[0].plain_string().unwrap().to_string(),
+                reply => panic!("unexpected reply {:?}", reply),
+            },
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        let expires_at = commands.split(' ').nth(4).unwrap().parse::<u64>().unwrap();
+        assert_eq!(
+            commands,
+            format!(
+                "SET string value PXAT {expires_at} SADD set a b c SREM set {popped} XADD stream \
+                 MAXLEN 2 {id} f v MULTI SADD other x SMOVE other set x EXEC "
+            )
+        );
+
+        let loaded = RwLock::new(Storage::default());
+        let valid = load(aof.as_bytes(), &mut loaded.write().unwrap()).unwrap();
+        assert_eq!(valid, aof.len());
+        for read in [
+            &["GET", "string"][..],
+            &["SMISMEMBER", "set", "a", "b", "c", "x"],
+            &["XRANGE", "stream", "-", "+"],
+            &["SCARD", "other"],
+        ] {
+            assert_eq!(run(&loaded, read), run(&storage, read));
+        }
+
+        // a crash may leave a command, or a transaction, unfinished
+        let complete = aof.len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"*1\r\n$5\r\nMULTI\r\n*2\r\n$3\r\nDEL\r\n$6\r\nstr")
+            .unwrap();
+
+        let mut storage = Storage::default();
+        assert!(load_file(&path, &mut storage, false).is_err());
+        let mut storage = Storage::default();
+        load_file(&path, &mut storage, true).unwrap();
+        assert!(storage
+            .get(&Resp::BulkString(BulkString(Some("string".to_string()))))
+            .unwrap()
+            .is_some());
+        assert_eq!(fs::metadata(&path).unwrap().len(), complete as u64);
+
+        file.write_all(b"+OK\r\n").unwrap();
+        assert!(load_file(&path, &mut Storage::default(), true).is_err());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_manifest() {
+        let config = test_config("manifest");
+        fs::write(
+            config.dir.join("appendonly.aof"),
+            command(["SET", "legacy", "1"]).to_string(),
+        )
+        .unwrap();
+
+        // an AOF from before the manifest becomes the base
+        let mut storage = Storage::default();
+        let aof = Aof::load(&config, &mut storage).unwrap();
+        assert!(storage.get(&bulk("legacy")).unwrap().is_some());
+        assert_eq!(
+            fs::read_to_string(config.aof_dir().join("appendonly.aof.manifest")).unwrap(),
+            "file appendonly.aof seq 1 type b\nfile appendonly.aof.1.incr.aof seq 1 type i\n"
+        );
+        assert_eq!(aof.current_size(), aof.base_size());
+
+        let storage = RwLock::new(storage);
+        storage.write().unwrap().aof = Some(Arc::new(aof));
+        run(&storage, &["SET", "incr", "2"]);
+
+        let mut storage = Storage::default();
+        Aof::load(&config, &mut storage).unwrap();
+        assert!(storage.get(&bulk("legacy")).unwrap().is_some());
+        assert!(storage.get(&bulk("incr")).unwrap().is_some());
+
+        // a file the manifest lists can't be missing
+        fs::remove_file(config.aof_dir().join("appendonly.aof")).unwrap();
+        assert!(Aof::load(&config, &mut Storage::default()).is_err());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{bail, ensure, Result};
+
+use crate::resp::{Array, BulkString, Resp};
+
+/// Reads the command starting at `pos`, which is logged as an array of bulk strings, and returns
+/// it with the offset it ends at. Returns `None` if `aof` ends before the command does.
+pub fn read_command(aof: &[u8], pos: usize) -> Result<Option<(Resp, usize)>> {
+    let start = pos;
+    let mut pos = pos;
+    let Some(len) = read_length(aof, &mut pos, b'*')? else {
+        return Ok(None);
+    };
+    ensure!(len > 0, "Empty command at offset {}", start);
+
+    let mut elements = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        let Some(size) = read_length(aof, &mut pos, b'$')? else {
+            return Ok(None);
+        };
+        let Some(bytes) = (size.checked_add(2)).and_then(|len| aof.get(pos..pos.checked_add(len)?))
+        else {
+            return Ok(None);
+        };
+        ensure!(
+            bytes.ends_with(b"\r\n"),
+            "Expected CRLF at offset {}",
+            pos + size
+        );
+
+        let arg = String::from_utf8_lossy(&bytes[..size]).into_owned();
+        elements.push(Resp::BulkString(BulkString(Some(arg))));
+        pos += size + 2;
+    }
+
+    Ok(Some((Resp::Array(Array(elements)), pos)))
+}
+
+/// Reads a `<prefix><length>\r\n` line, and moves `pos` past it.
+fn read_length(aof: &[u8], pos: &mut usize, prefix: u8) -> Result<Option<usize>> {
+    let rest = &aof[*pos..];
+    if rest.is_empty() {
+        return Ok(None);
+    }
+    if rest[0] != prefix {
+        bail!("Expected '{}' at offset {}", prefix as char, *pos);
+    }
+
+    let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+        return Ok(None);
+    };
+    let line = rest[1..end].strip_suffix(b"\r");
+    let len = line
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .and_then(|line| line.parse().ok());
+    let Some(len) = len else {
+        bail!("Invalid length at offset {}", *pos + 1);
+    };
+
+    *pos += end + 1;
+    Ok(Some(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_command() {
+        let aof = b"*2\r\n$3\r\nDEL\r\n$1\r\nk\r\n*1\r\n$4\r\nPING\r\n";
+
+        let (command, end) = read_command(aof, 0).unwrap().unwrap();
+        assert_eq!(command.to_string(), "*2\r\n$3\r\nDEL\r\n$1\r\nk\r\n");
+        assert_eq!(end, 20);
+        let (_, end) = read_command(aof, end).unwrap().unwrap();
+        assert_eq!(end, aof.len());
+        assert!(read_command(aof, end).unwrap().is_none());
+
+        // cut short anywhere, the command is incomplete rather than invalid
+        for len in 1..20 {
+            assert!(read_command(&aof[..len], 0).unwrap().is_none(), "{}", len);
+        }
+
+        assert!(read_command(b"+OK\r\n", 0).is_err());
+        assert!(read_command(b"*1\r\n$x\r\n", 0).is_err());
+        assert!(read_command(b"*1\r\n$2\r\nabc\r\n", 0).is_err());
+        assert!(read_command(b"*0\r\n", 0).is_err());
+    }
+}
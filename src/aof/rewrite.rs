@@ -0,0 +1,423 @@
+//! Rewriting the AOF: the dataset is written to a new base file from a snapshot, without the
+//! storage lock, while writes go on to a new incremental file. Once the base is written, the
+//! manifest lists it and that incremental file only, and the files they replace are deleted.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::aof::manifest::Manifest;
+use crate::aof::{command, open_incr, write_manifest, Aof};
+use crate::rdb;
+use crate::storage::{format_score, Snapshot, Storage, Stream, StreamId, Value};
+
+/// How many elements of a collection each command of the base adds, like in Redis.
+const ITEMS_PER_COMMAND: usize = 64;
+
+/// How long to wait after a failed automatic rewrite before trying again.
+const REWRITE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+impl Aof {
+    /// Whether the AOF grew by `percentage` percent since it was last rewritten, and is over
+    /// `min_size` bytes.
+    pub fn should_rewrite(&self, percentage: u64, min_size: u64) -> bool {
+        if self.rewrite_in_progress() {
+            return false;
+        }
+        if !self.last_rewrite_ok() {
+            let last_try = *self.last_rewrite_try.lock().unwrap();
+            if last_try.is_some_and(|last_try| last_try.elapsed() < REWRITE_RETRY_DELAY) {
+                return false;
+            }
+        }
+
+        let size = self.current_size();
+        let base_size = self.base_size().max(1);
+        size > min_size && size.saturating_sub(base_size) * 100 / base_size >= percentage
+    }
+
+    /// Writes what is pending to the current incremental file, then starts a new one for the
+    /// writes that come after the snapshot being rewritten.
+    fn open_next_incr(&self) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        self.write_pending(&mut log)?;
+        log.file.sync_data().context("Can't fsync the AOF file")?;
+
+        let incr = log.manifest.add_incr(&self.filename).clone();
+        let path = self.dir.join(&incr.name);
+        let file = open_incr(&path).and_then(|file| {
+            write_manifest(&self.dir, &self.filename, &log.manifest)?;
+            Ok(file)
+        });
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                log.manifest.incrs.pop();
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+
+        log.other_size += log.size;
+        log.size = 0;
+        log.file = file;
+        log.unsynced = false;
+
+        Ok(())
+    }
+
+    /// Makes the file at `temp_path` the base, in place of the files written before the
+    /// current incremental one.
+    fn install_base(&self, temp_path: &Path, rdb: bool) -> Result<()> {
+        let mut log = self.log.lock().unwrap();
+        let base = log.manifest.next_base(&self.filename, rdb);
+        let base_path = self.dir.join(&base.name);
+        fs::rename(temp_path, &base_path)
+            .with_context(|| format!("Failed renaming the AOF base {}", base.name))?;
+
+        let incr = log.manifest.incrs.last().cloned();
+        let manifest = Manifest {
+            base: Some(base),
+            incrs: incr.into_iter().collect(),
+        };
+        if let Err(e) = write_manifest(&self.dir, &self.filename, &manifest) {
+            let _ = fs::remove_file(&base_path);
+            return Err(e);
+        }
+
+        let old = std::mem::replace(&mut log.manifest, manifest);
+        for file in old.files() {
+            if !log.manifest.files().any(|kept| kept.name == file.name) {
+                let _ = fs::remove_file(self.dir.join(&file.name));
+            }
+        }
+
+        log.other_size = fs::metadata(&base_path)?.len();
+        self.base_size
+            .store(log.other_size + log.size, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Writes the snapshot as the new base, in RDB format if `aof-use-rdb-preamble` is on or
+    /// the dataset has something commands can't recreate.
+    fn rewrite(&self, snapshot: &Snapshot) -> Result<()> {
+        let commands = if self.use_rdb_preamble {
+            None
+        } else {
+            encode_commands(snapshot)
+        };
+        let rdb = commands.is_none();
+        let base = commands.map_or_else(|| rdb::encode(snapshot), String::into_bytes);
+
+        let temp_path = self
+            .dir
+            .join(format!("temp-rewriteaof-bg-{}.aof", std::process::id()));
+        let result = File::create(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(&base)?;
+                file.sync_all()
+            })
+            .with_context(|| format!("Failed writing {}", temp_path.display()))
+            .and_then(|()| self.install_base(&temp_path, rdb));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+}
+
+/// Starts rewriting the AOF in the background, and returns false if a rewrite is already
+/// running. Only taking the snapshot and switching to a new incremental file holds the storage
+/// lock, so no write is lost or logged in both the base and that file.
+pub fn start_rewrite(storage: &Storage) -> Result<bool> {
+    let aof = Arc::clone(storage.aof.as_ref().context("AOF is not enabled")?);
+    if aof
+        .rewrite_in_progress
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Ok(false);
+    }
+    *aof.last_rewrite_try.lock().unwrap() = Some(Instant::now());
+
+    if let Err(e) = aof.open_next_incr() {
+        aof.last_rewrite_ok.store(false, Ordering::SeqCst);
+        aof.rewrite_in_progress.store(false, Ordering::SeqCst);
+        return Err(e);
+    }
+    let snapshot = storage.snapshot();
+
+    tokio::task::spawn_blocking(move || {
+        let result = aof.rewrite(&snapshot);
+        drop(snapshot);
+
+        if let Err(e) = &result {
+            eprintln!("background AOF rewrite failed; error = {:?}", e);
+        }
+        aof.last_rewrite_ok.store(result.is_ok(), Ordering::SeqCst);
+        aof.rewrite_in_progress.store(false, Ordering::SeqCst);
+    });
+
+    Ok(true)
+}
+
+/// Recreates the dataset with as few commands as possible, or returns `None` if it has lists,
+/// hashes, or expiring keys other than strings, which no command recreates.
+fn encode_commands(snapshot: &Snapshot) -> Option<String> {
+    let mut aof = String::new();
+    let mut push = |args: Vec<String>| aof.push_str(&command(args).to_string());
+
+    for library in snapshot.functions.libraries() {
+        push(vec!["FUNCTION".into(), "LOAD".into(), library.code.clone()]);
+    }
+
+    for (key, value, expiry) in snapshot.entries() {
+        let key = key.plain_string().unwrap_or_default().to_string();
+        match value {
+            Value::String(s) => {
+                let mut args = vec!["SET".into(), key, s.plain_string().ok()?.to_string()];
+                if let Some(expiry) = expiry {
+                    let at = expiry.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    args.extend(["PXAT".into(), at.as_millis().to_string()]);
+                }
+                push(args);
+                continue;
+            }
+            _ if expiry.is_some() => return None,
+            Value::List(_) | Value::Hash(_) => return None,
+            Value::Set(set) => {
+                let members = set.iter().cloned().collect::<Vec<_>>();
+                for chunk in members.chunks(ITEMS_PER_COMMAND) {
+                    push([vec!["SADD".into(), key.clone()], chunk.to_vec()].concat());
+                }
+            }
+            Value::SortedSet(sorted_set) => {
+                let members = sorted_set.iter().collect::<Vec<_>>();
+                for chunk in members.chunks(ITEMS_PER_COMMAND) {
+                    let mut args = vec!["ZADD".into(), key.clone()];
+                    for (member, score) in chunk {
+                        args.extend([format_score(*score), member.to_string()]);
+                    }
+                    push(args);
+                }
+            }
+            Value::Stream(stream) => encode_stream(&key, stream, &mut push),
+        }
+    }
+
+    Some(aof)
+}
+
+/// Adds the entries with their IDs, then sets what XADD can't: the stream's last ID and
+/// counters, its consumer groups, and their pending entries.
+fn encode_stream(key: &str, stream: &Stream, push: &mut impl FnMut(Vec<String>)) {
+    let entries = stream.range(StreamId::MIN, StreamId::MAX, false, None);
+    for (id, fields) in &entries {
+        let mut args = vec!["XADD".into(), key.to_string(), id.to_string()];
+        for (field, value) in fields.iter() {
+            args.extend([field.clone(), value.clone()]);
+        }
+        push(args);
+    }
+    if entries.is_empty() {
+        // an empty stream is created by adding an entry and trimming it right away
+        let id = stream.last_id().max(StreamId { ms: 0, seq: 1 });
+        push(
+            ["XADD", key, "MAXLEN", "0", &id.to_string(), "x", "y"]
+                .map(String::from)
+                .to_vec(),
+        );
+    }
+    push(vec![
+        "XSETID".into(),
+        key.to_string(),
+        stream.last_id().to_string(),
+        "ENTRIESADDED".into(),
+        stream.entries_added().to_string(),
+        "MAXDELETEDID".into(),
+        stream.max_deleted_id().to_string(),
+    ]);
+
+    for (name, group) in stream.groups() {
+        let mut args = vec![
+            "XGROUP".into(),
+            "CREATE".into(),
+            key.to_string(),
+            name.clone(),
+            group.last_delivered_id().to_string(),
+        ];
+        if let Some(entries_read) = group.entries_read() {
+            args.extend(["ENTRIESREAD".into(), entries_read.to_string()]);
+        }
+        push(args);
+
+        for consumer in group.consumers().keys() {
+            push(vec![
+                "XGROUP".into(),
+                "CREATECONSUMER".into(),
+                key.to_string(),
+                name.clone(),
+                consumer.clone(),
+            ]);
+        }
+        for (id, pending) in group.pending() {
+            push(vec![
+                "XCLAIM".into(),
+                key.to_string(),
+                name.clone(),
+                pending.consumer.clone(),
+                "0".into(),
+                id.to_string(),
+                "TIME".into(),
+                pending.delivery_time.to_string(),
+                "RETRYCOUNT".into(),
+                pending.delivery_count.to_string(),
+                "JUSTID".into(),
+                "FORCE".into(),
+            ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use super::*;
+    use crate::aof::tests::{run, test_config};
+    use crate::config::Config;
+
+    fn load(config: &Config) -> RwLock<Storage> {
+        let mut storage = Storage::default();
+        let aof = Aof::load(config, &mut storage).unwrap();
+        storage.aof = Some(Arc::new(aof));
+
+        RwLock::new(storage)
+    }
+
+    /// Rewrites the AOF the way `start_rewrite` does, with `write` running while the base is
+    /// written.
+    fn rewrite(storage: &RwLock<Storage>, write: &[&str]) {
+        let aof = Arc::clone(storage.read().unwrap().aof.as_ref().unwrap());
+        aof.open_next_incr().unwrap();
+        let snapshot = storage.read().unwrap().snapshot();
+        run(storage, write);
+        aof.rewrite(&snapshot).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite() {
+        for rdb_preamble in [false, true] {
+            let config = Config {
+                aof_use_rdb_preamble: rdb_preamble,
+                ..test_config(&format!("rewrite-{}", rdb_preamble))
+            };
+            let storage = load(&config);
+
+            run(&storage, &["SET", "string", "value", "PX", "100000"]);
+            run(&storage, &["SET", "string", "value2", "KEEPTTL"]);
+            run(&storage, &["SADD", "set", "a", "b", "c"]);
+            run(&storage, &["SREM", "set", "b"]);
+            run(&storage, &["ZADD", "zset", "1.5", "a", "-inf", "b"]);
+            run(&storage, &["XADD", "stream", "1-1", "f", "v"]);
+            run(&storage, &["XADD", "stream", "2-1", "f", "v"]);
+            run(&storage, &["XDEL", "stream", "2-1"]);
+            run(&storage, &["XGROUP", "CREATE", "stream", "group", "0"]);
+            run(
+                &storage,
+                &[
+                    "XREADGROUP",
+                    "GROUP",
+                    "group",
+                    "alice",
+                    "STREAMS",
+                    "stream",
+                    ">",
+                ],
+            );
+            run(
+                &storage,
+                &["XGROUP", "CREATECONSUMER", "stream", "group", "bob"],
+            );
+            run(
+                &storage,
+                &["XGROUP", "CREATE", "empty", "group", "$", "MKSTREAM"],
+            );
+            run(
+                &storage,
+                &[
+                    "FUNCTION",
+                    "LOAD",
+                    "#!lua name=lib\nredis.register_function('f', function() return 1 end)",
+                ],
+            );
+
+            rewrite(&storage, &["SET", "during", "rewrite"]);
+
+            let aof_dir = config.aof_dir();
+            let base = if rdb_preamble {
+                "appendonly.aof.1.base.rdb"
+            } else {
+                "appendonly.aof.1.base.aof"
+            };
+            assert_eq!(
+                fs::read_to_string(aof_dir.join("appendonly.aof.manifest")).unwrap(),
+                format!("file {base} seq 1 type b\nfile appendonly.aof.2.incr.aof seq 2 type i\n")
+            );
+            assert!(!aof_dir.join("appendonly.aof.1.incr.aof").exists());
+            let aof = Arc::clone(storage.read().unwrap().aof.as_ref().unwrap());
+            assert_eq!(aof.base_size(), aof.current_size());
+            if !rdb_preamble {
+                let base = fs::read_to_string(aof_dir.join(base)).unwrap();
+                assert!(base.contains("XSETID"));
+                assert!(!base.contains("SREM"));
+            }
+
+            let loaded = load(&config);
+            for read in [
+                &["GET", "string"][..],
+                &["GET", "during"],
+                &["SMISMEMBER", "set", "a", "b", "c"],
+                &["ZRANGE", "zset", "0", "-1", "WITHSCORES"],
+                &["XRANGE", "stream", "-", "+"],
+                &["XINFO", "STREAM", "stream"],
+                &["XPENDING", "stream", "group"],
+                &["XINFO", "STREAM", "empty"],
+                &["FCALL", "f", "0"],
+            ] {
+                assert_eq!(run(&loaded, read), run(&storage, read), "{:?}", read);
+            }
+
+            fs::remove_dir_all(&config.dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_should_rewrite() {
+        let config = test_config("auto");
+        let storage = load(&config);
+        let aof = Arc::clone(storage.read().unwrap().aof.as_ref().unwrap());
+
+        assert!(!aof.should_rewrite(100, 0));
+        run(&storage, &["SET", "key", "value"]);
+        // the AOF was empty, so it grew by as much as any percentage
+        assert!(aof.should_rewrite(100, 0));
+        assert!(!aof.should_rewrite(100, 1 << 20));
+
+        rewrite(&storage, &["SET", "other", "value"]);
+        assert!(!aof.should_rewrite(100, 0));
+        for _ in 0..10 {
+            run(&storage, &["SET", "key", "value"]);
+        }
+        assert!(aof.should_rewrite(100, 0));
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
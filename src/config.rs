@@ -1,12 +1,43 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 
+use crate::aof::{self, Fsync};
+use crate::rdb::{SaveRule, DEFAULT_FILENAME};
+use crate::storage::KeyspaceEvents;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
     pub role: Role,
+    /// How long a script runs before other clients are told the server is busy.
+    pub busy_reply_threshold: Duration,
+    pub notify_keyspace_events: KeyspaceEvents,
+    /// Where snapshots are loaded from at startup and saved to.
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// When to save in the background. There are none unless `--save` is given.
+    pub save_rules: Vec<SaveRule>,
+    /// Whether writes are logged to the AOF, which is then loaded at startup instead of the
+    /// snapshot.
+    pub appendonly: bool,
+    /// The prefix of the names of the AOF files.
+    pub appendfilename: String,
+    /// The directory in `dir` the AOF files and their manifest are in.
+    pub appenddirname: String,
+    pub appendfsync: Fsync,
+    /// Whether an AOF that ends in the middle of a command is loaded anyway.
+    pub aof_load_truncated: bool,
+    /// Whether the base file a rewrite produces is a snapshot rather than commands.
+    pub aof_use_rdb_preamble: bool,
+    /// How much the AOF grows past its size after the last rewrite before it is rewritten
+    /// again, in percent. 0 disables automatic rewrites.
+    pub auto_aof_rewrite_percentage: u64,
+    /// The size under which the AOF isn't rewritten automatically, in bytes.
+    pub auto_aof_rewrite_min_size: u64,
 }
 
 impl Config {
@@ -36,7 +67,109 @@ impl Config {
             Some(replica_of) => Role::new_slave(replica_of)?,
         };
 
-        Ok(Config { port, role })
+        let busy_reply_threshold = result
+            .get("busy-reply-threshold")
+            .map(|s| s.as_str())
+            .unwrap_or("5000")
+            .parse()
+            .map(Duration::from_millis)?;
+
+        let notify_keyspace_events = KeyspaceEvents::parse(
+            result
+                .get("notify-keyspace-events")
+                .map(|s| s.as_str())
+                .unwrap_or(""),
+        )?;
+
+        let dir = PathBuf::from(result.get("dir").map(|s| s.as_str()).unwrap_or("."));
+
+        let dbfilename = result
+            .get("dbfilename")
+            .map(|s| s.as_str())
+            .unwrap_or(DEFAULT_FILENAME)
+            .to_string();
+
+        let save_rules =
+            SaveRule::parse_rules(result.get("save").map(|s| s.as_str()).unwrap_or(""))?;
+
+        let appendonly = parse_yes_no(
+            "appendonly",
+            result.get("appendonly").map(|s| s.as_str()).unwrap_or("no"),
+        )?;
+
+        let appendfilename = result
+            .get("appendfilename")
+            .map(|s| s.as_str())
+            .unwrap_or(aof::DEFAULT_FILENAME)
+            .to_string();
+
+        let appendfsync = Fsync::parse(
+            result
+                .get("appendfsync")
+                .map(|s| s.as_str())
+                .unwrap_or("everysec"),
+        )?;
+
+        let aof_load_truncated = parse_yes_no(
+            "aof-load-truncated",
+            result
+                .get("aof-load-truncated")
+                .map(|s| s.as_str())
+                .unwrap_or("yes"),
+        )?;
+
+        let appenddirname = result
+            .get("appenddirname")
+            .map(|s| s.as_str())
+            .unwrap_or(aof::DEFAULT_DIRNAME)
+            .to_string();
+
+        let aof_use_rdb_preamble = parse_yes_no(
+            "aof-use-rdb-preamble",
+            result
+                .get("aof-use-rdb-preamble")
+                .map(|s| s.as_str())
+                .unwrap_or("yes"),
+        )?;
+
+        let auto_aof_rewrite_percentage = result
+            .get("auto-aof-rewrite-percentage")
+            .map(|s| s.as_str())
+            .unwrap_or("100")
+            .parse()?;
+
+        let auto_aof_rewrite_min_size = parse_memory(
+            result
+                .get("auto-aof-rewrite-min-size")
+                .map(|s| s.as_str())
+                .unwrap_or("64mb"),
+        )?;
+
+        Ok(Config {
+            port,
+            role,
+            busy_reply_threshold,
+            notify_keyspace_events,
+            dir,
+            dbfilename,
+            save_rules,
+            appendonly,
+            appendfilename,
+            appenddirname,
+            appendfsync,
+            aof_load_truncated,
+            aof_use_rdb_preamble,
+            auto_aof_rewrite_percentage,
+            auto_aof_rewrite_min_size,
+        })
+    }
+
+    pub fn rdb_path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+
+    pub fn aof_dir(&self) -> PathBuf {
+        self.dir.join(&self.appenddirname)
     }
 }
 
@@ -46,10 +179,53 @@ impl Default for Config {
         Config {
             port: 6379,
             role: Role::Master,
+            busy_reply_threshold: Duration::from_millis(5000),
+            notify_keyspace_events: KeyspaceEvents::default(),
+            dir: PathBuf::from("."),
+            dbfilename: DEFAULT_FILENAME.to_string(),
+            save_rules: Vec::new(),
+            appendonly: false,
+            appendfilename: aof::DEFAULT_FILENAME.to_string(),
+            appenddirname: aof::DEFAULT_DIRNAME.to_string(),
+            appendfsync: Fsync::EverySec,
+            aof_load_truncated: true,
+            aof_use_rdb_preamble: true,
+            auto_aof_rewrite_percentage: 100,
+            auto_aof_rewrite_min_size: 64 << 20,
         }
     }
 }
 
+fn parse_yes_no(name: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => bail!("argument of {} must be 'yes' or 'no'", name),
+    }
+}
+
+/// Parses a size in bytes such as `64mb`, where `k`, `m` and `g` are powers of 1000, and `kb`,
+/// `mb` and `gb` powers of 1024.
+fn parse_memory(value: &str) -> Result<u64> {
+    let value = value.to_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: u64 = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1 << 10,
+        "m" => 1000 * 1000,
+        "mb" => 1 << 20,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1 << 30,
+        unit => bail!("invalid memory unit: {}", unit),
+    };
+
+    let value: u64 = digits
+        .parse()
+        .with_context(|| format!("invalid memory value: {}", value))?;
+    value.checked_mul(unit).context("memory value too large")
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Role {
     Master,
@@ -0,0 +1,91 @@
+//! The server, and the RDB and AOF checkers that share its code.
+
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+
+use task::{active_expire, append_fsync, auto_rewrite_aof, auto_save, replication, serve_client};
+
+use crate::aof::{Aof, Fsync};
+use crate::config::{Config, Role};
+use crate::storage::Storage;
+
+mod aof;
+mod config;
+mod lua;
+mod rdb;
+mod resp;
+mod scripting;
+mod session;
+mod storage;
+mod task;
+mod utils;
+
+pub use aof::check::run as check_aof;
+pub use rdb::check::run as check_rdb;
+
+/// Loads the data and serves clients, configured by the command line `args`.
+pub async fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let config = Arc::new(Config::parse_parameter(args)?);
+    let mut storage = Storage::new(&config);
+    if config.appendonly {
+        // the AOF has every write, so the snapshot isn't needed
+        storage.aof = Some(Arc::new(Aof::load(&config, &mut storage)?));
+    } else {
+        rdb::load_file(&config.rdb_path(), &mut storage)?;
+    }
+    let storage = Arc::new(RwLock::new(storage));
+
+    let mut join_set: JoinSet<Result<()>> = JoinSet::new();
+
+    if let Role::Slave {
+        master_host,
+        master_port,
+    } = &config.role
+    {
+        let replication_task = replication::start_replication(
+            format!("{}:{}", master_host, master_port),
+            Arc::clone(&storage),
+            Arc::clone(&config),
+        );
+
+        join_set.spawn(replication_task);
+    }
+
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", config.port)).await?;
+
+    join_set.spawn(active_expire::run(Arc::clone(&storage)));
+    if !config.save_rules.is_empty() {
+        let rules = config.save_rules.clone();
+        join_set.spawn(auto_save::run(Arc::clone(&storage), rules));
+    }
+    if let (Some(aof), Fsync::EverySec) = (&storage.read().unwrap().aof, config.appendfsync) {
+        join_set.spawn(append_fsync::run(Arc::clone(aof)));
+    }
+    if config.appendonly && config.auto_aof_rewrite_percentage > 0 {
+        join_set.spawn(auto_rewrite_aof::run(
+            Arc::clone(&storage),
+            config.auto_aof_rewrite_percentage,
+            config.auto_aof_rewrite_min_size,
+        ));
+    }
+    join_set.spawn(serve_client::run(listener, storage));
+
+    while let Some(join_result) = join_set.join_next().await {
+        match join_result {
+            Ok(result) => {
+                if let Err(e) = result {
+                    eprintln!("error occurred; error = {:?}", e);
+                }
+            }
+            Err(e) => {
+                eprintln!("join error occurred; error = {:?}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
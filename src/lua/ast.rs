@@ -0,0 +1,119 @@
+//! The syntax tree the interpreter runs. Variables are resolved while parsing: locals are slots in
+//! their function's frame, and upvalues are indexes into the closure's captured cells.
+
+use std::sync::Arc;
+
+use crate::lua::value::LuaString;
+
+pub type Block = Vec<Stat>;
+
+#[derive(Debug)]
+pub struct FunctionProto {
+    pub num_params: usize,
+    pub is_vararg: bool,
+    pub num_slots: usize,
+    pub upvalues: Vec<Upvalue>,
+    pub body: Block,
+}
+
+/// Where a closure finds a captured variable when it is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upvalue {
+    /// A local slot of the enclosing function.
+    Local(usize),
+    /// An upvalue of the enclosing function.
+    Upvalue(usize),
+}
+
+#[derive(Debug)]
+pub struct Stat {
+    pub line: u32,
+    pub kind: StatKind,
+}
+
+#[derive(Debug)]
+pub enum StatKind {
+    Expr(Expr),
+    Local(Vec<usize>, Vec<Expr>),
+    LocalFunction(usize, Arc<FunctionProto>),
+    Assign(Vec<Expr>, Vec<Expr>),
+    If(Vec<(Expr, Block)>, Option<Block>),
+    While(Expr, Block),
+    Repeat(Block, Expr),
+    NumericFor {
+        var: usize,
+        start: Expr,
+        limit: Expr,
+        step: Option<Expr>,
+        body: Block,
+    },
+    GenericFor {
+        vars: Vec<usize>,
+        exprs: Vec<Expr>,
+        body: Block,
+    },
+    Do(Block),
+    Return(Vec<Expr>),
+    Break,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Nil,
+    True,
+    False,
+    Vararg,
+    Number(f64),
+    String(LuaString),
+    Local(usize, LuaString),
+    Upvalue(usize, LuaString),
+    Global(LuaString),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Method(Box<Expr>, LuaString, Vec<Expr>),
+    Function(Arc<FunctionProto>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Table(Vec<Field>),
+    /// A parenthesized expression, which truncates multiple results to one.
+    Paren(Box<Expr>),
+}
+
+impl Expr {
+    /// Calls and `...` can produce any number of values at the end of a list.
+    pub fn is_multi(&self) -> bool {
+        matches!(self, Expr::Call(..) | Expr::Method(..) | Expr::Vararg)
+    }
+}
+
+#[derive(Debug)]
+pub enum Field {
+    Positional(Expr),
+    Keyed(Expr, Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    Len,
+}
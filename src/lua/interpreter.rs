@@ -0,0 +1,850 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::lua::ast::{BinOp, Block, Expr, Field, FunctionProto, Stat, StatKind, UnOp, Upvalue};
+use crate::lua::stdlib;
+use crate::lua::value::{
+    Closure, Function, LuaError, LuaResult, LuaString, Table, TableRef, Value,
+};
+
+/// Like Lua's `LUAI_MAXCCALLS`.
+const MAX_CALL_DEPTH: usize = 200;
+/// How many statements and loop iterations run between two interrupt checks.
+const INTERRUPT_CHECK_STEPS: u32 = 1000;
+/// How many `__index` or `__newindex` tables are followed before giving up, like `MAXTAGLOOP`.
+const MAX_META_CHAIN: usize = 100;
+
+/// What the interpreter is embedded in.
+pub trait Host {
+    /// Runs a function the host provides, e.g. `redis.call`.
+    fn call(&mut self, name: &str, args: Vec<Value>) -> LuaResult<Vec<Value>>;
+
+    /// Called regularly while code runs. An error stops the code with `LuaError::Interrupted`.
+    fn check_interrupt(&mut self) -> Result<(), String>;
+}
+
+pub struct Interpreter<'h> {
+    pub globals: TableRef,
+    pub string_metatable: Option<TableRef>,
+    host: &'h mut dyn Host,
+    chunk_name: String,
+    /// The line of the statement running in the innermost Lua function. It is left at the
+    /// failing statement when an error unwinds.
+    line: u32,
+    /// The lines functions were called from, for `error` with a level.
+    call_lines: Vec<u32>,
+    depth: usize,
+    steps: u32,
+}
+
+struct Frame {
+    slots: Vec<Rc<RefCell<Value>>>,
+    closure: Rc<Closure>,
+    varargs: Vec<Value>,
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Return(Vec<Value>),
+}
+
+fn cell(value: Value) -> Rc<RefCell<Value>> {
+    Rc::new(RefCell::new(value))
+}
+
+fn first(values: Vec<Value>) -> Value {
+    values.into_iter().next().unwrap_or_default()
+}
+
+/// Describes an operand of the wrong type, naming the variable it came from like Lua does.
+fn describe(expr: Option<&Expr>, value: &Value) -> String {
+    let variable = match expr {
+        Some(Expr::Local(_, name)) => format!("local '{}'", name),
+        Some(Expr::Upvalue(_, name)) => format!("upvalue '{}'", name),
+        Some(Expr::Global(name)) => format!("global '{}'", name),
+        Some(Expr::Index(_, key)) => match &**key {
+            Expr::String(key) => format!("field '{}'", key),
+            _ => return format!("a {} value", value.type_name()),
+        },
+        _ => return format!("a {} value", value.type_name()),
+    };
+    format!("{} (a {} value)", variable, value.type_name())
+}
+
+impl<'h> Interpreter<'h> {
+    /// Creates an interpreter with the standard libraries loaded. Errors are reported as
+    /// coming from `chunk_name`.
+    pub fn new(host: &'h mut dyn Host, chunk_name: &str) -> Self {
+        let mut interpreter = Interpreter {
+            globals: TableRef::new(Table::default()),
+            string_metatable: None,
+            host,
+            chunk_name: chunk_name.to_string(),
+            line: 0,
+            call_lines: Vec::new(),
+            depth: 0,
+            steps: 0,
+        };
+        stdlib::open(&mut interpreter);
+        interpreter
+    }
+
+    pub fn host(&mut self) -> &mut dyn Host {
+        &mut *self.host
+    }
+
+    /// The line of the statement that is running, or that failed.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn set_global(&self, name: &str, value: impl Into<Value>) {
+        self.globals.set_str(name, value);
+    }
+
+    /// Turns a compiled chunk into a function that can be called.
+    pub fn load(&self, proto: Arc<FunctionProto>) -> Value {
+        Value::Function(Function::Lua(Rc::new(Closure {
+            proto,
+            upvalues: Vec::new(),
+        })))
+    }
+
+    /// An error raised at the current position, like `luaL_error`.
+    pub fn runtime_error(&self, message: impl Display) -> LuaError {
+        self.error_at_level(message, 1)
+    }
+
+    /// An error with the position of the function `level` frames up, like `error(message, level)`.
+    pub fn error_at_level(&self, message: impl Display, level: usize) -> LuaError {
+        let line = match level {
+            0 => None,
+            1 => Some(self.line),
+            level => self
+                .call_lines
+                .len()
+                .checked_sub(level - 1)
+                .map(|i| self.call_lines[i]),
+        };
+
+        match line {
+            Some(line) if line > 0 => LuaError::Error(Value::string(format!(
+                "{}:{}: {}",
+                self.chunk_name, line, message
+            ))),
+            _ => LuaError::Error(Value::string(message.to_string())),
+        }
+    }
+
+    pub fn metatable(&self, value: &Value) -> Option<TableRef> {
+        match value {
+            Value::Table(table) => table.metatable(),
+            Value::String(_) => self.string_metatable.clone(),
+            _ => None,
+        }
+    }
+
+    fn metamethod(&self, value: &Value, event: &str) -> Option<Value> {
+        self.metatable(value)
+            .map(|metatable| metatable.get_str(event))
+            .filter(|handler| !handler.is_nil())
+    }
+
+    pub fn call(&mut self, function: &Value, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+        self.call_with(function, args, None)
+    }
+
+    /// Calls a function and catches its errors, like `pcall`. Interruptions are not caught.
+    pub fn protected_call(
+        &mut self,
+        function: &Value,
+        args: Vec<Value>,
+    ) -> LuaResult<Result<Vec<Value>, Value>> {
+        let call_lines = self.call_lines.len();
+        let line = self.line;
+
+        match self.call(function, args) {
+            Ok(values) => Ok(Ok(values)),
+            Err(LuaError::Error(error)) => {
+                self.call_lines.truncate(call_lines);
+                self.line = line;
+                Ok(Err(error))
+            }
+            Err(interrupted) => Err(interrupted),
+        }
+    }
+
+    fn call_with(
+        &mut self,
+        function: &Value,
+        mut args: Vec<Value>,
+        expr: Option<&Expr>,
+    ) -> LuaResult<Vec<Value>> {
+        let function = match function {
+            Value::Function(function) => function.clone(),
+            other => match self.metamethod(other, "__call") {
+                Some(handler) => {
+                    args.insert(0, other.clone());
+                    return self.call(&handler, args);
+                }
+                None => {
+                    return Err(
+                        self.runtime_error(format!("attempt to call {}", describe(expr, other)))
+                    );
+                }
+            },
+        };
+
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(self.runtime_error("stack overflow"));
+        }
+
+        self.depth += 1;
+        let result = match function {
+            Function::Lua(closure) => self.call_closure(closure, args),
+            Function::Native(_, native) => native(self, args),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn call_closure(&mut self, closure: Rc<Closure>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+        let proto = &closure.proto;
+        let mut args = args.into_iter();
+
+        let mut slots = Vec::with_capacity(proto.num_slots);
+        slots.extend((0..proto.num_params).map(|_| cell(args.next().unwrap_or_default())));
+        slots.extend((proto.num_params..proto.num_slots).map(|_| cell(Value::Nil)));
+        let varargs = if proto.is_vararg {
+            args.collect()
+        } else {
+            Vec::new()
+        };
+
+        let proto = Arc::clone(proto);
+        let mut frame = Frame {
+            slots,
+            closure,
+            varargs,
+        };
+
+        let caller_line = self.line;
+        self.call_lines.push(caller_line);
+        let flow = self.exec_block(&proto.body, &mut frame)?;
+        self.call_lines.pop();
+        self.line = caller_line;
+
+        Ok(match flow {
+            Flow::Return(values) => values,
+            _ => Vec::new(),
+        })
+    }
+
+    fn exec_block(&mut self, block: &Block, frame: &mut Frame) -> LuaResult<Flow> {
+        for stat in block {
+            match self.exec(stat, frame)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Counts statements and loop iterations, checking for an interrupt every so often, so that
+    /// even a loop with an empty body can be stopped.
+    fn step(&mut self) -> LuaResult<()> {
+        self.steps += 1;
+        if self.steps >= INTERRUPT_CHECK_STEPS {
+            self.steps = 0;
+            self.host.check_interrupt().map_err(LuaError::Interrupted)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a loop body, returning whether the loop should stop and how.
+    fn exec_loop_body(&mut self, body: &Block, frame: &mut Frame) -> LuaResult<Option<Flow>> {
+        self.step()?;
+        Ok(match self.exec_block(body, frame)? {
+            Flow::Normal => None,
+            Flow::Break => Some(Flow::Normal),
+            flow => Some(flow),
+        })
+    }
+
+    fn exec(&mut self, stat: &Stat, frame: &mut Frame) -> LuaResult<Flow> {
+        self.line = stat.line;
+        self.step()?;
+
+        match &stat.kind {
+            StatKind::Expr(expr) => {
+                self.eval_multi(expr, frame)?;
+            }
+            StatKind::Local(slots, exprs) => {
+                let mut values = self.eval_list(exprs, frame)?.into_iter();
+                // every execution creates new variables, which closures capture separately
+                for slot in slots {
+                    frame.slots[*slot] = cell(values.next().unwrap_or_default());
+                }
+            }
+            StatKind::LocalFunction(slot, proto) => {
+                frame.slots[*slot] = cell(Value::Nil);
+                let function = self.closure(proto, frame);
+                *frame.slots[*slot].borrow_mut() = function;
+            }
+            StatKind::Assign(targets, exprs) => {
+                if let ([target], [expr]) = (targets.as_slice(), exprs.as_slice()) {
+                    let value = self.eval(expr, frame)?;
+                    self.assign(target, value, frame)?;
+                } else {
+                    let mut values = self.eval_list(exprs, frame)?.into_iter();
+                    for target in targets {
+                        self.assign(target, values.next().unwrap_or_default(), frame)?;
+                    }
+                }
+            }
+            StatKind::If(branches, otherwise) => {
+                for (condition, block) in branches {
+                    if self.eval(condition, frame)?.is_truthy() {
+                        return self.exec_block(block, frame);
+                    }
+                }
+                if let Some(block) = otherwise {
+                    return self.exec_block(block, frame);
+                }
+            }
+            StatKind::While(condition, body) => {
+                while self.eval(condition, frame)?.is_truthy() {
+                    if let Some(flow) = self.exec_loop_body(body, frame)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StatKind::Repeat(body, condition) => loop {
+                if let Some(flow) = self.exec_loop_body(body, frame)? {
+                    return Ok(flow);
+                }
+                if self.eval(condition, frame)?.is_truthy() {
+                    break;
+                }
+            },
+            StatKind::NumericFor {
+                var,
+                start,
+                limit,
+                step,
+                body,
+            } => {
+                let mut for_number =
+                    |expr: &Expr, what: &str| match self.eval(expr, frame)?.to_number() {
+                        Some(n) => Ok(n),
+                        None => Err(self.runtime_error(format!("'for' {} must be a number", what))),
+                    };
+
+                let start = for_number(start, "initial value")?;
+                let limit = for_number(limit, "limit")?;
+                let step = match step {
+                    Some(step) => for_number(step, "step")?,
+                    None => 1.0,
+                };
+
+                let mut i = start;
+                while (step > 0.0 && i <= limit) || (step <= 0.0 && i >= limit) {
+                    frame.slots[*var] = cell(Value::Number(i));
+                    if let Some(flow) = self.exec_loop_body(body, frame)? {
+                        return Ok(flow);
+                    }
+                    i += step;
+                }
+            }
+            StatKind::GenericFor { vars, exprs, body } => {
+                let mut values = self.eval_list(exprs, frame)?.into_iter();
+                let iterator = values.next().unwrap_or_default();
+                let state = values.next().unwrap_or_default();
+                let mut control = values.next().unwrap_or_default();
+
+                loop {
+                    let results = self.call(&iterator, vec![state.clone(), control.clone()])?;
+                    let mut results = results.into_iter();
+                    control = results.next().unwrap_or_default();
+                    if control.is_nil() {
+                        break;
+                    }
+
+                    frame.slots[vars[0]] = cell(control.clone());
+                    for var in &vars[1..] {
+                        frame.slots[*var] = cell(results.next().unwrap_or_default());
+                    }
+
+                    if let Some(flow) = self.exec_loop_body(body, frame)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            StatKind::Do(block) => return self.exec_block(block, frame),
+            StatKind::Return(exprs) => return Ok(Flow::Return(self.eval_list(exprs, frame)?)),
+            StatKind::Break => return Ok(Flow::Break),
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value, frame: &mut Frame) -> LuaResult<()> {
+        match target {
+            Expr::Local(slot, _) => *frame.slots[*slot].borrow_mut() = value,
+            Expr::Upvalue(i, _) => *frame.closure.upvalues[*i].borrow_mut() = value,
+            Expr::Global(name) => {
+                let globals = Value::Table(self.globals.clone());
+                self.set_index(&globals, Value::String(name.clone()), value)?;
+            }
+            Expr::Index(object, key) => {
+                let object_value = self.eval(object, frame)?;
+                let key = self.eval(key, frame)?;
+                self.set_index_with(&object_value, key, value, Some(object))?;
+            }
+            _ => unreachable!("the parser only produces assignable targets"),
+        }
+        Ok(())
+    }
+
+    fn closure(&self, proto: &Arc<FunctionProto>, frame: &Frame) -> Value {
+        let upvalues = proto
+            .upvalues
+            .iter()
+            .map(|upvalue| match upvalue {
+                Upvalue::Local(slot) => Rc::clone(&frame.slots[*slot]),
+                Upvalue::Upvalue(i) => Rc::clone(&frame.closure.upvalues[*i]),
+            })
+            .collect();
+
+        Value::Function(Function::Lua(Rc::new(Closure {
+            proto: Arc::clone(proto),
+            upvalues,
+        })))
+    }
+
+    fn eval_list(&mut self, exprs: &[Expr], frame: &mut Frame) -> LuaResult<Vec<Value>> {
+        let mut values = Vec::with_capacity(exprs.len());
+        for (i, expr) in exprs.iter().enumerate() {
+            if i == exprs.len() - 1 && expr.is_multi() {
+                values.extend(self.eval_multi(expr, frame)?);
+            } else {
+                values.push(self.eval(expr, frame)?);
+            }
+        }
+        Ok(values)
+    }
+
+    fn eval_multi(&mut self, expr: &Expr, frame: &mut Frame) -> LuaResult<Vec<Value>> {
+        match expr {
+            Expr::Call(function, args) => {
+                let function_value = self.eval(function, frame)?;
+                let args = self.eval_list(args, frame)?;
+                self.call_with(&function_value, args, Some(function))
+            }
+            Expr::Method(object, name, args) => {
+                let object_value = self.eval(object, frame)?;
+                let method =
+                    self.index_with(&object_value, &Value::String(name.clone()), Some(object))?;
+
+                let mut call_args = vec![object_value];
+                call_args.extend(self.eval_list(args, frame)?);
+
+                if !matches!(method, Value::Function(_))
+                    && self.metamethod(&method, "__call").is_none()
+                {
+                    return Err(self.runtime_error(format!(
+                        "attempt to call method '{}' (a {} value)",
+                        name,
+                        method.type_name()
+                    )));
+                }
+                self.call(&method, call_args)
+            }
+            Expr::Vararg => Ok(frame.varargs.clone()),
+            expr => Ok(vec![self.eval(expr, frame)?]),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, frame: &mut Frame) -> LuaResult<Value> {
+        Ok(match expr {
+            Expr::Nil => Value::Nil,
+            Expr::True => Value::Boolean(true),
+            Expr::False => Value::Boolean(false),
+            Expr::Vararg => frame.varargs.first().cloned().unwrap_or_default(),
+            Expr::Number(n) => Value::Number(*n),
+            Expr::String(s) => Value::String(s.clone()),
+            Expr::Local(slot, _) => frame.slots[*slot].borrow().clone(),
+            Expr::Upvalue(i, _) => frame.closure.upvalues[*i].borrow().clone(),
+            Expr::Global(name) => {
+                let globals = Value::Table(self.globals.clone());
+                self.index(&globals, &Value::String(name.clone()))?
+            }
+            Expr::Index(object, key) => {
+                let object_value = self.eval(object, frame)?;
+                let key = self.eval(key, frame)?;
+                self.index_with(&object_value, &key, Some(object))?
+            }
+            Expr::Call(..) | Expr::Method(..) => first(self.eval_multi(expr, frame)?),
+            Expr::Function(proto) => self.closure(proto, frame),
+            Expr::And(left, right) => {
+                let left = self.eval(left, frame)?;
+                if left.is_truthy() {
+                    self.eval(right, frame)?
+                } else {
+                    left
+                }
+            }
+            Expr::Or(left, right) => {
+                let left = self.eval(left, frame)?;
+                if left.is_truthy() {
+                    left
+                } else {
+                    self.eval(right, frame)?
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let a = self.eval(left, frame)?;
+                let b = self.eval(right, frame)?;
+                self.binary(*op, a, b, Some((left, right)))?
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand, frame)?;
+                self.unary(*op, value, Some(operand))?
+            }
+            Expr::Table(fields) => self.table(fields, frame)?,
+            Expr::Paren(expr) => self.eval(expr, frame)?,
+        })
+    }
+
+    fn table(&mut self, fields: &[Field], frame: &mut Frame) -> LuaResult<Value> {
+        let mut table = Table::default();
+        let mut n = 1.0;
+
+        for (i, field) in fields.iter().enumerate() {
+            match field {
+                Field::Positional(expr) if i == fields.len() - 1 && expr.is_multi() => {
+                    for value in self.eval_multi(expr, frame)? {
+                        let _ = table.set(Value::Number(n), value);
+                        n += 1.0;
+                    }
+                }
+                Field::Positional(expr) => {
+                    let value = self.eval(expr, frame)?;
+                    let _ = table.set(Value::Number(n), value);
+                    n += 1.0;
+                }
+                Field::Keyed(key, value) => {
+                    let key = self.eval(key, frame)?;
+                    let value = self.eval(value, frame)?;
+                    table.set(key, value).map_err(|e| self.runtime_error(e))?;
+                }
+            }
+        }
+
+        Ok(Value::Table(TableRef::new(table)))
+    }
+
+    /// `object[key]`, following `__index`.
+    pub fn index(&mut self, object: &Value, key: &Value) -> LuaResult<Value> {
+        self.index_with(object, key, None)
+    }
+
+    fn index_with(&mut self, object: &Value, key: &Value, expr: Option<&Expr>) -> LuaResult<Value> {
+        let mut object = object.clone();
+
+        for _ in 0..MAX_META_CHAIN {
+            let handler = match &object {
+                Value::Table(table) => {
+                    let value = table.get(key);
+                    if !value.is_nil() {
+                        return Ok(value);
+                    }
+                    match self.metamethod(&object, "__index") {
+                        Some(handler) => handler,
+                        None => return Ok(Value::Nil),
+                    }
+                }
+                other => match self.metamethod(other, "__index") {
+                    Some(handler) => handler,
+                    None => {
+                        return Err(self
+                            .runtime_error(format!("attempt to index {}", describe(expr, other))));
+                    }
+                },
+            };
+
+            if let Value::Function(_) = handler {
+                return Ok(first(self.call(&handler, vec![object, key.clone()])?));
+            }
+            object = handler;
+        }
+
+        Err(self.runtime_error("loop in gettable"))
+    }
+
+    /// `object[key] = value`, following `__newindex`.
+    pub fn set_index(&mut self, object: &Value, key: Value, value: Value) -> LuaResult<()> {
+        self.set_index_with(object, key, value, None)
+    }
+
+    fn set_index_with(
+        &mut self,
+        object: &Value,
+        key: Value,
+        value: Value,
+        expr: Option<&Expr>,
+    ) -> LuaResult<()> {
+        let mut object = object.clone();
+
+        for _ in 0..MAX_META_CHAIN {
+            let handler = match &object {
+                Value::Table(table) => {
+                    let handler = if table.get(&key).is_nil() {
+                        self.metamethod(&object, "__newindex")
+                    } else {
+                        None
+                    };
+
+                    match handler {
+                        Some(handler) => handler,
+                        None => return self.raw_set(table, key, value),
+                    }
+                }
+                other => match self.metamethod(other, "__newindex") {
+                    Some(handler) => handler,
+                    None => {
+                        return Err(self
+                            .runtime_error(format!("attempt to index {}", describe(expr, other))));
+                    }
+                },
+            };
+
+            if let Value::Function(_) = handler {
+                self.call(&handler, vec![object, key, value])?;
+                return Ok(());
+            }
+            object = handler;
+        }
+
+        Err(self.runtime_error("loop in settable"))
+    }
+
+    /// Sets a field without metamethods, which still fails on read-only tables.
+    pub fn raw_set(&self, table: &TableRef, key: Value, value: Value) -> LuaResult<()> {
+        let mut table = table.0.borrow_mut();
+        if table.readonly {
+            return Err(self.runtime_error("Attempt to modify a readonly table"));
+        }
+        table.set(key, value).map_err(|e| self.runtime_error(e))
+    }
+
+    fn binary(
+        &mut self,
+        op: BinOp,
+        a: Value,
+        b: Value,
+        exprs: Option<(&Expr, &Expr)>,
+    ) -> LuaResult<Value> {
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::Pow => {
+                self.arithmetic(op, a, b, exprs)
+            }
+            BinOp::Concat => self.concat(a, b, exprs),
+            BinOp::Eq => Ok(Value::Boolean(self.equals(&a, &b)?)),
+            BinOp::Ne => Ok(Value::Boolean(!self.equals(&a, &b)?)),
+            BinOp::Lt => Ok(Value::Boolean(self.less_than(&a, &b)?)),
+            BinOp::Le => Ok(Value::Boolean(self.less_equal(&a, &b)?)),
+            BinOp::Gt => Ok(Value::Boolean(self.less_than(&b, &a)?)),
+            BinOp::Ge => Ok(Value::Boolean(self.less_equal(&b, &a)?)),
+        }
+    }
+
+    pub fn arithmetic(
+        &mut self,
+        op: BinOp,
+        a: Value,
+        b: Value,
+        exprs: Option<(&Expr, &Expr)>,
+    ) -> LuaResult<Value> {
+        if let (Some(x), Some(y)) = (a.to_number(), b.to_number()) {
+            return Ok(Value::Number(match op {
+                BinOp::Add => x + y,
+                BinOp::Sub => x - y,
+                BinOp::Mul => x * y,
+                BinOp::Div => x / y,
+                BinOp::Mod => x - (x / y).floor() * y,
+                _ => x.powf(y),
+            }));
+        }
+
+        let event = match op {
+            BinOp::Add => "__add",
+            BinOp::Sub => "__sub",
+            BinOp::Mul => "__mul",
+            BinOp::Div => "__div",
+            BinOp::Mod => "__mod",
+            _ => "__pow",
+        };
+
+        let handler = self
+            .metamethod(&a, event)
+            .or_else(|| self.metamethod(&b, event));
+        match handler {
+            Some(handler) => Ok(first(self.call(&handler, vec![a, b])?)),
+            None => {
+                let (bad, expr) = if a.to_number().is_none() {
+                    (a, exprs.map(|(left, _)| left))
+                } else {
+                    (b, exprs.map(|(_, right)| right))
+                };
+                Err(self.runtime_error(format!(
+                    "attempt to perform arithmetic on {}",
+                    describe(expr, &bad)
+                )))
+            }
+        }
+    }
+
+    fn concat(&mut self, a: Value, b: Value, exprs: Option<(&Expr, &Expr)>) -> LuaResult<Value> {
+        if let (Some(x), Some(y)) = (a.to_lua_string(), b.to_lua_string()) {
+            let mut bytes = Vec::with_capacity(x.as_bytes().len() + y.as_bytes().len());
+            bytes.extend_from_slice(x.as_bytes());
+            bytes.extend_from_slice(y.as_bytes());
+            return Ok(Value::string(bytes));
+        }
+
+        let handler = self
+            .metamethod(&a, "__concat")
+            .or_else(|| self.metamethod(&b, "__concat"));
+        match handler {
+            Some(handler) => Ok(first(self.call(&handler, vec![a, b])?)),
+            None => {
+                let (bad, expr) = if a.to_lua_string().is_none() {
+                    (a, exprs.map(|(left, _)| left))
+                } else {
+                    (b, exprs.map(|(_, right)| right))
+                };
+                Err(self.runtime_error(format!("attempt to concatenate {}", describe(expr, &bad))))
+            }
+        }
+    }
+
+    /// `a == b`, with `__eq` for tables that share the handler.
+    pub fn equals(&mut self, a: &Value, b: &Value) -> LuaResult<bool> {
+        if a.raw_equals(b) {
+            return Ok(true);
+        }
+
+        if let (Value::Table(_), Value::Table(_)) = (a, b) {
+            if let (Some(x), Some(y)) = (self.metamethod(a, "__eq"), self.metamethod(b, "__eq")) {
+                if x.raw_equals(&y) {
+                    let result = self.call(&x, vec![a.clone(), b.clone()])?;
+                    return Ok(first(result).is_truthy());
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn order_error(&self, a: &Value, b: &Value) -> LuaError {
+        if a.type_name() == b.type_name() {
+            self.runtime_error(format!("attempt to compare two {} values", a.type_name()))
+        } else {
+            self.runtime_error(format!(
+                "attempt to compare {} with {}",
+                a.type_name(),
+                b.type_name()
+            ))
+        }
+    }
+
+    fn order_metamethod(&mut self, a: &Value, b: &Value, event: &str) -> LuaResult<Option<bool>> {
+        if a.type_name() != b.type_name() {
+            return Ok(None);
+        }
+
+        match (self.metamethod(a, event), self.metamethod(b, event)) {
+            (Some(x), Some(y)) if x.raw_equals(&y) => {
+                let result = self.call(&x, vec![a.clone(), b.clone()])?;
+                Ok(Some(first(result).is_truthy()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn less_than(&mut self, a: &Value, b: &Value) -> LuaResult<bool> {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => Ok(x < y),
+            (Value::String(x), Value::String(y)) => Ok(x < y),
+            _ => match self.order_metamethod(a, b, "__lt")? {
+                Some(result) => Ok(result),
+                None => Err(self.order_error(a, b)),
+            },
+        }
+    }
+
+    fn less_equal(&mut self, a: &Value, b: &Value) -> LuaResult<bool> {
+        match (a, b) {
+            (Value::Number(x), Value::Number(y)) => Ok(x <= y),
+            (Value::String(x), Value::String(y)) => Ok(x <= y),
+            _ => {
+                if let Some(result) = self.order_metamethod(a, b, "__le")? {
+                    return Ok(result);
+                }
+                match self.order_metamethod(b, a, "__lt")? {
+                    Some(result) => Ok(!result),
+                    None => Err(self.order_error(a, b)),
+                }
+            }
+        }
+    }
+
+    fn unary(&mut self, op: UnOp, value: Value, expr: Option<&Expr>) -> LuaResult<Value> {
+        match op {
+            UnOp::Not => Ok(Value::Boolean(!value.is_truthy())),
+            UnOp::Neg => match value.to_number() {
+                Some(n) => Ok(Value::Number(-n)),
+                None => match self.metamethod(&value, "__unm") {
+                    Some(handler) => Ok(first(self.call(&handler, vec![value.clone(), value])?)),
+                    None => Err(self.runtime_error(format!(
+                        "attempt to perform arithmetic on {}",
+                        describe(expr, &value)
+                    ))),
+                },
+            },
+            UnOp::Len => match &value {
+                Value::String(s) => Ok(Value::Number(s.as_bytes().len() as f64)),
+                Value::Table(table) => Ok(Value::Number(table.len() as f64)),
+                _ => match self.metamethod(&value, "__len") {
+                    Some(handler) => Ok(first(self.call(&handler, vec![value])?)),
+                    None => Err(self.runtime_error(format!(
+                        "attempt to get length of {}",
+                        describe(expr, &value)
+                    ))),
+                },
+            },
+        }
+    }
+
+    /// Converts a value to a string like `tostring`, using `__tostring`.
+    pub fn tostring(&mut self, value: &Value) -> LuaResult<LuaString> {
+        if let Some(handler) = self.metamethod(value, "__tostring") {
+            return match first(self.call(&handler, vec![value.clone()])?) {
+                Value::String(s) => Ok(s),
+                Value::Number(n) => Ok(Value::Number(n).to_lua_string().unwrap()),
+                _ => Err(self.runtime_error("'__tostring' must return a string")),
+            };
+        }
+
+        Ok(match value {
+            Value::Nil => "nil".into(),
+            Value::Boolean(b) => b.to_string().into(),
+            Value::Number(_) | Value::String(_) => value.to_lua_string().unwrap(),
+            other => other.address_string().into(),
+        })
+    }
+}
@@ -0,0 +1,465 @@
+use std::fmt::{Display, Formatter};
+
+use crate::lua::number::parse_number;
+use crate::lua::value::LuaString;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Name(LuaString),
+    String(LuaString),
+    Number(f64),
+    // keywords
+    And,
+    Break,
+    Do,
+    Else,
+    Elseif,
+    End,
+    False,
+    For,
+    Function,
+    If,
+    In,
+    Local,
+    Nil,
+    Not,
+    Or,
+    Repeat,
+    Return,
+    Then,
+    True,
+    Until,
+    While,
+    // symbols
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Hash,
+    Eq,
+    Ne,
+    Le,
+    Ge,
+    Lt,
+    Gt,
+    Assign,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Semicolon,
+    Colon,
+    Comma,
+    Dot,
+    Concat,
+    Ellipsis,
+    Eof,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Token::Name(name) | Token::String(name) => return write!(f, "{}", name),
+            Token::Number(n) => return write!(f, "{}", crate::lua::number::format_number(*n)),
+            Token::And => "and",
+            Token::Break => "break",
+            Token::Do => "do",
+            Token::Else => "else",
+            Token::Elseif => "elseif",
+            Token::End => "end",
+            Token::False => "false",
+            Token::For => "for",
+            Token::Function => "function",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Local => "local",
+            Token::Nil => "nil",
+            Token::Not => "not",
+            Token::Or => "or",
+            Token::Repeat => "repeat",
+            Token::Return => "return",
+            Token::Then => "then",
+            Token::True => "true",
+            Token::Until => "until",
+            Token::While => "while",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Caret => "^",
+            Token::Hash => "#",
+            Token::Eq => "==",
+            Token::Ne => "~=",
+            Token::Le => "<=",
+            Token::Ge => ">=",
+            Token::Lt => "<",
+            Token::Gt => ">",
+            Token::Assign => "=",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::Semicolon => ";",
+            Token::Colon => ":",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Concat => "..",
+            Token::Ellipsis => "...",
+            Token::Eof => "<eof>",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A compile error, already formatted like Lua's `chunk:line: message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError(pub String);
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+pub struct Lexer<'a> {
+    source: &'a [u8],
+    position: usize,
+    line: u32,
+    chunk_name: &'a str,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(chunk_name: &'a str, source: &'a [u8]) -> Self {
+        let mut lexer = Lexer {
+            source,
+            position: 0,
+            line: 1,
+            chunk_name,
+        };
+
+        // skip a first line starting with '#', e.g. a shebang
+        if source.first() == Some(&b'#') {
+            while lexer.peek().is_some_and(|c| c != b'\n') {
+                lexer.position += 1;
+            }
+        }
+
+        lexer
+    }
+
+    /// Splits the whole source into tokens, each with the line it ends on.
+    pub fn tokenize(mut self) -> Result<Vec<(Token, u32)>, SyntaxError> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = self.next_token()?;
+            let eof = token == Token::Eof;
+            tokens.push((token, self.line));
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    pub fn error(&self, message: &str, near: Option<&str>) -> SyntaxError {
+        match near {
+            Some(near) => SyntaxError(format!(
+                "{}:{}: {} near '{}'",
+                self.chunk_name, self.line, message, near
+            )),
+            None => SyntaxError(format!("{}:{}: {}", self.chunk_name, self.line, message)),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.source.get(self.position + offset).copied()
+    }
+
+    fn near(&self, start: usize) -> String {
+        String::from_utf8_lossy(&self.source[start..self.position.min(self.source.len())])
+            .into_owned()
+    }
+
+    fn next_token(&mut self) -> Result<Token, SyntaxError> {
+        loop {
+            let Some(c) = self.peek() else {
+                return Ok(Token::Eof);
+            };
+
+            match c {
+                b'\n' => {
+                    self.line += 1;
+                    self.position += 1;
+                }
+                b' ' | b'\t' | b'\r' | 0x0b | 0x0c => self.position += 1,
+                b'-' if self.peek_at(1) == Some(b'-') => {
+                    self.position += 2;
+                    self.skip_comment()?;
+                }
+                _ => break,
+            }
+        }
+
+        let start = self.position;
+        let c = self.peek().unwrap();
+        self.position += 1;
+
+        let token = match c {
+            b'+' => Token::Plus,
+            b'-' => Token::Minus,
+            b'*' => Token::Star,
+            b'/' => Token::Slash,
+            b'%' => Token::Percent,
+            b'^' => Token::Caret,
+            b'#' => Token::Hash,
+            b'(' => Token::LeftParen,
+            b')' => Token::RightParen,
+            b'{' => Token::LeftBrace,
+            b'}' => Token::RightBrace,
+            b']' => Token::RightBracket,
+            b';' => Token::Semicolon,
+            b':' => Token::Colon,
+            b',' => Token::Comma,
+            b'=' => self.either(b'=', Token::Eq, Token::Assign),
+            b'<' => self.either(b'=', Token::Le, Token::Lt),
+            b'>' => self.either(b'=', Token::Ge, Token::Gt),
+            b'~' if self.peek() == Some(b'=') => {
+                self.position += 1;
+                Token::Ne
+            }
+            b'[' => match self.long_bracket_level() {
+                Some(level) => Token::String(self.long_string(level, "string")?.into()),
+                None => Token::LeftBracket,
+            },
+            b'"' | b'\'' => Token::String(self.short_string(c)?.into()),
+            b'.' if self.peek() == Some(b'.') => {
+                self.position += 1;
+                if self.peek() == Some(b'.') {
+                    self.position += 1;
+                    Token::Ellipsis
+                } else {
+                    Token::Concat
+                }
+            }
+            b'.' if self.peek().is_some_and(|c| c.is_ascii_digit()) => self.number(start)?,
+            b'.' => Token::Dot,
+            c if c.is_ascii_digit() => self.number(start)?,
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+                {
+                    self.position += 1;
+                }
+                keyword_or_name(&self.source[start..self.position])
+            }
+            _ => {
+                let near = if c.is_ascii_control() {
+                    format!("char({})", c)
+                } else {
+                    self.near(start)
+                };
+                return Err(self.error("unexpected symbol", Some(&near)));
+            }
+        };
+
+        Ok(token)
+    }
+
+    fn either(&mut self, next: u8, matched: Token, otherwise: Token) -> Token {
+        if self.peek() == Some(next) {
+            self.position += 1;
+            matched
+        } else {
+            otherwise
+        }
+    }
+
+    fn skip_comment(&mut self) -> Result<(), SyntaxError> {
+        if self.peek() == Some(b'[') {
+            let start = self.position;
+            self.position += 1;
+            if let Some(level) = self.long_bracket_level() {
+                self.long_string(level, "comment")?;
+                return Ok(());
+            }
+            self.position = start;
+        }
+
+        while self.peek().is_some_and(|c| c != b'\n') {
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    /// After a '[', checks for the rest of an opening long bracket `[==[` and returns its level.
+    fn long_bracket_level(&mut self) -> Option<usize> {
+        let mut level = 0;
+        while self.peek_at(level) == Some(b'=') {
+            level += 1;
+        }
+
+        if self.peek_at(level) == Some(b'[') {
+            self.position += level + 1;
+            Some(level)
+        } else {
+            None
+        }
+    }
+
+    fn long_string(&mut self, level: usize, what: &str) -> Result<Vec<u8>, SyntaxError> {
+        // a newline right after the opening bracket is skipped
+        if self.peek() == Some(b'\r') {
+            self.position += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.line += 1;
+            self.position += 1;
+        }
+
+        let mut content = Vec::new();
+        loop {
+            match self.peek() {
+                None => {
+                    return Err(self.error(&format!("unfinished long {}", what), Some("<eof>")));
+                }
+                Some(b']')
+                    if (0..level).all(|i| self.peek_at(1 + i) == Some(b'='))
+                        && self.peek_at(1 + level) == Some(b']') =>
+                {
+                    self.position += level + 2;
+                    return Ok(content);
+                }
+                Some(c) => {
+                    if c == b'\n' {
+                        self.line += 1;
+                    }
+                    content.push(c);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn short_string(&mut self, quote: u8) -> Result<Vec<u8>, SyntaxError> {
+        let start = self.position - 1;
+        let mut content = Vec::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unfinished string", Some("<eof>")));
+            };
+            self.position += 1;
+
+            match c {
+                c if c == quote => return Ok(content),
+                b'\n' => {
+                    return Err(self.error("unfinished string", Some(&self.near(start))));
+                }
+                b'\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("unfinished string", Some("<eof>")));
+                    };
+                    self.position += 1;
+
+                    let byte = match escape {
+                        b'a' => 0x07,
+                        b'b' => 0x08,
+                        b'f' => 0x0c,
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'v' => 0x0b,
+                        b'\n' => {
+                            self.line += 1;
+                            b'\n'
+                        }
+                        c if c.is_ascii_digit() => {
+                            let mut value = (c - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(c) if c.is_ascii_digit() => {
+                                        value = value * 10 + (c - b'0') as u32;
+                                        self.position += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            if value > 255 {
+                                return Err(self.error("escape sequence too large", None));
+                            }
+                            value as u8
+                        }
+                        // any other escaped character stands for itself, like \\ and \"
+                        c => c,
+                    };
+                    content.push(byte);
+                }
+                c => content.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<Token, SyntaxError> {
+        loop {
+            match self.peek() {
+                Some(b'e' | b'E') => {
+                    self.position += 1;
+                    if matches!(self.peek(), Some(b'+' | b'-')) {
+                        self.position += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_alphanumeric() || c == b'.' || c == b'_' => {
+                    self.position += 1
+                }
+                _ => break,
+            }
+        }
+
+        let text = &self.source[start..self.position];
+        match parse_number(text) {
+            Some(n) => Ok(Token::Number(n)),
+            None => Err(self.error("malformed number", Some(&self.near(start)))),
+        }
+    }
+}
+
+fn keyword_or_name(word: &[u8]) -> Token {
+    match word {
+        b"and" => Token::And,
+        b"break" => Token::Break,
+        b"do" => Token::Do,
+        b"else" => Token::Else,
+        b"elseif" => Token::Elseif,
+        b"end" => Token::End,
+        b"false" => Token::False,
+        b"for" => Token::For,
+        b"function" => Token::Function,
+        b"if" => Token::If,
+        b"in" => Token::In,
+        b"local" => Token::Local,
+        b"nil" => Token::Nil,
+        b"not" => Token::Not,
+        b"or" => Token::Or,
+        b"repeat" => Token::Repeat,
+        b"return" => Token::Return,
+        b"then" => Token::Then,
+        b"true" => Token::True,
+        b"until" => Token::Until,
+        b"while" => Token::While,
+        name => Token::Name(name.into()),
+    }
+}
@@ -0,0 +1,43 @@
+//! A Lua 5.1 interpreter for scripts, without dependencies on the C implementation.
+//!
+//! Sources are parsed into a syntax tree with local variables resolved to slots, and the tree is
+//! run directly. Compiled chunks are `Send`, so they can be cached and shared, while the values
+//! of a run live in one interpreter on one thread.
+
+pub use ast::FunctionProto;
+pub use interpreter::{Host, Interpreter};
+pub use parser::parse;
+pub use stdlib::{check_integer, check_string, library};
+pub use value::{Function, LuaError, LuaResult, Table, TableRef, Value};
+
+use std::thread;
+
+mod ast;
+mod interpreter;
+mod lexer;
+mod number;
+mod parser;
+mod stdlib;
+mod value;
+
+/// The interpreter recurses for nested calls and expressions, so code runs on a thread with a
+/// stack large enough for the deepest nesting the parser and `MAX_CALL_DEPTH` allow.
+const STACK_SIZE: usize = 256 << 20;
+
+/// Runs `f` on a thread with a stack large enough for the interpreter, waiting for it to finish.
+pub fn with_stack<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .name("lua".to_string())
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the Lua thread");
+
+        handle
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+//! Conversions between Lua numbers and strings, which follow C's `strtod` and `printf`.
+
+/// Parses a number the way Lua converts strings: decimal with an optional exponent, or hex
+/// integers, surrounded by optional whitespace.
+pub fn parse_number(s: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(s)
+        .ok()?
+        .trim_matches(|c: char| c.is_ascii_whitespace());
+
+    let (negative, unsigned) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let n = hex.bytes().fold(0.0, |n, b| {
+            n * 16.0 + (b as char).to_digit(16).unwrap() as f64
+        });
+        return Some(if negative { -n } else { n });
+    }
+
+    // Rust also accepts "inf" and "nan", which strtod does but Lua's lexer doesn't produce
+    let valid = unsigned.bytes().any(|b| b.is_ascii_digit())
+        && unsigned
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-'));
+    if !valid {
+        return None;
+    }
+
+    s.parse().ok()
+}
+
+/// Formats a number like Lua's `tostring`, which is `printf("%.14g")`.
+pub fn format_number(n: f64) -> String {
+    format_float(n, 'g', Some(14), false)
+}
+
+/// Formats a float with a printf conversion (`e`, `E`, `f`, `F`, `g` or `G`), without padding.
+pub fn format_float(n: f64, conversion: char, precision: Option<usize>, alternate: bool) -> String {
+    let upper = conversion.is_ascii_uppercase();
+
+    if !n.is_finite() {
+        let s = if n.is_nan() {
+            if n.is_sign_negative() {
+                "-nan"
+            } else {
+                "nan"
+            }
+        } else if n < 0.0 {
+            "-inf"
+        } else {
+            "inf"
+        };
+        return if upper {
+            s.to_uppercase()
+        } else {
+            s.to_string()
+        };
+    }
+
+    let precision = precision.unwrap_or(6);
+
+    let s = match conversion.to_ascii_lowercase() {
+        'f' => {
+            let mut s = format!("{:.*}", precision, n);
+            if alternate && precision == 0 {
+                s.push('.');
+            }
+            s
+        }
+        'e' => format_exponent(n, precision, alternate),
+        _ => {
+            let precision = precision.max(1);
+            let exponent = decimal_exponent(n, precision);
+
+            let s = if exponent < -4 || exponent >= precision as i32 {
+                format_exponent(n, precision - 1, alternate)
+            } else {
+                format!("{:.*}", (precision as i32 - 1 - exponent) as usize, n)
+            };
+
+            if alternate {
+                s
+            } else {
+                strip_trailing_zeros(&s)
+            }
+        }
+    };
+
+    if upper {
+        s.to_uppercase()
+    } else {
+        s
+    }
+}
+
+/// The exponent `n` has in scientific notation, after rounding to `precision` significant digits.
+fn decimal_exponent(n: f64, precision: usize) -> i32 {
+    let s = format!("{:.*e}", precision - 1, n);
+    s[s.find('e').unwrap() + 1..].parse().unwrap()
+}
+
+/// `%e`: Rust writes `1.5e3` where C writes `1.5e+03`.
+fn format_exponent(n: f64, precision: usize, alternate: bool) -> String {
+    let s = format!("{:.*e}", precision, n);
+    let (mantissa, exponent) = s.split_at(s.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+
+    let dot = if alternate && precision == 0 { "." } else { "" };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}{}e{}{:02}", mantissa, dot, sign, exponent.abs())
+}
+
+fn strip_trailing_zeros(s: &str) -> String {
+    let (mantissa, exponent) = match s.find('e') {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+
+    format!("{}{}", mantissa, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(1.0), "1");
+        assert_eq!(format_number(-0.5), "-0.5");
+        assert_eq!(format_number(1.23456), "1.23456");
+        assert_eq!(format_number(1e15), "1e+15");
+        assert_eq!(format_number(123456789012345.0), "1.2345678901234e+14");
+        assert_eq!(format_number(1e14 - 1.0), "99999999999999");
+        assert_eq!(format_number(0.0001), "0.0001");
+        assert_eq!(format_number(0.00001), "1e-05");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(format_float(2.5, 'f', Some(2), false), "2.50");
+        assert_eq!(format_float(12345.678, 'E', Some(2), false), "1.23E+04");
+        assert_eq!(format_float(100000.0, 'g', None, false), "100000");
+        assert_eq!(format_float(1000000.0, 'g', None, false), "1e+06");
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number(b"10"), Some(10.0));
+        assert_eq!(parse_number(b" -1.5e2 "), Some(-150.0));
+        assert_eq!(parse_number(b"0x1F"), Some(31.0));
+        assert_eq!(parse_number(b".5"), Some(0.5));
+        assert_eq!(parse_number(b"5."), Some(5.0));
+        assert_eq!(parse_number(b""), None);
+        assert_eq!(parse_number(b"inf"), None);
+        assert_eq!(parse_number(b"nan"), None);
+        assert_eq!(parse_number(b"1x"), None);
+        assert_eq!(parse_number(b"0x"), None);
+    }
+}
@@ -0,0 +1,799 @@
+use std::sync::Arc;
+
+use crate::lua::ast::{BinOp, Block, Expr, Field, FunctionProto, Stat, StatKind, UnOp, Upvalue};
+use crate::lua::lexer::{Lexer, SyntaxError, Token};
+use crate::lua::value::LuaString;
+
+const UNARY_PRIORITY: u8 = 8;
+const MAX_SYNTAX_LEVELS: usize = 200;
+
+/// Compiles a chunk into the prototype of its main function, which takes `...` as arguments.
+pub fn parse(chunk_name: &str, source: &[u8]) -> Result<Arc<FunctionProto>, SyntaxError> {
+    let tokens = Lexer::new(chunk_name, source).tokenize()?;
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        chunk_name,
+        levels: 0,
+        functions: vec![FunctionState::new(true)],
+    };
+
+    let body = parser.block()?;
+    if *parser.peek() != Token::Eof {
+        return Err(parser.error("'<eof>' expected"));
+    }
+
+    let main = parser.functions.pop().unwrap();
+    Ok(Arc::new(FunctionProto {
+        num_params: 0,
+        is_vararg: true,
+        num_slots: main.num_slots,
+        upvalues: Vec::new(),
+        body,
+    }))
+}
+
+struct FunctionState {
+    /// The locals in scope, innermost block last.
+    blocks: Vec<Vec<(LuaString, usize)>>,
+    num_slots: usize,
+    upvalues: Vec<Upvalue>,
+    is_vararg: bool,
+    loop_depth: usize,
+}
+
+impl FunctionState {
+    fn new(is_vararg: bool) -> Self {
+        FunctionState {
+            blocks: vec![Vec::new()],
+            num_slots: 0,
+            upvalues: Vec::new(),
+            is_vararg,
+            loop_depth: 0,
+        }
+    }
+}
+
+enum BinaryToken {
+    Op(BinOp),
+    And,
+    Or,
+}
+
+impl BinaryToken {
+    fn new(token: &Token) -> Option<Self> {
+        let op = match token {
+            Token::And => return Some(BinaryToken::And),
+            Token::Or => return Some(BinaryToken::Or),
+            Token::Plus => BinOp::Add,
+            Token::Minus => BinOp::Sub,
+            Token::Star => BinOp::Mul,
+            Token::Slash => BinOp::Div,
+            Token::Percent => BinOp::Mod,
+            Token::Caret => BinOp::Pow,
+            Token::Concat => BinOp::Concat,
+            Token::Eq => BinOp::Eq,
+            Token::Ne => BinOp::Ne,
+            Token::Lt => BinOp::Lt,
+            Token::Le => BinOp::Le,
+            Token::Gt => BinOp::Gt,
+            Token::Ge => BinOp::Ge,
+            _ => return None,
+        };
+        Some(BinaryToken::Op(op))
+    }
+
+    /// Left and right priorities, as in Lua's parser. Right associative operators bind tighter
+    /// on the left.
+    fn priority(&self) -> (u8, u8) {
+        match self {
+            BinaryToken::Or => (1, 1),
+            BinaryToken::And => (2, 2),
+            BinaryToken::Op(op) => match op {
+                BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => (3, 3),
+                BinOp::Concat => (5, 4),
+                BinOp::Add | BinOp::Sub => (6, 6),
+                BinOp::Mul | BinOp::Div | BinOp::Mod => (7, 7),
+                BinOp::Pow => (10, 9),
+            },
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, u32)>,
+    position: usize,
+    chunk_name: &'a str,
+    /// How deeply statements and expressions are nested, which bounds the recursion.
+    levels: usize,
+    functions: Vec<FunctionState>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let i = (self.position + offset).min(self.tokens.len() - 1);
+        &self.tokens[i].0
+    }
+
+    fn line(&self) -> u32 {
+        self.tokens[self.position].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn check(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> SyntaxError {
+        let near = match self.peek() {
+            Token::String(s) => format!("'{}'", s),
+            Token::Eof => "'<eof>'".to_string(),
+            token => format!("'{}'", token),
+        };
+        SyntaxError(format!(
+            "{}:{}: {} near {}",
+            self.chunk_name,
+            self.line(),
+            message,
+            near
+        ))
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), SyntaxError> {
+        if self.check(&token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}' expected", token)))
+        }
+    }
+
+    /// Expects the token closing a construct, naming the opening one if it's on another line.
+    fn expect_match(&mut self, token: Token, opening: Token, line: u32) -> Result<(), SyntaxError> {
+        if self.check(&token) {
+            Ok(())
+        } else if line == self.line() {
+            Err(self.error(&format!("'{}' expected", token)))
+        } else {
+            Err(self.error(&format!(
+                "'{}' expected (to close '{}' at line {})",
+                token, opening, line
+            )))
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<LuaString, SyntaxError> {
+        match self.peek().clone() {
+            Token::Name(name) => {
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error("<name> expected")),
+        }
+    }
+
+    fn function(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn declare(&mut self, name: LuaString) -> usize {
+        let function = self.function();
+        let slot = function.num_slots;
+        function.num_slots += 1;
+        function.blocks.last_mut().unwrap().push((name, slot));
+        slot
+    }
+
+    fn find_local(&self, function: usize, name: &LuaString) -> Option<usize> {
+        self.functions[function]
+            .blocks
+            .iter()
+            .rev()
+            .flat_map(|block| block.iter().rev())
+            .find(|(local, _)| local == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    fn find_upvalue(&mut self, function: usize, name: &LuaString) -> Option<usize> {
+        if function == 0 {
+            return None;
+        }
+
+        let upvalue = match self.find_local(function - 1, name) {
+            Some(slot) => Upvalue::Local(slot),
+            None => Upvalue::Upvalue(self.find_upvalue(function - 1, name)?),
+        };
+
+        let upvalues = &mut self.functions[function].upvalues;
+        Some(match upvalues.iter().position(|u| *u == upvalue) {
+            Some(i) => i,
+            None => {
+                upvalues.push(upvalue);
+                upvalues.len() - 1
+            }
+        })
+    }
+
+    fn variable(&mut self, name: LuaString) -> Expr {
+        let function = self.functions.len() - 1;
+        if let Some(slot) = self.find_local(function, &name) {
+            Expr::Local(slot, name)
+        } else if let Some(i) = self.find_upvalue(function, &name) {
+            Expr::Upvalue(i, name)
+        } else {
+            Expr::Global(name)
+        }
+    }
+
+    fn block_follows(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::Else | Token::Elseif | Token::End | Token::Until | Token::Eof
+        )
+    }
+
+    /// Parses statements up to the end of a block, in the current scope.
+    fn block(&mut self) -> Result<Block, SyntaxError> {
+        let mut block = Vec::new();
+
+        while !self.block_follows() {
+            let line = self.line();
+            let last = matches!(self.peek(), Token::Return | Token::Break);
+
+            let kind = self.statement()?;
+            block.push(Stat { line, kind });
+            self.check(&Token::Semicolon);
+
+            if last {
+                break;
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Parses a block in a new scope.
+    fn scoped_block(&mut self) -> Result<Block, SyntaxError> {
+        self.function().blocks.push(Vec::new());
+        let block = self.block();
+        self.function().blocks.pop();
+        block
+    }
+
+    fn loop_body(&mut self) -> Result<Block, SyntaxError> {
+        self.function().loop_depth += 1;
+        let block = self.block();
+        self.function().loop_depth -= 1;
+        block
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, SyntaxError>,
+    ) -> Result<T, SyntaxError> {
+        if self.levels >= MAX_SYNTAX_LEVELS {
+            return Err(self.error("chunk has too many syntax levels"));
+        }
+
+        self.levels += 1;
+        let result = parse(self);
+        self.levels -= 1;
+        result
+    }
+
+    fn statement(&mut self) -> Result<StatKind, SyntaxError> {
+        self.nested(Self::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<StatKind, SyntaxError> {
+        let line = self.line();
+
+        match self.peek() {
+            Token::If => self.if_statement(line),
+            Token::While => {
+                self.advance();
+                let condition = self.expr()?;
+                self.expect(Token::Do)?;
+                self.function().blocks.push(Vec::new());
+                let body = self.loop_body();
+                self.function().blocks.pop();
+                let body = body?;
+                self.expect_match(Token::End, Token::While, line)?;
+                Ok(StatKind::While(condition, body))
+            }
+            Token::Do => {
+                self.advance();
+                let body = self.scoped_block()?;
+                self.expect_match(Token::End, Token::Do, line)?;
+                Ok(StatKind::Do(body))
+            }
+            Token::For => self.for_statement(line),
+            Token::Repeat => {
+                self.advance();
+                // the condition can see the body's locals
+                self.function().blocks.push(Vec::new());
+                let result = self.loop_body().and_then(|body| {
+                    self.expect_match(Token::Until, Token::Repeat, line)?;
+                    Ok(StatKind::Repeat(body, self.expr()?))
+                });
+                self.function().blocks.pop();
+                result
+            }
+            Token::Function => {
+                self.advance();
+                let name = self.expect_name()?;
+                let mut target = self.variable(name);
+                let mut is_method = false;
+
+                while matches!(self.peek(), Token::Dot | Token::Colon) {
+                    is_method = self.advance() == Token::Colon;
+                    let key = self.expect_name()?;
+                    target = Expr::Index(Box::new(target), Box::new(Expr::String(key)));
+                    if is_method {
+                        break;
+                    }
+                }
+
+                let function = self.function_body(is_method, line)?;
+                Ok(StatKind::Assign(
+                    vec![target],
+                    vec![Expr::Function(function)],
+                ))
+            }
+            Token::Local => {
+                self.advance();
+                if self.check(&Token::Function) {
+                    let name = self.expect_name()?;
+                    let slot = self.declare(name.clone());
+                    let function = self.function_body(false, line)?;
+                    return Ok(StatKind::LocalFunction(slot, function));
+                }
+
+                let mut names = vec![self.expect_name()?];
+                while self.check(&Token::Comma) {
+                    names.push(self.expect_name()?);
+                }
+
+                let exprs = if self.check(&Token::Assign) {
+                    self.expr_list()?
+                } else {
+                    Vec::new()
+                };
+
+                // the new locals are only in scope after the statement
+                let slots = names.into_iter().map(|name| self.declare(name)).collect();
+                Ok(StatKind::Local(slots, exprs))
+            }
+            Token::Return => {
+                self.advance();
+                let exprs = if self.block_follows() || *self.peek() == Token::Semicolon {
+                    Vec::new()
+                } else {
+                    self.expr_list()?
+                };
+                Ok(StatKind::Return(exprs))
+            }
+            Token::Break => {
+                self.advance();
+                if self.function().loop_depth == 0 {
+                    return Err(self.error("no loop to break"));
+                }
+                Ok(StatKind::Break)
+            }
+            _ => self.expr_statement(),
+        }
+    }
+
+    fn if_statement(&mut self, line: u32) -> Result<StatKind, SyntaxError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+
+        // the first token is 'if', then one 'elseif' per branch
+        self.advance();
+        loop {
+            let condition = self.expr()?;
+            self.expect(Token::Then)?;
+            branches.push((condition, self.scoped_block()?));
+
+            if self.check(&Token::Elseif) {
+                continue;
+            }
+            if self.check(&Token::Else) {
+                otherwise = Some(self.scoped_block()?);
+            }
+            self.expect_match(Token::End, Token::If, line)?;
+            return Ok(StatKind::If(branches, otherwise));
+        }
+    }
+
+    fn for_statement(&mut self, line: u32) -> Result<StatKind, SyntaxError> {
+        self.advance();
+        let first = self.expect_name()?;
+
+        if self.check(&Token::Assign) {
+            let start = self.expr()?;
+            self.expect(Token::Comma)?;
+            let limit = self.expr()?;
+            let step = if self.check(&Token::Comma) {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            self.expect(Token::Do)?;
+
+            self.function().blocks.push(Vec::new());
+            let var = self.declare(first);
+            let body = self.loop_body();
+            self.function().blocks.pop();
+            let body = body?;
+
+            self.expect_match(Token::End, Token::For, line)?;
+            return Ok(StatKind::NumericFor {
+                var,
+                start,
+                limit,
+                step,
+                body,
+            });
+        }
+
+        let mut names = vec![first];
+        while self.check(&Token::Comma) {
+            names.push(self.expect_name()?);
+        }
+        if !self.check(&Token::In) {
+            return Err(self.error("'=' or 'in' expected"));
+        }
+        let exprs = self.expr_list()?;
+        self.expect(Token::Do)?;
+
+        self.function().blocks.push(Vec::new());
+        let vars = names.into_iter().map(|name| self.declare(name)).collect();
+        let body = self.loop_body();
+        self.function().blocks.pop();
+        let body = body?;
+
+        self.expect_match(Token::End, Token::For, line)?;
+        Ok(StatKind::GenericFor { vars, exprs, body })
+    }
+
+    fn expr_statement(&mut self) -> Result<StatKind, SyntaxError> {
+        let first = self.suffixed_expr()?;
+
+        if matches!(self.peek(), Token::Assign | Token::Comma) {
+            let mut targets = vec![first];
+            while self.check(&Token::Comma) {
+                targets.push(self.suffixed_expr()?);
+            }
+
+            let assignable = |expr: &Expr| {
+                matches!(
+                    expr,
+                    Expr::Local(..) | Expr::Upvalue(..) | Expr::Global(_) | Expr::Index(..)
+                )
+            };
+            if !targets.iter().all(assignable) {
+                return Err(self.error("syntax error"));
+            }
+
+            self.expect(Token::Assign)?;
+            let exprs = self.expr_list()?;
+            return Ok(StatKind::Assign(targets, exprs));
+        }
+
+        match first {
+            Expr::Call(..) | Expr::Method(..) => Ok(StatKind::Expr(first)),
+            _ => Err(self.error("syntax error")),
+        }
+    }
+
+    fn function_body(
+        &mut self,
+        is_method: bool,
+        line: u32,
+    ) -> Result<Arc<FunctionProto>, SyntaxError> {
+        self.functions.push(FunctionState::new(false));
+        let result = self.function_body_inner(is_method, line);
+        let function = self.functions.pop().unwrap();
+        let (num_params, body) = result?;
+
+        Ok(Arc::new(FunctionProto {
+            num_params,
+            is_vararg: function.is_vararg,
+            num_slots: function.num_slots,
+            upvalues: function.upvalues,
+            body,
+        }))
+    }
+
+    fn function_body_inner(
+        &mut self,
+        is_method: bool,
+        line: u32,
+    ) -> Result<(usize, Block), SyntaxError> {
+        if is_method {
+            self.declare("self".into());
+        }
+
+        self.expect(Token::LeftParen)?;
+        if *self.peek() != Token::RightParen {
+            loop {
+                match self.peek().clone() {
+                    Token::Name(name) => {
+                        self.advance();
+                        self.declare(name);
+                    }
+                    Token::Ellipsis => {
+                        self.advance();
+                        self.function().is_vararg = true;
+                        break;
+                    }
+                    _ => return Err(self.error("<name> or '...' expected")),
+                }
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RightParen)?;
+
+        let num_params = self.function().num_slots;
+        let body = self.block()?;
+        self.expect_match(Token::End, Token::Function, line)?;
+        Ok((num_params, body))
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>, SyntaxError> {
+        let mut exprs = vec![self.expr()?];
+        while self.check(&Token::Comma) {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn expr(&mut self) -> Result<Expr, SyntaxError> {
+        self.sub_expr(0)
+    }
+
+    fn sub_expr(&mut self, limit: u8) -> Result<Expr, SyntaxError> {
+        self.nested(|parser| parser.sub_expr_inner(limit))
+    }
+
+    fn sub_expr_inner(&mut self, limit: u8) -> Result<Expr, SyntaxError> {
+        let unary = match self.peek() {
+            Token::Minus => Some(UnOp::Neg),
+            Token::Not => Some(UnOp::Not),
+            Token::Hash => Some(UnOp::Len),
+            _ => None,
+        };
+
+        let mut left = match unary {
+            Some(op) => {
+                self.advance();
+                match (op, self.sub_expr(UNARY_PRIORITY)?) {
+                    (UnOp::Neg, Expr::Number(n)) => Expr::Number(-n),
+                    (op, operand) => Expr::Unary(op, Box::new(operand)),
+                }
+            }
+            None => self.simple_expr()?,
+        };
+
+        while let Some(op) = BinaryToken::new(self.peek()) {
+            let (left_priority, right_priority) = op.priority();
+            if left_priority <= limit {
+                break;
+            }
+
+            self.advance();
+            let right = Box::new(self.sub_expr(right_priority)?);
+            let operand = Box::new(left);
+            left = match op {
+                BinaryToken::And => Expr::And(operand, right),
+                BinaryToken::Or => Expr::Or(operand, right),
+                BinaryToken::Op(op) => Expr::Binary(op, operand, right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn simple_expr(&mut self) -> Result<Expr, SyntaxError> {
+        let expr = match self.peek().clone() {
+            Token::Number(n) => Expr::Number(n),
+            Token::String(s) => Expr::String(s),
+            Token::Nil => Expr::Nil,
+            Token::True => Expr::True,
+            Token::False => Expr::False,
+            Token::Ellipsis => {
+                if !self.function().is_vararg {
+                    return Err(self.error("cannot use '...' outside a vararg function"));
+                }
+                Expr::Vararg
+            }
+            Token::LeftBrace => return self.table_constructor(),
+            Token::Function => {
+                let line = self.line();
+                self.advance();
+                let function = self.function_body(false, line)?;
+                return Ok(Expr::Function(function));
+            }
+            _ => return self.suffixed_expr(),
+        };
+
+        self.advance();
+        Ok(expr)
+    }
+
+    fn primary_expr(&mut self) -> Result<Expr, SyntaxError> {
+        match self.peek().clone() {
+            Token::Name(name) => {
+                self.advance();
+                Ok(self.variable(name))
+            }
+            Token::LeftParen => {
+                let line = self.line();
+                self.advance();
+                let expr = self.expr()?;
+                self.expect_match(Token::RightParen, Token::LeftParen, line)?;
+                Ok(Expr::Paren(Box::new(expr)))
+            }
+            _ => Err(self.error("unexpected symbol")),
+        }
+    }
+
+    fn suffixed_expr(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.primary_expr()?;
+
+        loop {
+            match self.peek() {
+                Token::Dot => {
+                    self.advance();
+                    let key = self.expect_name()?;
+                    expr = Expr::Index(Box::new(expr), Box::new(Expr::String(key)));
+                }
+                Token::LeftBracket => {
+                    self.advance();
+                    let key = self.expr()?;
+                    self.expect(Token::RightBracket)?;
+                    expr = Expr::Index(Box::new(expr), Box::new(key));
+                }
+                Token::Colon => {
+                    self.advance();
+                    let name = self.expect_name()?;
+                    let args = self.call_args()?;
+                    expr = Expr::Method(Box::new(expr), name, args);
+                }
+                Token::LeftParen | Token::String(_) | Token::LeftBrace => {
+                    if *self.peek() == Token::LeftParen
+                        && self.line() != self.tokens[self.position - 1].1
+                    {
+                        return Err(self.error("ambiguous syntax (function call x new statement)"));
+                    }
+                    let args = self.call_args()?;
+                    expr = Expr::Call(Box::new(expr), args);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn call_args(&mut self) -> Result<Vec<Expr>, SyntaxError> {
+        match self.peek().clone() {
+            Token::String(s) => {
+                self.advance();
+                Ok(vec![Expr::String(s)])
+            }
+            Token::LeftBrace => Ok(vec![self.table_constructor()?]),
+            Token::LeftParen => {
+                let line = self.line();
+                self.advance();
+                if self.check(&Token::RightParen) {
+                    return Ok(Vec::new());
+                }
+                let args = self.expr_list()?;
+                self.expect_match(Token::RightParen, Token::LeftParen, line)?;
+                Ok(args)
+            }
+            _ => Err(self.error("function arguments expected")),
+        }
+    }
+
+    fn table_constructor(&mut self) -> Result<Expr, SyntaxError> {
+        let line = self.line();
+        self.expect(Token::LeftBrace)?;
+        let mut fields = Vec::new();
+
+        while *self.peek() != Token::RightBrace {
+            let field = match self.peek().clone() {
+                Token::Name(name) if *self.peek_at(1) == Token::Assign => {
+                    self.advance();
+                    self.advance();
+                    Field::Keyed(Expr::String(name), self.expr()?)
+                }
+                Token::LeftBracket => {
+                    self.advance();
+                    let key = self.expr()?;
+                    self.expect(Token::RightBracket)?;
+                    self.expect(Token::Assign)?;
+                    Field::Keyed(key, self.expr()?)
+                }
+                _ => Field::Positional(self.expr()?),
+            };
+            fields.push(field);
+
+            if !self.check(&Token::Comma) && !self.check(&Token::Semicolon) {
+                break;
+            }
+        }
+
+        self.expect_match(Token::RightBrace, Token::LeftBrace, line)?;
+        Ok(Expr::Table(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_variables() {
+        let main = parse("test", b"local a = 1 local function f() return a + b end").unwrap();
+        assert_eq!(main.num_slots, 2);
+
+        let StatKind::LocalFunction(1, f) = &main.body[1].kind else {
+            panic!("expected a local function");
+        };
+        assert_eq!(f.upvalues, vec![Upvalue::Local(0)]);
+
+        let StatKind::Return(exprs) = &f.body[0].kind else {
+            panic!("expected a return");
+        };
+        assert!(matches!(
+            &exprs[0],
+            Expr::Binary(BinOp::Add, a, b)
+                if matches!(**a, Expr::Upvalue(0, _)) && matches!(**b, Expr::Global(_))
+        ));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = |source: &str| parse("user_script", source.as_bytes()).unwrap_err().0;
+
+        assert_eq!(
+            error("return 1 +"),
+            "user_script:1: unexpected symbol near '<eof>'"
+        );
+        assert_eq!(
+            error("if true then\nreturn 1"),
+            "user_script:2: 'end' expected (to close 'if' at line 1) near '<eof>'"
+        );
+        assert_eq!(
+            error("x = = 1"),
+            "user_script:1: unexpected symbol near '='"
+        );
+        assert_eq!(
+            error("break"),
+            "user_script:1: no loop to break near '<eof>'"
+        );
+        assert_eq!(
+            error("local s = 'abc"),
+            "user_script:1: unfinished string near '<eof>'"
+        );
+    }
+}
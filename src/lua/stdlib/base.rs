@@ -0,0 +1,274 @@
+use crate::lua::interpreter::Interpreter;
+use crate::lua::stdlib::{
+    arg, arg_error, check_any, check_integer, check_table, opt_integer, type_error, NativeFn,
+};
+use crate::lua::value::{Function, LuaError, LuaResult, Value};
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    let functions: [(&'static str, NativeFn); 18] = [
+        ("assert", assert),
+        ("error", error),
+        ("getmetatable", getmetatable),
+        ("ipairs", ipairs),
+        ("next", next),
+        ("pairs", pairs),
+        ("pcall", pcall),
+        ("print", print),
+        ("rawequal", rawequal),
+        ("rawget", rawget),
+        ("rawset", rawset),
+        ("select", select),
+        ("setmetatable", setmetatable),
+        ("tonumber", tonumber),
+        ("tostring", tostring),
+        ("type", type_),
+        ("unpack", unpack),
+        ("xpcall", xpcall),
+    ];
+
+    for (name, function) in functions {
+        interpreter.set_global(name, Value::Function(Function::native(name, function)));
+    }
+
+    interpreter.set_global("_G", interpreter.globals.clone());
+    interpreter.set_global("_VERSION", "Lua 5.1");
+}
+
+fn assert(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let value = check_any(interpreter, &args, 0, "assert")?;
+    if value.is_truthy() {
+        return Ok(args);
+    }
+
+    match arg(&args, 1) {
+        Value::Nil => Err(interpreter.runtime_error("assertion failed!")),
+        message => match message.to_lua_string() {
+            Some(message) => Err(interpreter.runtime_error(message)),
+            None => Err(type_error(interpreter, &args, 1, "assert", "string")),
+        },
+    }
+}
+
+fn error(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let level = opt_integer(interpreter, &args, 1, "error", 1)?;
+
+    match arg(&args, 0) {
+        Value::String(message) if level > 0 => {
+            Err(interpreter.error_at_level(message, level as usize))
+        }
+        value => Err(LuaError::Error(value)),
+    }
+}
+
+fn getmetatable(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let value = check_any(interpreter, &args, 0, "getmetatable")?;
+
+    Ok(vec![match interpreter.metatable(&value) {
+        None => Value::Nil,
+        Some(metatable) => match metatable.get_str("__metatable") {
+            Value::Nil => Value::Table(metatable),
+            protected => protected,
+        },
+    }])
+}
+
+fn setmetatable(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "setmetatable")?;
+    let metatable = match arg(&args, 1) {
+        Value::Nil => None,
+        Value::Table(metatable) => Some(metatable),
+        _ => {
+            return Err(type_error(
+                interpreter,
+                &args,
+                1,
+                "setmetatable",
+                "nil or table",
+            ))
+        }
+    };
+
+    if table.0.borrow().readonly {
+        return Err(interpreter.runtime_error("Attempt to modify a readonly table"));
+    }
+    if table
+        .metatable()
+        .is_some_and(|current| !current.get_str("__metatable").is_nil())
+    {
+        return Err(interpreter.runtime_error("cannot change a protected metatable"));
+    }
+
+    table.0.borrow_mut().metatable = metatable;
+    Ok(vec![Value::Table(table)])
+}
+
+fn ipairs(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "ipairs")?;
+
+    let iterator = Function::native("ipairs_iterator", |interpreter, args| {
+        let table = check_table(interpreter, &args, 0, "ipairs")?;
+        let i = check_integer(interpreter, &args, 1, "ipairs")? + 1;
+        match table.get(&Value::Number(i as f64)) {
+            Value::Nil => Ok(vec![Value::Nil]),
+            value => Ok(vec![Value::Number(i as f64), value]),
+        }
+    });
+
+    Ok(vec![
+        Value::Function(iterator),
+        Value::Table(table),
+        Value::Number(0.0),
+    ])
+}
+
+fn next(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "next")?;
+    let entry = table.0.borrow().next(&arg(&args, 1));
+
+    match entry {
+        Ok(Some((key, value))) => Ok(vec![key, value]),
+        Ok(None) => Ok(vec![Value::Nil]),
+        Err(()) => Err(interpreter.runtime_error("invalid key to 'next'")),
+    }
+}
+
+fn pairs(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "pairs")?;
+    let next = Function::native("next", next);
+    Ok(vec![Value::Function(next), Value::Table(table), Value::Nil])
+}
+
+fn pcall(interpreter: &mut Interpreter<'_>, mut args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let function = check_any(interpreter, &args, 0, "pcall")?;
+    args.remove(0);
+
+    Ok(match interpreter.protected_call(&function, args)? {
+        Ok(mut values) => {
+            values.insert(0, Value::Boolean(true));
+            values
+        }
+        Err(error) => vec![Value::Boolean(false), error],
+    })
+}
+
+fn xpcall(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let function = check_any(interpreter, &args, 0, "xpcall")?;
+    let handler = arg(&args, 1);
+
+    Ok(match interpreter.protected_call(&function, Vec::new())? {
+        Ok(mut values) => {
+            values.insert(0, Value::Boolean(true));
+            values
+        }
+        Err(error) => {
+            let mut values = interpreter.call(&handler, vec![error])?;
+            values.insert(0, Value::Boolean(false));
+            values
+        }
+    })
+}
+
+fn print(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let mut line = Vec::new();
+    for (i, value) in args.iter().enumerate() {
+        if i > 0 {
+            line.push(b'\t');
+        }
+        line.extend_from_slice(interpreter.tostring(value)?.as_bytes());
+    }
+
+    println!("{}", String::from_utf8_lossy(&line));
+    Ok(Vec::new())
+}
+
+fn rawequal(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let a = check_any(interpreter, &args, 0, "rawequal")?;
+    let b = check_any(interpreter, &args, 1, "rawequal")?;
+    Ok(vec![Value::Boolean(a.raw_equals(&b))])
+}
+
+fn rawget(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "rawget")?;
+    let key = check_any(interpreter, &args, 1, "rawget")?;
+    Ok(vec![table.get(&key)])
+}
+
+fn rawset(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "rawset")?;
+    let key = check_any(interpreter, &args, 1, "rawset")?;
+    let value = check_any(interpreter, &args, 2, "rawset")?;
+    interpreter.raw_set(&table, key, value)?;
+    Ok(vec![Value::Table(table)])
+}
+
+fn select(interpreter: &mut Interpreter<'_>, mut args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    if let Value::String(s) = arg(&args, 0) {
+        if s.as_bytes().first() == Some(&b'#') {
+            return Ok(vec![Value::Number((args.len() - 1) as f64)]);
+        }
+    }
+
+    let n = check_integer(interpreter, &args, 0, "select")?;
+    let count = args.len() as i64 - 1;
+    let start = match n {
+        n if n < 0 => count + n,
+        n if n > 0 => n - 1,
+        _ => -1,
+    };
+    if start < 0 {
+        return Err(arg_error(interpreter, 0, "select", "index out of range"));
+    }
+
+    args.remove(0);
+    Ok(args.into_iter().skip(start as usize).collect())
+}
+
+fn tonumber(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let base = opt_integer(interpreter, &args, 1, "tonumber", 10)?;
+    let value = check_any(interpreter, &args, 0, "tonumber")?;
+
+    if base == 10 {
+        return Ok(vec![value.to_number().map_or(Value::Nil, Value::Number)]);
+    }
+
+    if !(2..=36).contains(&base) {
+        return Err(arg_error(interpreter, 1, "tonumber", "base out of range"));
+    }
+
+    let Some(s) = value.to_lua_string() else {
+        return Err(type_error(interpreter, &args, 0, "tonumber", "string"));
+    };
+    let s = String::from_utf8_lossy(s.as_bytes()).trim().to_string();
+
+    Ok(vec![match i64::from_str_radix(&s, base as u32) {
+        Ok(n) => Value::Number(n as f64),
+        Err(_) => Value::Nil,
+    }])
+}
+
+fn tostring(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let value = check_any(interpreter, &args, 0, "tostring")?;
+    Ok(vec![Value::String(interpreter.tostring(&value)?)])
+}
+
+fn type_(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let value = check_any(interpreter, &args, 0, "type")?;
+    Ok(vec![Value::string(value.type_name())])
+}
+
+fn unpack(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "unpack")?;
+    let start = opt_integer(interpreter, &args, 1, "unpack", 1)?;
+    let end = opt_integer(interpreter, &args, 2, "unpack", table.len() as i64)?;
+
+    if start > end {
+        return Ok(Vec::new());
+    }
+    if end - start >= 8000 {
+        return Err(interpreter.runtime_error("too many results to unpack"));
+    }
+
+    Ok((start..=end)
+        .map(|i| table.get(&Value::Number(i as f64)))
+        .collect())
+}
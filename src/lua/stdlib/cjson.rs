@@ -0,0 +1,341 @@
+//! The `cjson` library Redis bundles, for encoding and decoding JSON. JSON `null` decodes to
+//! `nil`, since there are no light userdata values to stand for it.
+
+use crate::lua::interpreter::Interpreter;
+use crate::lua::number::format_number;
+use crate::lua::stdlib::{check_any, check_string, library};
+use crate::lua::value::{LuaResult, Table, TableRef, Value};
+
+const MAX_DEPTH: usize = 1000;
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    let cjson = library(&[("decode", decode), ("encode", encode)]);
+    interpreter.set_global("cjson", cjson);
+}
+
+fn encode(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let value = check_any(interpreter, &args, 0, "encode")?;
+    let mut json = Vec::new();
+    encode_value(&value, 0, &mut json).map_err(|e| interpreter.runtime_error(e))?;
+    Ok(vec![Value::string(json)])
+}
+
+fn encode_value(value: &Value, depth: usize, json: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        Value::Nil => json.extend_from_slice(b"null"),
+        Value::Boolean(b) => json.extend_from_slice(if *b { b"true" } else { b"false" }),
+        Value::Number(n) if !n.is_finite() => {
+            return Err("Cannot serialise number: must not be NaN or Inf".to_string());
+        }
+        Value::Number(n) => json.extend_from_slice(format_number(*n).as_bytes()),
+        Value::String(s) => encode_string(s.as_bytes(), json),
+        Value::Table(table) => {
+            if depth >= MAX_DEPTH {
+                return Err(format!(
+                    "Cannot serialise, excessive nesting ({})",
+                    depth + 1
+                ));
+            }
+            encode_table(table, depth + 1, json)?;
+        }
+        Value::Function(_) => {
+            return Err("Cannot serialise function: type not supported".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn encode_table(table: &TableRef, depth: usize, json: &mut Vec<u8>) -> Result<(), String> {
+    let mut entries = Vec::new();
+    let mut key = Value::Nil;
+    while let Ok(Some((next_key, value))) = table.0.borrow().next(&key) {
+        entries.push((next_key.clone(), value));
+        key = next_key;
+    }
+
+    // a table whose keys are all positive integers is an array, holes become nulls
+    let array_len = entries.iter().try_fold(0.0_f64, |max, (key, _)| match key {
+        Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => Some(max.max(*n)),
+        _ => None,
+    });
+
+    match array_len {
+        Some(len) if len > 0.0 => {
+            json.push(b'[');
+            for i in 1..=len as usize {
+                if i > 1 {
+                    json.push(b',');
+                }
+                encode_value(&table.get(&Value::Number(i as f64)), depth, json)?;
+            }
+            json.push(b']');
+        }
+        _ => {
+            json.push(b'{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    json.push(b',');
+                }
+                match key {
+                    Value::String(s) => encode_string(s.as_bytes(), json),
+                    Value::Number(n) => encode_string(format_number(*n).as_bytes(), json),
+                    _ => {
+                        return Err(
+                            "Cannot serialise table: table key must be a number or string"
+                                .to_string(),
+                        )
+                    }
+                }
+                json.push(b':');
+                encode_value(value, depth, json)?;
+            }
+            json.push(b'}');
+        }
+    }
+    Ok(())
+}
+
+fn encode_string(s: &[u8], json: &mut Vec<u8>) {
+    json.push(b'"');
+    for &c in s {
+        match c {
+            b'"' => json.extend_from_slice(b"\\\""),
+            b'\\' => json.extend_from_slice(b"\\\\"),
+            b'/' => json.extend_from_slice(b"\\/"),
+            b'\n' => json.extend_from_slice(b"\\n"),
+            b'\r' => json.extend_from_slice(b"\\r"),
+            b'\t' => json.extend_from_slice(b"\\t"),
+            0x08 => json.extend_from_slice(b"\\b"),
+            0x0c => json.extend_from_slice(b"\\f"),
+            c if c < 0x20 || c == 0x7f => {
+                json.extend_from_slice(format!("\\u{:04x}", c).as_bytes());
+            }
+            c => json.push(c),
+        }
+    }
+    json.push(b'"');
+}
+
+fn decode(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let json = check_string(interpreter, &args, 0, "decode")?;
+    let mut decoder = Decoder {
+        json: json.as_bytes(),
+        position: 0,
+    };
+
+    let value = decoder
+        .value(0)
+        .and_then(|value| {
+            decoder.skip_whitespace();
+            match decoder.json.get(decoder.position) {
+                None => Ok(value),
+                Some(_) => Err(decoder.error("the end")),
+            }
+        })
+        .map_err(|e| interpreter.runtime_error(e))?;
+
+    Ok(vec![value])
+}
+
+struct Decoder<'a> {
+    json: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn error(&self, expected: &str) -> String {
+        let found = match self.json.get(self.position) {
+            None => "the end".to_string(),
+            Some(_) => "invalid token".to_string(),
+        };
+        format!(
+            "Expected {} but found {} at character {}",
+            expected,
+            found,
+            self.position + 1
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .json
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn consume(&mut self, literal: &[u8]) -> bool {
+        if self.json[self.position..].starts_with(literal) {
+            self.position += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth >= MAX_DEPTH {
+            return Err(format!(
+                "Found too many nested data structures ({}) at character {}",
+                depth + 1,
+                self.position + 1
+            ));
+        }
+
+        self.skip_whitespace();
+        match self.json.get(self.position) {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(Value::string),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.consume(b"true") => Ok(Value::Boolean(true)),
+            _ if self.consume(b"false") => Ok(Value::Boolean(false)),
+            _ if self.consume(b"null") => Ok(Value::Nil),
+            _ => Err(self.error("value")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, String> {
+        self.position += 1;
+        let mut table = Table::default();
+
+        self.skip_whitespace();
+        if self.consume(b"}") {
+            return Ok(Value::Table(TableRef::new(table)));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.json.get(self.position) != Some(&b'"') {
+                return Err(self.error("object key string"));
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            if !self.consume(b":") {
+                return Err(self.error("colon"));
+            }
+            let value = self.value(depth + 1)?;
+            let _ = table.set(Value::string(key), value);
+
+            self.skip_whitespace();
+            if self.consume(b"}") {
+                return Ok(Value::Table(TableRef::new(table)));
+            }
+            if !self.consume(b",") {
+                return Err(self.error("comma or object end"));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, String> {
+        self.position += 1;
+        let mut table = Table::default();
+
+        self.skip_whitespace();
+        if self.consume(b"]") {
+            return Ok(Value::Table(TableRef::new(table)));
+        }
+
+        let mut i = 1.0;
+        loop {
+            let value = self.value(depth + 1)?;
+            let _ = table.set(Value::Number(i), value);
+            i += 1.0;
+
+            self.skip_whitespace();
+            if self.consume(b"]") {
+                return Ok(Value::Table(TableRef::new(table)));
+            }
+            if !self.consume(b",") {
+                return Err(self.error("comma or array end"));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        let start = self.position;
+        self.position += 1;
+        let mut s = Vec::new();
+
+        loop {
+            let Some(&c) = self.json.get(self.position) else {
+                self.position = start;
+                return Err(self.error("string"));
+            };
+            self.position += 1;
+
+            match c {
+                b'"' => return Ok(s),
+                b'\\' => {
+                    let Some(&escape) = self.json.get(self.position) else {
+                        return Err(self.error("escape sequence"));
+                    };
+                    self.position += 1;
+                    match escape {
+                        b'"' | b'\\' | b'/' => s.push(escape),
+                        b'b' => s.push(0x08),
+                        b'f' => s.push(0x0c),
+                        b'n' => s.push(b'\n'),
+                        b'r' => s.push(b'\r'),
+                        b't' => s.push(b'\t'),
+                        b'u' => {
+                            let c = self.unicode_escape()?;
+                            let mut buffer = [0; 4];
+                            s.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                        _ => return Err(self.error("valid escape sequence")),
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .json
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("unicode escape code"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.consume(b"\\u") {
+                return Err(self.error("low surrogate"));
+            }
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unicode escape code"))
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while self
+            .json
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, b'-' | b'+' | b'.' | b'e' | b'E'))
+        {
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.json[start..self.position])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Value::Number)
+            .ok_or_else(|| {
+                self.position = start;
+                self.error("value")
+            })
+    }
+}
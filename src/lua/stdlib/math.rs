@@ -0,0 +1,188 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::lua::interpreter::Interpreter;
+use crate::lua::stdlib::{arg_error, check_integer, check_number, library};
+use crate::lua::value::{Function, LuaResult, Value};
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    let math = library(&[
+        ("abs", |i, a| unary(i, a, "abs", f64::abs)),
+        ("acos", |i, a| unary(i, a, "acos", f64::acos)),
+        ("asin", |i, a| unary(i, a, "asin", f64::asin)),
+        ("atan", |i, a| unary(i, a, "atan", f64::atan)),
+        ("atan2", |i, a| binary(i, a, "atan2", f64::atan2)),
+        ("ceil", |i, a| unary(i, a, "ceil", f64::ceil)),
+        ("cos", |i, a| unary(i, a, "cos", f64::cos)),
+        ("cosh", |i, a| unary(i, a, "cosh", f64::cosh)),
+        ("deg", |i, a| unary(i, a, "deg", f64::to_degrees)),
+        ("exp", |i, a| unary(i, a, "exp", f64::exp)),
+        ("floor", |i, a| unary(i, a, "floor", f64::floor)),
+        ("fmod", |i, a| binary(i, a, "fmod", |x, y| x % y)),
+        ("frexp", frexp),
+        ("ldexp", ldexp),
+        ("log", |i, a| unary(i, a, "log", f64::ln)),
+        ("log10", |i, a| unary(i, a, "log10", f64::log10)),
+        ("max", max),
+        ("min", min),
+        ("modf", modf),
+        ("pow", |i, a| binary(i, a, "pow", f64::powf)),
+        ("rad", |i, a| unary(i, a, "rad", f64::to_radians)),
+        ("sin", |i, a| unary(i, a, "sin", f64::sin)),
+        ("sinh", |i, a| unary(i, a, "sinh", f64::sinh)),
+        ("sqrt", |i, a| unary(i, a, "sqrt", f64::sqrt)),
+        ("tan", |i, a| unary(i, a, "tan", f64::tan)),
+        ("tanh", |i, a| unary(i, a, "tanh", f64::tanh)),
+    ]);
+    math.set_str("pi", std::f64::consts::PI);
+    math.set_str("huge", f64::INFINITY);
+
+    // scripts must be deterministic, so every interpreter starts from the same seed
+    let state = Rc::new(Cell::new(Rand48::new(0)));
+    let random_state = Rc::clone(&state);
+    math.set_str(
+        "random",
+        Value::Function(Function::native("random", move |interpreter, args| {
+            let mut rand = random_state.get();
+            let result = random(interpreter, &args, &mut rand);
+            random_state.set(rand);
+            result
+        })),
+    );
+    math.set_str(
+        "randomseed",
+        Value::Function(Function::native("randomseed", move |interpreter, args| {
+            let seed = check_integer(interpreter, &args, 0, "randomseed")?;
+            state.set(Rand48::new(seed as i32));
+            Ok(Vec::new())
+        })),
+    );
+
+    interpreter.set_global("math", math);
+}
+
+fn unary(
+    interpreter: &mut Interpreter<'_>,
+    args: Vec<Value>,
+    name: &str,
+    f: fn(f64) -> f64,
+) -> LuaResult<Vec<Value>> {
+    let x = check_number(interpreter, &args, 0, name)?;
+    Ok(vec![Value::Number(f(x))])
+}
+
+fn binary(
+    interpreter: &mut Interpreter<'_>,
+    args: Vec<Value>,
+    name: &str,
+    f: fn(f64, f64) -> f64,
+) -> LuaResult<Vec<Value>> {
+    let x = check_number(interpreter, &args, 0, name)?;
+    let y = check_number(interpreter, &args, 1, name)?;
+    Ok(vec![Value::Number(f(x, y))])
+}
+
+fn frexp(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let x = check_number(interpreter, &args, 0, "frexp")?;
+    if x == 0.0 || !x.is_finite() {
+        return Ok(vec![Value::Number(x), Value::Number(0.0)]);
+    }
+
+    let exponent = x.abs().log2().floor() as i32 + 1;
+    let mut mantissa = x / 2f64.powi(exponent);
+    let mut exponent = exponent;
+    // correct rounding at the edges of the binary exponent
+    if mantissa.abs() >= 1.0 {
+        mantissa /= 2.0;
+        exponent += 1;
+    } else if mantissa.abs() < 0.5 {
+        mantissa *= 2.0;
+        exponent -= 1;
+    }
+    Ok(vec![
+        Value::Number(mantissa),
+        Value::Number(exponent as f64),
+    ])
+}
+
+fn ldexp(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let x = check_number(interpreter, &args, 0, "ldexp")?;
+    let exponent = check_integer(interpreter, &args, 1, "ldexp")?;
+    Ok(vec![Value::Number(x * 2f64.powi(exponent as i32))])
+}
+
+fn modf(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let x = check_number(interpreter, &args, 0, "modf")?;
+    Ok(vec![Value::Number(x.trunc()), Value::Number(x.fract())])
+}
+
+fn max(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let mut max = check_number(interpreter, &args, 0, "max")?;
+    for i in 1..args.len() {
+        let x = check_number(interpreter, &args, i, "max")?;
+        if x > max {
+            max = x;
+        }
+    }
+    Ok(vec![Value::Number(max)])
+}
+
+fn min(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let mut min = check_number(interpreter, &args, 0, "min")?;
+    for i in 1..args.len() {
+        let x = check_number(interpreter, &args, i, "min")?;
+        if x < min {
+            min = x;
+        }
+    }
+    Ok(vec![Value::Number(min)])
+}
+
+fn random(
+    interpreter: &mut Interpreter<'_>,
+    args: &[Value],
+    rand: &mut Rand48,
+) -> LuaResult<Vec<Value>> {
+    let r = (rand.next() % i32::MAX as u32) as f64 / i32::MAX as f64;
+
+    let n = match args.len() {
+        0 => r,
+        1 => {
+            let upper = check_integer(interpreter, args, 0, "random")?;
+            if upper < 1 {
+                return Err(arg_error(interpreter, 0, "random", "interval is empty"));
+            }
+            (r * upper as f64).floor() + 1.0
+        }
+        2 => {
+            let lower = check_integer(interpreter, args, 0, "random")?;
+            let upper = check_integer(interpreter, args, 1, "random")?;
+            if lower > upper {
+                return Err(arg_error(interpreter, 1, "random", "interval is empty"));
+            }
+            (r * (upper - lower + 1) as f64).floor() + lower as f64
+        }
+        _ => return Err(interpreter.runtime_error("wrong number of arguments")),
+    };
+
+    Ok(vec![Value::Number(n)])
+}
+
+/// The `lrand48` generator Redis uses for `math.random`, so sequences match across platforms.
+#[derive(Debug, Clone, Copy)]
+struct Rand48(u64);
+
+impl Rand48 {
+    const A: u64 = 0x5DEECE66D;
+    const C: u64 = 0xB;
+    const MASK: u64 = (1 << 48) - 1;
+
+    fn new(seed: i32) -> Self {
+        Rand48((((seed as u32 as u64) << 16) | 0x330E) & Self::MASK)
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 = (Self::A.wrapping_mul(self.0).wrapping_add(Self::C)) & Self::MASK;
+        (self.0 >> 17) as u32
+    }
+}
@@ -0,0 +1,164 @@
+//! The parts of Lua's standard library that scripts can use, plus the `cjson` library Redis
+//! bundles. Files and the OS are left out on purpose.
+
+use crate::lua::interpreter::Interpreter;
+use crate::lua::value::{Function, LuaError, LuaResult, LuaString, Table, TableRef, Value};
+
+mod base;
+mod cjson;
+mod math;
+mod pattern;
+mod string;
+mod table;
+
+pub type NativeFn = fn(&mut Interpreter<'_>, Vec<Value>) -> LuaResult<Vec<Value>>;
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    base::open(interpreter);
+    string::open(interpreter);
+    table::open(interpreter);
+    math::open(interpreter);
+    cjson::open(interpreter);
+}
+
+/// Creates a library table with the given functions.
+pub fn library(functions: &[(&'static str, NativeFn)]) -> TableRef {
+    let table = TableRef::new(Table::default());
+    for &(name, function) in functions {
+        table.set_str(name, Value::Function(Function::native(name, function)));
+    }
+    table
+}
+
+fn arg(args: &[Value], i: usize) -> Value {
+    args.get(i).cloned().unwrap_or_default()
+}
+
+pub fn arg_error(
+    interpreter: &Interpreter<'_>,
+    i: usize,
+    function: &str,
+    message: impl std::fmt::Display,
+) -> LuaError {
+    interpreter.runtime_error(format!(
+        "bad argument #{} to '{}' ({})",
+        i + 1,
+        function,
+        message
+    ))
+}
+
+fn type_error(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+    expected: &str,
+) -> LuaError {
+    let got = match args.get(i) {
+        Some(value) => value.type_name(),
+        None => "no value",
+    };
+    arg_error(
+        interpreter,
+        i,
+        function,
+        format!("{} expected, got {}", expected, got),
+    )
+}
+
+pub fn check_any(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+) -> LuaResult<Value> {
+    match args.get(i) {
+        Some(value) => Ok(value.clone()),
+        None => Err(arg_error(interpreter, i, function, "value expected")),
+    }
+}
+
+pub fn check_number(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+) -> LuaResult<f64> {
+    arg(args, i)
+        .to_number()
+        .ok_or_else(|| type_error(interpreter, args, i, function, "number"))
+}
+
+/// Lua 5.1 converts numbers to integers by rounding to the nearest.
+pub fn to_integer(n: f64) -> i64 {
+    n.round_ties_even() as i64
+}
+
+pub fn check_integer(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+) -> LuaResult<i64> {
+    check_number(interpreter, args, i, function).map(to_integer)
+}
+
+pub fn opt_integer(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+    default: i64,
+) -> LuaResult<i64> {
+    match arg(args, i) {
+        Value::Nil => Ok(default),
+        _ => check_integer(interpreter, args, i, function),
+    }
+}
+
+pub fn check_string(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+) -> LuaResult<LuaString> {
+    arg(args, i)
+        .to_lua_string()
+        .ok_or_else(|| type_error(interpreter, args, i, function, "string"))
+}
+
+pub fn opt_string(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+    default: &str,
+) -> LuaResult<LuaString> {
+    match arg(args, i) {
+        Value::Nil => Ok(default.into()),
+        _ => check_string(interpreter, args, i, function),
+    }
+}
+
+pub fn check_table(
+    interpreter: &Interpreter<'_>,
+    args: &[Value],
+    i: usize,
+    function: &str,
+) -> LuaResult<TableRef> {
+    match arg(args, i) {
+        Value::Table(table) => Ok(table),
+        _ => Err(type_error(interpreter, args, i, function, "table")),
+    }
+}
+
+/// Converts a relative string position to an absolute one, where negative counts from the end.
+fn string_position(position: i64, len: usize) -> i64 {
+    let position = if position < 0 {
+        position + len as i64 + 1
+    } else {
+        position
+    };
+    position.max(0)
+}
@@ -0,0 +1,370 @@
+//! Lua pattern matching, ported from Lua 5.1's `lstrlib.c`.
+
+use crate::lua::value::Value;
+
+const ESCAPE: u8 = b'%';
+pub const SPECIALS: &[u8] = b"^$*+?.([%-";
+const MAX_CAPTURES: usize = 32;
+/// Bounds the recursion of backtracking, like Lua 5.2's `MAXCCALLS`.
+const MAX_DEPTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureLen {
+    Position,
+    Unfinished,
+    Len(usize),
+}
+
+pub struct Matcher<'a> {
+    source: &'a [u8],
+    pattern: &'a [u8],
+    level: usize,
+    captures: [(usize, CaptureLen); MAX_CAPTURES],
+    depth: usize,
+}
+
+impl<'a> Matcher<'a> {
+    pub fn new(source: &'a [u8], pattern: &'a [u8]) -> Self {
+        Matcher {
+            source,
+            pattern,
+            level: 0,
+            captures: [(0, CaptureLen::Unfinished); MAX_CAPTURES],
+            depth: 0,
+        }
+    }
+
+    /// Tries to match the pattern from `p` on at the source position `s`, returning where the
+    /// match ends.
+    pub fn try_match(&mut self, s: usize, p: usize) -> Result<Option<usize>, String> {
+        self.level = 0;
+        self.depth = 0;
+        self.do_match(s, p)
+    }
+
+    pub fn matched(&self, s: usize, e: usize) -> &'a [u8] {
+        &self.source[s..e]
+    }
+
+    /// The capture `i`, or the whole match `s..e` if the pattern has no captures.
+    pub fn capture(&self, i: usize, s: usize, e: usize) -> Result<Value, String> {
+        if i >= self.level {
+            return if i == 0 {
+                Ok(Value::string(&self.source[s..e]))
+            } else {
+                Err("invalid capture index".to_string())
+            };
+        }
+
+        match self.captures[i] {
+            (_, CaptureLen::Unfinished) => Err("unfinished capture".to_string()),
+            (start, CaptureLen::Position) => Ok(Value::Number((start + 1) as f64)),
+            (start, CaptureLen::Len(len)) => Ok(Value::string(&self.source[start..start + len])),
+        }
+    }
+
+    /// All captures, or the whole match if there are none and `whole` is set.
+    pub fn captures(&self, s: usize, e: usize, whole: bool) -> Result<Vec<Value>, String> {
+        let count = if self.level == 0 && whole {
+            1
+        } else {
+            self.level
+        };
+        (0..count).map(|i| self.capture(i, s, e)).collect()
+    }
+
+    fn class_end(&self, mut p: usize) -> Result<usize, String> {
+        let c = self.pattern[p];
+        p += 1;
+
+        if c == ESCAPE {
+            if p >= self.pattern.len() {
+                return Err("malformed pattern (ends with '%')".to_string());
+            }
+            return Ok(p + 1);
+        }
+
+        if c == b'[' {
+            if self.pattern.get(p) == Some(&b'^') {
+                p += 1;
+            }
+            // the first character is never the closing bracket, so "[]]" matches ']'
+            loop {
+                if p >= self.pattern.len() {
+                    return Err("malformed pattern (missing ']')".to_string());
+                }
+                let c = self.pattern[p];
+                p += 1;
+                if c == ESCAPE && p < self.pattern.len() {
+                    p += 1;
+                }
+                if self.pattern.get(p) == Some(&b']') {
+                    return Ok(p + 1);
+                }
+            }
+        }
+
+        Ok(p)
+    }
+
+    fn single_match(&self, c: u8, p: usize, ep: usize) -> bool {
+        match self.pattern[p] {
+            b'.' => true,
+            ESCAPE => match_class(c, self.pattern[p + 1]),
+            b'[' => self.match_bracket_class(c, p, ep - 1),
+            pc => pc == c,
+        }
+    }
+
+    /// Matches `c` against the class `[...]` from `p` to the closing bracket at `ec`.
+    fn match_bracket_class(&self, c: u8, mut p: usize, ec: usize) -> bool {
+        let mut found = true;
+        if self.pattern[p + 1] == b'^' {
+            found = false;
+            p += 1;
+        }
+
+        p += 1;
+        while p < ec {
+            if self.pattern[p] == ESCAPE {
+                p += 1;
+                if match_class(c, self.pattern[p]) {
+                    return found;
+                }
+            } else if self.pattern[p + 1] == b'-' && p + 2 < ec {
+                if self.pattern[p] <= c && c <= self.pattern[p + 2] {
+                    return found;
+                }
+                p += 2;
+            } else if self.pattern[p] == c {
+                return found;
+            }
+            p += 1;
+        }
+
+        !found
+    }
+
+    fn do_match(&mut self, s: usize, p: usize) -> Result<Option<usize>, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("pattern too complex".to_string());
+        }
+        let result = self.do_match_inner(s, p);
+        self.depth -= 1;
+        result
+    }
+
+    fn do_match_inner(&mut self, mut s: usize, mut p: usize) -> Result<Option<usize>, String> {
+        loop {
+            if p >= self.pattern.len() {
+                return Ok(Some(s));
+            }
+
+            match self.pattern[p] {
+                b'(' => {
+                    return if self.pattern.get(p + 1) == Some(&b')') {
+                        self.start_capture(s, p + 2, CaptureLen::Position)
+                    } else {
+                        self.start_capture(s, p + 1, CaptureLen::Unfinished)
+                    };
+                }
+                b')' => return self.end_capture(s, p + 1),
+                b'$' if p + 1 == self.pattern.len() => {
+                    return Ok((s == self.source.len()).then_some(s));
+                }
+                ESCAPE if self.pattern.get(p + 1) == Some(&b'b') => {
+                    match self.match_balance(s, p + 2)? {
+                        Some(end) => {
+                            s = end;
+                            p += 4;
+                            continue;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                ESCAPE if self.pattern.get(p + 1) == Some(&b'f') => {
+                    p += 2;
+                    if self.pattern.get(p) != Some(&b'[') {
+                        return Err("missing '[' after '%f' in pattern".to_string());
+                    }
+                    let ep = self.class_end(p)?;
+                    let previous = if s == 0 { 0 } else { self.source[s - 1] };
+                    let current = self.source.get(s).copied().unwrap_or(0);
+                    if self.match_bracket_class(previous, p, ep - 1)
+                        || !self.match_bracket_class(current, p, ep - 1)
+                    {
+                        return Ok(None);
+                    }
+                    p = ep;
+                    continue;
+                }
+                ESCAPE if self.pattern.get(p + 1).is_some_and(u8::is_ascii_digit) => {
+                    match self.match_capture(s, self.pattern[p + 1])? {
+                        Some(end) => {
+                            s = end;
+                            p += 2;
+                            continue;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                _ => {}
+            }
+
+            let ep = self.class_end(p)?;
+            let matched = s < self.source.len() && self.single_match(self.source[s], p, ep);
+
+            match self.pattern.get(ep) {
+                Some(b'?') => {
+                    if matched {
+                        if let Some(end) = self.do_match(s + 1, ep + 1)? {
+                            return Ok(Some(end));
+                        }
+                    }
+                    p = ep + 1;
+                }
+                Some(b'*') => return self.max_expand(s, p, ep),
+                Some(b'+') => {
+                    return if matched {
+                        self.max_expand(s + 1, p, ep)
+                    } else {
+                        Ok(None)
+                    };
+                }
+                Some(b'-') => return self.min_expand(s, p, ep),
+                _ => {
+                    if !matched {
+                        return Ok(None);
+                    }
+                    s += 1;
+                    p = ep;
+                }
+            }
+        }
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, ep: usize) -> Result<Option<usize>, String> {
+        let mut count = 0;
+        while s + count < self.source.len() && self.single_match(self.source[s + count], p, ep) {
+            count += 1;
+        }
+
+        loop {
+            if let Some(end) = self.do_match(s + count, ep + 1)? {
+                return Ok(Some(end));
+            }
+            if count == 0 {
+                return Ok(None);
+            }
+            count -= 1;
+        }
+    }
+
+    fn min_expand(&mut self, mut s: usize, p: usize, ep: usize) -> Result<Option<usize>, String> {
+        loop {
+            if let Some(end) = self.do_match(s, ep + 1)? {
+                return Ok(Some(end));
+            }
+            if s < self.source.len() && self.single_match(self.source[s], p, ep) {
+                s += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn start_capture(
+        &mut self,
+        s: usize,
+        p: usize,
+        what: CaptureLen,
+    ) -> Result<Option<usize>, String> {
+        if self.level >= MAX_CAPTURES {
+            return Err("too many captures".to_string());
+        }
+
+        self.captures[self.level] = (s, what);
+        self.level += 1;
+
+        let result = self.do_match(s, p)?;
+        if result.is_none() {
+            self.level -= 1;
+        }
+        Ok(result)
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Result<Option<usize>, String> {
+        let open = (0..self.level)
+            .rev()
+            .find(|&i| self.captures[i].1 == CaptureLen::Unfinished)
+            .ok_or("invalid pattern capture")?;
+
+        self.captures[open].1 = CaptureLen::Len(s - self.captures[open].0);
+        let result = self.do_match(s, p)?;
+        if result.is_none() {
+            self.captures[open].1 = CaptureLen::Unfinished;
+        }
+        Ok(result)
+    }
+
+    fn match_balance(&self, s: usize, p: usize) -> Result<Option<usize>, String> {
+        if p + 1 >= self.pattern.len() {
+            return Err("unbalanced pattern".to_string());
+        }
+        if self.source.get(s) != Some(&self.pattern[p]) {
+            return Ok(None);
+        }
+
+        let (open, close) = (self.pattern[p], self.pattern[p + 1]);
+        let mut depth = 1;
+        for (i, &c) in self.source.iter().enumerate().skip(s + 1) {
+            if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(i + 1));
+                }
+            } else if c == open {
+                depth += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    fn match_capture(&self, s: usize, digit: u8) -> Result<Option<usize>, String> {
+        let i = (digit as usize).wrapping_sub(b'1' as usize);
+        if i >= self.level || self.captures[i].1 == CaptureLen::Unfinished {
+            return Err("invalid capture index".to_string());
+        }
+
+        let (start, len) = match self.captures[i] {
+            (start, CaptureLen::Len(len)) => (start, len),
+            (start, _) => (start, 0),
+        };
+        let captured = &self.source[start..start + len];
+
+        Ok(self.source[s..].starts_with(captured).then_some(s + len))
+    }
+}
+
+fn match_class(c: u8, class: u8) -> bool {
+    let matched = match class.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        b's' => c.is_ascii_whitespace() || c == 0x0b,
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        b'z' => c == 0,
+        _ => return class == c,
+    };
+
+    if class.is_ascii_uppercase() {
+        !matched
+    } else {
+        matched
+    }
+}
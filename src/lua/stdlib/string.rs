@@ -0,0 +1,559 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::lua::interpreter::Interpreter;
+use crate::lua::number::format_float;
+use crate::lua::stdlib::pattern::{Matcher, SPECIALS};
+use crate::lua::stdlib::{
+    arg, arg_error, check_integer, check_number, check_string, library, opt_integer,
+    string_position, to_integer, type_error,
+};
+use crate::lua::value::{Function, LuaResult, LuaString, Table, TableRef, Value};
+
+/// The largest string `string.rep` builds, so a script can't exhaust the memory in one call.
+const MAX_REP_LEN: usize = 512 * 1024 * 1024;
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    let string = library(&[
+        ("byte", byte),
+        ("char", char),
+        ("find", find),
+        ("format", format),
+        ("gmatch", gmatch),
+        ("gsub", gsub),
+        ("len", len),
+        ("lower", lower),
+        ("match", match_),
+        ("rep", rep),
+        ("reverse", reverse),
+        ("sub", sub),
+        ("upper", upper),
+    ]);
+
+    let metatable = TableRef::new(Table::default());
+    metatable.set_str("__index", string.clone());
+    interpreter.string_metatable = Some(metatable);
+    interpreter.set_global("string", string);
+}
+
+fn byte(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "byte")?;
+    let s = s.as_bytes();
+    let start = string_position(opt_integer(interpreter, &args, 1, "byte", 1)?, s.len()).max(1);
+    let end = string_position(opt_integer(interpreter, &args, 2, "byte", start)?, s.len())
+        .min(s.len() as i64);
+
+    if start > end {
+        return Ok(Vec::new());
+    }
+    Ok(s[start as usize - 1..end as usize]
+        .iter()
+        .map(|&b| Value::Number(b as f64))
+        .collect())
+}
+
+fn char(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let mut bytes = Vec::with_capacity(args.len());
+    for i in 0..args.len() {
+        let c = check_integer(interpreter, &args, i, "char")?;
+        let c = u8::try_from(c).map_err(|_| arg_error(interpreter, i, "char", "invalid value"))?;
+        bytes.push(c);
+    }
+    Ok(vec![Value::string(bytes)])
+}
+
+fn len(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "len")?;
+    Ok(vec![Value::Number(s.as_bytes().len() as f64)])
+}
+
+fn lower(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "lower")?;
+    Ok(vec![Value::string(s.as_bytes().to_ascii_lowercase())])
+}
+
+fn upper(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "upper")?;
+    Ok(vec![Value::string(s.as_bytes().to_ascii_uppercase())])
+}
+
+fn rep(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "rep")?;
+    let n = check_integer(interpreter, &args, 1, "rep")?;
+
+    if n <= 0 || s.as_bytes().is_empty() {
+        return Ok(vec![Value::string("")]);
+    }
+    if s.as_bytes().len().saturating_mul(n as usize) > MAX_REP_LEN {
+        return Err(interpreter.runtime_error("resulting string too large"));
+    }
+    Ok(vec![Value::string(s.as_bytes().repeat(n as usize))])
+}
+
+fn reverse(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "reverse")?;
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.reverse();
+    Ok(vec![Value::string(bytes)])
+}
+
+fn sub(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "sub")?;
+    let s = s.as_bytes();
+    let start = string_position(check_integer(interpreter, &args, 1, "sub")?, s.len()).max(1);
+    let end = string_position(opt_integer(interpreter, &args, 2, "sub", -1)?, s.len())
+        .min(s.len() as i64);
+
+    if start > end {
+        return Ok(vec![Value::string("")]);
+    }
+    Ok(vec![Value::string(&s[start as usize - 1..end as usize])])
+}
+
+fn find(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    find_or_match(interpreter, args, true)
+}
+
+fn match_(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    find_or_match(interpreter, args, false)
+}
+
+fn find_or_match(
+    interpreter: &mut Interpreter<'_>,
+    args: Vec<Value>,
+    find: bool,
+) -> LuaResult<Vec<Value>> {
+    let name = if find { "find" } else { "match" };
+    let source = check_string(interpreter, &args, 0, name)?;
+    let pattern = check_string(interpreter, &args, 1, name)?;
+    let (source, pattern) = (source.as_bytes(), pattern.as_bytes());
+
+    let init = string_position(opt_integer(interpreter, &args, 2, name, 1)?, source.len()) - 1;
+    let init = init.clamp(0, source.len() as i64) as usize;
+
+    let plain = arg(&args, 3).is_truthy() || !pattern.iter().any(|c| SPECIALS.contains(c));
+    if find && plain {
+        let found = if pattern.is_empty() {
+            Some(init)
+        } else {
+            source[init..]
+                .windows(pattern.len())
+                .position(|window| window == pattern)
+                .map(|i| init + i)
+        };
+        return Ok(match found {
+            Some(start) => vec![
+                Value::Number((start + 1) as f64),
+                Value::Number((start + pattern.len()) as f64),
+            ],
+            None => vec![Value::Nil],
+        });
+    }
+
+    let anchor = pattern.first() == Some(&b'^');
+    let p = anchor as usize;
+    let mut matcher = Matcher::new(source, pattern);
+    let mut s = init;
+
+    loop {
+        let end = matcher
+            .try_match(s, p)
+            .map_err(|e| interpreter.runtime_error(e))?;
+
+        if let Some(end) = end {
+            return if find {
+                let mut values = vec![Value::Number((s + 1) as f64), Value::Number(end as f64)];
+                values.extend(
+                    matcher
+                        .captures(s, end, false)
+                        .map_err(|e| interpreter.runtime_error(e))?,
+                );
+                Ok(values)
+            } else {
+                matcher
+                    .captures(s, end, true)
+                    .map_err(|e| interpreter.runtime_error(e))
+            };
+        }
+
+        s += 1;
+        if anchor || s > source.len() {
+            return Ok(vec![Value::Nil]);
+        }
+    }
+}
+
+fn gmatch(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let source = check_string(interpreter, &args, 0, "gmatch")?;
+    let pattern = check_string(interpreter, &args, 1, "gmatch")?;
+    let position = Rc::new(Cell::new(0));
+
+    let iterator = Function::native("gmatch_iterator", move |interpreter, _| {
+        let (source, pattern) = (source.as_bytes(), pattern.as_bytes());
+        let mut matcher = Matcher::new(source, pattern);
+
+        let mut s = position.get();
+        while s <= source.len() {
+            let end = matcher
+                .try_match(s, 0)
+                .map_err(|e| interpreter.runtime_error(e))?;
+
+            if let Some(end) = end {
+                // an empty match moves on by one, so the iteration ends
+                position.set(if end == s { end + 1 } else { end });
+                return matcher
+                    .captures(s, end, true)
+                    .map_err(|e| interpreter.runtime_error(e));
+            }
+            s += 1;
+        }
+
+        position.set(s);
+        Ok(vec![Value::Nil])
+    });
+
+    Ok(vec![Value::Function(iterator)])
+}
+
+fn gsub(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let source = check_string(interpreter, &args, 0, "gsub")?;
+    let pattern = check_string(interpreter, &args, 1, "gsub")?;
+    let replacement = arg(&args, 2);
+    if !matches!(
+        replacement,
+        Value::Number(_) | Value::String(_) | Value::Table(_) | Value::Function(_)
+    ) {
+        return Err(type_error(
+            interpreter,
+            &args,
+            2,
+            "gsub",
+            "string/function/table",
+        ));
+    }
+    let max = opt_integer(
+        interpreter,
+        &args,
+        3,
+        "gsub",
+        source.as_bytes().len() as i64 + 1,
+    )?;
+
+    let (source, pattern) = (source.as_bytes(), pattern.as_bytes());
+    let anchor = pattern.first() == Some(&b'^');
+    let p = anchor as usize;
+    let mut matcher = Matcher::new(source, pattern);
+
+    let mut result = Vec::with_capacity(source.len());
+    let mut s = 0;
+    let mut count = 0;
+
+    while count < max {
+        let end = matcher
+            .try_match(s, p)
+            .map_err(|e| interpreter.runtime_error(e))?;
+
+        if let Some(end) = end {
+            count += 1;
+            add_replacement(interpreter, &matcher, &replacement, s, end, &mut result)?;
+        }
+
+        match end {
+            Some(end) if end > s => s = end,
+            _ if s < source.len() => {
+                result.push(source[s]);
+                s += 1;
+            }
+            _ => break,
+        }
+
+        if anchor {
+            break;
+        }
+    }
+
+    result.extend_from_slice(&source[s..]);
+    Ok(vec![Value::string(result), Value::Number(count as f64)])
+}
+
+fn add_replacement(
+    interpreter: &mut Interpreter<'_>,
+    matcher: &Matcher<'_>,
+    replacement: &Value,
+    s: usize,
+    e: usize,
+    result: &mut Vec<u8>,
+) -> LuaResult<()> {
+    let capture_error = |interpreter: &Interpreter<'_>, e: String| interpreter.runtime_error(e);
+
+    let value = match replacement {
+        Value::String(_) | Value::Number(_) => {
+            let replacement = replacement.to_lua_string().unwrap();
+            let mut bytes = replacement.as_bytes().iter();
+
+            while let Some(&c) = bytes.next() {
+                if c != b'%' {
+                    result.push(c);
+                    continue;
+                }
+
+                match bytes.next() {
+                    Some(&d) if d.is_ascii_digit() => {
+                        let capture = if d == b'0' {
+                            Value::string(matcher.matched(s, e))
+                        } else {
+                            matcher
+                                .capture((d - b'1') as usize, s, e)
+                                .map_err(|e| capture_error(interpreter, e))?
+                        };
+                        result.extend_from_slice(capture.to_lua_string().unwrap().as_bytes());
+                    }
+                    Some(&c) => result.push(c),
+                    None => {}
+                }
+            }
+            return Ok(());
+        }
+        Value::Table(_) => {
+            let key = matcher
+                .capture(0, s, e)
+                .map_err(|e| capture_error(interpreter, e))?;
+            interpreter.index(replacement, &key)?
+        }
+        _ => {
+            let captures = matcher
+                .captures(s, e, true)
+                .map_err(|e| capture_error(interpreter, e))?;
+            interpreter
+                .call(replacement, captures)?
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+        }
+    };
+
+    match value {
+        Value::Nil | Value::Boolean(false) => result.extend_from_slice(matcher.matched(s, e)),
+        value => match value.to_lua_string() {
+            Some(value) => result.extend_from_slice(value.as_bytes()),
+            None => {
+                return Err(interpreter.runtime_error(format!(
+                    "invalid replacement value (a {})",
+                    value.type_name()
+                )));
+            }
+        },
+    }
+    Ok(())
+}
+
+/// A conversion in a `string.format` string, like `%-5.2f`.
+#[derive(Default, Clone, Copy)]
+struct FormatSpec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    fn pad(&self, prefix: &str, body: &str, numeric: bool) -> String {
+        let len = prefix.len() + body.len();
+        if self.width <= len {
+            return format!("{}{}", prefix, body);
+        }
+
+        let padding = self.width - len;
+        if self.left {
+            format!("{}{}{}", prefix, body, " ".repeat(padding))
+        } else if self.zero && numeric {
+            format!("{}{}{}", prefix, "0".repeat(padding), body)
+        } else {
+            format!("{}{}{}", " ".repeat(padding), prefix, body)
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    fn format_integer(&self, n: i64, conversion: u8) -> String {
+        let mut digits = match conversion {
+            b'o' => format!("{:o}", n as u64),
+            b'x' => format!("{:x}", n as u64),
+            b'X' => format!("{:X}", n as u64),
+            b'u' => (n as u64).to_string(),
+            _ => n.unsigned_abs().to_string(),
+        };
+
+        if let Some(precision) = self.precision {
+            if precision == 0 && n == 0 {
+                digits.clear();
+            } else if digits.len() < precision {
+                digits = format!("{}{}", "0".repeat(precision - digits.len()), digits);
+            }
+        }
+
+        let prefix = match conversion {
+            b'd' | b'i' => self.sign(n < 0),
+            b'x' if self.alternate && n != 0 => "0x",
+            b'X' if self.alternate && n != 0 => "0X",
+            b'o' if self.alternate && !digits.starts_with('0') => "0",
+            _ => "",
+        };
+
+        // the zero flag is ignored when a precision is given
+        let spec = FormatSpec {
+            zero: self.zero && self.precision.is_none(),
+            ..*self
+        };
+        spec.pad(prefix, &digits, true)
+    }
+
+    fn format_float(&self, n: f64, conversion: u8) -> String {
+        let body = format_float(n.abs(), conversion as char, self.precision, self.alternate);
+        let negative = n.is_sign_negative() && !n.is_nan();
+        self.pad(self.sign(negative), &body, n.is_finite())
+    }
+}
+
+fn format(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let format = check_string(interpreter, &args, 0, "format")?;
+    let format = format.as_bytes();
+    let mut result = Vec::with_capacity(format.len());
+    let mut arg = 0;
+    let mut i = 0;
+
+    while i < format.len() {
+        let c = format[i];
+        i += 1;
+
+        if c != b'%' {
+            result.push(c);
+            continue;
+        }
+        if format.get(i) == Some(&b'%') {
+            result.push(b'%');
+            i += 1;
+            continue;
+        }
+
+        let mut spec = FormatSpec::default();
+        let flags_start = i;
+        while let Some(&flag) = format.get(i) {
+            match flag {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if i - flags_start > 5 {
+            return Err(interpreter.runtime_error("invalid format (repeated flags)"));
+        }
+
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while format.get(*i).is_some_and(u8::is_ascii_digit) {
+                *i += 1;
+            }
+            (
+                *i - start,
+                std::str::from_utf8(&format[start..*i])
+                    .unwrap()
+                    .parse()
+                    .unwrap_or(0),
+            )
+        };
+
+        let (width_digits, width) = digits(&mut i);
+        spec.width = width;
+        let mut precision_digits = 0;
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            let (count, precision) = digits(&mut i);
+            precision_digits = count;
+            spec.precision = Some(precision);
+        }
+        if width_digits > 2 || precision_digits > 2 {
+            return Err(interpreter.runtime_error("invalid format (width or precision too long)"));
+        }
+
+        let Some(&conversion) = format.get(i) else {
+            return Err(interpreter.runtime_error("invalid option '%' to 'format'"));
+        };
+        i += 1;
+        arg += 1;
+
+        let formatted = match conversion {
+            b'c' => {
+                let c = check_number(interpreter, &args, arg, "format")?;
+                vec![to_integer(c) as u8]
+            }
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+                let n = check_number(interpreter, &args, arg, "format")?;
+                spec.format_integer(n as i64, conversion).into_bytes()
+            }
+            b'e' | b'E' | b'f' | b'g' | b'G' => {
+                let n = check_number(interpreter, &args, arg, "format")?;
+                spec.format_float(n, conversion).into_bytes()
+            }
+            b'q' => quoted(&check_string(interpreter, &args, arg, "format")?),
+            b's' => {
+                let s = check_string(interpreter, &args, arg, "format")?;
+                let mut s = s.as_bytes().to_vec();
+                if let Some(precision) = spec.precision {
+                    s.truncate(precision);
+                }
+                if s.len() < spec.width {
+                    let padding = vec![b' '; spec.width - s.len()];
+                    if spec.left {
+                        s.extend(padding);
+                    } else {
+                        s.splice(0..0, padding);
+                    }
+                }
+                s
+            }
+            c => {
+                return Err(interpreter
+                    .runtime_error(format!("invalid option '%{}' to 'format'", c as char)));
+            }
+        };
+        result.extend(formatted);
+    }
+
+    Ok(vec![Value::string(result)])
+}
+
+/// `%q`: a string literal Lua can read back.
+fn quoted(s: &LuaString) -> Vec<u8> {
+    let mut result = vec![b'"'];
+    for &c in s.as_bytes() {
+        match c {
+            b'"' | b'\\' | b'\n' => {
+                result.push(b'\\');
+                result.push(c);
+            }
+            b'\r' => result.extend_from_slice(b"\\r"),
+            0 => result.extend_from_slice(b"\\000"),
+            c => result.push(c),
+        }
+    }
+    result.push(b'"');
+    result
+}
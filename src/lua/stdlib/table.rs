@@ -0,0 +1,198 @@
+use crate::lua::interpreter::Interpreter;
+use crate::lua::stdlib::{
+    arg, arg_error, check_integer, check_table, library, opt_integer, opt_string,
+};
+use crate::lua::value::{LuaResult, TableRef, Value};
+
+pub fn open(interpreter: &mut Interpreter<'_>) {
+    let table = library(&[
+        ("concat", concat),
+        ("foreach", foreach),
+        ("foreachi", foreachi),
+        ("getn", getn),
+        ("insert", insert),
+        ("maxn", maxn),
+        ("remove", remove),
+        ("sort", sort),
+    ]);
+    interpreter.set_global("table", table);
+}
+
+fn get(table: &TableRef, i: i64) -> Value {
+    table.get(&Value::Number(i as f64))
+}
+
+fn set(interpreter: &Interpreter<'_>, table: &TableRef, i: i64, value: Value) -> LuaResult<()> {
+    interpreter.raw_set(table, Value::Number(i as f64), value)
+}
+
+fn concat(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "concat")?;
+    let separator = opt_string(interpreter, &args, 1, "concat", "")?;
+    let start = opt_integer(interpreter, &args, 2, "concat", 1)?;
+    let end = opt_integer(interpreter, &args, 3, "concat", table.len() as i64)?;
+
+    let mut result = Vec::new();
+    for i in start..=end {
+        let Some(value) = get(&table, i).to_lua_string() else {
+            return Err(interpreter.runtime_error(format!(
+                "invalid value (at index {}) in table for 'concat'",
+                i
+            )));
+        };
+        result.extend_from_slice(value.as_bytes());
+        if i < end {
+            result.extend_from_slice(separator.as_bytes());
+        }
+    }
+
+    Ok(vec![Value::string(result)])
+}
+
+fn foreach(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "foreach")?;
+    let function = arg(&args, 1);
+
+    let mut key = Value::Nil;
+    loop {
+        let entry = table.0.borrow().next(&key);
+        let Ok(Some((next_key, value))) = entry else {
+            return Ok(Vec::new());
+        };
+
+        let result = interpreter.call(&function, vec![next_key.clone(), value])?;
+        if let Some(result) = result.into_iter().next().filter(|v| !v.is_nil()) {
+            return Ok(vec![result]);
+        }
+        key = next_key;
+    }
+}
+
+fn foreachi(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "foreachi")?;
+    let function = arg(&args, 1);
+
+    for i in 1..=table.len() as i64 {
+        let result = interpreter.call(&function, vec![Value::Number(i as f64), get(&table, i)])?;
+        if let Some(result) = result.into_iter().next().filter(|v| !v.is_nil()) {
+            return Ok(vec![result]);
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn getn(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "getn")?;
+    Ok(vec![Value::Number(table.len() as f64)])
+}
+
+fn maxn(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "maxn")?;
+
+    let mut max = 0.0_f64;
+    let mut key = Value::Nil;
+    while let Ok(Some((next_key, _))) = table.0.borrow().next(&key) {
+        if let Value::Number(n) = next_key {
+            max = max.max(n);
+        }
+        key = next_key;
+    }
+    Ok(vec![Value::Number(max)])
+}
+
+fn insert(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "insert")?;
+    let end = table.len() as i64 + 1;
+
+    match args.len() {
+        2 => set(interpreter, &table, end, args[1].clone())?,
+        3 => {
+            let position = check_integer(interpreter, &args, 1, "insert")?;
+            // move up the elements from the position on
+            for i in (position + 1..=end).rev() {
+                set(interpreter, &table, i, get(&table, i - 1))?;
+            }
+            set(interpreter, &table, position, args[2].clone())?;
+        }
+        _ => return Err(interpreter.runtime_error("wrong number of arguments to 'insert'")),
+    }
+
+    Ok(Vec::new())
+}
+
+fn remove(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "remove")?;
+    let end = table.len() as i64;
+    let position = opt_integer(interpreter, &args, 1, "remove", end)?;
+
+    if end == 0 {
+        return Ok(Vec::new());
+    }
+
+    let removed = get(&table, position);
+    for i in position..end {
+        set(interpreter, &table, i, get(&table, i + 1))?;
+    }
+    set(interpreter, &table, end, Value::Nil)?;
+    Ok(vec![removed])
+}
+
+fn sort(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let table = check_table(interpreter, &args, 0, "sort")?;
+    let comparator = match arg(&args, 1) {
+        Value::Nil => None,
+        comparator @ Value::Function(_) => Some(comparator),
+        _ => return Err(arg_error(interpreter, 1, "sort", "function expected")),
+    };
+
+    let n = table.len() as i64;
+    let mut values: Vec<Value> = (1..=n).map(|i| get(&table, i)).collect();
+
+    let mut less = |interpreter: &mut Interpreter<'_>, a: &Value, b: &Value| match &comparator {
+        Some(comparator) => Ok(interpreter
+            .call(comparator, vec![a.clone(), b.clone()])?
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .is_truthy()),
+        None => interpreter.less_than(a, b),
+    };
+    merge_sort(interpreter, &mut values, &mut less)?;
+
+    for (i, value) in values.into_iter().enumerate() {
+        set(interpreter, &table, i as i64 + 1, value)?;
+    }
+    Ok(Vec::new())
+}
+
+type LessThan<'a> = dyn FnMut(&mut Interpreter<'_>, &Value, &Value) -> LuaResult<bool> + 'a;
+
+/// A merge sort, since the comparison can fail and `slice::sort_by` can't stop on errors.
+fn merge_sort(
+    interpreter: &mut Interpreter<'_>,
+    values: &mut Vec<Value>,
+    less: &mut LessThan<'_>,
+) -> LuaResult<()> {
+    if values.len() <= 1 {
+        return Ok(());
+    }
+
+    let mut right = values.split_off(values.len() / 2);
+    merge_sort(interpreter, values, less)?;
+    merge_sort(interpreter, &mut right, less)?;
+
+    let left = std::mem::take(values);
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if less(interpreter, b, a)? {
+            values.push(right.next().unwrap());
+        } else {
+            values.push(left.next().unwrap());
+        }
+    }
+    values.extend(left);
+    values.extend(right);
+    Ok(())
+}
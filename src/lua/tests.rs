@@ -0,0 +1,265 @@
+use super::*;
+
+struct NoHost;
+
+impl Host for NoHost {
+    fn call(&mut self, name: &str, _: Vec<Value>) -> LuaResult<Vec<Value>> {
+        Err(LuaError::Error(Value::string(format!(
+            "no host function {}",
+            name
+        ))))
+    }
+
+    fn check_interrupt(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Runs a chunk and shows its results like `tostring`, joined by spaces.
+fn run(source: &str) -> Result<String, String> {
+    with_stack(|| run_on_current_thread(source))
+}
+
+fn run_on_current_thread(source: &str) -> Result<String, String> {
+    let proto = parse("test", source.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut host = NoHost;
+    let mut interpreter = Interpreter::new(&mut host, "test");
+    let function = interpreter.load(proto);
+
+    match interpreter.call(&function, Vec::new()) {
+        Ok(values) => Ok(values
+            .iter()
+            .map(|value| interpreter.tostring(value).unwrap().to_string())
+            .collect::<Vec<_>>()
+            .join(" ")),
+        Err(LuaError::Error(value)) => Err(interpreter.tostring(&value).unwrap().to_string()),
+        Err(LuaError::Interrupted(message)) => Err(message),
+    }
+}
+
+#[test]
+fn test_expressions() {
+    assert_eq!(
+        run("return 1 + 2 * 3, 2 ^ 3 ^ 2, -2 ^ 2").unwrap(),
+        "7 512 -4"
+    );
+    assert_eq!(run("return 7 % 3, -7 % 3, 7 / 2").unwrap(), "1 2 3.5");
+    assert_eq!(run("return 1 .. 2, 'a' .. 'b' .. 'c'").unwrap(), "12 abc");
+    assert_eq!(run("return '10' + 5, 10 .. ''").unwrap(), "15 10");
+    assert_eq!(
+        run("return nil or 'x', false and 1, 1 and 2").unwrap(),
+        "x false 2"
+    );
+    assert_eq!(
+        run("return 1 < 2, 'a' < 'b', 1 == '1', #'abc'").unwrap(),
+        "true true false 3"
+    );
+    assert_eq!(run("return not nil, not 0").unwrap(), "true false");
+}
+
+#[test]
+fn test_statements() {
+    let source = "
+        local sum = 0
+        for i = 1, 10 do
+            if i % 2 == 0 then sum = sum + i elseif i == 5 then break end
+        end
+        local n = 0
+        while true do n = n + 1 if n >= 3 then break end end
+        repeat local m = n; n = n - 1 until m <= 1
+        local t = {}
+        for k, v in pairs({a = 1, b = 2}) do t[#t + 1] = k .. v end
+        table.sort(t)
+        return sum, n, table.concat(t, ',')
+    ";
+    assert_eq!(run(source).unwrap(), "6 0 a1,b2");
+
+    let source = "
+        local a, b, c = (function() return 1, 2, 3 end)()
+        local d, e = ((function() return 4, 5 end)())
+        local function count(...) return select('#', ...) end
+        return a + b + c, d, e, count(1, nil, 3), count(unpack({1, 2}))
+    ";
+    assert_eq!(run(source).unwrap(), "6 4 nil 3 2");
+}
+
+#[test]
+fn test_closures() {
+    let source = "
+        local counters = {}
+        for i = 1, 3 do
+            local count = i * 10
+            counters[i] = function() count = count + 1 return count end
+        end
+        counters[1]()
+        return counters[1](), counters[2](), counters[3]()
+    ";
+    assert_eq!(run(source).unwrap(), "12 21 31");
+
+    let source = "
+        local function fib(n) if n < 2 then return n end return fib(n - 1) + fib(n - 2) end
+        return fib(20)
+    ";
+    assert_eq!(run(source).unwrap(), "6765");
+}
+
+#[test]
+fn test_metatables() {
+    let source = "
+        local Point = {}
+        Point.__index = Point
+        Point.__add = function(a, b) return Point.new(a.x + b.x, a.y + b.y) end
+        Point.__tostring = function(p) return '(' .. p.x .. ', ' .. p.y .. ')' end
+        Point.__eq = function(a, b) return a.x == b.x and a.y == b.y end
+        function Point.new(x, y) return setmetatable({x = x, y = y}, Point) end
+        function Point:length() return math.sqrt(self.x ^ 2 + self.y ^ 2) end
+        local p = Point.new(1, 2) + Point.new(2, 2)
+        return tostring(p), p:length(), p == Point.new(3, 4)
+    ";
+    assert_eq!(run(source).unwrap(), "(3, 4) 5 true");
+
+    let source = "
+        local defaults = setmetatable({}, {__index = function(t, k) return k .. '!' end})
+        return defaults.hello, rawget(defaults, 'hello')
+    ";
+    assert_eq!(run(source).unwrap(), "hello! nil");
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(
+        run("local t = nil\nreturn t.x").unwrap_err(),
+        "test:2: attempt to index local 't' (a nil value)"
+    );
+    assert_eq!(
+        run("return undefined_function()").unwrap_err(),
+        "test:1: attempt to call global 'undefined_function' (a nil value)"
+    );
+    assert_eq!(
+        run("return {} + 1").unwrap_err(),
+        "test:1: attempt to perform arithmetic on a table value"
+    );
+    assert_eq!(
+        run("return 1 < 'x'").unwrap_err(),
+        "test:1: attempt to compare number with string"
+    );
+    assert_eq!(
+        run("return math.floor('x')").unwrap_err(),
+        "test:1: bad argument #1 to 'floor' (number expected, got string)"
+    );
+    assert_eq!(
+        run("local function f() return f() end return f()").unwrap_err(),
+        "test:1: stack overflow"
+    );
+
+    let source = "
+        local ok, err = pcall(error, {code = 42})
+        local ok2, err2 = pcall(function() error('boom') end)
+        local ok3, err3 = pcall(function() error('plain', 0) end)
+        return ok, err.code, ok2, err2, err3
+    ";
+    assert_eq!(run(source).unwrap(), "false 42 false test:3: boom plain");
+}
+
+#[test]
+fn test_string_library() {
+    assert_eq!(
+        run("return ('hello'):upper(), string.sub('hello', 2, -2), ('x'):rep(3, ',')").unwrap(),
+        "HELLO ell xxx"
+    );
+    assert_eq!(
+        run("return string.byte('A'), string.char(72, 105), string.len('abc')").unwrap(),
+        "65 Hi 3"
+    );
+    assert_eq!(
+        run("return string.find('hello world', 'o w'), string.find('a.b', '.', 1, true)").unwrap(),
+        "5 2 2"
+    );
+    assert_eq!(
+        run("return string.match('key=value', '(%w+)=(%w+)')").unwrap(),
+        "key value"
+    );
+    assert_eq!(
+        run("return string.gsub('hello world', 'o', '0')").unwrap(),
+        "hell0 w0rld 2"
+    );
+    assert_eq!(
+        run("return string.gsub('abc', '%w', '%0%0'), string.gsub('$x $y', '%$(%w+)', {x = 1})")
+            .unwrap(),
+        "aabbcc 1 $y 2"
+    );
+    assert_eq!(
+        run("local t = {} for w in string.gmatch('one two  three', '%a+') do t[#t+1] = w end return table.concat(t, '|')")
+            .unwrap(),
+        "one|two|three"
+    );
+    assert_eq!(
+        run("return string.match('  trim  ', '^%s*(.-)%s*$'), string.match('f(a(b)c)', '%b()')")
+            .unwrap(),
+        "trim (a(b)c)"
+    );
+    assert_eq!(
+        run("return string.format('%d %5.2f %-3s| %x %q %g', 42, 3.14159, 'a', 255, 'a\\n\"', 1e20)")
+            .unwrap(),
+        "42  3.14 a  | ff \"a\\\n\\\"\" 1e+20"
+    );
+}
+
+#[test]
+fn test_table_and_math_library() {
+    let source = "
+        local t = {5, 2, 8, 1}
+        table.insert(t, 3)
+        table.insert(t, 1, 9)
+        local removed = table.remove(t, 2)
+        table.sort(t, function(a, b) return a > b end)
+        return table.concat(t, ' '), removed, #t
+    ";
+    assert_eq!(run(source).unwrap(), "9 8 3 2 1 5 5");
+
+    assert_eq!(
+        run("return math.floor(3.7), math.max(1, 5, 3), math.huge, math.fmod(7, 3)").unwrap(),
+        "3 5 inf 1"
+    );
+    assert_eq!(
+        run("return math.random(10) == math.random(10) or true, tonumber('0x10'), tonumber('z', 36)")
+            .unwrap(),
+        "true 16 35"
+    );
+}
+
+#[test]
+fn test_cjson() {
+    assert_eq!(
+        run("return cjson.encode({1, 2, {a = 'x/y'}})").unwrap(),
+        r#"[1,2,{"a":"x\/y"}]"#
+    );
+    assert_eq!(
+        run(r#"local t = cjson.decode('{"a": [1, 2.5, true], "b": "é"}') return t.a[2], t.a[3], t.b"#)
+            .unwrap(),
+        "2.5 true é"
+    );
+    assert_eq!(
+        run("return cjson.decode('[1,')").unwrap_err(),
+        "test:1: Expected value but found the end at character 4"
+    );
+}
+
+#[test]
+fn test_readonly_table() {
+    let proto = parse("test", b"x = 1").unwrap();
+    let mut host = NoHost;
+    let mut interpreter = Interpreter::new(&mut host, "test");
+    interpreter.globals.0.borrow_mut().readonly = true;
+
+    let function = interpreter.load(proto);
+    let Err(LuaError::Error(Value::String(message))) = interpreter.call(&function, Vec::new())
+    else {
+        panic!("expected an error");
+    };
+    assert_eq!(
+        message.to_string(),
+        "test:1: Attempt to modify a readonly table"
+    );
+}
@@ -0,0 +1,433 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::lua::ast::FunctionProto;
+use crate::lua::interpreter::Interpreter;
+use crate::lua::number::format_number;
+
+/// An immutable Lua string. Lua strings are bytes, not necessarily UTF-8.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LuaString(Arc<[u8]>);
+
+impl LuaString {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0).into_owned()
+    }
+}
+
+impl From<&[u8]> for LuaString {
+    fn from(bytes: &[u8]) -> Self {
+        LuaString(Arc::from(bytes))
+    }
+}
+
+impl From<Vec<u8>> for LuaString {
+    fn from(bytes: Vec<u8>) -> Self {
+        LuaString(Arc::from(bytes))
+    }
+}
+
+impl From<&str> for LuaString {
+    fn from(s: &str) -> Self {
+        LuaString::from(s.as_bytes())
+    }
+}
+
+impl From<String> for LuaString {
+    fn from(s: String) -> Self {
+        LuaString::from(s.into_bytes())
+    }
+}
+
+impl Debug for LuaString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string_lossy())
+    }
+}
+
+impl Display for LuaString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+pub type NativeFn = dyn Fn(&mut Interpreter<'_>, Vec<Value>) -> LuaResult<Vec<Value>>;
+
+#[derive(Clone)]
+pub enum Function {
+    Lua(Rc<Closure>),
+    Native(&'static str, Rc<NativeFn>),
+}
+
+impl Function {
+    pub fn native(
+        name: &'static str,
+        f: impl Fn(&mut Interpreter<'_>, Vec<Value>) -> LuaResult<Vec<Value>> + 'static,
+    ) -> Self {
+        Function::Native(name, Rc::new(f))
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Function::Lua(closure) => Rc::as_ptr(closure) as usize,
+            Function::Native(_, f) => Rc::as_ptr(f) as *const () as usize,
+        }
+    }
+}
+
+pub struct Closure {
+    pub proto: Arc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Value>>>,
+}
+
+#[derive(Clone, Default)]
+pub enum Value {
+    #[default]
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(LuaString),
+    Table(TableRef),
+    Function(Function),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Table(_) => "table",
+            Value::Function(_) => "function",
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn string(s: impl Into<LuaString>) -> Self {
+        Value::String(s.into())
+    }
+
+    /// The number this value converts to in arithmetic, following Lua's string coercion.
+    pub fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => crate::lua::number::parse_number(s.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// The string this value converts to in concatenation, following Lua's number coercion.
+    pub fn to_lua_string(&self) -> Option<LuaString> {
+        match self {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(LuaString::from(format_number(*n))),
+            _ => None,
+        }
+    }
+
+    /// Raw equality, without the `__eq` metamethod.
+    pub fn raw_equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Table(a), Value::Table(b)) => Rc::ptr_eq(&a.0, &b.0),
+            (Value::Function(a), Value::Function(b)) => a.address() == b.address(),
+            _ => false,
+        }
+    }
+
+    /// How `tostring` shows values that have no natural string form.
+    pub fn address_string(&self) -> String {
+        match self {
+            Value::Table(table) => format!("table: {:p}", Rc::as_ptr(&table.0)),
+            Value::Function(Function::Native(name, _)) => format!("builtin: {}", name),
+            Value::Function(function) => format!("function: 0x{:x}", function.address()),
+            _ => String::new(),
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::String(s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self.address_string()),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::string(s)
+    }
+}
+
+impl From<TableRef> for Value {
+    fn from(table: TableRef) -> Self {
+        Value::Table(table)
+    }
+}
+
+/// An error raised while running Lua code.
+#[derive(Debug, Clone)]
+pub enum LuaError {
+    /// An error value raised with `error` or by the runtime, which `pcall` can catch.
+    Error(Value),
+    /// The host stopped the script, e.g. with `SCRIPT KILL`. It can't be caught.
+    Interrupted(String),
+}
+
+pub type LuaResult<T> = Result<T, LuaError>;
+
+/// A hashable table key. Reference types are keyed by address, their value in the table's
+/// entries keeps them alive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Boolean(bool),
+    Number(u64),
+    String(LuaString),
+    Reference(usize),
+}
+
+impl Key {
+    fn new(value: &Value) -> Option<Key> {
+        match value {
+            Value::Nil => None,
+            Value::Boolean(b) => Some(Key::Boolean(*b)),
+            Value::Number(n) if n.is_nan() => None,
+            // 0.0 and -0.0 are the same key
+            Value::Number(n) => Some(Key::Number((n + 0.0).to_bits())),
+            Value::String(s) => Some(Key::String(s.clone())),
+            Value::Table(table) => Some(Key::Reference(Rc::as_ptr(&table.0) as usize)),
+            Value::Function(function) => Some(Key::Reference(function.address())),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TableRef(pub Rc<RefCell<Table>>);
+
+impl TableRef {
+    pub fn new(table: Table) -> Self {
+        TableRef(Rc::new(RefCell::new(table)))
+    }
+
+    pub fn from_array(values: Vec<Value>) -> Self {
+        let mut table = Table::default();
+        for (i, value) in values.into_iter().enumerate() {
+            // array keys are never invalid
+            let _ = table.set(Value::Number((i + 1) as f64), value);
+        }
+        TableRef::new(table)
+    }
+
+    pub fn get(&self, key: &Value) -> Value {
+        self.0.borrow().get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Value {
+        self.get(&Value::string(key))
+    }
+
+    /// Sets a field, bypassing metamethods and the read-only flag.
+    pub fn set_str(&self, key: &str, value: impl Into<Value>) {
+        let _ = self.0.borrow_mut().set(Value::string(key), value.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn metatable(&self) -> Option<TableRef> {
+        self.0.borrow().metatable.clone()
+    }
+}
+
+/// A Lua table: an array part for the keys `1..=n`, and a hash part that keeps insertion order so
+/// that `next` can resume from any key.
+#[derive(Default)]
+pub struct Table {
+    array: Vec<Value>,
+    entries: Vec<(Value, Value)>,
+    index: HashMap<Key, usize>,
+    /// Hash entries whose value was set to nil. They are kept so that a traversal can go on
+    /// after fields are cleared, and compacted away when new keys are added.
+    removed: usize,
+    pub metatable: Option<TableRef>,
+    /// Read-only tables reject assignments, like the globals of Redis scripts.
+    pub readonly: bool,
+}
+
+impl Table {
+    fn array_index(&self, key: &Value) -> Option<usize> {
+        match key {
+            Value::Number(n)
+                if n.fract() == 0.0 && *n >= 1.0 && *n <= self.array.len() as f64 + 1.0 =>
+            {
+                Some(*n as usize - 1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &Value) -> Value {
+        if let Some(i) = self.array_index(key) {
+            return self.array.get(i).cloned().unwrap_or_default();
+        }
+
+        Key::new(key)
+            .and_then(|key| self.index.get(&key))
+            .map(|&i| self.entries[i].1.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, key: Value, value: Value) -> Result<(), &'static str> {
+        if let Some(i) = self.array_index(&key) {
+            if i < self.array.len() {
+                self.array[i] = value;
+                while self.array.last().is_some_and(Value::is_nil) {
+                    self.array.pop();
+                }
+            } else if !value.is_nil() {
+                self.array.push(value);
+                self.migrate_to_array();
+            }
+            return Ok(());
+        }
+
+        let hash_key = match &key {
+            Value::Nil => return Err("table index is nil"),
+            Value::Number(n) if n.is_nan() => return Err("table index is NaN"),
+            key => Key::new(key).ok_or("invalid table index")?,
+        };
+
+        match self.index.get(&hash_key) {
+            Some(&i) => {
+                if self.entries[i].1.is_nil() && !value.is_nil() {
+                    self.removed -= 1;
+                } else if !self.entries[i].1.is_nil() && value.is_nil() {
+                    self.removed += 1;
+                }
+                self.entries[i].1 = value;
+            }
+            None if value.is_nil() => {}
+            None => {
+                if self.removed > 0 && self.removed * 2 >= self.entries.len() {
+                    self.compact();
+                }
+                self.index.insert(hash_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the keys that now follow the array part from the hash part into it.
+    fn migrate_to_array(&mut self) {
+        loop {
+            let next = Value::Number(self.array.len() as f64 + 1.0);
+            let Some(key) = Key::new(&next) else {
+                return;
+            };
+            let Some(i) = self.index.remove(&key) else {
+                return;
+            };
+
+            let value = std::mem::take(&mut self.entries[i].1);
+            if value.is_nil() {
+                self.removed -= 1;
+                self.index.insert(key, i);
+                return;
+            }
+
+            // leave a removed entry behind, so that the other indexes stay valid
+            self.entries[i].0 = Value::Boolean(false);
+            self.removed += 1;
+            self.array.push(value);
+        }
+    }
+
+    fn compact(&mut self) {
+        let entries = std::mem::take(&mut self.entries);
+        self.index.clear();
+        self.removed = 0;
+
+        for (key, value) in entries {
+            if !value.is_nil() {
+                if let Some(hash_key) = Key::new(&key) {
+                    self.index.insert(hash_key, self.entries.len());
+                    self.entries.push((key, value));
+                }
+            }
+        }
+    }
+
+    /// The length operator: the size of the array part, which never ends with nil.
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    /// The entry after `key` in traversal order, or `Err` if `key` isn't in the table.
+    pub fn next(&self, key: &Value) -> Result<Option<(Value, Value)>, ()> {
+        let mut array_start = 0;
+        let mut entries_start = 0;
+
+        match key {
+            Value::Nil => {}
+            key => match self.array_index(key) {
+                Some(i) if i < self.array.len() => array_start = i + 1,
+                _ => {
+                    let i = Key::new(key)
+                        .and_then(|key| self.index.get(&key))
+                        .ok_or(())?;
+                    array_start = self.array.len();
+                    entries_start = i + 1;
+                }
+            },
+        }
+
+        for i in array_start..self.array.len() {
+            if !self.array[i].is_nil() {
+                return Ok(Some((Value::Number((i + 1) as f64), self.array[i].clone())));
+            }
+        }
+
+        Ok(self.entries[entries_start..]
+            .iter()
+            .find(|(_, value)| !value.is_nil())
+            .cloned())
+    }
+}
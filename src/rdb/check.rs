@@ -0,0 +1,179 @@
+//! The RDB checker, which is this binary run as `redis-check-rdb` like in Redis. It reads a
+//! snapshot without loading it, tells at which offset it stops making sense, and can list its
+//! keys. Keys with binary strings are reported, since the server skips them.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::rdb::{read, BinaryString, Item};
+use crate::scripting::Library;
+use crate::storage::Value;
+use crate::utils::json_string;
+
+const USAGE: &str = "Usage: redis-check-rdb <rdb-file-name> [--dump]";
+
+/// Checks the RDB file in `args`. With `--dump`, its keys are written to stdout as JSON lines,
+/// and the report goes to stderr instead.
+pub fn run(args: impl Iterator<Item = String>) -> Result<()> {
+    let mut path = None;
+    let mut dump = false;
+    for arg in args {
+        match arg.as_str() {
+            "--dump" => dump = true,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => bail!(USAGE),
+        }
+    }
+    let path = path.context(USAGE)?;
+    let report = |line: String| {
+        if dump {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+
+    report(format!("[offset 0] Checking RDB file {}", path.display()));
+    let rdb = fs::read(&path).with_context(|| format!("Failed opening {}", path.display()))?;
+
+    let now = SystemTime::now();
+    let (mut keys, mut expires, mut already_expired, mut binary) = (0, 0, 0, 0);
+    let result = read(&rdb, |item, pos| {
+        match item {
+            Item::Aux(name, value) => report(format!(
+                "[offset {}] AUX FIELD {} = '{}'",
+                pos,
+                String::from_utf8_lossy(&name),
+                String::from_utf8_lossy(&value)
+            )),
+            Item::Binary(key) => {
+                binary += 1;
+                report(format!(
+                    "[offset {}] Key '{}' would be skipped: {}",
+                    pos,
+                    String::from_utf8_lossy(&key),
+                    BinaryString
+                ));
+            }
+            Item::Library(code) => {
+                Library::load(&code)
+                    .with_context(|| format!("Failed loading the library at offset {}", pos))?;
+            }
+            Item::Key { key, value, expiry } => {
+                keys += 1;
+                if let Some(expiry) = expiry {
+                    expires += 1;
+                    if expiry <= now {
+                        already_expired += 1;
+                    }
+                }
+                if dump {
+                    println!(
+                        "{}",
+                        dump_line(&String::from_utf8_lossy(&key), &value, expiry)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    });
+
+    report(format!("[info] {} keys read", keys));
+    report(format!("[info] {} expires", expires));
+    report(format!("[info] {} already expired", already_expired));
+    if binary > 0 {
+        report(format!(
+            "[info] {} keys with binary strings, which aren't loaded",
+            binary
+        ));
+    }
+    match result {
+        Ok(version) if version < 5 => report(format!(
+            "[info] RDB format version {}, without a checksum",
+            version
+        )),
+        Ok(_) if rdb.ends_with(&[0; 8]) => {
+            report("RDB file was saved with checksum disabled: no check performed.".to_string())
+        }
+        Ok(version) => report(format!(
+            "[info] RDB format version {}, checksum OK",
+            version
+        )),
+        Err(e) => {
+            report("--- RDB ERROR DETECTED ---".to_string());
+            return Err(e);
+        }
+    }
+    report("\\o/ RDB looks OK! \\o/".to_string());
+
+    Ok(())
+}
+
+/// A key as a line of JSON, with its type and when it expires, in Unix time in milliseconds.
+pub fn dump_line(key: &str, value: &Value, expiry: Option<SystemTime>) -> String {
+    let expires_at = expiry.map_or("null".to_string(), |expiry| {
+        let at = expiry.duration_since(UNIX_EPOCH).unwrap_or_default();
+        at.as_millis().to_string()
+    });
+
+    format!(
+        r#"{{"key":{},"type":"{}","expires_at":{}}}"#,
+        json_string(key),
+        value.type_name(),
+        expires_at
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::rdb::encode;
+    use crate::resp::bulk;
+    use crate::storage::Storage;
+
+    #[test]
+    fn test_run() {
+        let mut storage = Storage::default();
+        let key = bulk("key");
+        storage.restore(key.clone(), Value::String(key), None);
+        let mut rdb = encode(&storage.snapshot());
+
+        let path = std::env::temp_dir().join(format!("test-check-{}.rdb", std::process::id()));
+        fs::write(&path, &rdb).unwrap();
+        run([path.display().to_string()].into_iter()).unwrap();
+
+        let len = rdb.len();
+        rdb[len - 3] ^= 1;
+        fs::write(&path, &rdb).unwrap();
+        let e = run([path.display().to_string()].into_iter()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!("Wrong RDB checksum at offset {}", len - 8)
+        );
+
+        fs::write(&path, &rdb[..len - 12]).unwrap();
+        assert!(run([path.display().to_string()].into_iter()).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dump_line() {
+        let value = Value::String(bulk("v"));
+        assert_eq!(
+            dump_line("k\"", &value, None),
+            r#"{"key":"k\"","type":"string","expires_at":null}"#
+        );
+        let expiry = UNIX_EPOCH + Duration::from_millis(1500);
+        assert_eq!(
+            dump_line("k", &value, Some(expiry)),
+            r#"{"key":"k","type":"string","expires_at":1500}"#
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Intsets, the sorted arrays of integers Redis keeps small sets of integers in.
+
+use anyhow::{ensure, Result};
+
+/// Encodes `ints` with the narrowest width that fits all of them.
+pub fn encode(mut ints: Vec<i64>) -> Vec<u8> {
+    ints.sort_unstable();
+    ints.dedup();
+
+    let fits = |min, max| ints.iter().all(|i| (min..=max).contains(i));
+    let width: usize = if fits(i16::MIN as i64, i16::MAX as i64) {
+        2
+    } else if fits(i32::MIN as i64, i32::MAX as i64) {
+        4
+    } else {
+        8
+    };
+
+    let mut bytes = Vec::with_capacity(8 + width * ints.len());
+    bytes.extend_from_slice(&(width as u32).to_le_bytes());
+    bytes.extend_from_slice(&(ints.len() as u32).to_le_bytes());
+    for i in ints {
+        bytes.extend_from_slice(&i.to_le_bytes()[..width]);
+    }
+
+    bytes
+}
+
+/// Reads the integers of an intset as decimal strings.
+pub fn decode(bytes: &[u8]) -> Result<Vec<String>> {
+    const INVALID: &str = "invalid intset";
+
+    ensure!(bytes.len() >= 8, INVALID);
+    let width = u32::from_le_bytes(bytes[..4].try_into()?) as usize;
+    let len = u32::from_le_bytes(bytes[4..8].try_into()?) as usize;
+    ensure!(matches!(width, 2 | 4 | 8), INVALID);
+    ensure!(bytes.len() == 8 + width * len, INVALID);
+
+    let ints = bytes[8..].chunks(width).map(|int| {
+        // little-endian, so the sign is extended from the last byte
+        let fill = if int[width - 1] & 0x80 != 0 { 0xff } else { 0 };
+        let mut i = [fill; 8];
+        i[..width].copy_from_slice(int);
+        i64::from_le_bytes(i).to_string()
+    });
+
+    Ok(ints.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intset() {
+        let bytes = encode(vec![3, -1, 3, 1]);
+        assert_eq!(bytes, [2, 0, 0, 0, 3, 0, 0, 0, 0xff, 0xff, 1, 0, 3, 0]);
+        assert_eq!(decode(&bytes).unwrap(), ["-1", "1", "3"]);
+
+        let bytes = encode(vec![-100000, i64::MAX]);
+        assert_eq!(bytes[..4], [8, 0, 0, 0]);
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            ["-100000".to_string(), i64::MAX.to_string()]
+        );
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
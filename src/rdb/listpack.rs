@@ -0,0 +1,249 @@
+//! Listpacks, the compact lists of strings and integers Redis keeps small collections and the
+//! nodes of streams in, and saves as they are.
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::rdb::parse_integer;
+
+/// Written in place of the element count once it doesn't fit the header.
+const UNKNOWN_LEN: u16 = u16::MAX;
+
+#[derive(Debug, Default)]
+pub struct Listpack {
+    entries: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    /// Appends a string, as an integer if it is one, like Redis does.
+    pub fn push_str(&mut self, s: &str) {
+        match parse_integer(s.as_bytes()) {
+            Some(i) => self.push_int(i),
+            None => self.push_entry(&encode_str(s.as_bytes())),
+        }
+    }
+
+    pub fn push_int(&mut self, i: i64) {
+        self.push_entry(&encode_int(i));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The size of the listpack once saved.
+    pub fn size(&self) -> usize {
+        4 + 2 + self.entries.len() + 1
+    }
+
+    /// The listpack as saved: its total size and element count, the entries, and an end
+    /// marker.
+    pub fn into_bytes(self) -> Vec<u8> {
+        let total = self.size();
+        let len = u16::try_from(self.len)
+            .ok()
+            .filter(|&len| len != UNKNOWN_LEN)
+            .unwrap_or(UNKNOWN_LEN);
+
+        let mut bytes = Vec::with_capacity(total);
+        bytes.extend_from_slice(&(total as u32).to_le_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&self.entries);
+        bytes.push(0xff);
+
+        bytes
+    }
+
+    /// Each entry is followed by its own size, so that listpacks can be walked backwards.
+    fn push_entry(&mut self, entry: &[u8]) {
+        self.entries.extend_from_slice(entry);
+        encode_backlen(entry.len(), &mut self.entries);
+        self.len += 1;
+    }
+}
+
+/// Reads the elements of a listpack, with integers as their decimal strings.
+pub fn decode(bytes: &[u8]) -> Result<Vec<String>> {
+    const INVALID: &str = "invalid listpack";
+
+    ensure!(bytes.len() >= 7 && bytes.last() == Some(&0xff), INVALID);
+    let total = u32::from_le_bytes(bytes[..4].try_into()?) as usize;
+    let len = u16::from_le_bytes(bytes[4..6].try_into()?);
+    ensure!(total == bytes.len(), INVALID);
+
+    let mut elements = Vec::new();
+    let mut rest = &bytes[6..bytes.len() - 1];
+    while let Some(&first) = rest.first() {
+        let bytes_at = |range: std::ops::Range<usize>| rest.get(range).context(INVALID);
+
+        let (element, size) = match first {
+            0x00..=0x7f => (first.to_string(), 1),
+            0x80..=0xbf => {
+                let len = (first & 0x3f) as usize;
+                (super::utf8(bytes_at(1..1 + len)?)?, 1 + len)
+            }
+            0xc0..=0xdf => {
+                let i = ((first as i64 & 0x1f) << 8) | bytes_at(1..2)?[0] as i64;
+                let i = if i >= 1 << 12 { i - (1 << 13) } else { i };
+                (i.to_string(), 2)
+            }
+            0xe0..=0xef => {
+                let len = ((first as usize & 0x0f) << 8) | bytes_at(1..2)?[0] as usize;
+                (super::utf8(bytes_at(2..2 + len)?)?, 2 + len)
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(bytes_at(1..5)?.try_into()?) as usize;
+                (super::utf8(bytes_at(5..5 + len)?)?, 5 + len)
+            }
+            0xf1 => (
+                i16::from_le_bytes(bytes_at(1..3)?.try_into()?).to_string(),
+                3,
+            ),
+            0xf2 => {
+                let mut i = [0; 4];
+                i[1..].copy_from_slice(bytes_at(1..4)?);
+                // shifting back extends the sign of the 24 bits
+                ((i32::from_le_bytes(i) >> 8).to_string(), 4)
+            }
+            0xf3 => (
+                i32::from_le_bytes(bytes_at(1..5)?.try_into()?).to_string(),
+                5,
+            ),
+            0xf4 => (
+                i64::from_le_bytes(bytes_at(1..9)?.try_into()?).to_string(),
+                9,
+            ),
+            _ => bail!(INVALID),
+        };
+
+        let backlen = backlen_size(size);
+        ensure!(rest.len() >= size + backlen, INVALID);
+        elements.push(element);
+        rest = &rest[size + backlen..];
+    }
+
+    ensure!(
+        len == UNKNOWN_LEN || len as usize == elements.len(),
+        INVALID
+    );
+
+    Ok(elements)
+}
+
+fn encode_int(i: i64) -> Vec<u8> {
+    match i {
+        0..=127 => vec![i as u8],
+        -4096..=4095 => {
+            let i = (i as u16) & 0x1fff;
+            vec![0xc0 | (i >> 8) as u8, i as u8]
+        }
+        -32768..=32767 => {
+            let mut entry = vec![0xf1];
+            entry.extend_from_slice(&(i as i16).to_le_bytes());
+            entry
+        }
+        -8388608..=8388607 => {
+            let mut entry = vec![0xf2];
+            entry.extend_from_slice(&(i as i32).to_le_bytes()[..3]);
+            entry
+        }
+        -2147483648..=2147483647 => {
+            let mut entry = vec![0xf3];
+            entry.extend_from_slice(&(i as i32).to_le_bytes());
+            entry
+        }
+        _ => {
+            let mut entry = vec![0xf4];
+            entry.extend_from_slice(&i.to_le_bytes());
+            entry
+        }
+    }
+}
+
+fn encode_str(s: &[u8]) -> Vec<u8> {
+    let len = s.len();
+    let mut entry = if len < 1 << 6 {
+        vec![0x80 | len as u8]
+    } else if len < 1 << 12 {
+        vec![0xe0 | (len >> 8) as u8, len as u8]
+    } else {
+        let mut entry = vec![0xf0];
+        entry.extend_from_slice(&(len as u32).to_le_bytes());
+        entry
+    };
+    entry.extend_from_slice(s);
+
+    entry
+}
+
+/// Writes the size of an entry in 7-bit groups, most significant first, with the high bit set
+/// on all but the first so that it can be read from its last byte.
+fn encode_backlen(len: usize, bytes: &mut Vec<u8>) {
+    let groups = backlen_size(len);
+
+    for group in (0..groups).rev() {
+        let bits = ((len >> (7 * group)) & 0x7f) as u8;
+        bytes.push(if group == groups - 1 {
+            bits
+        } else {
+            bits | 0x80
+        });
+    }
+}
+
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listpack() {
+        let mut listpack = Listpack::default();
+        listpack.push_str("a");
+        listpack.push_str("42");
+        listpack.push_int(-1);
+        listpack.push_int(10000);
+        listpack.push_int(100000);
+
+        let bytes = listpack.into_bytes();
+        assert_eq!(
+            bytes,
+            [
+                24, 0, 0, 0, 5, 0, // header
+                0x81, b'a', 2, // "a"
+                42, 1, // 7-bit integer
+                0xdf, 0xff, 2, // 13-bit integer
+                0xf1, 0x10, 0x27, 3, // 16-bit integer
+                0xf2, 0xa0, 0x86, 0x01, 4, // 24-bit integer
+                0xff,
+            ]
+        );
+
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            ["a", "42", "-1", "10000", "100000"]
+        );
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut long = Listpack::default();
+        long.push_str(&"x".repeat(200));
+        long.push_int(-5000000);
+        assert_eq!(
+            decode(&long.into_bytes()).unwrap(),
+            ["x".repeat(200), "-5000000".to_string()]
+        );
+
+        let mut backlen = Vec::new();
+        encode_backlen(500, &mut backlen);
+        assert_eq!(backlen, [3, 0xf4]);
+    }
+}
@@ -0,0 +1,65 @@
+//! The LZF compression Redis applies to long strings in RDB files.
+
+use anyhow::{ensure, Context, Result};
+
+/// A back reference of 3 bytes adds at most 264 bytes, which bounds what any input expands to.
+const MAX_EXPANSION: usize = 88;
+
+/// Decompresses `compressed` into exactly `len` bytes.
+pub fn decompress(compressed: &[u8], len: usize) -> Result<Vec<u8>> {
+    const INVALID: &str = "invalid LZF compressed string";
+
+    // the length comes from the file, so it is only trusted once the input could fill it
+    ensure!(
+        len <= compressed.len().saturating_mul(MAX_EXPANSION),
+        INVALID
+    );
+    let mut output = Vec::with_capacity(len);
+    let mut input = compressed.iter().copied();
+
+    while let Some(ctrl) = input.next() {
+        let ctrl = ctrl as usize;
+
+        if ctrl < 1 << 5 {
+            // a run of literal bytes
+            for _ in 0..=ctrl {
+                output.push(input.next().context(INVALID)?);
+            }
+        } else {
+            // a back reference to bytes already decompressed, which may overlap the ones it adds
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += input.next().context(INVALID)? as usize;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + input.next().context(INVALID)? as usize + 1;
+            ensure!(offset <= output.len(), INVALID);
+
+            let start = output.len() - offset;
+            for i in 0..run + 2 {
+                output.push(output[start + i]);
+            }
+        }
+
+        ensure!(output.len() <= len, INVALID);
+    }
+
+    ensure!(output.len() == len, INVALID);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        // "aaaaaaaaaabc": a literal "a", nine more through a back reference, then "bc"
+        let compressed = [0x00, b'a', 0xe0, 0x00, 0x00, 0x01, b'b', b'c'];
+        assert_eq!(decompress(&compressed, 12).unwrap(), b"aaaaaaaaaabc");
+
+        assert!(decompress(&compressed, 11).is_err());
+        assert!(decompress(&[0x20, 0x00], 3).is_err());
+        assert!(decompress(&compressed, usize::MAX).is_err());
+    }
+}
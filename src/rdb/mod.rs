@@ -0,0 +1,390 @@
+//! The RDB format Redis saves snapshots of the dataset in, which is also what replicas are sent
+//! on a full resynchronization.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::storage::Storage;
+
+pub use reader::{load, read, Item, Reader};
+pub use writer::encode;
+
+pub mod check;
+mod intset;
+mod listpack;
+mod lzf;
+mod reader;
+mod writer;
+mod ziplist;
+
+pub const RDB_VERSION: u16 = 11;
+/// The newest version that can be read, from Redis 7.4. Files only differ from version 11 in
+/// the hash field expiries it added, whose types are rejected as unknown.
+pub const RDB_MAX_VERSION: u16 = 12;
+
+pub const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Nodes of quicklists saved since Redis 7 are either a single large element, or a listpack.
+const QUICKLIST_NODE_CONTAINER_PLAIN: u64 = 1;
+const QUICKLIST_NODE_CONTAINER_PACKED: u64 = 2;
+
+/// Stream entries that were deleted but still take space in their node.
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+/// Stream entries with the same fields as the first entry of their node only store values.
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Where snapshots are saved, relative to the working directory.
+pub const DEFAULT_FILENAME: &str = "dump.rdb";
+
+/// How long to wait before retrying a background save that failed.
+const BGSAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A `save <seconds> <changes>` rule: a background save starts once there were at least
+/// `changes` writes, and `seconds` passed since the last save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl SaveRule {
+    /// Parses rules such as `900 1 300 10`. An empty string disables saving.
+    pub fn parse_rules(s: &str) -> Result<Vec<Self>> {
+        let numbers = s
+            .split_whitespace()
+            .map(|n| n.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|numbers| numbers.len() % 2 == 0)
+            .context("Invalid save parameters")?;
+
+        Ok(numbers
+            .chunks(2)
+            .map(|rule| SaveRule {
+                seconds: rule[0],
+                changes: rule[1],
+            })
+            .collect())
+    }
+}
+
+/// When the dataset was last saved, and whether a background save is running.
+///
+/// Background saves write the file without the storage lock, so this is shared with them the
+/// way [`ScriptControl`](crate::scripting::ScriptControl) is shared with scripts.
+#[derive(Debug)]
+pub struct SaveState {
+    path: PathBuf,
+    last_save: Mutex<SystemTime>,
+    in_progress: AtomicBool,
+    /// The count of writes to the storage the last save included.
+    saved_dirty: AtomicU64,
+    last_background_ok: AtomicBool,
+    last_background_try: Mutex<SystemTime>,
+}
+
+impl Default for SaveState {
+    fn default() -> Self {
+        SaveState::new(PathBuf::from(DEFAULT_FILENAME))
+    }
+}
+
+impl SaveState {
+    /// Until something is saved, the last save is when the server started, like in Redis.
+    pub fn new(path: PathBuf) -> Self {
+        SaveState {
+            path,
+            last_save: Mutex::new(SystemTime::now()),
+            in_progress: AtomicBool::new(false),
+            saved_dirty: AtomicU64::new(0),
+            last_background_ok: AtomicBool::new(true),
+            last_background_try: Mutex::new(UNIX_EPOCH),
+        }
+    }
+
+    pub fn last_save(&self) -> SystemTime {
+        *self.last_save.lock().unwrap()
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::SeqCst)
+    }
+
+    pub fn last_background_ok(&self) -> bool {
+        self.last_background_ok.load(Ordering::SeqCst)
+    }
+
+    /// How many of the `dirty` writes to the storage happened since the last save.
+    pub fn changes_since_last_save(&self, dirty: u64) -> u64 {
+        dirty.saturating_sub(self.saved_dirty.load(Ordering::SeqCst))
+    }
+
+    /// Whether one of `rules` is met. After a failed background save, Redis waits
+    /// [`BGSAVE_RETRY_DELAY`] before trying again, so that a full disk isn't written to in a
+    /// loop.
+    pub fn should_save(&self, rules: &[SaveRule], dirty: u64) -> bool {
+        let changes = self.changes_since_last_save(dirty);
+        let since_save = self.last_save().elapsed().unwrap_or_default().as_secs();
+        let since_try = self
+            .last_background_try
+            .lock()
+            .unwrap()
+            .elapsed()
+            .unwrap_or_default();
+
+        rules
+            .iter()
+            .any(|rule| changes >= rule.changes && since_save > rule.seconds)
+            && (self.last_background_ok() || since_try > BGSAVE_RETRY_DELAY)
+    }
+
+    /// Writes a snapshot of the storage after its first `dirty` writes in the foreground.
+    pub fn save(&self, rdb: &[u8], dirty: u64) -> Result<()> {
+        write_file(&self.path, rdb)?;
+        *self.last_save.lock().unwrap() = SystemTime::now();
+        self.saved_dirty.store(dirty, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Marks a background save as started, unless one already is.
+    pub fn start_background(&self) -> bool {
+        let started = self
+            .in_progress
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if started {
+            *self.last_background_try.lock().unwrap() = SystemTime::now();
+        }
+
+        started
+    }
+
+    /// Writes a snapshot, then marks the background save as done.
+    pub fn save_background(&self, rdb: &[u8], dirty: u64) -> Result<()> {
+        let result = self.save(rdb, dirty);
+        self.last_background_ok
+            .store(result.is_ok(), Ordering::SeqCst);
+        self.in_progress.store(false, Ordering::SeqCst);
+
+        result
+    }
+}
+
+/// Takes a snapshot of the dataset, then serializes it and writes it to disk on a blocking
+/// thread, so the storage lock is only held for the snapshot. Returns false if a background
+/// save was already running.
+pub fn start_background_save(storage: &Storage) -> bool {
+    if !storage.saves.start_background() {
+        return false;
+    }
+
+    let saves = Arc::clone(&storage.saves);
+    let dirty = storage.dirty();
+    let snapshot = storage.snapshot();
+
+    tokio::task::spawn_blocking(move || {
+        let rdb = encode(&snapshot);
+        // the shards the storage didn't copy yet are freed along with the snapshot
+        drop(snapshot);
+
+        if let Err(e) = saves.save_background(&rdb, dirty) {
+            eprintln!("background saving failed; error = {:?}", e);
+        }
+    });
+
+    true
+}
+
+/// Writes `rdb` to a temporary file next to `path`, then renames it, so that a crash never
+/// leaves a partial snapshot behind.
+///
+/// Each save gets a temporary file of its own, since `SAVE` may run while a background save
+/// is still writing.
+fn write_file(path: &Path, rdb: &[u8]) -> Result<()> {
+    static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+    let temp_path = path.with_file_name(format!(
+        "temp-{}-{}.rdb",
+        std::process::id(),
+        NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = File::create(&temp_path)
+        .with_context(|| format!("Failed opening {} for saving", temp_path.display()))?;
+    file.write_all(rdb)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path).with_context(|| {
+        format!(
+            "Error moving temp DB file {} on the final destination {}",
+            temp_path.display(),
+            path.display()
+        )
+    })
+}
+
+/// Loads the snapshot at `path` into `storage`, if there is one.
+pub fn load_file(path: &Path, storage: &mut Storage) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let rdb = fs::read(path).with_context(|| format!("Failed opening {}", path.display()))?;
+    load(&rdb, storage).with_context(|| format!("Failed loading {}", path.display()))
+}
+
+/// Writes a length, in as few bytes as its size allows.
+pub fn encode_length(len: u64, rdb: &mut Vec<u8>) {
+    if len < 1 << 6 {
+        rdb.push(len as u8);
+    } else if len < 1 << 14 {
+        rdb.extend_from_slice(&((len as u16) | 0x4000).to_be_bytes());
+    } else if len <= u32::MAX as u64 {
+        rdb.push(0x80);
+        rdb.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        rdb.push(0x81);
+        rdb.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Writes a length-prefixed string, without compression. Short strings that are integers are
+/// written as integers, like Redis does.
+pub fn encode_string(s: &[u8], rdb: &mut Vec<u8>) {
+    if s.len() <= 11 {
+        if let Some(i) = parse_integer(s) {
+            if let Ok(i) = i8::try_from(i) {
+                rdb.push(0xc0);
+                rdb.extend_from_slice(&i.to_le_bytes());
+                return;
+            } else if let Ok(i) = i16::try_from(i) {
+                rdb.push(0xc1);
+                rdb.extend_from_slice(&i.to_le_bytes());
+                return;
+            } else if let Ok(i) = i32::try_from(i) {
+                rdb.push(0xc2);
+                rdb.extend_from_slice(&i.to_le_bytes());
+                return;
+            }
+        }
+    }
+
+    encode_length(s.len() as u64, rdb);
+    rdb.extend_from_slice(s);
+}
+
+/// Parses `s` if it is an integer written the way Redis would write it back, without a sign
+/// or zeros it would drop.
+fn parse_integer(s: &[u8]) -> Option<i64> {
+    let s = std::str::from_utf8(s).ok()?;
+    let i = s.parse::<i64>().ok()?;
+
+    (i.to_string() == s).then_some(i)
+}
+
+/// Strings are UTF-8 here, so a key with a binary one is left out rather than be silently
+/// altered.
+#[derive(Debug, thiserror::Error)]
+#[error("Binary strings are not supported, only UTF-8 ones")]
+pub struct BinaryString;
+
+fn utf8(bytes: &[u8]) -> Result<String> {
+    Ok(String::from_utf8(bytes.to_vec()).map_err(|_| BinaryString)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_rules() {
+        let rules = SaveRule::parse_rules("900 1 300 10").unwrap();
+        assert_eq!(
+            rules,
+            [
+                SaveRule {
+                    seconds: 900,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 10
+                }
+            ]
+        );
+        assert_eq!(SaveRule::parse_rules("").unwrap(), []);
+        assert!(SaveRule::parse_rules("900").is_err());
+        assert!(SaveRule::parse_rules("900 x").is_err());
+
+        let saves = SaveState::default();
+        let rules = [SaveRule {
+            seconds: 0,
+            changes: 2,
+        }];
+        *saves.last_save.lock().unwrap() -= Duration::from_secs(1);
+        assert!(!saves.should_save(&rules, 1));
+        assert!(saves.should_save(&rules, 2));
+
+        // a failed background save is retried later
+        saves.last_background_ok.store(false, Ordering::SeqCst);
+        assert!(saves.start_background());
+        saves.in_progress.store(false, Ordering::SeqCst);
+        assert!(!saves.should_save(&rules, 2));
+    }
+
+    #[test]
+    fn test_encode_string() {
+        let encode = |s: &str| {
+            let mut rdb = Vec::new();
+            encode_string(s.as_bytes(), &mut rdb);
+            rdb
+        };
+
+        assert_eq!(encode("abc"), b"\x03abc");
+        assert_eq!(encode("12"), [0xc0, 12]);
+        assert_eq!(encode("-300"), [0xc1, 0xd4, 0xfe]);
+        assert_eq!(encode("1700000000"), [0xc2, 0x00, 0xf1, 0x53, 0x65]);
+        assert_eq!(encode("012"), b"\x03012");
+        assert_eq!(encode("+1"), b"\x02+1");
+        assert_eq!(encode(&"x".repeat(100))[..2], [0x40, 100]);
+
+        let long = "x".repeat(20000);
+        let rdb = encode(&long);
+        assert_eq!(rdb[..5], [0x80, 0, 0, 0x4e, 0x20]);
+        assert_eq!(Reader::new(&rdb).read_string().unwrap(), long.as_bytes());
+    }
+}
@@ -0,0 +1,892 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, ensure, Context, Result};
+
+use crate::rdb::{
+    intset, listpack, lzf, utf8, ziplist, BinaryString, QUICKLIST_NODE_CONTAINER_PACKED,
+    QUICKLIST_NODE_CONTAINER_PLAIN, RDB_MAX_VERSION, RDB_OPCODE_AUX, RDB_OPCODE_EOF,
+    RDB_OPCODE_EXPIRETIME, RDB_OPCODE_EXPIRETIME_MS, RDB_OPCODE_FREQ, RDB_OPCODE_FUNCTION2,
+    RDB_OPCODE_IDLE, RDB_OPCODE_RESIZEDB, RDB_OPCODE_SELECTDB, RDB_TYPE_HASH,
+    RDB_TYPE_HASH_LISTPACK, RDB_TYPE_HASH_ZIPLIST, RDB_TYPE_LIST, RDB_TYPE_LIST_QUICKLIST,
+    RDB_TYPE_LIST_QUICKLIST_2, RDB_TYPE_LIST_ZIPLIST, RDB_TYPE_SET, RDB_TYPE_SET_INTSET,
+    RDB_TYPE_SET_LISTPACK, RDB_TYPE_STREAM_LISTPACKS, RDB_TYPE_STREAM_LISTPACKS_2,
+    RDB_TYPE_STREAM_LISTPACKS_3, RDB_TYPE_STRING, RDB_TYPE_ZSET, RDB_TYPE_ZSET_2,
+    RDB_TYPE_ZSET_LISTPACK, RDB_TYPE_ZSET_ZIPLIST, STREAM_ITEM_FLAG_DELETED,
+    STREAM_ITEM_FLAG_SAMEFIELDS,
+};
+use crate::resp::{BulkString, Resp};
+use crate::scripting::Library;
+use crate::storage::{
+    Consumer, ConsumerGroup, Fields, PendingEntry, PersistentMap, PersistentSet, SortedSet,
+    Storage, Stream, StreamId, Value,
+};
+use crate::utils::crc64;
+
+/// Reads the parts of an RDB file in order. Errors tell the offset of the part that doesn't
+/// make sense.
+pub struct Reader<'a> {
+    rdb: &'a [u8],
+    pos: usize,
+    /// Whether a binary string was read since this was last cleared. Reading goes on past it,
+    /// so that the key it belongs to can be skipped.
+    binary: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(rdb: &'a [u8]) -> Self {
+        Reader {
+            rdb,
+            pos: 0,
+            binary: false,
+        }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.rdb.len()
+    }
+
+    pub fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("{} at offset {}", message, self.pos)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let rdb = self.rdb;
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| rdb.get(self.pos..end))
+            .ok_or_else(|| anyhow!("Unexpected end of file at offset {}", rdb.len()))?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_length(&mut self) -> Result<u64> {
+        match self.read_length_or_encoding()? {
+            Length::Plain(len) => Ok(len),
+            Length::Encoded(_) => Err(self.error("Unexpected string encoding")),
+        }
+    }
+
+    /// Reads a string, which may be an integer or compressed.
+    pub fn read_string(&mut self) -> Result<Vec<u8>> {
+        match self.read_length_or_encoding()? {
+            Length::Plain(len) => {
+                let len = usize::try_from(len).map_err(|_| self.error("Invalid string length"))?;
+                Ok(self.read_bytes(len)?.to_vec())
+            }
+            Length::Encoded(0) => Ok((self.read_u8()? as i8).to_string().into_bytes()),
+            Length::Encoded(1) => {
+                let i = i16::from_le_bytes(self.read_bytes(2)?.try_into()?);
+                Ok(i.to_string().into_bytes())
+            }
+            Length::Encoded(2) => Ok((self.read_u32()? as i32).to_string().into_bytes()),
+            Length::Encoded(3) => {
+                let compressed_len = usize::try_from(self.read_length()?)
+                    .map_err(|_| self.error("Invalid string length"))?;
+                let len = usize::try_from(self.read_length()?)
+                    .map_err(|_| self.error("Invalid string length"))?;
+                let start = self.pos;
+                let compressed = self.read_bytes(compressed_len)?;
+
+                lzf::decompress(compressed, len).map_err(|e| anyhow!("{} at offset {}", e, start))
+            }
+            Length::Encoded(encoding) => {
+                Err(self.error(&format!("Unknown string encoding {}", encoding)))
+            }
+        }
+    }
+
+    /// Reads a string that has to be UTF-8, noting a binary one instead of failing.
+    fn read_utf8(&mut self) -> Result<String> {
+        let bytes = self.read_string()?;
+
+        Ok(utf8(&bytes).unwrap_or_else(|_| {
+            self.binary = true;
+            String::from_utf8_lossy(&bytes).into_owned()
+        }))
+    }
+
+    fn read_length_or_encoding(&mut self) -> Result<Length> {
+        let first = self.read_u8()?;
+
+        match first >> 6 {
+            0 => Ok(Length::Plain((first & 0x3f) as u64)),
+            1 => {
+                let second = self.read_u8()?;
+                Ok(Length::Plain(((first as u64 & 0x3f) << 8) | second as u64))
+            }
+            2 => match first {
+                0x80 => {
+                    let len = u32::from_be_bytes(self.read_bytes(4)?.try_into()?);
+                    Ok(Length::Plain(len as u64))
+                }
+                0x81 => Ok(Length::Plain(u64::from_be_bytes(
+                    self.read_bytes(8)?.try_into()?,
+                ))),
+                _ => Err(anyhow!(
+                    "Unknown length encoding {} at offset {}",
+                    first,
+                    self.pos - 1
+                )),
+            },
+            _ => Ok(Length::Encoded(first & 0x3f)),
+        }
+    }
+
+    /// Scores of the first sorted set encoding are written as strings, with a length byte that
+    /// also stands for the special values.
+    fn read_string_double(&mut self) -> Result<f64> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => {
+                let s = self.read_bytes(len as usize)?;
+                std::str::from_utf8(s)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| self.error("Invalid double"))
+            }
+        }
+    }
+
+    fn read_binary_double(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    fn read_stream_id(&mut self) -> Result<StreamId> {
+        Ok(StreamId {
+            ms: self.read_length()?,
+            seq: self.read_length()?,
+        })
+    }
+
+    fn read_raw_stream_id(&mut self) -> Result<StreamId> {
+        decode_stream_id(self.read_bytes(16)?)
+    }
+}
+
+enum Length {
+    Plain(u64),
+    /// Strings saved as integers or compressed.
+    Encoded(u8),
+}
+
+/// A part of an RDB file, with the offset it starts at.
+pub enum Item {
+    Aux(Vec<u8>, Vec<u8>),
+    /// The code of a function library.
+    Library(String),
+    Key {
+        key: Vec<u8>,
+        value: Value,
+        expiry: Option<SystemTime>,
+    },
+    /// A key with binary strings, which can't be loaded.
+    Binary(Vec<u8>),
+}
+
+/// Loads the keys and function libraries of an RDB file into `storage`. Keys that expired
+/// while the server was down are left out.
+pub fn load(rdb: &[u8], storage: &mut Storage) -> Result<()> {
+    let now = SystemTime::now();
+
+    read(rdb, |item, pos| {
+        match item {
+            Item::Aux(..) => {}
+            Item::Binary(key) => eprintln!(
+                "Skipping the key {:?} at offset {}: {}",
+                String::from_utf8_lossy(&key),
+                pos,
+                BinaryString
+            ),
+            Item::Library(code) => {
+                Library::load(&code)
+                    .and_then(|library| storage.functions.insert(library, false))
+                    .with_context(|| format!("Failed loading the library at offset {}", pos))?;
+            }
+            Item::Key { key, value, expiry } => {
+                if expiry.map_or(true, |expiry| expiry > now) && !is_empty(&value) {
+                    let key = bulk(key)
+                        .with_context(|| format!("Failed reading the key at offset {}", pos))?;
+                    storage.restore(key, value, expiry);
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Reads an RDB file, passing `f` every part of it that has data, and returns its version once
+/// the checksum matches. The keys of databases other than 0 are skipped.
+pub fn read(rdb: &[u8], mut f: impl FnMut(Item, usize) -> Result<()>) -> Result<u16> {
+    let mut reader = Reader::new(rdb);
+
+    let magic = reader.read_bytes(9)?;
+    let version = magic
+        .strip_prefix(b"REDIS")
+        .and_then(|version| std::str::from_utf8(version).ok())
+        .and_then(|version| version.parse::<u16>().ok())
+        .context("Wrong signature trying to load DB from file")?;
+    ensure!(
+        (1..=RDB_MAX_VERSION).contains(&version),
+        "Can't handle RDB format version {}",
+        version
+    );
+
+    let mut expiry = None;
+    // only database 0 is kept, the keys of the others are read past
+    let mut db = 0;
+
+    loop {
+        let pos = reader.pos();
+        match reader.read_u8()? {
+            RDB_OPCODE_EOF => break,
+            RDB_OPCODE_AUX => {
+                let name = reader.read_string()?;
+                let value = reader.read_string()?;
+                f(Item::Aux(name, value), pos)?;
+            }
+            RDB_OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            RDB_OPCODE_SELECTDB => {
+                db = reader.read_length()?;
+                if db != 0 {
+                    eprintln!(
+                        "Skipping the keys of database {} at offset {}, only database 0 is \
+                         supported",
+                        db, pos
+                    );
+                }
+            }
+            RDB_OPCODE_EXPIRETIME => {
+                let seconds = reader.read_u32()?;
+                expiry = Some(UNIX_EPOCH + Duration::from_secs(seconds as u64));
+            }
+            RDB_OPCODE_EXPIRETIME_MS => {
+                let ms = reader.read_u64()?;
+                expiry = Some(UNIX_EPOCH + Duration::from_millis(ms));
+            }
+            // eviction hints, which don't matter without a memory limit
+            RDB_OPCODE_IDLE => {
+                reader.read_length()?;
+            }
+            RDB_OPCODE_FREQ => {
+                reader.read_u8()?;
+            }
+            RDB_OPCODE_FUNCTION2 => {
+                let code = reader.read_string()?;
+                f(Item::Library(string(code)?), pos)?;
+            }
+            type_id => {
+                let (key, value) = read_key(&mut reader, type_id)
+                    .with_context(|| format!("Failed reading the key at offset {}", pos))?;
+                if db != 0 {
+                    expiry = None;
+                    continue;
+                }
+                if std::mem::take(&mut reader.binary) {
+                    expiry = None;
+                    f(Item::Binary(key), pos)?;
+                    continue;
+                }
+                f(
+                    Item::Key {
+                        key,
+                        value,
+                        expiry: expiry.take(),
+                    },
+                    pos,
+                )?;
+            }
+        }
+    }
+
+    // the checksum came with version 5, and is left at 0 when it wasn't computed
+    if version >= 5 {
+        let end = reader.pos();
+        let checksum = reader.read_u64()?;
+        ensure!(
+            checksum == 0 || checksum == crc64(&rdb[..end]),
+            "Wrong RDB checksum at offset {}",
+            end
+        );
+    }
+
+    Ok(version)
+}
+
+fn read_key(reader: &mut Reader<'_>, type_id: u8) -> Result<(Vec<u8>, Value)> {
+    let key = reader.read_string()?;
+    reader.binary = std::str::from_utf8(&key).is_err();
+    let value = read_value(reader, type_id)?;
+
+    Ok((key, value))
+}
+
+fn read_value(reader: &mut Reader<'_>, type_id: u8) -> Result<Value> {
+    let value = match type_id {
+        RDB_TYPE_STRING => Value::String(Resp::BulkString(BulkString(Some(reader.read_utf8()?)))),
+        RDB_TYPE_LIST => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.read_length()? {
+                list.push_back(reader.read_utf8()?);
+            }
+            Value::List(list)
+        }
+        RDB_TYPE_LIST_ZIPLIST => Value::List(read_encoded(reader, ziplist::decode)?.into()),
+        RDB_TYPE_LIST_QUICKLIST | RDB_TYPE_LIST_QUICKLIST_2 => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.read_length()? {
+                if type_id == RDB_TYPE_LIST_QUICKLIST {
+                    list.extend(read_encoded(reader, ziplist::decode)?);
+                    continue;
+                }
+
+                match reader.read_length()? {
+                    QUICKLIST_NODE_CONTAINER_PLAIN => list.push_back(reader.read_utf8()?),
+                    QUICKLIST_NODE_CONTAINER_PACKED => {
+                        list.extend(read_encoded(reader, listpack::decode)?)
+                    }
+                    _ => return Err(reader.error("Unknown quicklist node container")),
+                }
+            }
+            Value::List(list)
+        }
+        RDB_TYPE_SET => {
+            let mut set = PersistentSet::new();
+            for _ in 0..reader.read_length()? {
+                set.insert(reader.read_utf8()?);
+            }
+            Value::Set(set)
+        }
+        RDB_TYPE_SET_INTSET => {
+            Value::Set(read_encoded(reader, intset::decode)?.into_iter().collect())
+        }
+        RDB_TYPE_SET_LISTPACK => Value::Set(
+            read_encoded(reader, listpack::decode)?
+                .into_iter()
+                .collect(),
+        ),
+        RDB_TYPE_ZSET | RDB_TYPE_ZSET_2 => {
+            let mut sorted_set = SortedSet::default();
+            for _ in 0..reader.read_length()? {
+                let member = reader.read_utf8()?;
+                let score = if type_id == RDB_TYPE_ZSET_2 {
+                    reader.read_binary_double()?
+                } else {
+                    reader.read_string_double()?
+                };
+                sorted_set.insert(member, score);
+            }
+            Value::SortedSet(sorted_set)
+        }
+        RDB_TYPE_ZSET_ZIPLIST | RDB_TYPE_ZSET_LISTPACK => {
+            let pos = reader.pos();
+            let decode = if type_id == RDB_TYPE_ZSET_ZIPLIST {
+                ziplist::decode
+            } else {
+                listpack::decode
+            };
+
+            let mut sorted_set = SortedSet::default();
+            for (member, score) in pairs(read_encoded(reader, decode)?, pos)? {
+                let score = score
+                    .parse()
+                    .ok()
+                    .filter(|score: &f64| !score.is_nan())
+                    .with_context(|| format!("Invalid score at offset {}", pos))?;
+                sorted_set.insert(member, score);
+            }
+            Value::SortedSet(sorted_set)
+        }
+        RDB_TYPE_HASH => {
+            let mut hash = HashMap::new();
+            for _ in 0..reader.read_length()? {
+                let field = reader.read_utf8()?;
+                hash.insert(field, reader.read_utf8()?);
+            }
+            Value::Hash(hash)
+        }
+        RDB_TYPE_HASH_ZIPLIST | RDB_TYPE_HASH_LISTPACK => {
+            let pos = reader.pos();
+            let decode = if type_id == RDB_TYPE_HASH_ZIPLIST {
+                ziplist::decode
+            } else {
+                listpack::decode
+            };
+            Value::Hash(pairs(read_encoded(reader, decode)?, pos)?.collect())
+        }
+        RDB_TYPE_STREAM_LISTPACKS | RDB_TYPE_STREAM_LISTPACKS_2 | RDB_TYPE_STREAM_LISTPACKS_3 => {
+            Value::Stream(read_stream(reader, type_id)?)
+        }
+        _ => {
+            return Err(anyhow!(
+                "Unknown RDB encoding type {} at offset {}",
+                type_id,
+                reader.pos() - 1
+            ))
+        }
+    };
+
+    Ok(value)
+}
+
+/// Reads a collection saved as a single string in one of the compact encodings. One with a
+/// binary string is noted and read as empty, since its key is skipped anyway.
+fn read_encoded(
+    reader: &mut Reader<'_>,
+    decode: fn(&[u8]) -> Result<Vec<String>>,
+) -> Result<Vec<String>> {
+    let pos = reader.pos();
+
+    match decode(&reader.read_string()?) {
+        Err(e) if e.is::<BinaryString>() => {
+            reader.binary = true;
+            Ok(Vec::new())
+        }
+        result => result.with_context(|| format!("Invalid encoding at offset {}", pos)),
+    }
+}
+
+/// Groups the elements of a flattened hash or sorted set into pairs.
+fn pairs(elements: Vec<String>, pos: usize) -> Result<impl Iterator<Item = (String, String)>> {
+    ensure!(
+        elements.len() % 2 == 0,
+        "Odd number of elements at offset {}",
+        pos
+    );
+
+    let mut elements = elements.into_iter();
+    Ok(std::iter::from_fn(move || {
+        Some((elements.next()?, elements.next()?))
+    }))
+}
+
+/// Reads a stream saved as listpacks keyed by the ID of their first entry, followed by its
+/// metadata and consumer groups. Each version of the type added to the metadata.
+fn read_stream(reader: &mut Reader<'_>, type_id: u8) -> Result<Stream> {
+    let mut entries = PersistentMap::new();
+
+    for _ in 0..reader.read_length()? {
+        let key = reader.read_string()?;
+        let master_id = decode_stream_id(&key).map_err(|_| reader.error("Invalid stream node"))?;
+
+        let pos = reader.pos();
+        match listpack::decode(&reader.read_string()?) {
+            Err(e) if e.is::<BinaryString>() => reader.binary = true,
+            elements => elements
+                .and_then(|elements| read_stream_node(master_id, &elements, &mut entries))
+                .with_context(|| format!("Invalid stream node at offset {}", pos))?,
+        }
+    }
+
+    let len = reader.read_length()?;
+    ensure!(
+        reader.binary || len == entries.len() as u64,
+        reader.error("Stream length doesn't match its entries")
+    );
+    let last_id = reader.read_stream_id()?;
+    let (max_deleted_id, entries_added) = if type_id == RDB_TYPE_STREAM_LISTPACKS {
+        // the first version didn't count entries ever added, which Redis then assumes were
+        // never deleted
+        (StreamId::MIN, len)
+    } else {
+        let _first_id = reader.read_stream_id()?;
+        (reader.read_stream_id()?, reader.read_length()?)
+    };
+
+    let mut groups = PersistentMap::new();
+    for _ in 0..reader.read_length()? {
+        let name = reader.read_utf8()?;
+        let last_delivered_id = reader.read_stream_id()?;
+        // an unknown count is saved as -1, and older versions didn't save it
+        let entries_read = match type_id {
+            RDB_TYPE_STREAM_LISTPACKS => None,
+            _ => Some(reader.read_length()?).filter(|&read| read != u64::MAX),
+        };
+
+        let mut pending = PersistentMap::new();
+        for _ in 0..reader.read_length()? {
+            let id = reader.read_raw_stream_id()?;
+            let delivery_time = reader.read_u64()?;
+            let delivery_count = reader.read_length()?;
+            let entry = PendingEntry {
+                consumer: String::new(),
+                delivery_time,
+                delivery_count,
+            };
+            pending.insert(id, entry);
+        }
+
+        let mut consumers = PersistentMap::new();
+        for _ in 0..reader.read_length()? {
+            let name = reader.read_utf8()?;
+            let seen_time = reader.read_u64()?;
+            // before it was saved, Redis took it to be the seen time
+            let active_time = match type_id {
+                RDB_TYPE_STREAM_LISTPACKS_3 => {
+                    Some(reader.read_u64()?).filter(|&time| time as i64 >= 0)
+                }
+                _ => Some(seen_time),
+            };
+
+            let mut consumer_pending = PersistentSet::new();
+            for _ in 0..reader.read_length()? {
+                let id = reader.read_raw_stream_id()?;
+                let entry = pending
+                    .get_mut(&id)
+                    .ok_or_else(|| reader.error("Consumer entry not in the group's PEL"))?;
+                entry.consumer = name.clone();
+                consumer_pending.insert(id);
+            }
+
+            consumers.insert(
+                name,
+                Consumer::restore(seen_time, active_time, consumer_pending),
+            );
+        }
+
+        ensure!(
+            pending.values().all(|entry| !entry.consumer.is_empty()),
+            reader.error("Group PEL entry without a consumer")
+        );
+        groups.insert(
+            name,
+            ConsumerGroup::restore(last_delivered_id, entries_read, pending, consumers),
+        );
+    }
+
+    Ok(Stream::restore(
+        entries,
+        last_id,
+        max_deleted_id,
+        entries_added,
+        groups,
+    ))
+}
+
+/// Reads the entries of a stream node: a master entry with the fields later entries may
+/// share, then entries whose IDs are relative to the node's.
+fn read_stream_node(
+    master_id: StreamId,
+    elements: &[String],
+    entries: &mut PersistentMap<StreamId, Fields>,
+) -> Result<()> {
+    let mut elements = elements.iter();
+
+    let count = next_int(&mut elements)?;
+    let deleted = next_int(&mut elements)?;
+    let master_fields = (0..next_int(&mut elements)?)
+        .map(|_| next(&mut elements).cloned())
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        next_int(&mut elements)? == 0,
+        "missing master entry terminator"
+    );
+
+    for _ in 0..count + deleted {
+        let flags = next_int(&mut elements)?;
+        let id = StreamId {
+            ms: master_id.ms.wrapping_add(next_int(&mut elements)? as u64),
+            seq: master_id.seq.wrapping_add(next_int(&mut elements)? as u64),
+        };
+
+        let mut fields = Vec::new();
+        if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            for field in &master_fields {
+                fields.push((field.clone(), next(&mut elements)?.clone()));
+            }
+        } else {
+            for _ in 0..next_int(&mut elements)? {
+                let field = next(&mut elements)?.clone();
+                fields.push((field, next(&mut elements)?.clone()));
+            }
+        }
+        next_int(&mut elements)?;
+
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.insert(id, fields);
+        }
+    }
+
+    ensure!(elements.next().is_none(), "unexpected elements");
+
+    Ok(())
+}
+
+fn next<'a>(elements: &mut std::slice::Iter<'a, String>) -> Result<&'a String> {
+    elements.next().context("missing elements")
+}
+
+fn next_int(elements: &mut std::slice::Iter<'_, String>) -> Result<i64> {
+    next(elements)?.parse().context("invalid integer")
+}
+
+fn decode_stream_id(bytes: &[u8]) -> Result<StreamId> {
+    ensure!(bytes.len() == 16, "invalid stream ID");
+
+    Ok(StreamId {
+        ms: u64::from_be_bytes(bytes[..8].try_into()?),
+        seq: u64::from_be_bytes(bytes[8..].try_into()?),
+    })
+}
+
+/// Redis never saves empty collections, but a file written by something else might have them.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::List(list) => list.is_empty(),
+        Value::Hash(hash) => hash.is_empty(),
+        Value::Set(set) => set.is_empty(),
+        Value::SortedSet(sorted_set) => sorted_set.is_empty(),
+        Value::String(_) | Value::Stream(_) => false,
+    }
+}
+
+fn string(bytes: Vec<u8>) -> Result<String> {
+    utf8(&bytes)
+}
+
+fn bulk(bytes: Vec<u8>) -> Result<Resp> {
+    Ok(Resp::BulkString(BulkString(Some(string(bytes)?))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::encode;
+    use crate::resp::bulk;
+    use crate::storage::NewStreamId;
+
+    #[test]
+    fn test_load() {
+        let mut storage = Storage::default();
+        storage.set(bulk("greeting"), bulk("hello"), None);
+        storage.set(bulk("counter"), bulk("-42"), Some(Duration::from_secs(60)));
+        storage.set(bulk("long"), bulk(&"x".repeat(20000)), None);
+        storage
+            .get_or_default_as_mut::<PersistentSet<String>>(&bulk("tags"))
+            .unwrap()
+            .insert("a".to_string());
+        storage
+            .get_or_default_as_mut::<SortedSet>(&bulk("scores"))
+            .unwrap()
+            .insert("a".to_string(), f64::INFINITY);
+        storage
+            .get_or_default_as_mut::<PersistentSet<String>>(&bulk("numbers"))
+            .unwrap()
+            .extend(["1".to_string(), "-70000".to_string()]);
+        let list = (0..1000).map(|i| i.to_string().repeat(5)).collect();
+        storage.restore(bulk("list"), Value::List(list), None);
+        let hash = HashMap::from([("f".to_string(), "v".to_string())]);
+        storage.restore(bulk("hash"), Value::Hash(hash), None);
+
+        let stream = storage
+            .get_or_default_as_mut::<Stream>(&bulk("events"))
+            .unwrap();
+        for seq in 1..=150 {
+            let id = NewStreamId::Explicit(StreamId { ms: 1, seq });
+            let fields = if seq % 2 == 0 {
+                vec![("f".to_string(), seq.to_string())]
+            } else {
+                vec![
+                    ("g".to_string(), "x".to_string()),
+                    ("h".to_string(), "y".to_string()),
+                ]
+            };
+            stream.add(id, fields).unwrap();
+        }
+        stream.create_group("group", StreamId::MIN, None);
+        stream.read_group_new("group", "alice", Some(3), false, 1000);
+
+        let rdb = encode(&storage.snapshot());
+        let mut loaded = Storage::default();
+        load(&rdb, &mut loaded).unwrap();
+
+        assert_eq!(loaded.get(&bulk("greeting")).unwrap(), Some(&bulk("hello")));
+        assert_eq!(loaded.get(&bulk("counter")).unwrap(), Some(&bulk("-42")));
+        assert_eq!(
+            loaded.get(&bulk("long")).unwrap(),
+            Some(&bulk(&"x".repeat(20000)))
+        );
+        assert!(loaded
+            .get_as::<PersistentSet<String>>(&bulk("tags"))
+            .unwrap()
+            .unwrap()
+            .contains("a"));
+        assert_eq!(
+            loaded
+                .get_as::<SortedSet>(&bulk("scores"))
+                .unwrap()
+                .unwrap()
+                .score("a"),
+            Some(f64::INFINITY)
+        );
+
+        assert_eq!(
+            loaded
+                .get_as::<PersistentSet<String>>(&bulk("numbers"))
+                .unwrap()
+                .unwrap()
+                .len(),
+            2
+        );
+        let Some(Value::List(list)) = loaded.get_value(&bulk("list")) else {
+            panic!("not a list");
+        };
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list[999], "999999999999999");
+        let Some(Value::Hash(hash)) = loaded.get_value(&bulk("hash")) else {
+            panic!("not a hash");
+        };
+        assert_eq!(hash["f"], "v");
+
+        let stream = loaded.get_as::<Stream>(&bulk("events")).unwrap().unwrap();
+        assert_eq!(stream.len(), 150);
+        assert_eq!(stream.last_id(), StreamId { ms: 1, seq: 150 });
+        assert_eq!(
+            stream.range(StreamId::MIN, StreamId::MAX, false, None)[1],
+            (
+                &StreamId { ms: 1, seq: 2 },
+                &vec![("f".to_string(), "2".to_string())]
+            )
+        );
+        let group = stream.group("group").unwrap();
+        assert_eq!(group.pending().len(), 3);
+        assert_eq!(group.consumers()["alice"].pending().len(), 3);
+
+        // a flipped bit fails the checksum
+        let mut corrupt = rdb.clone();
+        corrupt[20] ^= 1;
+        assert!(load(&corrupt, &mut Storage::default()).is_err());
+
+        let error = load(&rdb[..rdb.len() - 20], &mut Storage::default()).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Failed reading the key at offset"));
+        assert!(error
+            .root_cause()
+            .to_string()
+            .starts_with("Unexpected end of file"));
+    }
+
+    #[test]
+    fn test_load_encodings() {
+        let mut rdb = b"REDIS0006".to_vec();
+        // a key that expired, in seconds
+        rdb.extend_from_slice(b"\xfd\x01\x00\x00\x00\x00\x03old\x01v");
+        // an integer, and an LZF compressed string
+        rdb.extend_from_slice(b"\x00\x03int\xc1\x39\x30");
+        rdb.extend_from_slice(b"\x00\x03lzf\xc3\x08\x0c\x00a\xe0\x00\x00\x01bc");
+        // a sorted set with scores as strings
+        rdb.extend_from_slice(b"\x03\x01z\x02\x01a\x031.5\x01b\xff");
+        // a hash and a list of ziplists, as saved before Redis 7, and a set of integers
+        let ziplist = b"\x11\x11\0\0\0\x0d\0\0\0\x02\0\0\x01f\x03\x01v\xff";
+        rdb.extend_from_slice(b"\x0d\x01h");
+        rdb.extend_from_slice(ziplist);
+        rdb.extend_from_slice(b"\x0e\x01l\x01");
+        rdb.extend_from_slice(ziplist);
+        rdb.extend_from_slice(b"\x0b\x01s\x0c\x02\0\0\0\x02\0\0\0\x01\0\x05\0");
+        rdb.push(RDB_OPCODE_EOF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut storage = Storage::default();
+        load(&rdb, &mut storage).unwrap();
+
+        assert_eq!(storage.get(&bulk("old")).unwrap(), None);
+        assert_eq!(storage.get(&bulk("int")).unwrap(), Some(&bulk("12345")));
+        assert_eq!(
+            storage.get(&bulk("lzf")).unwrap(),
+            Some(&bulk("aaaaaaaaaabc"))
+        );
+        let sorted_set = storage.get_as::<SortedSet>(&bulk("z")).unwrap().unwrap();
+        assert_eq!(sorted_set.score("a"), Some(1.5));
+        assert_eq!(sorted_set.score("b"), Some(f64::NEG_INFINITY));
+        let Some(Value::Hash(hash)) = storage.get_value(&bulk("h")) else {
+            panic!("not a hash");
+        };
+        assert_eq!(hash["f"], "v");
+        let Some(Value::List(list)) = storage.get_value(&bulk("l")) else {
+            panic!("not a list");
+        };
+        assert_eq!(list, &["f", "v"]);
+        let set = storage
+            .get_as::<PersistentSet<String>>(&bulk("s"))
+            .unwrap()
+            .unwrap();
+        assert!(set.contains("1") && set.contains("5"));
+
+        // Redis 7.4 writes version 12
+        let mut rdb = b"REDIS0012\x00\x01k\x01v".to_vec();
+        rdb.push(RDB_OPCODE_EOF);
+        rdb.extend_from_slice(&[0; 8]);
+        let mut storage = Storage::default();
+        load(&rdb, &mut storage).unwrap();
+        assert_eq!(storage.get(&bulk("k")).unwrap(), Some(&bulk("v")));
+
+        let error = load(b"REDIS0013", &mut Storage::default()).unwrap_err();
+        assert_eq!(error.to_string(), "Can't handle RDB format version 13");
+
+        // a corrupt length isn't trusted to allocate the decompressed string
+        let mut rdb = b"REDIS0011\x00\x03lzf\xc3\x08\x81".to_vec();
+        rdb.extend_from_slice(&[0xff; 8]);
+        rdb.extend_from_slice(b"\x00a\xe0\x00\x00\x01bc");
+        let error = load(&rdb, &mut Storage::default()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Failed reading the key at offset 9: invalid LZF compressed string at offset 25"
+        );
+    }
+
+    #[test]
+    fn test_load_other_databases() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend_from_slice(b"\xfe\x01\x00\x05other\x01v");
+        rdb.extend_from_slice(b"\xfe\x00\x00\x04kept\x01v");
+        rdb.push(RDB_OPCODE_EOF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut storage = Storage::default();
+        load(&rdb, &mut storage).unwrap();
+        assert_eq!(storage.get(&bulk("other")).unwrap(), None);
+        assert_eq!(storage.get(&bulk("kept")).unwrap(), Some(&bulk("v")));
+
+        // binary strings can't be kept as they are, so their keys are skipped
+        let mut rdb = b"REDIS0011\x00\x01k\x02\xff\xfe".to_vec();
+        rdb.extend_from_slice(b"\x00\x02\xff\xfe\x01v");
+        rdb.extend_from_slice(b"\x02\x01l\x02\x01a\x02\xff\xfe");
+        // a set as a listpack of one binary string
+        rdb.extend_from_slice(b"\x14\x01s\x0a\x0a\0\0\0\x01\0\x81\xff\x02\xff");
+        rdb.extend_from_slice(b"\x00\x04kept\x01v");
+        rdb.push(RDB_OPCODE_EOF);
+        rdb.extend_from_slice(&[0; 8]);
+
+        let mut storage = Storage::default();
+        load(&rdb, &mut storage).unwrap();
+        assert_eq!(storage.snapshot().entries().count(), 1);
+        assert_eq!(storage.get(&bulk("kept")).unwrap(), Some(&bulk("v")));
+    }
+}
@@ -0,0 +1,424 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::rdb::listpack::Listpack;
+use crate::rdb::{
+    encode_length, encode_string, intset, parse_integer, QUICKLIST_NODE_CONTAINER_PACKED,
+    RDB_OPCODE_AUX, RDB_OPCODE_EOF, RDB_OPCODE_EXPIRETIME_MS, RDB_OPCODE_RESIZEDB,
+    RDB_OPCODE_SELECTDB, RDB_TYPE_HASH, RDB_TYPE_HASH_LISTPACK, RDB_TYPE_LIST_QUICKLIST_2,
+    RDB_TYPE_SET, RDB_TYPE_SET_INTSET, RDB_TYPE_SET_LISTPACK, RDB_TYPE_STREAM_LISTPACKS_3,
+    RDB_TYPE_STRING, RDB_TYPE_ZSET_2, RDB_TYPE_ZSET_LISTPACK, RDB_VERSION,
+    STREAM_ITEM_FLAG_SAMEFIELDS,
+};
+use crate::resp::Resp;
+use crate::storage::{
+    format_score, PersistentSet, Snapshot, SortedSet, Stream, StreamId, Value,
+    STREAM_NODE_MAX_ENTRIES,
+};
+use crate::utils::crc64;
+
+/// The version of Redis whose RDB files these are.
+const REDIS_VERSION: &str = "7.2.0";
+
+/// The limits under which Redis keeps collections in compact encodings, by default. Sets,
+/// sorted sets and hashes have the same listpack limits.
+const SET_MAX_INTSET_ENTRIES: usize = 512;
+const MAX_LISTPACK_ENTRIES: usize = 128;
+const MAX_LISTPACK_VALUE: usize = 64;
+const LIST_MAX_LISTPACK_SIZE: usize = 8192;
+
+/// Serializes the whole dataset and the function libraries, with the aux fields Redis writes
+/// and a CRC-64 of the file at the end.
+pub fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let mut rdb = format!("REDIS{:04}", RDB_VERSION).into_bytes();
+
+    let ctime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for (name, value) in [
+        ("redis-ver", REDIS_VERSION.to_string()),
+        ("redis-bits", "64".to_string()),
+        ("ctime", ctime.to_string()),
+        ("aof-base", "0".to_string()),
+    ] {
+        rdb.push(RDB_OPCODE_AUX);
+        encode_string(name.as_bytes(), &mut rdb);
+        encode_string(value.as_bytes(), &mut rdb);
+    }
+
+    snapshot.functions.encode(&mut rdb);
+
+    let entries = snapshot.entries().collect::<Vec<_>>();
+    if !entries.is_empty() {
+        rdb.push(RDB_OPCODE_SELECTDB);
+        encode_length(0, &mut rdb);
+
+        let expires = entries.iter().filter(|(_, _, expiry)| expiry.is_some());
+        rdb.push(RDB_OPCODE_RESIZEDB);
+        encode_length(entries.len() as u64, &mut rdb);
+        encode_length(expires.count() as u64, &mut rdb);
+    }
+
+    for (key, value, expiry) in entries {
+        if let Some(expiry) = expiry {
+            rdb.push(RDB_OPCODE_EXPIRETIME_MS);
+            rdb.extend_from_slice(&unix_ms(expiry).to_le_bytes());
+        }
+        encode_entry(key, value, &mut rdb);
+    }
+
+    rdb.push(RDB_OPCODE_EOF);
+    let checksum = crc64(&rdb);
+    rdb.extend_from_slice(&checksum.to_le_bytes());
+
+    rdb
+}
+
+fn encode_entry(key: &Resp, value: &Value, rdb: &mut Vec<u8>) {
+    // the type depends on the encoding the value gets, so it is filled in after
+    let type_pos = rdb.len();
+    rdb.push(0);
+    encode_resp(key, rdb);
+
+    rdb[type_pos] = match value {
+        Value::String(s) => {
+            encode_resp(s, rdb);
+            RDB_TYPE_STRING
+        }
+        Value::List(list) => encode_list(list, rdb),
+        Value::Hash(hash) => encode_hash(hash, rdb),
+        Value::Set(set) => encode_set(set, rdb),
+        Value::SortedSet(sorted_set) => encode_sorted_set(sorted_set, rdb),
+        Value::Stream(stream) => {
+            encode_stream(stream, rdb);
+            RDB_TYPE_STREAM_LISTPACKS_3
+        }
+    };
+}
+
+fn encode_resp(resp: &Resp, rdb: &mut Vec<u8>) {
+    encode_string(resp.plain_string().unwrap_or_default().as_bytes(), rdb);
+}
+
+/// Lists are saved as quicklists, whose nodes are listpacks of up to
+/// [`LIST_MAX_LISTPACK_SIZE`] bytes.
+fn encode_list(list: &VecDeque<String>, rdb: &mut Vec<u8>) -> u8 {
+    let mut nodes = Vec::new();
+    let mut node = Listpack::default();
+    for element in list {
+        if !node.is_empty() && node.size() + element.len() > LIST_MAX_LISTPACK_SIZE {
+            nodes.push(std::mem::take(&mut node));
+        }
+        node.push_str(element);
+    }
+    nodes.push(node);
+
+    encode_length(nodes.len() as u64, rdb);
+    for node in nodes {
+        encode_length(QUICKLIST_NODE_CONTAINER_PACKED, rdb);
+        encode_raw(&node.into_bytes(), rdb);
+    }
+
+    RDB_TYPE_LIST_QUICKLIST_2
+}
+
+fn encode_hash(hash: &HashMap<String, String>, rdb: &mut Vec<u8>) -> u8 {
+    if fits_listpack(
+        hash.len(),
+        hash.iter()
+            .flat_map(|(field, value)| [field.as_str(), value]),
+    ) {
+        let mut listpack = Listpack::default();
+        for (field, value) in hash {
+            listpack.push_str(field);
+            listpack.push_str(value);
+        }
+        encode_raw(&listpack.into_bytes(), rdb);
+
+        return RDB_TYPE_HASH_LISTPACK;
+    }
+
+    encode_length(hash.len() as u64, rdb);
+    for (field, value) in hash {
+        encode_string(field.as_bytes(), rdb);
+        encode_string(value.as_bytes(), rdb);
+    }
+
+    RDB_TYPE_HASH
+}
+
+/// Sets of integers are saved as intsets, and other small sets as listpacks.
+fn encode_set(set: &PersistentSet<String>, rdb: &mut Vec<u8>) -> u8 {
+    if set.len() <= SET_MAX_INTSET_ENTRIES {
+        let ints = set.iter().map(|member| parse_integer(member.as_bytes()));
+        if let Some(ints) = ints.collect::<Option<Vec<_>>>() {
+            encode_raw(&intset::encode(ints), rdb);
+            return RDB_TYPE_SET_INTSET;
+        }
+    }
+
+    if fits_listpack(set.len(), set.iter().map(String::as_str)) {
+        let mut listpack = Listpack::default();
+        for member in set {
+            listpack.push_str(member);
+        }
+        encode_raw(&listpack.into_bytes(), rdb);
+
+        return RDB_TYPE_SET_LISTPACK;
+    }
+
+    encode_length(set.len() as u64, rdb);
+    for member in set {
+        encode_string(member.as_bytes(), rdb);
+    }
+
+    RDB_TYPE_SET
+}
+
+/// Small sorted sets are saved as listpacks of members and scores in order, and others with
+/// binary scores.
+fn encode_sorted_set(sorted_set: &SortedSet, rdb: &mut Vec<u8>) -> u8 {
+    let members = sorted_set.iter().map(|(member, _)| member);
+    if fits_listpack(sorted_set.len(), members) {
+        let mut listpack = Listpack::default();
+        for (member, score) in sorted_set.iter() {
+            listpack.push_str(member);
+            listpack.push_str(&format_score(score));
+        }
+        encode_raw(&listpack.into_bytes(), rdb);
+
+        return RDB_TYPE_ZSET_LISTPACK;
+    }
+
+    encode_length(sorted_set.len() as u64, rdb);
+    for (member, score) in sorted_set.iter() {
+        encode_string(member.as_bytes(), rdb);
+        rdb.extend_from_slice(&score.to_le_bytes());
+    }
+
+    RDB_TYPE_ZSET_2
+}
+
+fn fits_listpack<'a>(len: usize, elements: impl IntoIterator<Item = &'a str>) -> bool {
+    len <= MAX_LISTPACK_ENTRIES
+        && elements
+            .into_iter()
+            .all(|element| element.len() <= MAX_LISTPACK_VALUE)
+}
+
+/// Streams are saved as the radix tree Redis keeps them in: listpacks of up to
+/// [`STREAM_NODE_MAX_ENTRIES`] entries keyed by the ID of their first entry, followed by the
+/// stream's metadata and its consumer groups.
+fn encode_stream(stream: &Stream, rdb: &mut Vec<u8>) {
+    let entries = stream.range(StreamId::MIN, StreamId::MAX, false, None);
+
+    let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+    encode_length(nodes.len() as u64, rdb);
+
+    for node in nodes {
+        let (&master_id, master_fields) = node[0];
+
+        // the node starts with the fields of its first entry, which others may share
+        let mut listpack = Listpack::default();
+        listpack.push_int(node.len() as i64);
+        listpack.push_int(0);
+        listpack.push_int(master_fields.len() as i64);
+        for (field, _) in master_fields {
+            listpack.push_str(field);
+        }
+        listpack.push_int(0);
+
+        for &(id, fields) in node {
+            let same_fields = fields.len() == master_fields.len()
+                && fields
+                    .iter()
+                    .zip(master_fields)
+                    .all(|((field, _), (master_field, _))| field == master_field);
+
+            listpack.push_int(if same_fields {
+                STREAM_ITEM_FLAG_SAMEFIELDS
+            } else {
+                0
+            });
+            listpack.push_int(id.ms.wrapping_sub(master_id.ms) as i64);
+            listpack.push_int(id.seq.wrapping_sub(master_id.seq) as i64);
+
+            if same_fields {
+                for (_, value) in fields {
+                    listpack.push_str(value);
+                }
+            } else {
+                listpack.push_int(fields.len() as i64);
+                for (field, value) in fields {
+                    listpack.push_str(field);
+                    listpack.push_str(value);
+                }
+            }
+
+            // the number of elements of the entry before this one, to walk it backwards
+            let elements = if same_fields {
+                fields.len()
+            } else {
+                2 * fields.len() + 1
+            };
+            listpack.push_int(elements as i64 + 3);
+        }
+
+        encode_raw(&encode_stream_id(master_id), rdb);
+        encode_raw(&listpack.into_bytes(), rdb);
+    }
+
+    let first_id = stream.first_entry().map_or(StreamId::MIN, |(&id, _)| id);
+    encode_length(stream.len() as u64, rdb);
+    for id in [stream.last_id(), first_id, stream.max_deleted_id()] {
+        encode_length(id.ms, rdb);
+        encode_length(id.seq, rdb);
+    }
+    encode_length(stream.entries_added(), rdb);
+
+    encode_length(stream.groups().len() as u64, rdb);
+    for (name, group) in stream.groups() {
+        encode_string(name.as_bytes(), rdb);
+        encode_length(group.last_delivered_id().ms, rdb);
+        encode_length(group.last_delivered_id().seq, rdb);
+        // an unknown count is saved as -1
+        encode_length(group.entries_read().unwrap_or(u64::MAX), rdb);
+
+        encode_length(group.pending().len() as u64, rdb);
+        for (id, pending) in group.pending() {
+            rdb.extend_from_slice(&encode_stream_id(*id));
+            rdb.extend_from_slice(&pending.delivery_time.to_le_bytes());
+            encode_length(pending.delivery_count, rdb);
+        }
+
+        encode_length(group.consumers().len() as u64, rdb);
+        for (name, consumer) in group.consumers() {
+            encode_string(name.as_bytes(), rdb);
+            rdb.extend_from_slice(&consumer.seen_time.to_le_bytes());
+            let active_time = consumer.active_time.map_or(-1, |time| time as i64);
+            rdb.extend_from_slice(&active_time.to_le_bytes());
+
+            // the entries themselves are in the group's list
+            encode_length(consumer.pending().len() as u64, rdb);
+            for id in consumer.pending() {
+                rdb.extend_from_slice(&encode_stream_id(*id));
+            }
+        }
+    }
+}
+
+/// IDs are keys of the radix tree, so they are saved big-endian to sort bytewise.
+fn encode_stream_id(id: StreamId) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&id.ms.to_be_bytes());
+    bytes[8..].copy_from_slice(&id.seq.to_be_bytes());
+
+    bytes
+}
+
+/// Writes bytes as a string, which they never are an integer encoding of.
+fn encode_raw(bytes: &[u8], rdb: &mut Vec<u8>) {
+    encode_length(bytes.len() as u64, rdb);
+    rdb.extend_from_slice(bytes);
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::resp::bulk;
+    use crate::storage::{NewStreamId, Storage};
+
+    fn contains(rdb: &[u8], bytes: &[u8]) -> bool {
+        rdb.windows(bytes.len()).any(|window| window == bytes)
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut storage = Storage::default();
+        storage.set(bulk("greeting"), bulk("hello"), None);
+        storage.set(bulk("counter"), bulk("42"), Some(Duration::from_secs(60)));
+        storage
+            .get_or_default_as_mut::<SortedSet>(&bulk("scores"))
+            .unwrap()
+            .insert("a".to_string(), 1.5);
+
+        let stream = storage
+            .get_or_default_as_mut::<Stream>(&bulk("events"))
+            .unwrap();
+        for (seq, value) in [(0, "v"), (1, "w")] {
+            let id = NewStreamId::Explicit(StreamId { ms: 1, seq });
+            stream
+                .add(id, vec![("f".to_string(), value.to_string())])
+                .unwrap();
+        }
+
+        let rdb = encode(&storage.snapshot());
+
+        assert!(rdb.starts_with(b"REDIS0011\xfa\x09redis-ver\x057.2.0"));
+        let (body, checksum) = rdb.split_at(rdb.len() - 8);
+        assert_eq!(crc64(body).to_le_bytes(), checksum);
+
+        assert!(contains(&rdb, b"\xfe\x00\xfb\x04\x01"));
+        assert!(contains(&rdb, b"\x00\x08greeting\x05hello"));
+        assert!(contains(&rdb, b"\x00\x07counter\xc0\x2a"));
+
+        // a small sorted set is a listpack of members and scores
+        assert!(contains(
+            &rdb,
+            b"\x11\x06scores\x0f\x0f\0\0\0\x02\0\x81a\x02\x831.5\x04\xff"
+        ));
+
+        let mut stream = b"\x15\x06events\x01\x10".to_vec();
+        stream.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        stream.extend_from_slice(&[40, 40, 0, 0, 0, 15, 0]);
+        // the node: the count of entries, of deleted ones, and the master fields
+        stream.extend_from_slice(&[2, 1, 0, 1, 1, 1, 0x81, b'f', 2, 0, 1]);
+        // entries with the same fields only store their values
+        stream.extend_from_slice(&[2, 1, 0, 1, 0, 1, 0x81, b'v', 2, 4, 1]);
+        stream.extend_from_slice(&[2, 1, 0, 1, 1, 1, 0x81, b'w', 2, 4, 1, 0xff]);
+        // length, last, first and max deleted IDs, entries added and groups
+        stream.extend_from_slice(&[2, 1, 1, 1, 0, 0, 0, 2, 0]);
+        assert!(contains(&rdb, &stream));
+    }
+
+    #[test]
+    fn test_encode_collections() {
+        let encode = |value: Value| {
+            let mut rdb = Vec::new();
+            encode_entry(&bulk("k"), &value, &mut rdb);
+            rdb
+        };
+
+        let set = |members: &[String]| Value::Set(members.iter().cloned().collect());
+        let ints = (0..3).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(encode(set(&ints))[..4], [RDB_TYPE_SET_INTSET, 1, b'k', 14]);
+        let strings = ["a".to_string(), "b".to_string()];
+        assert_eq!(encode(set(&strings))[0], RDB_TYPE_SET_LISTPACK);
+        let many = (0..600).map(|i| format!("m{}", i)).collect::<Vec<_>>();
+        assert_eq!(encode(set(&many))[0], RDB_TYPE_SET);
+
+        let mut sorted_set = SortedSet::default();
+        sorted_set.insert("x".repeat(65), 1.0);
+        assert_eq!(encode(Value::SortedSet(sorted_set))[0], RDB_TYPE_ZSET_2);
+
+        let hash = HashMap::from([("f".to_string(), "1".to_string())]);
+        assert_eq!(
+            encode(Value::Hash(hash)),
+            b"\x10\x01k\x0c\x0c\0\0\0\x02\0\x81f\x02\x01\x01\xff"
+        );
+
+        // list nodes are split once they reach 8KB
+        let list = (0..1000).map(|_| "x".repeat(20)).collect();
+        let rdb = encode(Value::List(list));
+        assert_eq!(rdb[..4], [RDB_TYPE_LIST_QUICKLIST_2, 1, b'k', 3]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Ziplists, which listpacks replaced in Redis 7. Files saved by older versions still have
+//! them, so they are only read.
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// Reads the elements of a ziplist, with integers as their decimal strings.
+pub fn decode(bytes: &[u8]) -> Result<Vec<String>> {
+    const INVALID: &str = "invalid ziplist";
+
+    ensure!(bytes.len() >= 11 && bytes.last() == Some(&0xff), INVALID);
+    let total = u32::from_le_bytes(bytes[..4].try_into()?) as usize;
+    let len = u16::from_le_bytes(bytes[8..10].try_into()?);
+    ensure!(total == bytes.len(), INVALID);
+
+    let mut elements = Vec::new();
+    let mut rest = &bytes[10..bytes.len() - 1];
+    while !rest.is_empty() {
+        // each entry starts with the size of the one before it
+        let prevlen_size = if rest[0] < 0xfe { 1 } else { 5 };
+        let entry = rest.get(prevlen_size..).context(INVALID)?;
+        let bytes_at = |range: std::ops::Range<usize>| entry.get(range).context(INVALID);
+
+        let first = *entry.first().context(INVALID)?;
+        let (element, size) = match first >> 6 {
+            0 => {
+                let len = (first & 0x3f) as usize;
+                (super::utf8(bytes_at(1..1 + len)?)?, 1 + len)
+            }
+            1 => {
+                let len = ((first as usize & 0x3f) << 8) | bytes_at(1..2)?[0] as usize;
+                (super::utf8(bytes_at(2..2 + len)?)?, 2 + len)
+            }
+            2 => {
+                let len = u32::from_be_bytes(bytes_at(1..5)?.try_into()?) as usize;
+                (super::utf8(bytes_at(5..5 + len)?)?, 5 + len)
+            }
+            _ => match first {
+                0xc0 => (
+                    i16::from_le_bytes(bytes_at(1..3)?.try_into()?).to_string(),
+                    3,
+                ),
+                0xd0 => (
+                    i32::from_le_bytes(bytes_at(1..5)?.try_into()?).to_string(),
+                    5,
+                ),
+                0xe0 => (
+                    i64::from_le_bytes(bytes_at(1..9)?.try_into()?).to_string(),
+                    9,
+                ),
+                0xf0 => {
+                    let mut i = [0; 4];
+                    i[1..].copy_from_slice(bytes_at(1..4)?);
+                    // shifting back extends the sign of the 24 bits
+                    ((i32::from_le_bytes(i) >> 8).to_string(), 4)
+                }
+                0xfe => ((bytes_at(1..2)?[0] as i8).to_string(), 2),
+                // integers from 0 to 12 are stored in the encoding byte, off by one
+                0xf1..=0xfd => (((first & 0x0f) - 1).to_string(), 1),
+                _ => bail!(INVALID),
+            },
+        };
+
+        elements.push(element);
+        rest = &entry[size..];
+    }
+
+    // the count saturates, and has to be walked when it does
+    ensure!(len == u16::MAX || len as usize == elements.len(), INVALID);
+
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut bytes = vec![0, 0, 0, 0, 0, 0, 0, 0, 4, 0];
+        bytes.extend_from_slice(&[0, 0x02, b'a', b'b']); // "ab"
+        bytes.extend_from_slice(&[4, 0xf6]); // 5, in the encoding byte
+        bytes.extend_from_slice(&[2, 0xfe, 0x9c]); // -100
+        bytes.extend_from_slice(&[3, 0xc0, 0x10, 0x27]); // 10000
+        bytes.push(0xff);
+        let total = bytes.len() as u32;
+        bytes[..4].copy_from_slice(&total.to_le_bytes());
+
+        assert_eq!(decode(&bytes).unwrap(), ["ab", "5", "-100", "10000"]);
+
+        bytes[8] = 5;
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Result};
+
+use crate::aof;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Resp, SimpleString};
+use crate::storage::Storage;
+
+/// Rewrites the AOF in the background, so that it has one base file with the dataset as it is
+/// now, and the writes from then on.
+pub async fn bgrewriteaof(
+    _args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    ensure!(
+        aof::start_rewrite(&storage.read().unwrap())?,
+        "Background append only file rewriting already in progress"
+    );
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString(
+            "Background append only file rewriting started".to_string(),
+        ))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use super::zset_ops::{pop_first_non_empty, score_reply};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{block_on_keys, parse_timeout, Storage};
+
+pub async fn bzpopmin(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    bzpop(args, storage, false).await
+}
+
+pub async fn bzpopmax(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    bzpop(args, storage, true).await
+}
+
+async fn bzpop(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+    max: bool,
+) -> Result<RespEffect<'static>> {
+    let timeout = parse_timeout(&args.pop_back().context("missing timeout")?)?;
+
+    ensure!(!args.is_empty(), "missing key");
+
+    let keys = Vec::from(args);
+
+    let popped = block_on_keys(storage, &keys, timeout, |storage| {
+        pop_first_non_empty(storage, &keys, 1, max)
+    })
+    .await?;
+
+    let reply = match popped {
+        None => Resp::NullArray,
+        Some((key, popped)) => {
+            let (member, score) = popped.into_iter().next().context("empty sorted set")?;
+
+            Resp::Array(Array(vec![
+                key,
+                Resp::BulkString(BulkString(Some(member))),
+                score_reply(score),
+            ]))
+        }
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,25 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::Result;
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, Storage};
+
+pub async fn del(args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'static>> {
+    let mut storage = storage.write().unwrap();
+
+    let mut removed = 0;
+    for key in &args {
+        if storage.remove(key) {
+            storage.notify(KeyspaceEvents::GENERIC, "del", key);
+            removed += 1;
+        }
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(removed))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,35 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::scripting::{self, parse_keys_and_args, Script};
+use crate::storage::Storage;
+
+pub async fn eval(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let source = args.pop_front().context("missing script")?;
+    let (keys, args) = parse_keys_and_args(args)?;
+
+    let mut storage = storage.write().unwrap();
+
+    // the script is cached, so that EVALSHA can run it afterwards
+    let (sha, script) = storage.scripts.load(source.plain_string()?)?;
+    let read_only = script.read_only;
+    let reply = scripting::run(
+        &mut storage,
+        Script::Eval { sha, script },
+        keys,
+        args,
+        read_only,
+    )?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
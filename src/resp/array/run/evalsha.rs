@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::scripting::{self, parse_keys_and_args, Script};
+use crate::storage::Storage;
+
+pub async fn evalsha(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let sha = args.pop_front().context("missing SHA1")?;
+    let sha = sha.plain_string()?.to_lowercase();
+    let (keys, args) = parse_keys_and_args(args)?;
+
+    let mut storage = storage.write().unwrap();
+
+    let script = storage
+        .scripts
+        .get(&sha)
+        .context("NOSCRIPT No matching script. Please use EVAL.")?;
+    let read_only = script.read_only;
+    let reply = scripting::run(
+        &mut storage,
+        Script::Eval { sha, script },
+        keys,
+        args,
+        read_only,
+    )?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
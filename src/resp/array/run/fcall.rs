@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::scripting::{self, parse_keys_and_args, Script};
+use crate::storage::Storage;
+
+pub async fn fcall(args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'static>> {
+    call(args, storage, false)
+}
+
+/// Like `FCALL`, for functions registered with the `no-writes` flag only.
+pub async fn fcall_ro(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    call(args, storage, true)
+}
+
+fn call(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+    ro_command: bool,
+) -> Result<RespEffect<'static>> {
+    let name = args.pop_front().context("missing function name")?;
+    let name = name.plain_string()?.to_string();
+    let (keys, args) = parse_keys_and_args(args)?;
+
+    let mut storage = storage.write().unwrap();
+
+    let library = storage
+        .functions
+        .find(&name)
+        .cloned()
+        .context("Function not found")?;
+    let read_only = library.functions[&name].is_read_only();
+
+    ensure!(
+        read_only || !ro_command,
+        "Can not execute a script with write flag using *_ro command."
+    );
+    // replicas only take writes from their master
+    ensure!(
+        read_only || !storage.replication.is_replica(),
+        "READONLY You can't write against a read only replica."
+    );
+
+    let script = Script::Function { library, name };
+    let reply = scripting::run(&mut storage, script, keys, args, read_only)?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
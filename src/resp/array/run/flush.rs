@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Resp, SimpleString};
+use crate::storage::Storage;
+
+/// There is a single database, so `FLUSHALL` and `FLUSHDB` are the same.
+pub async fn flushall(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    flush(args, storage)
+}
+
+pub async fn flushdb(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    flush(args, storage)
+}
+
+fn flush(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'static>> {
+    // flushing is always synchronous, so both modes are accepted
+    if let Some(mode) = args.pop_front() {
+        match mode.plain_string()?.to_uppercase().as_str() {
+            "ASYNC" | "SYNC" => {}
+            _ => bail!("syntax error"),
+        }
+    }
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    storage.write().unwrap().flush();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString("OK".to_string()))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp, SimpleString};
+use crate::scripting::{Library, RestorePolicy, ScriptKind};
+use crate::storage::Storage;
+use crate::utils::glob_match;
+
+pub async fn function(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let subcommand = args.pop_front().context("missing subcommand")?;
+    let subcommand = subcommand.plain_string()?.to_uppercase();
+
+    let reply = match subcommand.as_str() {
+        "DELETE" => {
+            let name = args.pop_front().context("missing library name")?;
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'function|delete' command"
+            );
+
+            let mut storage = storage.write().unwrap();
+            storage.functions.delete(name.plain_string()?)?;
+            storage.propagate();
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        // the payload is binary, so each byte goes in a char of its own
+        "DUMP" => {
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'function|dump' command"
+            );
+
+            let payload = storage.read().unwrap().functions.dump();
+            let payload = payload.into_iter().map(char::from).collect();
+
+            Resp::BulkString(BulkString(Some(payload)))
+        }
+        "FLUSH" => {
+            // libraries are always flushed synchronously, so both modes are accepted
+            match args.pop_front() {
+                None => {}
+                Some(mode) => match mode.plain_string()?.to_uppercase().as_str() {
+                    "ASYNC" | "SYNC" => {}
+                    _ => bail!("FUNCTION FLUSH only supports SYNC|ASYNC option"),
+                },
+            }
+            ensure!(args.is_empty(), "syntax error");
+
+            let mut storage = storage.write().unwrap();
+            storage.functions.flush();
+            storage.propagate();
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        // like SCRIPT KILL, this only gets here when no function is running
+        "KILL" => {
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'function|kill' command"
+            );
+
+            storage
+                .read()
+                .unwrap()
+                .scripts
+                .control
+                .kill(ScriptKind::Function)?;
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        "LIST" => list(args, storage)?,
+        "LOAD" => {
+            let replace = match args.len() {
+                1 => false,
+                2 => {
+                    let option = args.pop_front().unwrap();
+                    match option.plain_string()?.to_uppercase().as_str() {
+                        "REPLACE" => true,
+                        option => bail!("Unknown option given: {}", option),
+                    }
+                }
+                _ => bail!("wrong number of arguments for 'function|load' command"),
+            };
+            let code = args.pop_front().unwrap();
+
+            // loading runs the library, which doesn't need the storage
+            let library = Library::load(code.plain_string()?)?;
+            let name = library.name.clone();
+            let mut storage = storage.write().unwrap();
+            storage.functions.insert(library, replace)?;
+            storage.propagate();
+
+            Resp::BulkString(BulkString(Some(name)))
+        }
+        "RESTORE" => {
+            let payload = args.pop_front().context("missing payload")?;
+            let payload = payload
+                .plain_string()?
+                .chars()
+                .map(u8::try_from)
+                .collect::<Result<Vec<_>, _>>()
+                .context("payload version or checksum are wrong")?;
+
+            let policy = match args.pop_front() {
+                None => RestorePolicy::Append,
+                Some(policy) => match policy.plain_string()?.to_uppercase().as_str() {
+                    "APPEND" => RestorePolicy::Append,
+                    "FLUSH" => RestorePolicy::Flush,
+                    "REPLACE" => RestorePolicy::Replace,
+                    _ => bail!("Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE."),
+                },
+            };
+            ensure!(args.is_empty(), "syntax error");
+
+            let mut storage = storage.write().unwrap();
+            storage.functions.restore(&payload, policy)?;
+            storage.propagate();
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        _ => bail!("unknown subcommand {}", subcommand),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
+
+/// `FUNCTION LIST [LIBRARYNAME pattern] [WITHCODE]`
+fn list(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<Resp> {
+    let mut pattern = None;
+    let mut with_code = false;
+
+    while let Some(arg) = args.pop_front() {
+        match arg.plain_string()?.to_uppercase().as_str() {
+            "LIBRARYNAME" => {
+                let arg = args
+                    .pop_front()
+                    .context("library name argument was not given")?;
+                pattern = Some(arg.plain_string()?.to_string());
+            }
+            "WITHCODE" => with_code = true,
+            option => bail!("Unknown argument {}", option),
+        }
+    }
+
+    let bulk = |s: &str| Resp::BulkString(BulkString(Some(s.to_string())));
+
+    let storage = storage.read().unwrap();
+    let libraries = storage
+        .functions
+        .libraries()
+        .filter(|library| {
+            pattern
+                .as_ref()
+                .map_or(true, |pattern| glob_match(pattern, &library.name))
+        })
+        .map(|library| {
+            let functions = library
+                .functions
+                .iter()
+                .map(|(name, info)| {
+                    Resp::Array(Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("description"),
+                        Resp::BulkString(BulkString(info.description.clone())),
+                        bulk("flags"),
+                        Resp::Array(Array(info.flags.iter().map(|flag| bulk(flag)).collect())),
+                    ]))
+                })
+                .collect();
+
+            let mut fields = vec![
+                bulk("library_name"),
+                bulk(&library.name),
+                bulk("engine"),
+                bulk("LUA"),
+                bulk("functions"),
+                Resp::Array(Array(functions)),
+            ];
+            if with_code {
+                fields.extend([bulk("library_code"), bulk(&library.code)]);
+            }
+
+            Resp::Array(Array(fields))
+        })
+        .collect();
+
+    Ok(Resp::Array(Array(libraries)))
+}
@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::storage::geo::{self, GeoPoint, GeoShape, GeoShapeKind};
+use crate::storage::SortedSet;
+
+/// Parses a distance unit into how many meters make one unit.
+pub fn parse_unit(unit: &Resp) -> Result<f64> {
+    match unit.plain_string()?.to_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => bail!("unsupported unit provided. please use M, KM, FT, MI"),
+    }
+}
+
+pub fn parse_f64(arg: &Resp) -> Result<f64> {
+    arg.plain_string()?
+        .parse::<f64>()
+        .ok()
+        .filter(|f| !f.is_nan())
+        .context("value is not a valid float")
+}
+
+/// Parses a `longitude latitude` pair, rejecting positions that can't be indexed.
+pub fn parse_position(args: &mut VecDeque<Resp>) -> Result<(f64, f64)> {
+    let longitude = parse_f64(&args.pop_front().context("missing longitude")?)?;
+    let latitude = parse_f64(&args.pop_front().context("missing latitude")?)?;
+
+    ensure!(
+        geo::is_valid_position(longitude, latitude),
+        "invalid longitude,latitude pair {:.6},{:.6}",
+        longitude,
+        latitude
+    );
+
+    Ok((longitude, latitude))
+}
+
+/// Replies with a coordinate the way Redis formats it: 17 decimals without trailing zeros.
+pub fn coordinate_reply(coordinate: f64) -> Resp {
+    let formatted = format!("{:.17}", coordinate);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+
+    Resp::BulkString(BulkString(Some(formatted.to_string())))
+}
+
+pub fn position_reply(longitude: f64, latitude: f64) -> Resp {
+    Resp::Array(Array(vec![
+        coordinate_reply(longitude),
+        coordinate_reply(latitude),
+    ]))
+}
+
+pub fn distance_reply(distance: f64) -> Resp {
+    Resp::BulkString(BulkString(Some(format!("{:.4}", distance))))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFrom {
+    Member(String),
+    LonLat(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sort {
+    Asc,
+    Desc,
+}
+
+/// The arguments shared by `GEOSEARCH` and `GEOSEARCHSTORE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearch {
+    pub from: GeoFrom,
+    pub kind: GeoShapeKind,
+    pub conversion: f64,
+    pub sort: Option<Sort>,
+    pub count: Option<usize>,
+    pub any: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+    pub with_coord: bool,
+    pub store_dist: bool,
+}
+
+impl GeoSearch {
+    /// Parses `FROMMEMBER member | FROMLONLAT longitude latitude`,
+    /// `BYRADIUS radius unit | BYBOX width height unit`, `[ASC | DESC]`, `[COUNT count [ANY]]`
+    /// and either `[WITHCOORD] [WITHDIST] [WITHHASH]`, or `[STOREDIST]` when storing.
+    pub fn parse(args: &mut VecDeque<Resp>, store: bool) -> Result<Self> {
+        let mut from = None;
+        let mut shape = None;
+        let mut sort = None;
+        let mut count = None;
+        let mut any = false;
+        let mut with_dist = false;
+        let mut with_hash = false;
+        let mut with_coord = false;
+        let mut store_dist = false;
+        let mut from_count = 0;
+        let mut by_count = 0;
+
+        while let Some(option) = args.pop_front() {
+            match option.plain_string()?.to_uppercase().as_str() {
+                "FROMMEMBER" => {
+                    let member = args.pop_front().context("missing FROMMEMBER member")?;
+                    from = Some(GeoFrom::Member(member.plain_string()?.to_string()));
+                    from_count += 1;
+                }
+                "FROMLONLAT" => {
+                    let (longitude, latitude) = parse_position(args)?;
+                    from = Some(GeoFrom::LonLat(longitude, latitude));
+                    from_count += 1;
+                }
+                "BYRADIUS" => {
+                    let radius = parse_f64(&args.pop_front().context("missing radius")?)?;
+                    ensure!(radius >= 0.0, "radius cannot be negative");
+                    let conversion = parse_unit(&args.pop_front().context("missing unit")?)?;
+                    shape = Some((GeoShapeKind::Radius(radius), conversion));
+                    by_count += 1;
+                }
+                "BYBOX" => {
+                    let width = parse_f64(&args.pop_front().context("missing width")?)?;
+                    let height = parse_f64(&args.pop_front().context("missing height")?)?;
+                    ensure!(
+                        width >= 0.0 && height >= 0.0,
+                        "height or width cannot be negative"
+                    );
+                    let conversion = parse_unit(&args.pop_front().context("missing unit")?)?;
+                    shape = Some((GeoShapeKind::Box { width, height }, conversion));
+                    by_count += 1;
+                }
+                "ASC" => sort = Some(Sort::Asc),
+                "DESC" => sort = Some(Sort::Desc),
+                "COUNT" => {
+                    let value = args
+                        .pop_front()
+                        .context("missing COUNT value")?
+                        .plain_i64()?;
+                    ensure!(value > 0, "COUNT must be > 0");
+                    count = Some(value as usize);
+
+                    if args.front().is_some_and(|arg| {
+                        arg.plain_string()
+                            .is_ok_and(|arg| arg.eq_ignore_ascii_case("ANY"))
+                    }) {
+                        args.pop_front();
+                        any = true;
+                    }
+                }
+                "WITHDIST" if !store => with_dist = true,
+                "WITHHASH" if !store => with_hash = true,
+                "WITHCOORD" if !store => with_coord = true,
+                "STOREDIST" if store => store_dist = true,
+                _ => bail!("syntax error"),
+            }
+        }
+
+        ensure!(
+            from_count == 1,
+            "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+        );
+        ensure!(
+            by_count == 1,
+            "exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH"
+        );
+
+        let (from, (kind, conversion)) = from.zip(shape).context("syntax error")?;
+
+        // without ordering, COUNT would return arbitrary points instead of the nearest ones
+        if count.is_some() && sort.is_none() && !any {
+            sort = Some(Sort::Asc);
+        }
+
+        Ok(GeoSearch {
+            from,
+            kind,
+            conversion,
+            sort,
+            count,
+            any,
+            with_dist,
+            with_hash,
+            with_coord,
+            store_dist,
+        })
+    }
+
+    /// Searches `set`, returning the points to reply with, sorted and truncated to COUNT.
+    pub fn run(&self, set: &SortedSet) -> Result<Vec<GeoPoint>> {
+        let (longitude, latitude) = match &self.from {
+            GeoFrom::LonLat(longitude, latitude) => (*longitude, *latitude),
+            GeoFrom::Member(member) => geo::decode(
+                set.score(member)
+                    .context("could not decode requested zset member")?,
+            ),
+        };
+
+        let shape = GeoShape {
+            longitude,
+            latitude,
+            kind: self.kind,
+            conversion: self.conversion,
+        };
+
+        let mut points = geo::search(set, &shape, self.count.filter(|_| self.any));
+
+        match self.sort {
+            None => {}
+            Some(Sort::Asc) => points.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(Sort::Desc) => points.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        }
+
+        if let Some(count) = self.count {
+            points.truncate(count);
+        }
+
+        Ok(points)
+    }
+
+    pub fn reply(&self, points: &[GeoPoint]) -> Resp {
+        let with_any = self.with_dist || self.with_hash || self.with_coord;
+
+        let replies = points
+            .iter()
+            .map(|point| {
+                let member = Resp::BulkString(BulkString(Some(point.member.clone())));
+                if !with_any {
+                    return member;
+                }
+
+                let mut reply = vec![member];
+                if self.with_dist {
+                    reply.push(distance_reply(point.distance / self.conversion));
+                }
+                if self.with_hash {
+                    reply.push(Resp::Integer(Integer(point.score as i64)));
+                }
+                if self.with_coord {
+                    reply.push(position_reply(point.longitude, point.latitude));
+                }
+
+                Resp::Array(Array(reply))
+            })
+            .collect();
+
+        Resp::Array(Array(replies))
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::geo_ops::parse_position;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{geo, KeyspaceEvents, SortedSet, Storage};
+
+pub async fn geoadd(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let mut nx = false;
+    let mut xx = false;
+    let mut ch = false;
+
+    while let Some(option) = args.front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "CH" => ch = true,
+            _ => break,
+        }
+        args.pop_front();
+    }
+
+    ensure!(
+        !(nx && xx),
+        "XX and NX options at the same time are not compatible"
+    );
+
+    if args.is_empty() || args.len() % 3 != 0 {
+        bail!("syntax error");
+    }
+
+    let mut members = Vec::with_capacity(args.len() / 3);
+    while !args.is_empty() {
+        let (longitude, latitude) = parse_position(&mut args)?;
+        let member = args.pop_front().context("missing member")?;
+        // the position was validated, so it can always be encoded
+        let score = geo::encode(longitude, latitude).context("invalid position")?;
+
+        members.push((score, member.plain_string()?.to_string()));
+    }
+
+    let mut storage = storage.write().unwrap();
+    // XX only updates, so there is nothing to do without the key
+    if xx && storage.get_as::<SortedSet>(&key)?.is_none() {
+        return Ok(RespEffect {
+            run_result: RespRunResult::Owned(Resp::Integer(Integer(0))),
+            post_run_cmd: None,
+        });
+    }
+    let set = storage.get_or_default_as_mut::<SortedSet>(&key)?;
+
+    let mut added = 0;
+    let mut changed = 0;
+
+    for (score, member) in members {
+        match set.score(&member) {
+            None if xx => continue,
+            Some(_) if nx => continue,
+            Some(old_score) if old_score != score => changed += 1,
+            _ => {}
+        }
+
+        if set.insert(member, score) {
+            added += 1;
+        }
+    }
+    // GEOADD is ZADD underneath, and its event says so
+    if added + changed > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::ZSET, "zadd", &key);
+    }
+
+    let reply = if ch { added + changed } else { added };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(reply))),
+        post_run_cmd: None,
+    })
+}
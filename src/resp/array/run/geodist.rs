@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::geo_ops::{distance_reply, parse_unit};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{BulkString, Resp};
+use crate::storage::{geo, SortedSet, Storage};
+
+pub async fn geodist(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let member1 = args.pop_front().context("missing member")?;
+    let member2 = args.pop_front().context("missing member")?;
+    let conversion = match args.pop_front() {
+        None => 1.0,
+        Some(unit) => parse_unit(&unit)?,
+    };
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let storage = storage.read().unwrap();
+    let set = storage.get_as::<SortedSet>(&key)?;
+
+    let score = |member: &Resp| -> Result<Option<f64>> {
+        Ok(set.and_then(|set| set.score(member.plain_string().ok()?)))
+    };
+
+    let reply = match (score(&member1)?, score(&member2)?) {
+        (Some(score1), Some(score2)) => {
+            let (longitude1, latitude1) = geo::decode(score1);
+            let (longitude2, latitude2) = geo::decode(score2);
+
+            distance_reply(geo::distance(longitude1, latitude1, longitude2, latitude2) / conversion)
+        }
+        _ => Resp::BulkString(BulkString(None)),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
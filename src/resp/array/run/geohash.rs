@@ -0,0 +1,34 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{geo, SortedSet, Storage};
+
+pub async fn geohash(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let storage = storage.read().unwrap();
+    let set = storage.get_as::<SortedSet>(&key)?;
+
+    let hashes = args
+        .iter()
+        .map(|member| {
+            let hash = set
+                .and_then(|set| set.score(member.plain_string().ok()?))
+                .and_then(geo::hash_string);
+
+            Resp::BulkString(BulkString(hash))
+        })
+        .collect();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(hashes))),
+        post_run_cmd: None,
+    })
+}
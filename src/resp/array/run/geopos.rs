@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::geo_ops::position_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, Resp};
+use crate::storage::{geo, SortedSet, Storage};
+
+pub async fn geopos(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let storage = storage.read().unwrap();
+    let set = storage.get_as::<SortedSet>(&key)?;
+
+    let positions = args
+        .iter()
+        .map(|member| {
+            let score = set.and_then(|set| set.score(member.plain_string().ok()?));
+
+            match score {
+                None => Resp::NullArray,
+                Some(score) => {
+                    let (longitude, latitude) = geo::decode(score);
+                    position_reply(longitude, latitude)
+                }
+            }
+        })
+        .collect();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(positions))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,28 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::geo_ops::GeoSearch;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, Resp};
+use crate::storage::{SortedSet, Storage};
+
+pub async fn geosearch(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let search = GeoSearch::parse(&mut args, false)?;
+
+    let storage = storage.read().unwrap();
+    let reply = match storage.get_as::<SortedSet>(&key)? {
+        None => Resp::Array(Array(vec![])),
+        Some(set) => search.reply(&search.run(set)?),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
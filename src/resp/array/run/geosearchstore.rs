@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::geo_ops::GeoSearch;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, SortedSet, Storage};
+
+pub async fn geosearchstore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let destination = args.pop_front().context("missing destination")?;
+    let source = args.pop_front().context("missing source")?;
+    let search = GeoSearch::parse(&mut args, true)?;
+
+    let mut storage = storage.write().unwrap();
+    let mut result = SortedSet::default();
+    if let Some(set) = storage.get_as::<SortedSet>(&source)? {
+        for point in search.run(set)? {
+            let score = if search.store_dist {
+                point.distance / search.conversion
+            } else {
+                point.score
+            };
+
+            result.insert(point.member, score);
+        }
+    }
+    let len = result.len();
+
+    storage.insert_as(destination.clone(), result);
+    if len > 0 {
+        storage.notify(KeyspaceEvents::ZSET, "geosearchstore", &destination);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
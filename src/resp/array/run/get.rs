@@ -8,7 +8,7 @@ use crate::resp::resp_effect::{RespEffect, RespRunResult, RwLockReadGuardedResp}
 use crate::resp::{BulkString, Resp};
 use crate::storage::Storage;
 
-pub async fn get(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect> {
+pub async fn get(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
     let key = args.pop_front().context("missing key")?;
 
     if !args.is_empty() {
@@ -16,7 +16,7 @@ pub async fn get(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<
     }
 
     let lock = storage.read().unwrap();
-    let Some(value) = lock.get(&key) else {
+    let Some(value) = lock.get(&key)? else {
         return Ok(RespEffect {
             run_result: RespRunResult::Owned(Resp::BulkString(BulkString(None))),
             post_run_cmd: None,
@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
 use std::sync::RwLock;
+use std::time::UNIX_EPOCH;
 
 use anyhow::{anyhow, bail, Result};
 
@@ -19,6 +20,7 @@ pub async fn info(
 
     let s = match info_target.to_lowercase().as_str() {
         "replication" => storage.read().unwrap().replication.info(),
+        "persistence" => persistence(&storage.read().unwrap()),
         _ => bail!("unsupported info target: {}", info_target),
     };
 
@@ -27,3 +29,59 @@ pub async fn info(
         post_run_cmd: None,
     })
 }
+
+fn persistence(storage: &Storage) -> String {
+    let saves = &storage.saves;
+    let last_save = saves
+        .last_save()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut fields = vec![
+        "loading:0".to_string(),
+        format!(
+            "rdb_changes_since_last_save:{}",
+            saves.changes_since_last_save(storage.dirty())
+        ),
+        format!("rdb_bgsave_in_progress:{}", saves.in_progress() as u8),
+        format!("rdb_last_save_time:{}", last_save),
+        format!(
+            "rdb_last_bgsave_status:{}",
+            if saves.last_background_ok() {
+                "ok"
+            } else {
+                "err"
+            }
+        ),
+        format!("aof_enabled:{}", storage.aof.is_some() as u8),
+        format!(
+            "aof_rewrite_in_progress:{}",
+            storage
+                .aof
+                .as_ref()
+                .is_some_and(|aof| aof.rewrite_in_progress()) as u8
+        ),
+        format!(
+            "aof_last_bgrewrite_status:{}",
+            match &storage.aof {
+                Some(aof) if !aof.last_rewrite_ok() => "err",
+                _ => "ok",
+            }
+        ),
+        format!(
+            "aof_last_write_status:{}",
+            match &storage.aof {
+                Some(aof) if !aof.last_write_ok() => "err",
+                _ => "ok",
+            }
+        ),
+    ];
+    // like in Redis, the sizes are only there when the AOF is on
+    if let Some(aof) = &storage.aof {
+        fields.push(format!("aof_current_size:{}", aof.current_size()));
+        fields.push(format!("aof_base_size:{}", aof.base_size()));
+    }
+
+    fields.join("\n")
+}
@@ -0,0 +1,23 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Result;
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::Storage;
+
+/// The Unix time of the last successful save, or of the server start if there was none.
+pub async fn lastsave(
+    _args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let last_save = storage.read().unwrap().saves.last_save();
+    let seconds = last_save.duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(seconds as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -1,34 +1,403 @@
 use std::collections::VecDeque;
 use std::sync::RwLock;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 
-use crate::resp::{Array, RespEffect, RespRunnable};
+use crate::resp::{Array, Resp, RespEffect, RespRunnable};
 use crate::storage::Storage;
 
+mod bgrewriteaof;
+mod bzpop;
+mod del;
 mod echo;
+mod eval;
+mod evalsha;
+mod fcall;
+mod flush;
+mod function;
+mod geo_ops;
+mod geoadd;
+mod geodist;
+mod geohash;
+mod geopos;
+mod geosearch;
+mod geosearchstore;
 mod get;
 mod info;
+mod lastsave;
 mod ping;
 mod psync;
+mod publish;
+mod pubsub;
 mod replconf;
+mod sadd;
+mod save;
+mod scard;
+mod script;
+mod sdiff;
+mod sdiffstore;
 mod set;
+mod set_ops;
+mod shutdown;
+mod sinter;
+mod sintercard;
+mod sinterstore;
+mod sismember;
+mod smembers;
+mod smismember;
+mod smove;
+mod spop;
+mod srandmember;
+mod srem;
+mod sscan;
+mod stream_ops;
+mod sunion;
+mod sunionstore;
+mod unwatch;
+mod xack;
+mod xadd;
+mod xautoclaim;
+mod xclaim;
+mod xdel;
+mod xgroup;
+mod xinfo;
+mod xlen;
+mod xpending;
+mod xrange;
+mod xread;
+mod xreadgroup;
+mod xrevrange;
+mod xsetid;
+mod xtrim;
+mod zadd;
+mod zcard;
+mod zcount;
+mod zdiffstore;
+mod zincrby;
+mod zinterstore;
+mod zmpop;
+mod zmscore;
+mod zpop;
+mod zrange;
+mod zrangestore;
+mod zrank;
+mod zrem;
+mod zrevrank;
+mod zscore;
+mod zset_ops;
+mod zunionstore;
+
+/// The arity of every command, as in Redis' command table: a positive arity is the exact number
+/// of arguments, a negative one the minimum. Both count the command name itself.
+const ARITIES: &[(&str, i64)] = &[
+    ("BGREWRITEAOF", 1),
+    ("BGSAVE", -1),
+    ("BZPOPMAX", -3),
+    ("BZPOPMIN", -3),
+    ("CLIENT", -2),
+    ("DEL", -2),
+    ("DISCARD", 1),
+    ("ECHO", 2),
+    ("EVAL", -3),
+    ("EVALSHA", -3),
+    ("EXEC", 1),
+    ("FCALL", -3),
+    ("FCALL_RO", -3),
+    ("FLUSHALL", -1),
+    ("FLUSHDB", -1),
+    ("FUNCTION", -2),
+    ("GEOADD", -5),
+    ("GEODIST", -4),
+    ("GEOHASH", -2),
+    ("GEOPOS", -2),
+    ("GEOSEARCH", -7),
+    ("GEOSEARCHSTORE", -8),
+    ("GET", 2),
+    ("INFO", -1),
+    ("LASTSAVE", 1),
+    ("MULTI", 1),
+    ("PING", -1),
+    ("PSUBSCRIBE", -2),
+    ("PSYNC", -3),
+    ("PUBLISH", 3),
+    ("PUBSUB", -2),
+    ("PUNSUBSCRIBE", -1),
+    ("REPLCONF", -1),
+    ("SADD", -3),
+    ("SAVE", 1),
+    ("SCARD", 2),
+    ("SCRIPT", -2),
+    ("SDIFF", -2),
+    ("SDIFFSTORE", -3),
+    ("SET", -3),
+    ("SHUTDOWN", -1),
+    ("SINTER", -2),
+    ("SINTERCARD", -3),
+    ("SINTERSTORE", -3),
+    ("SISMEMBER", 3),
+    ("SMEMBERS", 2),
+    ("SMISMEMBER", -3),
+    ("SMOVE", 4),
+    ("SPOP", -2),
+    ("SPUBLISH", 3),
+    ("SRANDMEMBER", -2),
+    ("SREM", -3),
+    ("SSCAN", -3),
+    ("SSUBSCRIBE", -2),
+    ("SUBSCRIBE", -2),
+    ("SUNION", -2),
+    ("SUNIONSTORE", -3),
+    ("SUNSUBSCRIBE", -1),
+    ("UNSUBSCRIBE", -1),
+    ("UNWATCH", 1),
+    ("WATCH", -2),
+    ("XACK", -4),
+    ("XADD", -5),
+    ("XAUTOCLAIM", -6),
+    ("XCLAIM", -6),
+    ("XDEL", -3),
+    ("XGROUP", -2),
+    ("XINFO", -2),
+    ("XLEN", 2),
+    ("XPENDING", -3),
+    ("XRANGE", -4),
+    ("XREAD", -4),
+    ("XREADGROUP", -7),
+    ("XREVRANGE", -4),
+    ("XSETID", -3),
+    ("XTRIM", -4),
+    ("ZADD", -4),
+    ("ZCARD", 2),
+    ("ZCOUNT", 4),
+    ("ZDIFFSTORE", -4),
+    ("ZINCRBY", 4),
+    ("ZINTERSTORE", -4),
+    ("ZMPOP", -4),
+    ("ZMSCORE", -3),
+    ("ZPOPMAX", -2),
+    ("ZPOPMIN", -2),
+    ("ZRANGE", -4),
+    ("ZRANGESTORE", -5),
+    ("ZRANK", -3),
+    ("ZREM", -3),
+    ("ZREVRANK", -3),
+    ("ZSCORE", 3),
+    ("ZUNIONSTORE", -4),
+];
+
+/// The commands that may modify the storage.
+const WRITE_COMMANDS: &[&str] = &[
+    "BZPOPMAX",
+    "BZPOPMIN",
+    "DEL",
+    "FLUSHALL",
+    "FLUSHDB",
+    "GEOADD",
+    "GEOSEARCHSTORE",
+    "SADD",
+    "SDIFFSTORE",
+    "SET",
+    "SINTERSTORE",
+    "SMOVE",
+    "SPOP",
+    "SREM",
+    "SUNIONSTORE",
+    "XACK",
+    "XADD",
+    "XAUTOCLAIM",
+    "XCLAIM",
+    "XDEL",
+    "XGROUP",
+    "XREADGROUP",
+    "XSETID",
+    "XTRIM",
+    "ZADD",
+    "ZDIFFSTORE",
+    "ZINCRBY",
+    "ZINTERSTORE",
+    "ZMPOP",
+    "ZPOPMAX",
+    "ZPOPMIN",
+    "ZRANGESTORE",
+    "ZREM",
+    "ZUNIONSTORE",
+];
+
+/// The read-only commands that read their first key, for the clients tracking the keys they read.
+const FIRST_KEY_READ_COMMANDS: &[&str] = &[
+    "GEODIST",
+    "GEOHASH",
+    "GEOPOS",
+    "GEOSEARCH",
+    "GET",
+    "SCARD",
+    "SISMEMBER",
+    "SMEMBERS",
+    "SMISMEMBER",
+    "SRANDMEMBER",
+    "SSCAN",
+    "XLEN",
+    "XRANGE",
+    "XREVRANGE",
+    "ZCARD",
+    "ZCOUNT",
+    "ZMSCORE",
+    "ZRANGE",
+    "ZRANK",
+    "ZREVRANK",
+    "ZSCORE",
+];
+
+/// The read-only commands all of whose arguments are keys.
+const ALL_KEYS_READ_COMMANDS: &[&str] = &["SDIFF", "SINTER", "SUNION"];
+
+impl Array {
+    /// Checks that this is a known command with a valid number of arguments, without running it.
+    pub fn check_command(&self) -> Result<()> {
+        let cmd = self.0.first().context("empty array")?;
+        let plain_cmd = cmd.plain_string().context("invalid command")?;
+        let upper_cmd = plain_cmd.to_uppercase();
+
+        let Some(&(_, arity)) = ARITIES.iter().find(|(name, _)| *name == upper_cmd) else {
+            let args = self.0[1..]
+                .iter()
+                .map(|arg| format!("'{}' ", arg.plain_string().unwrap_or_default()))
+                .collect::<String>();
+            bail!(
+                "unknown command '{}', with args beginning with: {}",
+                plain_cmd,
+                args
+            );
+        };
+
+        let len = self.0.len() as i64;
+        ensure!(
+            if arity >= 0 {
+                len == arity
+            } else {
+                len >= -arity
+            },
+            "wrong number of arguments for '{}' command",
+            plain_cmd.to_lowercase()
+        );
+
+        Ok(())
+    }
+
+    pub fn is_write_command(&self) -> bool {
+        self.0
+            .first()
+            .and_then(|cmd| cmd.plain_string().ok())
+            .is_some_and(|cmd| WRITE_COMMANDS.contains(&cmd.to_uppercase().as_str()))
+    }
+
+    /// The keys a read-only command reads.
+    pub fn read_keys(&self) -> Vec<Resp> {
+        let Some(cmd) = self.0.first().and_then(|cmd| cmd.plain_string().ok()) else {
+            return Vec::new();
+        };
+        let cmd = cmd.to_uppercase();
+
+        if FIRST_KEY_READ_COMMANDS.contains(&cmd.as_str()) {
+            self.0.iter().skip(1).take(1).cloned().collect()
+        } else if ALL_KEYS_READ_COMMANDS.contains(&cmd.as_str()) {
+            self.0[1..].to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+}
 
 impl RespRunnable for Array {
-    async fn run(self, storage: &RwLock<Storage>) -> Result<RespEffect> {
+    async fn run(self, storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
         let mut deque = VecDeque::from(self.0);
         let cmd = deque.pop_front().context("empty array")?;
 
         let plain_cmd = cmd.plain_string().context("invalid command")?;
 
         match plain_cmd.to_uppercase().as_str() {
+            "BGREWRITEAOF" => bgrewriteaof::bgrewriteaof(deque, storage).await,
+            "BGSAVE" => save::bgsave(deque, storage).await,
+            "BZPOPMAX" => bzpop::bzpopmax(deque, storage).await,
+            "BZPOPMIN" => bzpop::bzpopmin(deque, storage).await,
+            "DEL" => del::del(deque, storage).await,
             "ECHO" => echo::echo(deque).await,
+            "EVAL" => eval::eval(deque, storage).await,
+            "EVALSHA" => evalsha::evalsha(deque, storage).await,
+            "FCALL" => fcall::fcall(deque, storage).await,
+            "FCALL_RO" => fcall::fcall_ro(deque, storage).await,
+            "FLUSHALL" => flush::flushall(deque, storage).await,
+            "FLUSHDB" => flush::flushdb(deque, storage).await,
+            "FUNCTION" => function::function(deque, storage).await,
+            "GEOADD" => geoadd::geoadd(deque, storage).await,
+            "GEODIST" => geodist::geodist(deque, storage).await,
+            "GEOHASH" => geohash::geohash(deque, storage).await,
+            "GEOPOS" => geopos::geopos(deque, storage).await,
+            "GEOSEARCH" => geosearch::geosearch(deque, storage).await,
+            "GEOSEARCHSTORE" => geosearchstore::geosearchstore(deque, storage).await,
             "GET" => get::get(deque, storage).await,
             "INFO" => info::info(deque, storage).await,
+            "LASTSAVE" => lastsave::lastsave(deque, storage).await,
             "PING" => ping::ping(deque).await,
             "PSYNC" => psync::psync(deque, storage).await,
+            "PUBLISH" => publish::publish(deque, storage).await,
+            "PUBSUB" => pubsub::pubsub(deque, storage).await,
             "REPLCONF" => replconf::replconf(deque).await,
+            "SADD" => sadd::sadd(deque, storage).await,
+            "SAVE" => save::save(deque, storage).await,
+            "SCARD" => scard::scard(deque, storage).await,
+            "SCRIPT" => script::script(deque, storage).await,
+            "SDIFF" => sdiff::sdiff(deque, storage).await,
+            "SDIFFSTORE" => sdiffstore::sdiffstore(deque, storage).await,
             "SET" => set::set(deque, storage).await,
+            "SHUTDOWN" => shutdown::shutdown(deque, storage).await,
+            "SINTER" => sinter::sinter(deque, storage).await,
+            "SINTERCARD" => sintercard::sintercard(deque, storage).await,
+            "SINTERSTORE" => sinterstore::sinterstore(deque, storage).await,
+            "SISMEMBER" => sismember::sismember(deque, storage).await,
+            "SMEMBERS" => smembers::smembers(deque, storage).await,
+            "SMISMEMBER" => smismember::smismember(deque, storage).await,
+            "SMOVE" => smove::smove(deque, storage).await,
+            "SPOP" => spop::spop(deque, storage).await,
+            "SPUBLISH" => publish::spublish(deque, storage).await,
+            "SRANDMEMBER" => srandmember::srandmember(deque, storage).await,
+            "SREM" => srem::srem(deque, storage).await,
+            "SSCAN" => sscan::sscan(deque, storage).await,
+            "SUNION" => sunion::sunion(deque, storage).await,
+            "SUNIONSTORE" => sunionstore::sunionstore(deque, storage).await,
+            "UNWATCH" => unwatch::unwatch(deque).await,
+            "XACK" => xack::xack(deque, storage).await,
+            "XADD" => xadd::xadd(deque, storage).await,
+            "XAUTOCLAIM" => xautoclaim::xautoclaim(deque, storage).await,
+            "XCLAIM" => xclaim::xclaim(deque, storage).await,
+            "XDEL" => xdel::xdel(deque, storage).await,
+            "XGROUP" => xgroup::xgroup(deque, storage).await,
+            "XINFO" => xinfo::xinfo(deque, storage).await,
+            "XLEN" => xlen::xlen(deque, storage).await,
+            "XPENDING" => xpending::xpending(deque, storage).await,
+            "XRANGE" => xrange::xrange(deque, storage).await,
+            "XREAD" => xread::xread(deque, storage).await,
+            "XREADGROUP" => xreadgroup::xreadgroup(deque, storage).await,
+            "XREVRANGE" => xrevrange::xrevrange(deque, storage).await,
+            "XSETID" => xsetid::xsetid(deque, storage).await,
+            "XTRIM" => xtrim::xtrim(deque, storage).await,
+            "ZADD" => zadd::zadd(deque, storage).await,
+            "ZCARD" => zcard::zcard(deque, storage).await,
+            "ZCOUNT" => zcount::zcount(deque, storage).await,
+            "ZDIFFSTORE" => zdiffstore::zdiffstore(deque, storage).await,
+            "ZINCRBY" => zincrby::zincrby(deque, storage).await,
+            "ZINTERSTORE" => zinterstore::zinterstore(deque, storage).await,
+            "ZMPOP" => zmpop::zmpop(deque, storage).await,
+            "ZMSCORE" => zmscore::zmscore(deque, storage).await,
+            "ZPOPMAX" => zpop::zpopmax(deque, storage).await,
+            "ZPOPMIN" => zpop::zpopmin(deque, storage).await,
+            "ZRANGE" => zrange::zrange(deque, storage).await,
+            "ZRANGESTORE" => zrangestore::zrangestore(deque, storage).await,
+            "ZRANK" => zrank::zrank(deque, storage).await,
+            "ZREM" => zrem::zrem(deque, storage).await,
+            "ZREVRANK" => zrevrank::zrevrank(deque, storage).await,
+            "ZSCORE" => zscore::zscore(deque, storage).await,
+            "ZUNIONSTORE" => zunionstore::zunionstore(deque, storage).await,
             _ => bail!("unknown command {}", plain_cmd),
         }
     }
@@ -7,7 +7,7 @@ use crate::resp::resp_effect::{PostRespRunCommand, RespEffect, RespRunResult};
 use crate::resp::{Resp, SimpleString};
 use crate::storage::Storage;
 
-pub async fn psync(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect> {
+pub async fn psync(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
     let replid = args.pop_front().context("missing replid")?;
     let replid = replid.plain_string()?;
 
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::Storage;
+
+pub async fn publish(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let channel = args.pop_front().context("missing channel")?;
+    let message = args.pop_front().context("missing message")?;
+
+    let receivers = storage
+        .read()
+        .unwrap()
+        .pubsub
+        .publish(channel.plain_string()?, message.plain_string()?);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(receivers as i64))),
+        post_run_cmd: None,
+    })
+}
+
+/// Like `PUBLISH`, for shard channels.
+pub async fn spublish(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let channel = args.pop_front().context("missing channel")?;
+    let message = args.pop_front().context("missing message")?;
+
+    let receivers = storage
+        .read()
+        .unwrap()
+        .pubsub
+        .spublish(channel.plain_string()?, message.plain_string()?);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(receivers as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::storage::Storage;
+
+pub async fn pubsub(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let subcommand = args.pop_front().context("missing subcommand")?;
+    let subcommand = subcommand.plain_string()?.to_uppercase();
+
+    let storage = storage.read().unwrap();
+
+    let reply = match subcommand.as_str() {
+        "CHANNELS" | "SHARDCHANNELS" => {
+            let pattern = args.pop_front();
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'pubsub|{}' command",
+                subcommand.to_lowercase()
+            );
+            let pattern = pattern.as_ref().map(Resp::plain_string).transpose()?;
+
+            let channels = if subcommand == "CHANNELS" {
+                storage.pubsub.channels(pattern)
+            } else {
+                storage.pubsub.shard_channels(pattern)
+            };
+            let channels = channels
+                .into_iter()
+                .map(|channel| Resp::BulkString(BulkString(Some(channel.to_string()))))
+                .collect();
+
+            Resp::Array(Array(channels))
+        }
+        "NUMPAT" => {
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'pubsub|numpat' command"
+            );
+
+            Resp::Integer(Integer(storage.pubsub.num_patterns() as i64))
+        }
+        // a flat list of channels, each followed by its number of subscribers
+        "NUMSUB" | "SHARDNUMSUB" => {
+            let counts = args
+                .into_iter()
+                .map(|channel| {
+                    let name = channel.plain_string()?;
+                    let count = if subcommand == "NUMSUB" {
+                        storage.pubsub.num_subscribers(name)
+                    } else {
+                        storage.pubsub.shard_num_subscribers(name)
+                    };
+                    Ok([channel, Resp::Integer(Integer(count as i64))])
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Resp::Array(Array(counts.into_iter().flatten().collect()))
+        }
+        _ => bail!("unknown subcommand {}", subcommand),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
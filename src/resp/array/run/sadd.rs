@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, PersistentSet, Storage};
+
+pub async fn sadd(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    ensure!(!args.is_empty(), "missing member");
+
+    let members = args
+        .iter()
+        .map(|member| member.plain_string().map(str::to_string))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut storage = storage.write().unwrap();
+    let set = storage.get_or_default_as_mut::<PersistentSet<String>>(&key)?;
+
+    let added = members
+        .into_iter()
+        .filter(|member| set.insert(member.clone()))
+        .count();
+    if added > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::SET, "sadd", &key);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(added as i64))),
+        post_run_cmd: None,
+    })
+}
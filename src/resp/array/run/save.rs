@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::rdb;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Resp, SimpleString};
+use crate::storage::Storage;
+
+const BGSAVE_IN_PROGRESS: &str = "Background save already in progress";
+
+/// Saves the dataset while holding the storage lock, so nothing changes until it is on disk.
+pub async fn save(args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<RespEffect<'static>> {
+    ensure!(
+        args.is_empty(),
+        "wrong number of arguments for 'save' command"
+    );
+
+    let storage = storage.read().unwrap();
+    ensure!(!storage.saves.in_progress(), BGSAVE_IN_PROGRESS);
+
+    storage
+        .saves
+        .save(&rdb::encode(&storage.snapshot()), storage.dirty())?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString("OK".to_string()))),
+        post_run_cmd: None,
+    })
+}
+
+/// Saves the dataset in the background, the way `save` rules do.
+pub async fn bgsave(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    ensure!(
+        rdb::start_background_save(&storage.read().unwrap()),
+        BGSAVE_IN_PROGRESS
+    );
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString(
+            "Background saving started".to_string(),
+        ))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Integer, Resp, SimpleString};
+use crate::scripting::ScriptKind;
+use crate::storage::Storage;
+
+pub async fn script(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let subcommand = args.pop_front().context("missing subcommand")?;
+    let subcommand = subcommand.plain_string()?.to_uppercase();
+
+    let reply = match subcommand.as_str() {
+        "EXISTS" => {
+            ensure!(
+                !args.is_empty(),
+                "wrong number of arguments for 'script|exists' command"
+            );
+
+            let storage = storage.read().unwrap();
+            let exists = args
+                .iter()
+                .map(|sha| {
+                    let exists = storage.scripts.get(sha.plain_string()?).is_some();
+                    Ok(Resp::Integer(Integer(exists as i64)))
+                })
+                .collect::<Result<_>>()?;
+
+            Resp::Array(Array(exists))
+        }
+        "FLUSH" => {
+            // the cache is always flushed synchronously, so both modes are accepted
+            match args.pop_front() {
+                None => {}
+                Some(mode) => match mode.plain_string()?.to_uppercase().as_str() {
+                    "ASYNC" | "SYNC" => {}
+                    _ => bail!("SCRIPT FLUSH only support SYNC|ASYNC option"),
+                },
+            }
+            ensure!(args.is_empty(), "syntax error");
+
+            storage.write().unwrap().scripts.flush();
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        // a running script holds the storage lock, so the session kills it without locking;
+        // once the lock is available here, there is nothing left to kill
+        "KILL" => {
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'script|kill' command"
+            );
+
+            storage
+                .read()
+                .unwrap()
+                .scripts
+                .control
+                .kill(ScriptKind::Eval)?;
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        "LOAD" => {
+            let source = args.pop_front().context("missing script")?;
+            ensure!(
+                args.is_empty(),
+                "wrong number of arguments for 'script|load' command"
+            );
+
+            let (sha, _) = storage
+                .write()
+                .unwrap()
+                .scripts
+                .load(source.plain_string()?)?;
+
+            Resp::BulkString(BulkString(Some(sha)))
+        }
+        _ => bail!("unknown subcommand {}", subcommand),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,40 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use super::set_ops::{difference, sets};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, PersistentSet, Storage};
+
+pub async fn sdiffstore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let destination = args.pop_front().context("missing destination")?;
+
+    ensure!(!args.is_empty(), "missing key");
+
+    let keys = Vec::from(args);
+
+    let mut storage = storage.write().unwrap();
+    let members = {
+        let sets = sets(&storage, &keys)?;
+        difference(&sets)
+    };
+    let len = members.len();
+
+    storage.insert_as(
+        destination.clone(),
+        members.into_iter().collect::<PersistentSet<_>>(),
+    );
+    if len > 0 {
+        storage.notify(KeyspaceEvents::SET, "sdiffstore", &destination);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -8,6 +8,7 @@ use crate::resp::integer::Integer;
 use crate::resp::resp_effect::{RespEffect, RespRunResult};
 use crate::resp::{BulkString, Resp, SimpleString};
 use crate::storage::Storage;
+use crate::utils::now_ms;
 
 pub async fn set(
     mut args: VecDeque<Resp>,
@@ -16,10 +17,13 @@ pub async fn set(
     let key = args.pop_front().context("missing key")?;
     let value = args.pop_front().context("missing value")?;
 
-    let expiry = if let Some(px) = args.pop_front() {
-        if px.plain_string()?.to_uppercase() != "PX" {
-            bail!("unknown argument {}", px.to_string());
-        }
+    // PXAT is what PX is logged to the AOF as, so that replaying it keeps the same expiry
+    let expires_at = if let Some(px) = args.pop_front() {
+        let absolute = match px.plain_string()?.to_uppercase().as_str() {
+            "PX" => false,
+            "PXAT" => true,
+            _ => bail!("unknown argument {}", px.to_string()),
+        };
 
         let expiry_i64 = match args.pop_front().context("missing px value")? {
             // NOTE: Codecrafters send the px value as a bulk string instead of Integer
@@ -34,7 +38,11 @@ pub async fn set(
             bail!("invalid px value {}", expiry_i64);
         }
 
-        Some(Duration::from_millis(expiry_i64 as u64))
+        if absolute {
+            Some(expiry_i64 as u64)
+        } else {
+            Some(now_ms() + expiry_i64 as u64)
+        }
     } else {
         None
     };
@@ -45,8 +53,16 @@ pub async fn set(
 
     let mut storage = storage.write().unwrap();
 
+    let expiry = expires_at.map(|at| Duration::from_millis(at.saturating_sub(now_ms())));
     storage.set(key, value, expiry);
 
+    if let Some(at) = expires_at {
+        storage.rewrite_propagated(|command| {
+            command[3] = Resp::BulkString(BulkString(Some("PXAT".to_string())));
+            command[4] = Resp::BulkString(BulkString(Some(at.to_string())));
+        });
+    }
+
     Ok(RespEffect {
         run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString("OK".to_string()))),
         post_run_cmd: None,
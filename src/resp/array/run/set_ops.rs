@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{PersistentSet, Storage};
+
+/// Looks up every key as a set, treating missing keys as empty sets.
+pub fn sets<'a>(
+    storage: &'a Storage,
+    keys: &[Resp],
+) -> Result<Vec<Option<&'a PersistentSet<String>>>> {
+    keys.iter()
+        .map(|key| storage.get_as::<PersistentSet<String>>(key))
+        .collect()
+}
+
+/// Intersects `sets`, stopping after `limit` members if it is given.
+///
+/// Iteration starts from the smallest set, so the cost is bounded by its size no matter how
+/// large the other sets are.
+pub fn intersection(sets: &[Option<&PersistentSet<String>>], limit: Option<usize>) -> Vec<String> {
+    let Some(mut sets) = sets.iter().copied().collect::<Option<Vec<_>>>() else {
+        return vec![];
+    };
+    sets.sort_by_key(|set| set.len());
+
+    let Some((smallest, rest)) = sets.split_first() else {
+        return vec![];
+    };
+
+    smallest
+        .iter()
+        .filter(|member| rest.iter().all(|set| set.contains(*member)))
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect()
+}
+
+pub fn union(sets: &[Option<&PersistentSet<String>>]) -> Vec<String> {
+    sets.iter()
+        .flatten()
+        .flat_map(|set| set.iter())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Returns the members of the first set that are in none of the others.
+pub fn difference(sets: &[Option<&PersistentSet<String>>]) -> Vec<String> {
+    let Some((Some(first), rest)) = sets.split_first() else {
+        return vec![];
+    };
+
+    first
+        .iter()
+        .filter(|member| rest.iter().flatten().all(|set| !set.contains(*member)))
+        .cloned()
+        .collect()
+}
+
+pub fn members_reply(members: Vec<String>) -> Resp {
+    Resp::Array(Array(
+        members
+            .into_iter()
+            .map(|member| Resp::BulkString(BulkString(Some(member))))
+            .collect(),
+    ))
+}
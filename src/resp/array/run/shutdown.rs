@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Result};
+
+use crate::rdb;
+use crate::resp::resp_effect::RespEffect;
+use crate::resp::Resp;
+use crate::storage::Storage;
+use crate::utils::exit_server;
+
+/// Exits the server, first saving the dataset if `SAVE` is given, or by default when `save`
+/// rules are set. Like in Redis, nothing is replied unless the server can't exit.
+pub async fn shutdown(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let mut save = None;
+    for arg in &args {
+        match arg.plain_string()?.to_uppercase().as_str() {
+            "NOSAVE" if save.is_none() => save = Some(false),
+            "SAVE" if save.is_none() => save = Some(true),
+            // there are no replicas to wait for, so the shutdown is always immediate
+            "NOW" | "FORCE" => {}
+            _ => bail!("syntax error"),
+        }
+    }
+
+    let storage = storage.read().unwrap();
+    println!("User requested shutdown...");
+
+    if save.unwrap_or(storage.save_on_shutdown) {
+        println!("Saving the final RDB snapshot before exiting.");
+        let rdb = rdb::encode(&storage.snapshot());
+        if let Err(e) = storage.saves.save(&rdb, storage.dirty()) {
+            eprintln!("Error trying to save the DB, can't exit; error = {:?}", e);
+            bail!("Errors trying to SHUTDOWN. Check logs.");
+        }
+    }
+
+    if let Some(aof) = &storage.aof {
+        storage.write_aof();
+        if let Err(e) = aof.fsync() {
+            eprintln!("Error syncing the AOF before exiting; error = {:?}", e);
+        }
+    }
+
+    exit_server()
+}
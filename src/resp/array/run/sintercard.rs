@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::set_ops::{intersection, sets};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::Storage;
+
+pub async fn sintercard(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let num_keys = args.pop_front().context("missing numkeys")?.plain_i64()?;
+
+    ensure!(num_keys > 0, "numkeys should be greater than 0");
+    ensure!(
+        num_keys as usize <= args.len(),
+        "Number of keys can't be greater than number of args"
+    );
+
+    let keys = args.drain(..num_keys as usize).collect::<Vec<_>>();
+
+    let mut limit = None;
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "LIMIT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing LIMIT value")?
+                    .plain_i64()?;
+                ensure!(value >= 0, "LIMIT can't be negative");
+                // LIMIT 0 means unlimited
+                limit = (value > 0).then_some(value as usize);
+            }
+            _ => bail!("unknown argument {}", option.to_string()),
+        }
+    }
+
+    let storage = storage.read().unwrap();
+    let sets = sets(&storage, &keys)?;
+    let len = intersection(&sets, limit).len();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
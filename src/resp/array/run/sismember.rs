@@ -0,0 +1,31 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{PersistentSet, Storage};
+
+pub async fn sismember(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let member = args.pop_front().context("missing member")?;
+    let member = member.plain_string()?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let is_member = storage
+        .get_as::<PersistentSet<String>>(&key)?
+        .is_some_and(|set| set.contains(member));
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(is_member as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,32 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{PersistentSet, Storage};
+
+pub async fn smembers(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let members = storage
+        .get_as::<PersistentSet<String>>(&key)?
+        .into_iter()
+        .flatten()
+        .map(|member| Resp::BulkString(BulkString(Some(member.clone()))))
+        .collect();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(members))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, Integer, Resp};
+use crate::storage::{PersistentSet, Storage};
+
+pub async fn smismember(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    ensure!(!args.is_empty(), "missing member");
+
+    let storage = storage.read().unwrap();
+    let set = storage.get_as::<PersistentSet<String>>(&key)?;
+
+    let replies = args
+        .iter()
+        .map(|member| {
+            let is_member = match set {
+                None => false,
+                Some(set) => set.contains(member.plain_string()?),
+            };
+
+            Ok(Resp::Integer(Integer(is_member as i64)))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(replies))),
+        post_run_cmd: None,
+    })
+}
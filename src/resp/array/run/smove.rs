@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, PersistentSet, Storage};
+
+pub async fn smove(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let source = args.pop_front().context("missing source")?;
+    let destination = args.pop_front().context("missing destination")?;
+    let member = args.pop_front().context("missing member")?;
+    let member = member.plain_string()?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let mut storage = storage.write().unwrap();
+
+    // type check the destination before touching the source
+    storage.get_as::<PersistentSet<String>>(&destination)?;
+
+    let moved = match storage.get_as_mut::<PersistentSet<String>>(&source)? {
+        None => false,
+        Some(set) if source == destination => set.contains(member),
+        Some(set) => set.remove(member),
+    };
+
+    if moved && source != destination {
+        storage.touch(&source);
+        storage.notify(KeyspaceEvents::SET, "srem", &source);
+        storage.remove_if_empty::<PersistentSet<String>>(&source);
+        let added = storage
+            .get_or_default_as_mut::<PersistentSet<String>>(&destination)?
+            .insert(member.to_string());
+        if added {
+            storage.touch(&destination);
+            storage.notify(KeyspaceEvents::SET, "sadd", &destination);
+        }
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(moved as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{KeyspaceEvents, PersistentSet, Storage};
+use crate::utils::shuffle_prefix;
+
+pub async fn spop(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let count = match args.pop_front() {
+        None => None,
+        Some(count) => {
+            let count = count.plain_i64()?;
+            ensure!(count >= 0, "value is out of range, must be positive");
+            Some(count as usize)
+        }
+    };
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let mut storage = storage.write().unwrap();
+
+    let popped = match storage.get_as_mut::<PersistentSet<String>>(&key)? {
+        None => vec![],
+        Some(set) => {
+            let mut members = set.iter().cloned().collect::<Vec<_>>();
+            let count = count.unwrap_or(1).min(members.len());
+
+            shuffle_prefix(&mut members, count);
+            members.truncate(count);
+
+            for member in &members {
+                set.remove(member);
+            }
+
+            members
+        }
+    };
+    if !popped.is_empty() {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::SET, "spop", &key);
+
+        // the members are popped at random, so the AOF gets the ones that were
+        storage.rewrite_propagated(|command| {
+            command.truncate(2);
+            command[0] = Resp::BulkString(BulkString(Some("SREM".to_string())));
+            command.extend(
+                popped
+                    .iter()
+                    .map(|member| Resp::BulkString(BulkString(Some(member.clone())))),
+            );
+        });
+    }
+    storage.remove_if_empty::<PersistentSet<String>>(&key);
+
+    let reply = match count {
+        None => Resp::BulkString(BulkString(popped.into_iter().next())),
+        Some(_) => Resp::Array(Array(
+            popped
+                .into_iter()
+                .map(|member| Resp::BulkString(BulkString(Some(member))))
+                .collect(),
+        )),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
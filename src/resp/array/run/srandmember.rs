@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{PersistentSet, Storage};
+use crate::utils::{random_index, shuffle_prefix};
+
+pub async fn srandmember(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let count = args
+        .pop_front()
+        .map(|count| count.plain_i64())
+        .transpose()?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+    // like Redis, so that the count can be negated
+    ensure!(count != Some(i64::MIN), "value is out of range");
+
+    let storage = storage.read().unwrap();
+    let members = storage
+        .get_as::<PersistentSet<String>>(&key)?
+        .map(|set| set.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let reply = match count {
+        None if members.is_empty() => Resp::BulkString(BulkString(None)),
+        None => Resp::BulkString(BulkString(Some(
+            members[random_index(members.len())].clone(),
+        ))),
+        Some(count) => {
+            let chosen = if members.is_empty() {
+                vec![]
+            } else if count < 0 {
+                // a negative count allows the same member to be returned multiple times. The
+                // reply grows as members are picked, as the count is the client's to choose
+                let mut chosen = Vec::new();
+                for _ in 0..count.unsigned_abs() {
+                    chosen.push(members[random_index(members.len())]);
+                }
+                chosen
+            } else {
+                let mut members = members;
+                let count = (count as usize).min(members.len());
+
+                shuffle_prefix(&mut members, count);
+                members.truncate(count);
+
+                members
+            };
+
+            Resp::Array(Array(
+                chosen
+                    .into_iter()
+                    .map(|member| Resp::BulkString(BulkString(Some(member.clone()))))
+                    .collect(),
+            ))
+        }
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
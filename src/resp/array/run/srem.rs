@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, PersistentSet, Storage};
+
+pub async fn srem(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    ensure!(!args.is_empty(), "missing member");
+
+    let mut storage = storage.write().unwrap();
+    let removed = match storage.get_as_mut::<PersistentSet<String>>(&key)? {
+        None => 0,
+        Some(set) => {
+            let mut removed = 0;
+            for member in &args {
+                if set.remove(member.plain_string()?) {
+                    removed += 1;
+                }
+            }
+            removed
+        }
+    };
+    if removed > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::SET, "srem", &key);
+    }
+    storage.remove_if_empty::<PersistentSet<String>>(&key);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(removed))),
+        post_run_cmd: None,
+    })
+}
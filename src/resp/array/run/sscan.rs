@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{PersistentSet, Storage};
+use crate::utils::{glob_match, scan};
+
+pub async fn sscan(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let cursor = args
+        .pop_front()
+        .context("missing cursor")?
+        .plain_string()?
+        .parse::<u64>()
+        .context("invalid cursor")?;
+
+    let mut pattern = None;
+    let mut count = 10;
+
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "MATCH" => {
+                let value = args.pop_front().context("missing MATCH pattern")?;
+                pattern = Some(value.plain_string()?.to_string());
+            }
+            "COUNT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing COUNT value")?
+                    .plain_i64()?;
+                if value < 1 {
+                    bail!("syntax error");
+                }
+                count = value as usize;
+            }
+            _ => bail!("unknown argument {}", option.to_string()),
+        }
+    }
+
+    let storage = storage.read().unwrap();
+    let (next_cursor, members) = match storage.get_as::<PersistentSet<String>>(&key)? {
+        None => (0, vec![]),
+        Some(set) => scan(set.iter().map(String::as_str), cursor, count),
+    };
+
+    let members = members
+        .into_iter()
+        .filter(|member| pattern.as_deref().map_or(true, |p| glob_match(p, member)))
+        .map(|member| Resp::BulkString(BulkString(Some(member.to_string()))))
+        .collect();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some(next_cursor.to_string()))),
+            Resp::Array(Array(members)),
+        ]))),
+        post_run_cmd: None,
+    })
+}
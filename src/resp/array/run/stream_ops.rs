@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{ClaimOptions, Fields, StreamId, Trim, TrimStrategy, STREAM_NODE_MAX_ENTRIES};
+
+pub fn entry_reply(id: &StreamId, fields: &Fields) -> Resp {
+    Resp::Array(Array(vec![
+        Resp::BulkString(BulkString(Some(id.to_string()))),
+        Resp::Array(Array(
+            fields
+                .iter()
+                .flat_map(|(field, value)| [field, value])
+                .map(|s| Resp::BulkString(BulkString(Some(s.clone()))))
+                .collect(),
+        )),
+    ]))
+}
+
+/// Like [`entry_reply`], for an entry of a consumer's PEL that may have been deleted from the
+/// stream since it was delivered.
+pub fn pending_entry_reply(id: &StreamId, fields: Option<&Fields>) -> Resp {
+    match fields {
+        Some(fields) => entry_reply(id, fields),
+        None => Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some(id.to_string()))),
+            Resp::NullArray,
+        ])),
+    }
+}
+
+pub fn entries_reply<'a>(entries: impl IntoIterator<Item = (&'a StreamId, &'a Fields)>) -> Resp {
+    Resp::Array(Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_reply(id, fields))
+            .collect(),
+    ))
+}
+
+/// Parses the rest of a trim argument, `[= | ~] threshold [LIMIT count]`, after the `MAXLEN` or
+/// `MINID` in `strategy`.
+pub fn parse_trim(strategy: &str, args: &mut VecDeque<Resp>) -> Result<Trim> {
+    let mut threshold = args.pop_front().context("missing trim threshold")?;
+
+    let approximate = match threshold.plain_string()? {
+        "~" => true,
+        "=" => false,
+        _ => {
+            args.push_front(threshold);
+            false
+        }
+    };
+    threshold = args.pop_front().context("missing trim threshold")?;
+
+    let strategy = match strategy {
+        "MAXLEN" => {
+            let max_len = threshold.plain_i64()?;
+            ensure!(max_len >= 0, "The MAXLEN argument must be >= 0.");
+            TrimStrategy::MaxLen(max_len as usize)
+        }
+        "MINID" => TrimStrategy::MinId(StreamId::parse(threshold.plain_string()?, 0)?),
+        _ => bail!("syntax error"),
+    };
+
+    let limit = match args.front() {
+        Some(option) if option.plain_string()?.eq_ignore_ascii_case("LIMIT") => {
+            args.pop_front();
+
+            let limit = args
+                .pop_front()
+                .context("missing LIMIT value")?
+                .plain_i64()?;
+            ensure!(limit >= 0, "The LIMIT argument must be >= 0.");
+            ensure!(
+                approximate,
+                "syntax error, LIMIT cannot be used without the special ~ option"
+            );
+
+            // LIMIT 0 means unlimited
+            (limit > 0).then_some(limit as usize)
+        }
+        // approximate trimming is capped by default to keep single calls cheap
+        _ if approximate => Some(100 * STREAM_NODE_MAX_ENTRIES),
+        _ => None,
+    };
+
+    Ok(Trim {
+        strategy,
+        approximate,
+        limit,
+    })
+}
+
+pub fn no_group_error(key: &Resp, group: &str) -> anyhow::Error {
+    anyhow!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        key.plain_string().unwrap_or_default(),
+        group
+    )
+}
+
+/// What XCLAIM and XAUTOCLAIM are logged to the AOF as: an XCLAIM of the IDs that were claimed
+/// or found deleted, at the time they were. Replayed, it doesn't depend on how long entries have
+/// been idle. Empty if nothing was claimed, since then there's nothing to replay.
+pub fn claim_command(
+    key: &Resp,
+    group: &str,
+    consumer: &str,
+    ids: &[StreamId],
+    options: &ClaimOptions,
+    time: u64,
+) -> Vec<Resp> {
+    if ids.is_empty() {
+        return Vec::new();
+    }
+
+    let mut args = vec![
+        "XCLAIM".to_string(),
+        key.plain_string().unwrap_or_default().to_string(),
+        group.to_string(),
+        consumer.to_string(),
+        "0".to_string(),
+    ];
+    args.extend(ids.iter().map(StreamId::to_string));
+    args.extend(["TIME".to_string(), time.to_string()]);
+    if let Some(retry_count) = options.retry_count {
+        args.extend(["RETRYCOUNT".to_string(), retry_count.to_string()]);
+    }
+    if options.force {
+        args.push("FORCE".to_string());
+    }
+    if options.just_id {
+        args.push("JUSTID".to_string());
+    }
+
+    args.into_iter()
+        .map(|arg| Resp::BulkString(BulkString(Some(arg))))
+        .collect()
+}
@@ -0,0 +1,27 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Result};
+
+use super::set_ops::{members_reply, sets, union};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::storage::Storage;
+
+pub async fn sunion(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    ensure!(!args.is_empty(), "missing key");
+
+    let keys = Vec::from(args);
+
+    let storage = storage.read().unwrap();
+    let sets = sets(&storage, &keys)?;
+    let members = union(&sets);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(members_reply(members)),
+        post_run_cmd: None,
+    })
+}
@@ -1,9 +1,13 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
+use crate::rdb::SaveState;
 use crate::resp::array::Array;
 use crate::resp::simple_string::SimpleString;
 use crate::resp::tests::{assert_run, assert_run_with_storage};
-use crate::resp::{BulkString, Integer, Resp};
+use crate::resp::{bulk, command, BulkString, Integer, Resp, SimpleError};
+use crate::storage::{KeyspaceEvents, Replication};
+use crate::utils::sha1_hex;
 
 use super::*;
 
@@ -31,17 +35,16 @@ async fn test_run_ping_with_message() -> Result<()> {
 }
 
 #[tokio::test]
-async fn test_run_too_many_arguments() {
+async fn test_run_too_many_arguments() -> Result<()> {
     assert_run(
         Resp::Array(Array(vec![
             Resp::SimpleString(SimpleString("PING".to_string())),
             Resp::SimpleString(SimpleString("hello".to_string())),
             Resp::SimpleString(SimpleString("world".to_string())),
         ])),
-        Resp::SimpleString(SimpleString("PONG".to_string())),
+        Resp::SimpleError(SimpleError("ERR too many arguments".to_string())),
     )
     .await
-    .unwrap_err();
 }
 
 #[tokio::test]
@@ -135,6 +138,33 @@ async fn test_expiry() -> Result<()> {
     .await
 }
 
+#[tokio::test]
+async fn test_pxat_and_del() {
+    let storage: RwLock<Storage> = Default::default();
+    let run = |args: &[&str]| command(args).run_now(&storage);
+
+    let past = (crate::utils::now_ms() - 1).to_string();
+    let future = (crate::utils::now_ms() + 100_000).to_string();
+    run(&["SET", "expired", "value", "PXAT", &past]);
+    run(&["SET", "key", "value", "PXAT", &future]);
+    run(&["SADD", "set", "member"]);
+
+    assert_eq!(run(&["GET", "expired"]), Resp::BulkString(BulkString(None)));
+    assert_eq!(
+        run(&["GET", "key"]),
+        Resp::BulkString(BulkString(Some("value".to_string())))
+    );
+    assert_eq!(
+        run(&["DEL", "key", "set", "expired", "missing"]),
+        Resp::Integer(Integer(2))
+    );
+    assert_eq!(run(&["GET", "key"]), Resp::BulkString(BulkString(None)));
+    assert!(matches!(
+        run(&["SET", "key", "value", "PXAT", "0"]),
+        Resp::SimpleError(_)
+    ));
+}
+
 #[tokio::test]
 async fn test_default_info() {
     let cmd = Resp::Array(Array(vec![
@@ -153,3 +183,1238 @@ async fn test_default_info() {
         .iter()
         .any(|line| line.contains("master_repl_offset:")));
 }
+
+/// The message of the error reply that a command failed with.
+fn error_message(reply: Resp) -> String {
+    match reply {
+        Resp::SimpleError(SimpleError(message)) => message,
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+}
+
+fn bulk_strings(values: &[&str]) -> Resp {
+    Resp::Array(Array(
+        values
+            .iter()
+            .map(|value| Resp::BulkString(BulkString(Some(value.to_string()))))
+            .collect(),
+    ))
+}
+
+#[tokio::test]
+async fn test_sadd_srem_scard() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    assert_run_with_storage(
+        command(&["SADD", "tags", "a", "b", "a"]),
+        Resp::Integer(Integer(2)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SMISMEMBER", "tags", "a", "c"]),
+        Resp::Array(Array(vec![
+            Resp::Integer(Integer(1)),
+            Resp::Integer(Integer(0)),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SREM", "tags", "a", "b", "c"]),
+        Resp::Integer(Integer(2)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert!(
+        storage.read().unwrap().is_empty(),
+        "empty set should be removed"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sadd_wrong_type() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    assert_run_with_storage(
+        command(&["SET", "key", "value"]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SADD", "key", "a"]),
+        Resp::SimpleError(SimpleError(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        )),
+        storage,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_srandmember_negative_count_repeats() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    assert_run_with_storage(
+        command(&["SADD", "tags", "only"]),
+        Resp::Integer(Integer(1)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SRANDMEMBER", "tags", "-3"]),
+        bulk_strings(&["only", "only", "only"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SRANDMEMBER", "tags", "-9223372036854775808"]),
+        Resp::SimpleError(SimpleError("ERR value is out of range".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SRANDMEMBER", "tags", "3"]),
+        bulk_strings(&["only"]),
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_spop_and_smove() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    assert_run_with_storage(
+        command(&["SADD", "src", "a", "b", "c"]),
+        Resp::Integer(Integer(3)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SMOVE", "src", "dst", "a"]),
+        Resp::Integer(Integer(1)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SMEMBERS", "dst"]),
+        bulk_strings(&["a"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let mut buf = Vec::new();
+    command(&["SPOP", "src", "5"])
+        .run(&mut buf, Arc::clone(&storage))
+        .await?;
+    let popped = String::from_utf8(buf)?;
+    assert!(popped.starts_with("*2\r\n"));
+
+    assert_run_with_storage(
+        command(&["SCARD", "src"]),
+        Resp::Integer(Integer(0)),
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_set_algebra() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    for cmd in [
+        command(&["SADD", "a", "1", "2", "3", "4"]),
+        command(&["SADD", "b", "2", "3", "4", "5"]),
+        command(&["SADD", "c", "3", "4"]),
+    ] {
+        cmd.run(&mut Vec::new(), Arc::clone(&storage)).await?;
+    }
+
+    assert_run_with_storage(
+        command(&["SINTERSTORE", "dst", "a", "b", "c"]),
+        Resp::Integer(Integer(2)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SINTERCARD", "2", "a", "b", "LIMIT", "1"]),
+        Resp::Integer(Integer(1)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SDIFF", "a", "b", "missing"]),
+        bulk_strings(&["1"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SUNIONSTORE", "dst", "a", "b"]),
+        Resp::Integer(Integer(5)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SINTER", "a", "missing"]),
+        bulk_strings(&[]),
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_zadd_flags() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    for (cmd, expected) in [
+        (
+            command(&["ZADD", "board", "1", "a", "2", "b"]),
+            Resp::Integer(Integer(2)),
+        ),
+        (
+            command(&["ZADD", "board", "NX", "5", "a", "3", "c"]),
+            Resp::Integer(Integer(1)),
+        ),
+        (
+            command(&["ZADD", "board", "GT", "CH", "0", "a", "4", "b"]),
+            Resp::Integer(Integer(1)),
+        ),
+        (
+            command(&["ZADD", "board", "XX", "INCR", "1.5", "a"]),
+            Resp::BulkString(BulkString(Some("2.5".to_string()))),
+        ),
+        (
+            command(&["ZADD", "board", "XX", "INCR", "1", "missing"]),
+            Resp::BulkString(BulkString(None)),
+        ),
+        (
+            command(&["ZRANGE", "board", "0", "-1", "WITHSCORES"]),
+            bulk_strings(&["a", "2.5", "c", "3", "b", "4"]),
+        ),
+        (
+            command(&["ZREVRANK", "board", "a"]),
+            Resp::Integer(Integer(2)),
+        ),
+    ] {
+        assert_run_with_storage(cmd, expected, Arc::clone(&storage)).await?;
+    }
+
+    assert_run_with_storage(
+        command(&["ZADD", "board", "NX", "XX", "1", "a"]),
+        Resp::SimpleError(SimpleError(
+            "ERR XX and NX options at the same time are not compatible".to_string(),
+        )),
+        storage,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zrange_unified_syntax() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    command(&["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d"])
+        .run(&mut Vec::new(), Arc::clone(&storage))
+        .await?;
+
+    for (cmd, expected) in [
+        (
+            command(&["ZRANGE", "z", "(1", "+inf", "BYSCORE", "LIMIT", "1", "2"]),
+            bulk_strings(&["c", "d"]),
+        ),
+        (
+            command(&["ZRANGE", "z", "3", "-inf", "BYSCORE", "REV"]),
+            bulk_strings(&["c", "b", "a"]),
+        ),
+        (
+            command(&["ZRANGE", "z", "0", "1", "REV"]),
+            bulk_strings(&["d", "c"]),
+        ),
+        (
+            command(&["ZRANGE", "z", "[b", "(d", "BYLEX"]),
+            bulk_strings(&["b", "c"]),
+        ),
+        (
+            command(&["ZCOUNT", "z", "(1", "3"]),
+            Resp::Integer(Integer(2)),
+        ),
+    ] {
+        assert_run_with_storage(cmd, expected, Arc::clone(&storage)).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zunionstore_weights_aggregate() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    for cmd in [
+        command(&["ZADD", "h1", "1", "a", "2", "b"]),
+        command(&["ZADD", "h2", "10", "b", "20", "c"]),
+    ] {
+        cmd.run(&mut Vec::new(), Arc::clone(&storage)).await?;
+    }
+
+    for (cmd, expected) in [
+        (
+            command(&["ZUNIONSTORE", "day", "2", "h1", "h2", "WEIGHTS", "2", "1"]),
+            Resp::Integer(Integer(3)),
+        ),
+        (
+            command(&["ZRANGE", "day", "0", "-1", "WITHSCORES"]),
+            bulk_strings(&["a", "2", "b", "14", "c", "20"]),
+        ),
+        (
+            command(&["ZINTERSTORE", "both", "2", "h1", "h2", "AGGREGATE", "MAX"]),
+            Resp::Integer(Integer(1)),
+        ),
+        (
+            command(&["ZSCORE", "both", "b"]),
+            Resp::BulkString(BulkString(Some("10".to_string()))),
+        ),
+        (
+            command(&["ZDIFFSTORE", "only", "2", "h1", "h2"]),
+            Resp::Integer(Integer(1)),
+        ),
+        (
+            command(&["ZPOPMAX", "day", "2"]),
+            bulk_strings(&["c", "20", "b", "14"]),
+        ),
+    ] {
+        assert_run_with_storage(cmd, expected, Arc::clone(&storage)).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bzpopmin_wakes_on_zadd() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    let blocked = tokio::spawn({
+        let storage = Arc::clone(&storage);
+        async move {
+            let mut buf = Vec::new();
+            command(&["BZPOPMIN", "queue", "0"])
+                .run(&mut buf, storage)
+                .await
+                .map(|_| buf)
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+    assert!(!blocked.is_finished());
+
+    command(&["ZADD", "queue", "5", "job"])
+        .run(&mut Vec::new(), Arc::clone(&storage))
+        .await?;
+
+    let reply = String::from_utf8(blocked.await??)?;
+    assert_eq!(reply, bulk_strings(&["queue", "job", "5"]).to_string());
+
+    assert_run_with_storage(
+        command(&["BZPOPMIN", "queue", "0.05"]),
+        Resp::NullArray,
+        storage,
+    )
+    .await
+}
+
+fn stream_entry(id: &str, fields: &[&str]) -> Resp {
+    Resp::Array(Array(vec![
+        Resp::BulkString(BulkString(Some(id.to_string()))),
+        bulk_strings(fields),
+    ]))
+}
+
+#[tokio::test]
+async fn test_stream_commands() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    for (cmd, expected) in [
+        (
+            command(&["XADD", "log", "1-1", "event", "a"]),
+            Resp::BulkString(BulkString(Some("1-1".to_string()))),
+        ),
+        (
+            command(&["XADD", "log", "1-*", "event", "b"]),
+            Resp::BulkString(BulkString(Some("1-2".to_string()))),
+        ),
+        (
+            command(&["XADD", "log", "MAXLEN", "2", "3", "event", "c"]),
+            Resp::BulkString(BulkString(Some("3-0".to_string()))),
+        ),
+        (command(&["XLEN", "log"]), Resp::Integer(Integer(2))),
+        (
+            command(&["XRANGE", "log", "-", "+", "COUNT", "1"]),
+            Resp::Array(Array(vec![stream_entry("1-2", &["event", "b"])])),
+        ),
+        (
+            command(&["XREVRANGE", "log", "+", "(1-2"]),
+            Resp::Array(Array(vec![stream_entry("3-0", &["event", "c"])])),
+        ),
+        (
+            command(&["XDEL", "log", "3-0", "9-9"]),
+            Resp::Integer(Integer(1)),
+        ),
+        (
+            command(&["XADD", "missing", "NOMKSTREAM", "*", "event", "d"]),
+            Resp::BulkString(BulkString(None)),
+        ),
+    ] {
+        assert_run_with_storage(cmd, expected, Arc::clone(&storage)).await?;
+    }
+
+    assert_run_with_storage(
+        command(&["XADD", "log", "2-0", "event", "e"]),
+        Resp::SimpleError(SimpleError(
+            "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                .to_string(),
+        )),
+        storage,
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_xread_block_dollar() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    command(&["XADD", "log", "1-0", "event", "old"])
+        .run(&mut Vec::new(), Arc::clone(&storage))
+        .await?;
+
+    assert_run_with_storage(
+        command(&["XREAD", "STREAMS", "log", "0"]),
+        Resp::Array(Array(vec![Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("log".to_string()))),
+            Resp::Array(Array(vec![stream_entry("1-0", &["event", "old"])])),
+        ]))])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let blocked = tokio::spawn({
+        let storage = Arc::clone(&storage);
+        async move {
+            let mut buf = Vec::new();
+            command(&["XREAD", "BLOCK", "0", "STREAMS", "log", "other", "$", "$"])
+                .run(&mut buf, storage)
+                .await
+                .map(|_| buf)
+        }
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+    assert!(!blocked.is_finished());
+
+    command(&["XADD", "log", "2-0", "event", "new"])
+        .run(&mut Vec::new(), Arc::clone(&storage))
+        .await?;
+
+    let reply = String::from_utf8(blocked.await??)?;
+    assert_eq!(
+        reply,
+        Resp::Array(Array(vec![Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("log".to_string()))),
+            Resp::Array(Array(vec![stream_entry("2-0", &["event", "new"])])),
+        ]))]))
+        .to_string()
+    );
+
+    assert_run_with_storage(
+        command(&["XREAD", "BLOCK", "50", "STREAMS", "log", "$"]),
+        Resp::NullArray,
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_consumer_group() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    for id in ["1-0", "2-0", "3-0"] {
+        command(&["XADD", "jobs", id, "job", id])
+            .run(&mut Vec::new(), Arc::clone(&storage))
+            .await?;
+    }
+
+    assert_run_with_storage(
+        command(&["XGROUP", "CREATE", "jobs", "workers", "0"]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    error_message(command(&["XGROUP", "CREATE", "jobs", "workers", "$"]).run_now(&storage));
+
+    let jobs_reply = |entries: Vec<Resp>| {
+        Resp::Array(Array(vec![Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("jobs".to_string()))),
+            Resp::Array(Array(entries)),
+        ]))]))
+    };
+
+    assert_run_with_storage(
+        command(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "alice",
+            "COUNT",
+            "2",
+            "STREAMS",
+            "jobs",
+            ">",
+        ]),
+        jobs_reply(vec![
+            stream_entry("1-0", &["job", "1-0"]),
+            stream_entry("2-0", &["job", "2-0"]),
+        ]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "bob",
+            "STREAMS",
+            "jobs",
+            ">",
+        ]),
+        jobs_reply(vec![stream_entry("3-0", &["job", "3-0"])]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["XACK", "jobs", "workers", "1-0", "9-0"]),
+        Resp::Integer(Integer(1)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    // alice's history only holds what is still pending
+    assert_run_with_storage(
+        command(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "alice",
+            "STREAMS",
+            "jobs",
+            "0",
+        ]),
+        jobs_reply(vec![stream_entry("2-0", &["job", "2-0"])]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["XPENDING", "jobs", "workers"]),
+        Resp::Array(Array(vec![
+            Resp::Integer(Integer(2)),
+            Resp::BulkString(BulkString(Some("2-0".to_string()))),
+            Resp::BulkString(BulkString(Some("3-0".to_string()))),
+            Resp::Array(Array(vec![
+                bulk_strings(&["alice", "1"]),
+                bulk_strings(&["bob", "1"]),
+            ])),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["XCLAIM", "jobs", "workers", "bob", "0", "2-0", "JUSTID"]),
+        bulk_strings(&["2-0"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    command(&["XDEL", "jobs", "3-0"])
+        .run(&mut Vec::new(), Arc::clone(&storage))
+        .await?;
+
+    assert_run_with_storage(
+        command(&["XAUTOCLAIM", "jobs", "workers", "alice", "0", "0"]),
+        Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("0-0".to_string()))),
+            Resp::Array(Array(vec![stream_entry("2-0", &["job", "2-0"])])),
+            bulk_strings(&["3-0"]),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let error = error_message(
+        command(&[
+            "XAUTOCLAIM",
+            "jobs",
+            "workers",
+            "alice",
+            "0",
+            "0",
+            "COUNT",
+            &i64::MAX.to_string(),
+        ])
+        .run_now(&storage),
+    );
+    assert_eq!(error, "ERR COUNT must be > 0");
+
+    assert_run_with_storage(
+        command(&["XGROUP", "DELCONSUMER", "jobs", "workers", "alice"]),
+        Resp::Integer(Integer(1)),
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_geo() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    // the examples of the Redis documentation
+    assert_run_with_storage(
+        command(&[
+            "GEOADD",
+            "Sicily",
+            "13.361389",
+            "38.115556",
+            "Palermo",
+            "15.087269",
+            "37.502669",
+            "Catania",
+        ]),
+        Resp::Integer(Integer(2)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["GEODIST", "Sicily", "Palermo", "Catania", "km"]),
+        Resp::BulkString(BulkString(Some("166.2742".to_string()))),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["GEOHASH", "Sicily", "Palermo", "Catania"]),
+        bulk_strings(&["sqc8b49rny0", "sqdtr74hyu0"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["GEOPOS", "Sicily", "Palermo", "NonExisting"]),
+        Resp::Array(Array(vec![
+            bulk_strings(&["13.36138933897018433", "38.11555639549629859"]),
+            Resp::NullArray,
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    command(&[
+        "GEOADD",
+        "Sicily",
+        "12.758489",
+        "38.788135",
+        "edge1",
+        "17.241510",
+        "38.788135",
+        "edge2",
+    ])
+    .run(&mut Vec::new(), Arc::clone(&storage))
+    .await?;
+
+    assert_run_with_storage(
+        command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYRADIUS",
+            "200",
+            "km",
+            "ASC",
+        ]),
+        bulk_strings(&["Catania", "Palermo"]),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let point = |member: &str, distance: &str, longitude: &str, latitude: &str| {
+        Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some(member.to_string()))),
+            Resp::BulkString(BulkString(Some(distance.to_string()))),
+            bulk_strings(&[longitude, latitude]),
+        ]))
+    };
+
+    assert_run_with_storage(
+        command(&[
+            "GEOSEARCH",
+            "Sicily",
+            "FROMLONLAT",
+            "15",
+            "37",
+            "BYBOX",
+            "400",
+            "400",
+            "km",
+            "ASC",
+            "WITHCOORD",
+            "WITHDIST",
+        ]),
+        Resp::Array(Array(vec![
+            point(
+                "Catania",
+                "56.4413",
+                "15.08726745843887329",
+                "37.50266842333162032",
+            ),
+            point(
+                "Palermo",
+                "190.4424",
+                "13.36138933897018433",
+                "38.11555639549629859",
+            ),
+            point(
+                "edge2",
+                "279.7403",
+                "17.24151045083999634",
+                "38.78813451624225195",
+            ),
+            point(
+                "edge1",
+                "279.7405",
+                "12.7584877610206604",
+                "38.78813451624225195",
+            ),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&[
+            "GEOSEARCHSTORE",
+            "nearest",
+            "Sicily",
+            "FROMMEMBER",
+            "Palermo",
+            "BYRADIUS",
+            "200",
+            "km",
+            "COUNT",
+            "1",
+            "DESC",
+            "STOREDIST",
+        ]),
+        Resp::Integer(Integer(1)),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["ZRANGE", "nearest", "0", "-1"]),
+        bulk_strings(&["Catania"]),
+        storage,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_eval() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    assert_run_with_storage(
+        command(&[
+            "EVAL",
+            "return {KEYS[1], ARGV[1], #ARGV, 3.9, true, false, {ok = 'fine'}}",
+            "1",
+            "key",
+            "arg",
+            "other",
+        ]),
+        Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("key".to_string()))),
+            Resp::BulkString(BulkString(Some("arg".to_string()))),
+            Resp::Integer(Integer(2)),
+            Resp::Integer(Integer(3)),
+            Resp::Integer(Integer(1)),
+            Resp::BulkString(BulkString(None)),
+            Resp::SimpleString(SimpleString("fine".to_string())),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let script = "
+        redis.call('SADD', KEYS[1], 'a', 'b')
+        local members = redis.call('SMEMBERS', KEYS[1])
+        table.sort(members)
+        local err = redis.pcall('GET', KEYS[1])
+        return {redis.call('SCARD', KEYS[1]), members[2], err.err, redis.call('GET', 'missing')}
+    ";
+    assert_run_with_storage(
+        command(&["EVAL", script, "1", "s"]),
+        Resp::Array(Array(vec![
+            Resp::Integer(Integer(2)),
+            Resp::BulkString(BulkString(Some("b".to_string()))),
+            Resp::BulkString(BulkString(Some(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+            ))),
+            Resp::BulkString(BulkString(None)),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["EVAL", "return redis.error_reply('MY failure')", "0"]),
+        Resp::SimpleError(SimpleError("MY failure".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let error = |args: &[&str]| error_message(command(args).run_now(&storage));
+
+    assert_eq!(
+        error(&["EVAL", "return redis.call('GET', KEYS[1])", "1", "s"]),
+        format!(
+            "WRONGTYPE Operation against a key holding the wrong kind of value script: {}, on \
+             @user_script:1.",
+            sha1_hex(b"return redis.call('GET', KEYS[1])")
+        )
+    );
+    assert_eq!(
+        error(&["EVAL", "local x = 1\nerror('boom')", "0"]),
+        format!(
+            "ERR user_script:2: boom script: {}, on @user_script:2.",
+            sha1_hex(b"local x = 1\nerror('boom')")
+        )
+    );
+    assert_eq!(
+        error(&["EVAL", "return undefined", "0"]),
+        format!(
+            "ERR user_script:1: Script attempted to access nonexistent global variable \
+             'undefined' script: {}, on @user_script:1.",
+            sha1_hex(b"return undefined")
+        )
+    );
+    assert_eq!(
+        error(&["EVAL", "leak = 1", "0"]),
+        format!(
+            "ERR user_script:1: Attempt to modify a readonly table script: {}, on \
+             @user_script:1.",
+            sha1_hex(b"leak = 1")
+        )
+    );
+    assert_eq!(
+        error(&["EVAL", "return +", "0"]),
+        "ERR Error compiling script (new function): user_script:1: unexpected symbol near '+'"
+    );
+    assert_eq!(
+        error(&["EVAL", "return 1", "2", "a"]),
+        "ERR Number of keys can't be greater than number of args"
+    );
+
+    // a script that writes despite its no-writes flag fails, also when cached
+    let read_only = "#!lua flags=no-writes,allow-stale\nreturn redis.call('SET', 'k', 'v')";
+    let sha = sha1_hex(read_only.as_bytes());
+    assert_eq!(
+        error(&["EVAL", read_only, "0"]),
+        format!(
+            "ERR Write commands are not allowed from read-only scripts. script: {}, on \
+             @user_script:2.",
+            sha
+        )
+    );
+    assert!(error(&["EVALSHA", &sha, "0"]).starts_with("ERR Write commands are not allowed"));
+    assert!(storage.read().unwrap().get(&bulk("k"))?.is_none());
+    assert_eq!(
+        command(&[
+            "EVAL",
+            "#!lua flags=no-writes\nreturn redis.call('SCARD', 's')",
+            "0"
+        ])
+        .run_now(&storage),
+        Resp::Integer(Integer(2))
+    );
+    assert_eq!(
+        error(&["EVAL", "#!js\nreturn 1", "0"]),
+        "ERR Unexpected engine in script shebang: #!js"
+    );
+    assert_eq!(
+        error(&["EVAL", "#!lua name=lib\nreturn 1", "0"]),
+        "ERR Unknown lua shebang option: name=lib"
+    );
+    assert_eq!(
+        error(&["EVAL", "#!lua flags=fast\nreturn 1", "0"]),
+        "ERR Unexpected flag in script shebang: fast"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_evalsha_and_script() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    let sha = sha1_hex(b"return ARGV[1]");
+
+    assert_run_with_storage(
+        command(&["SCRIPT", "LOAD", "return ARGV[1]"]),
+        Resp::BulkString(BulkString(Some(sha.clone()))),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["EVALSHA", &sha.to_uppercase(), "0", "hello"]),
+        Resp::BulkString(BulkString(Some("hello".to_string()))),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SCRIPT", "EXISTS", &sha, "ffff"]),
+        Resp::Array(Array(vec![
+            Resp::Integer(Integer(1)),
+            Resp::Integer(Integer(0)),
+        ])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["SCRIPT", "FLUSH"]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let error = error_message(command(&["EVALSHA", &sha, "0"]).run_now(&storage));
+    assert_eq!(error, "NOSCRIPT No matching script. Please use EVAL.");
+
+    let error = error_message(command(&["SCRIPT", "KILL"]).run_now(&storage));
+    assert_eq!(error, "NOTBUSY No scripts in execution right now.");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_functions() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+
+    let library = "#!lua name=counters
+        local function incr(keys, args)
+            local count = tonumber(redis.call('GET', keys[1]) or '0') + args[1]
+            redis.call('SET', keys[1], count)
+            return count
+        end
+        redis.register_function('incr_by', incr)
+        redis.register_function{
+            function_name = 'count',
+            callback = function(keys) return redis.call('GET', keys[1]) end,
+            flags = {'no-writes'},
+        }
+        redis.register_function{
+            function_name = 'sneaky',
+            callback = function(keys) return redis.call('SET', keys[1], 'x') end,
+            flags = {'no-writes'},
+        }";
+
+    assert_run_with_storage(
+        command(&["FUNCTION", "LOAD", library]),
+        Resp::BulkString(BulkString(Some("counters".to_string()))),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let error = error_message(command(&["FUNCTION", "LOAD", library]).run_now(&storage));
+    assert_eq!(error, "ERR Library 'counters' already exists");
+
+    assert_run_with_storage(
+        command(&["FCALL", "incr_by", "1", "c", "5"]),
+        Resp::Integer(Integer(5)),
+        Arc::clone(&storage),
+    )
+    .await?;
+    assert_run_with_storage(
+        command(&["FCALL_RO", "count", "1", "c"]),
+        Resp::BulkString(BulkString(Some("5".to_string()))),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let error = error_message(command(&["FCALL_RO", "incr_by", "1", "c", "1"]).run_now(&storage));
+    assert_eq!(
+        error,
+        "ERR Can not execute a script with write flag using *_ro command."
+    );
+
+    let error = error_message(command(&["FCALL", "sneaky", "1", "c"]).run_now(&storage));
+    assert_eq!(
+        error,
+        "ERR Write commands are not allowed from read-only scripts. script: sneaky, on \
+         @user_function:15."
+    );
+
+    // libraries survive FLUSHALL, and can be dumped and restored
+    assert_run_with_storage(
+        command(&["FLUSHALL"]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    let payload = match command(&["FUNCTION", "DUMP"]).run_now(&storage) {
+        Resp::BulkString(BulkString(Some(payload))) => payload,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    assert_run_with_storage(
+        command(&["FUNCTION", "DELETE", "counters"]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+    let error = error_message(command(&["FCALL", "count", "1", "c"]).run_now(&storage));
+    assert_eq!(error, "ERR Function not found");
+
+    assert_run_with_storage(
+        command(&["FUNCTION", "RESTORE", &payload]),
+        Resp::SimpleString(SimpleString("OK".to_string())),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    assert_run_with_storage(
+        command(&["FUNCTION", "LIST", "LIBRARYNAME", "count*"]),
+        Resp::Array(Array(vec![Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("library_name".to_string()))),
+            Resp::BulkString(BulkString(Some("counters".to_string()))),
+            Resp::BulkString(BulkString(Some("engine".to_string()))),
+            Resp::BulkString(BulkString(Some("LUA".to_string()))),
+            Resp::BulkString(BulkString(Some("functions".to_string()))),
+            Resp::Array(Array(
+                [
+                    ("count", &["no-writes"][..]),
+                    ("incr_by", &[]),
+                    ("sneaky", &["no-writes"]),
+                ]
+                .into_iter()
+                .map(|(name, flags)| {
+                    Resp::Array(Array(vec![
+                        Resp::BulkString(BulkString(Some("name".to_string()))),
+                        Resp::BulkString(BulkString(Some(name.to_string()))),
+                        Resp::BulkString(BulkString(Some("description".to_string()))),
+                        Resp::BulkString(BulkString(None)),
+                        Resp::BulkString(BulkString(Some("flags".to_string()))),
+                        bulk_strings(flags),
+                    ]))
+                })
+                .collect(),
+            )),
+        ]))])),
+        Arc::clone(&storage),
+    )
+    .await?;
+
+    // replicas only run read-only functions
+    storage.write().unwrap().replication = Replication::Slave {
+        master_host: "localhost".to_string(),
+        master_port: 6379,
+    };
+    assert_run_with_storage(
+        command(&["FCALL", "count", "1", "c"]),
+        Resp::BulkString(BulkString(None)),
+        Arc::clone(&storage),
+    )
+    .await?;
+    let error = error_message(command(&["FCALL", "incr_by", "1", "c", "1"]).run_now(&storage));
+    assert_eq!(
+        error,
+        "READONLY You can't write against a read only replica."
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_keyspace_notifications() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    storage.write().unwrap().keyspace_events = KeyspaceEvents::parse("KEA")?;
+
+    let (outbox, mut messages) = tokio::sync::mpsc::unbounded_channel();
+    storage
+        .write()
+        .unwrap()
+        .pubsub
+        .psubscribe("__key*__:*", &outbox);
+
+    let mut events = || {
+        let mut events = Vec::new();
+        while let Ok(Resp::Array(Array(parts))) = messages.try_recv() {
+            let part = |i: usize| parts[i].plain_string().unwrap().to_string();
+            events.push(format!("{} {}", part(2), part(3)));
+        }
+        events
+    };
+
+    command(&["SADD", "s", "a", "b"]).run_now(&storage);
+    command(&["SREM", "s", "a", "b"]).run_now(&storage);
+    assert_eq!(
+        events(),
+        vec![
+            "__keyspace@0__:s sadd",
+            "__keyevent@0__:sadd s",
+            "__keyspace@0__:s srem",
+            "__keyevent@0__:srem s",
+            "__keyspace@0__:s del",
+            "__keyevent@0__:del s",
+        ]
+    );
+
+    // nothing is published for commands that change nothing
+    command(&["SREM", "s", "a"]).run_now(&storage);
+    command(&["ZADD", "y", "XX", "1", "a"]).run_now(&storage);
+    command(&["GEOADD", "y", "XX", "1", "1", "a"]).run_now(&storage);
+    command(&["ZADD", "z", "1", "a"]).run_now(&storage);
+    command(&["ZADD", "z", "1", "a"]).run_now(&storage);
+    assert_eq!(
+        events(),
+        vec!["__keyspace@0__:z zadd", "__keyevent@0__:zadd z"]
+    );
+
+    command(&["SET", "k", "v", "PX", "10"]).run_now(&storage);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    storage.write().unwrap().remove_expired();
+    assert_eq!(
+        events(),
+        vec![
+            "__keyspace@0__:k set",
+            "__keyevent@0__:set k",
+            "__keyspace@0__:k expire",
+            "__keyevent@0__:expire k",
+            "__keyspace@0__:k expired",
+            "__keyevent@0__:expired k",
+        ]
+    );
+
+    // only the selected classes are published, here expired keys on keyevent channels
+    storage.write().unwrap().keyspace_events = KeyspaceEvents::parse("Ex")?;
+    command(&["XADD", "x", "*", "f", "v"]).run_now(&storage);
+    command(&["SET", "k", "v", "PX", "10"]).run_now(&storage);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    storage.write().unwrap().remove_expired();
+    assert_eq!(events(), vec!["__keyevent@0__:expired k"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_save() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("test-save-{}.rdb", std::process::id()));
+    let storage: RwLock<Storage> = Default::default();
+    storage.write().unwrap().saves = Arc::new(SaveState::new(path.clone()));
+
+    let run = |args: &[&str]| command(args).run_now(&storage);
+
+    let last_save = || match run(&["LASTSAVE"]) {
+        Resp::Integer(Integer(last_save)) => last_save,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    let persistence = || match run(&["INFO", "persistence"]) {
+        Resp::BulkString(BulkString(Some(info))) => info,
+        reply => panic!("unexpected reply {:?}", reply),
+    };
+
+    let started = last_save();
+    assert_eq!(
+        run(&["SAVE", "now"]),
+        Resp::SimpleError(SimpleError(
+            "ERR wrong number of arguments for 'save' command".to_string()
+        ))
+    );
+    run(&["SET", "key", "value"]);
+    assert!(persistence().contains("rdb_changes_since_last_save:1\n"));
+    assert_eq!(
+        run(&["SAVE"]),
+        Resp::SimpleString(SimpleString("OK".to_string()))
+    );
+    assert!(persistence().contains("rdb_changes_since_last_save:0\n"));
+    let rdb = std::fs::read(&path)?;
+    assert!(rdb.starts_with(b"REDIS0011"));
+    assert!(rdb.windows(10).any(|window| window == b"\x03key\x05value"));
+
+    assert!(last_save() >= started);
+
+    run(&["SET", "other", "value"]);
+    assert_eq!(
+        run(&["BGSAVE"]),
+        Resp::SimpleString(SimpleString("Background saving started".to_string()))
+    );
+    while storage.read().unwrap().saves.in_progress() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    let rdb = std::fs::read(&path)?;
+    assert!(rdb.windows(6).any(|window| window == b"\x05other"));
+    assert!(persistence().contains("rdb_changes_since_last_save:0\n"));
+    assert!(persistence().contains("rdb_last_bgsave_status:ok\n"));
+    assert!(persistence().contains("aof_enabled:0\n"));
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_shutdown_errors() {
+    let storage: RwLock<Storage> = Default::default();
+    let path = std::env::temp_dir().join("missing-dir").join("dump.rdb");
+    storage.write().unwrap().saves = Arc::new(SaveState::new(path));
+
+    let error = |args: &[&str]| error_message(command(args).run_now(&storage));
+
+    assert_eq!(error(&["SHUTDOWN", "NOSAVE", "SAVE"]), "ERR syntax error");
+    assert_eq!(error(&["SHUTDOWN", "ABORT"]), "ERR syntax error");
+    // a server that can't save its data keeps running
+    assert_eq!(
+        error(&["SHUTDOWN", "SAVE"]),
+        "ERR Errors trying to SHUTDOWN. Check logs."
+    );
+}
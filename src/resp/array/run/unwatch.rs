@@ -0,0 +1,20 @@
+use std::collections::VecDeque;
+
+use anyhow::{ensure, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Resp, SimpleString};
+
+/// Watched keys belong to the client's session, which handles `UNWATCH` itself. This only runs
+/// when `UNWATCH` was queued in a transaction, whose `EXEC` already forgot the watched keys.
+pub async fn unwatch(args: VecDeque<Resp>) -> Result<RespEffect<'static>> {
+    ensure!(
+        args.is_empty(),
+        "wrong number of arguments for 'unwatch' command"
+    );
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString("OK".to_string()))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{Storage, Stream, StreamId};
+
+pub async fn xack(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let group = args.pop_front().context("missing group")?;
+    let group = group.plain_string()?;
+
+    ensure!(!args.is_empty(), "missing ID");
+
+    let ids = args
+        .iter()
+        .map(|id| StreamId::parse(id.plain_string()?, 0))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut storage = storage.write().unwrap();
+    let acked = match storage
+        .get_as_mut::<Stream>(&key)?
+        .and_then(|stream| stream.group_mut(group))
+    {
+        None => 0,
+        Some(group) => ids.iter().filter(|id| group.ack(id)).count(),
+    };
+    if acked > 0 {
+        storage.touch(&key);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(acked as i64))),
+        post_run_cmd: None,
+    })
+}
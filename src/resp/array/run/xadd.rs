@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::stream_ops::parse_trim;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{BulkString, Resp};
+use crate::storage::{KeyspaceEvents, NewStreamId, Storage, Stream};
+
+pub async fn xadd(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let arg_count = args.len();
+    let key = args.pop_front().context("missing key")?;
+
+    let mut no_mkstream = false;
+    let mut trim = None;
+
+    let (id, id_index) = loop {
+        let arg = args.pop_front().context("missing ID")?;
+
+        match arg.plain_string()?.to_uppercase().as_str() {
+            "NOMKSTREAM" => no_mkstream = true,
+            strategy @ ("MAXLEN" | "MINID") => trim = Some(parse_trim(strategy, &mut args)?),
+            // the name of the command comes before the arguments
+            _ => {
+                break (
+                    NewStreamId::parse(arg.plain_string()?)?,
+                    arg_count - args.len(),
+                )
+            }
+        }
+    };
+
+    if args.is_empty() || args.len() % 2 != 0 {
+        bail!("wrong number of arguments for 'xadd' command");
+    }
+
+    let mut fields = Vec::with_capacity(args.len() / 2);
+    while let (Some(field), Some(value)) = (args.pop_front(), args.pop_front()) {
+        fields.push((
+            field.plain_string()?.to_string(),
+            value.plain_string()?.to_string(),
+        ));
+    }
+
+    let mut storage = storage.write().unwrap();
+
+    let exists = storage.get_as::<Stream>(&key)?.is_some();
+    if no_mkstream && !exists {
+        return Ok(RespEffect {
+            run_result: RespRunResult::Owned(Resp::BulkString(BulkString(None))),
+            post_run_cmd: None,
+        });
+    }
+
+    // a new stream is only stored once its first entry was accepted
+    let mut created = None;
+    let stream = if exists {
+        storage.get_or_default_as_mut::<Stream>(&key)?
+    } else {
+        created.insert(Stream::default())
+    };
+
+    let id = stream.add(id, fields)?;
+    let trimmed = trim.is_some_and(|trim| stream.trim(trim) > 0);
+
+    if let Some(stream) = created {
+        *storage.get_or_default_as_mut::<Stream>(&key)? = stream;
+    }
+    storage.touch(&key);
+
+    // the ID may be generated from the time, so the AOF gets the one that was
+    storage.rewrite_propagated(|command| {
+        command[id_index] = Resp::BulkString(BulkString(Some(id.to_string())));
+    });
+
+    storage.notify(KeyspaceEvents::STREAM, "xadd", &key);
+    if trimmed {
+        storage.notify(KeyspaceEvents::STREAM, "xtrim", &key);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::BulkString(BulkString(Some(id.to_string())))),
+        post_run_cmd: None,
+    })
+}
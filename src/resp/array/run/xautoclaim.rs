@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::{claim_command, entry_reply, no_group_error};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{ClaimOptions, Claimed, Storage, Stream, StreamId};
+use crate::utils::now_ms;
+
+/// Like Redis, scan at most this many PEL entries per entry asked for with COUNT.
+const ATTEMPTS_FACTOR: usize = 10;
+
+pub async fn xautoclaim(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let group = args.pop_front().context("missing group")?;
+    let group = group.plain_string()?;
+    let consumer = args.pop_front().context("missing consumer")?;
+    let consumer = consumer.plain_string()?;
+    let min_idle_time = args
+        .pop_front()
+        .context("missing min-idle-time")?
+        .plain_i64()?;
+    let start = StreamId::parse_start(args.pop_front().context("missing start")?.plain_string()?)?;
+
+    let mut count = 100;
+    let mut just_id = false;
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing COUNT value")?
+                    .plain_i64()?;
+                // larger counts would overflow the number of entries to scan
+                ensure!(
+                    (1..=i64::MAX / ATTEMPTS_FACTOR as i64).contains(&value),
+                    "COUNT must be > 0"
+                );
+                count = value as usize;
+            }
+            "JUSTID" => just_id = true,
+            _ => bail!("syntax error"),
+        }
+    }
+
+    let options = ClaimOptions {
+        min_idle_time: min_idle_time.max(0) as u64,
+        just_id,
+        ..Default::default()
+    };
+    let now = now_ms();
+
+    let mut storage = storage.write().unwrap();
+    let stream = storage
+        .get_as_mut::<Stream>(&key)?
+        .ok_or_else(|| no_group_error(&key, group))?;
+
+    let candidates = stream
+        .group(group)
+        .ok_or_else(|| no_group_error(&key, group))?
+        .pending()
+        .range(start..)
+        .map(|(id, _)| *id)
+        .take(count * ATTEMPTS_FACTOR + 1)
+        .collect::<Vec<_>>();
+
+    let mut claimed = vec![];
+    let mut deleted = vec![];
+    let mut changed = vec![];
+    let mut next_cursor = StreamId::MIN;
+
+    for (attempt, id) in candidates.into_iter().enumerate() {
+        if claimed.len() == count || attempt == count * ATTEMPTS_FACTOR {
+            next_cursor = id;
+            break;
+        }
+
+        match stream
+            .claim(group, consumer, id, &options, now)
+            .ok_or_else(|| no_group_error(&key, group))?
+        {
+            Claimed::Entry(id, _) if just_id => {
+                claimed.push(Resp::BulkString(BulkString(Some(id.to_string()))));
+                changed.push(id);
+            }
+            Claimed::Entry(id, fields) => {
+                claimed.push(entry_reply(&id, &fields));
+                changed.push(id);
+            }
+            Claimed::Deleted(id) => {
+                deleted.push(Resp::BulkString(BulkString(Some(id.to_string()))));
+                changed.push(id);
+            }
+            Claimed::Skipped => {}
+        }
+    }
+
+    if !changed.is_empty() {
+        storage.touch(&key);
+    }
+    storage.rewrite_propagated(|command| {
+        *command = claim_command(&key, group, consumer, &changed, &options, now);
+    });
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some(next_cursor.to_string()))),
+            Resp::Array(Array(claimed)),
+            Resp::Array(Array(deleted)),
+        ]))),
+        post_run_cmd: None,
+    })
+}
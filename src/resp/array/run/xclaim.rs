@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::{claim_command, entry_reply, no_group_error};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{ClaimOptions, Claimed, Storage, Stream, StreamId};
+use crate::utils::now_ms;
+
+pub async fn xclaim(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let group = args.pop_front().context("missing group")?;
+    let group = group.plain_string()?;
+    let consumer = args.pop_front().context("missing consumer")?;
+    let consumer = consumer.plain_string()?;
+    let min_idle_time = args
+        .pop_front()
+        .context("missing min-idle-time")?
+        .plain_i64()?;
+
+    let now = now_ms();
+    let mut options = ClaimOptions {
+        min_idle_time: min_idle_time.max(0) as u64,
+        ..Default::default()
+    };
+    let mut last_id = None;
+
+    let mut ids = vec![];
+    while let Some(arg) = args.front() {
+        match StreamId::parse(arg.plain_string()?, 0) {
+            Ok(id) => ids.push(id),
+            Err(_) => break,
+        }
+        args.pop_front();
+    }
+
+    ensure!(!ids.is_empty(), "missing ID");
+
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "IDLE" => {
+                let idle = args
+                    .pop_front()
+                    .context("missing IDLE value")?
+                    .plain_i64()?;
+                options.delivery_time = Some(now.saturating_sub(idle.max(0) as u64));
+            }
+            "TIME" => {
+                let time = args
+                    .pop_front()
+                    .context("missing TIME value")?
+                    .plain_i64()?;
+                options.delivery_time = Some(time.max(0) as u64);
+            }
+            "RETRYCOUNT" => {
+                let count = args.pop_front().context("missing RETRYCOUNT value")?;
+                let count = count.plain_i64()?;
+                ensure!(count >= 0, "Invalid RETRYCOUNT option argument for XCLAIM");
+                options.retry_count = Some(count as u64);
+            }
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            "LASTID" => {
+                let id = args.pop_front().context("missing LASTID value")?;
+                last_id = Some(StreamId::parse(id.plain_string()?, 0)?);
+            }
+            _ => bail!("Unrecognized XCLAIM option '{}'", option.plain_string()?),
+        }
+    }
+
+    let mut storage = storage.write().unwrap();
+    let stream = storage
+        .get_as_mut::<Stream>(&key)?
+        .ok_or_else(|| no_group_error(&key, group))?;
+
+    let last_id = last_id.filter(|last_id| {
+        stream
+            .group(group)
+            .is_some_and(|group| *last_id > group.last_delivered_id())
+    });
+    if let Some(last_id) = last_id {
+        stream.set_group_id(group, last_id, None);
+    }
+
+    let mut claimed = vec![];
+    let mut changed = vec![];
+    for id in ids {
+        match stream
+            .claim(group, consumer, id, &options, now)
+            .ok_or_else(|| no_group_error(&key, group))?
+        {
+            Claimed::Entry(id, _) if options.just_id => {
+                claimed.push(Resp::BulkString(BulkString(Some(id.to_string()))));
+                changed.push(id);
+            }
+            Claimed::Entry(id, fields) => {
+                claimed.push(entry_reply(&id, &fields));
+                changed.push(id);
+            }
+            Claimed::Deleted(id) => changed.push(id),
+            Claimed::Skipped => {}
+        }
+    }
+
+    if !changed.is_empty() || last_id.is_some() {
+        storage.touch(&key);
+    }
+    let time = options.delivery_time.unwrap_or(now);
+    storage.rewrite_propagated(|command| {
+        *command = claim_command(&key, group, consumer, &changed, &options, time);
+
+        let Some(last_id) = last_id else {
+            return;
+        };
+        let bulk = |s: &str| Resp::BulkString(BulkString(Some(s.to_string())));
+        if command.is_empty() {
+            // XCLAIM needs an ID to claim, so the group's last ID is set on its own
+            let key = key.plain_string().unwrap_or_default();
+            command.extend([bulk("XGROUP"), bulk("SETID"), bulk(key), bulk(group)]);
+        } else {
+            command.push(bulk("LASTID"));
+        }
+        command.push(bulk(&last_id.to_string()));
+    });
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(claimed))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, Storage, Stream, StreamId};
+
+pub async fn xdel(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    ensure!(!args.is_empty(), "missing ID");
+
+    let ids = args
+        .iter()
+        .map(|id| StreamId::parse(id.plain_string()?, 0))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut storage = storage.write().unwrap();
+    let deleted = match storage.get_as_mut::<Stream>(&key)? {
+        None => 0,
+        Some(stream) => ids.iter().filter(|id| stream.delete(id)).count(),
+    };
+    if deleted > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::STREAM, "xdel", &key);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(deleted as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::no_group_error;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp, SimpleString};
+use crate::storage::{KeyspaceEvents, Storage, Stream, StreamId};
+use crate::utils::now_ms;
+
+pub async fn xgroup(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let subcommand = args.pop_front().context("missing subcommand")?;
+    let subcommand = subcommand.plain_string()?.to_uppercase();
+
+    let key = args.pop_front().context("missing key")?;
+    let group = args.pop_front().context("missing group")?;
+    let group = group.plain_string()?;
+
+    let mut storage = storage.write().unwrap();
+
+    let reply = match subcommand.as_str() {
+        "CREATE" | "SETID" => {
+            let id = args.pop_front().context("missing ID")?;
+
+            let mut mkstream = false;
+            let mut entries_read = None;
+            while let Some(option) = args.pop_front() {
+                match option.plain_string()?.to_uppercase().as_str() {
+                    "MKSTREAM" if subcommand == "CREATE" => mkstream = true,
+                    "ENTRIESREAD" => {
+                        let value = args.pop_front().context("missing ENTRIESREAD value")?;
+                        let value = value.plain_i64()?;
+                        ensure!(value >= 0, "value for ENTRIESREAD must be positive or -1");
+                        entries_read = Some(value as u64);
+                    }
+                    _ => bail!("syntax error"),
+                }
+            }
+
+            // `$` is the last ID of the stream, which may not exist yet
+            let id = match id.plain_string()? {
+                "$" => None,
+                id => Some(StreamId::parse(id, 0)?),
+            };
+
+            let stream = if mkstream {
+                storage.get_or_default_as_mut::<Stream>(&key)?
+            } else {
+                storage.get_as_mut::<Stream>(&key)?.context(
+                    "The XGROUP subcommand requires the key to exist. Note that for CREATE you \
+                     may want to use the MKSTREAM option to create an empty stream \
+                     automatically.",
+                )?
+            };
+
+            let id = id.unwrap_or_else(|| stream.last_id());
+
+            if subcommand == "CREATE" {
+                ensure!(
+                    stream.create_group(group, id, entries_read),
+                    "BUSYGROUP Consumer Group name already exists"
+                );
+            } else if !stream.set_group_id(group, id, entries_read) {
+                return Err(no_group_error(&key, group));
+            }
+            storage.touch(&key);
+            storage.notify(
+                KeyspaceEvents::STREAM,
+                &format!("xgroup-{}", subcommand.to_lowercase()),
+                &key,
+            );
+
+            Resp::SimpleString(SimpleString("OK".to_string()))
+        }
+        "DESTROY" => {
+            let destroyed = match storage.get_as_mut::<Stream>(&key)? {
+                None => false,
+                Some(stream) => stream.destroy_group(group),
+            };
+            if destroyed {
+                storage.touch(&key);
+                storage.notify(KeyspaceEvents::STREAM, "xgroup-destroy", &key);
+            }
+
+            Resp::Integer(Integer(destroyed as i64))
+        }
+        "CREATECONSUMER" | "DELCONSUMER" => {
+            let consumer = args.pop_front().context("missing consumer")?;
+            let consumer = consumer.plain_string()?;
+
+            let group_entry = storage
+                .get_as_mut::<Stream>(&key)?
+                .and_then(|stream| stream.group_mut(group))
+                .ok_or_else(|| no_group_error(&key, group))?;
+
+            let (reply, changed) = if subcommand == "CREATECONSUMER" {
+                let created = group_entry.create_consumer(consumer, now_ms());
+                (created as i64, created)
+            } else {
+                match group_entry.delete_consumer(consumer) {
+                    Some(pending) => (pending as i64, true),
+                    None => (0, false),
+                }
+            };
+            if changed {
+                storage.touch(&key);
+                storage.notify(
+                    KeyspaceEvents::STREAM,
+                    &format!("xgroup-{}", subcommand.to_lowercase()),
+                    &key,
+                );
+            }
+
+            Resp::Integer(Integer(reply))
+        }
+        _ => bail!("unknown subcommand {}", subcommand),
+    };
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
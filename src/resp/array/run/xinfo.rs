@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::{entries_reply, entry_reply, no_group_error};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::storage::{ConsumerGroup, Fields, Storage, Stream, StreamId, STREAM_NODE_MAX_ENTRIES};
+use crate::utils::now_ms;
+
+pub async fn xinfo(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let subcommand = args.pop_front().context("missing subcommand")?;
+
+    let reply = match subcommand.plain_string()?.to_uppercase().as_str() {
+        "STREAM" => xinfo_stream(args, storage)?,
+        "GROUPS" => xinfo_groups(args, storage)?,
+        "CONSUMERS" => xinfo_consumers(args, storage)?,
+        _ => bail!("unknown subcommand {}", subcommand.plain_string()?),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
+
+fn xinfo_stream(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<Resp> {
+    let key = args.pop_front().context("missing key")?;
+
+    let full = match args.pop_front() {
+        None => None,
+        Some(option) if option.plain_string()?.eq_ignore_ascii_case("FULL") => {
+            match args.pop_front() {
+                None => Some(10),
+                Some(option) if option.plain_string()?.eq_ignore_ascii_case("COUNT") => {
+                    let count = args
+                        .pop_front()
+                        .context("missing COUNT value")?
+                        .plain_i64()?;
+                    ensure!(count >= 0, "value is out of range, must be positive");
+                    // COUNT 0 returns every entry
+                    Some(count as usize)
+                }
+                Some(_) => bail!("syntax error"),
+            }
+        }
+        Some(_) => bail!("syntax error"),
+    };
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let storage = storage.read().unwrap();
+    let stream = storage.get_as::<Stream>(&key)?.context("no such key")?;
+
+    let field = |name: &str| Resp::BulkString(BulkString(Some(name.to_string())));
+    let id = |id: StreamId| Resp::BulkString(BulkString(Some(id.to_string())));
+    let integer = |i: usize| Resp::Integer(Integer(i as i64));
+
+    // entries are not stored in a radix tree of listpacks, so report what Redis would use
+    let radix_tree_keys = stream.len().div_ceil(STREAM_NODE_MAX_ENTRIES);
+
+    let mut reply = vec![
+        field("length"),
+        integer(stream.len()),
+        field("radix-tree-keys"),
+        integer(radix_tree_keys),
+        field("radix-tree-nodes"),
+        integer(radix_tree_keys + 1),
+        field("last-generated-id"),
+        id(stream.last_id()),
+        field("max-deleted-entry-id"),
+        id(stream.max_deleted_id()),
+        field("entries-added"),
+        integer(stream.entries_added() as usize),
+        field("recorded-first-entry-id"),
+        id(stream
+            .first_entry()
+            .map_or(StreamId::MIN, |(first_id, _)| *first_id)),
+    ];
+
+    match full {
+        None => {
+            let entry_or_nil = |entry: Option<(&StreamId, &Fields)>| {
+                entry.map_or(Resp::BulkString(BulkString(None)), |(id, fields)| {
+                    entry_reply(id, fields)
+                })
+            };
+
+            reply.extend([
+                field("groups"),
+                integer(stream.groups().len()),
+                field("first-entry"),
+                entry_or_nil(stream.first_entry()),
+                field("last-entry"),
+                entry_or_nil(stream.last_entry()),
+            ]);
+        }
+        Some(count) => {
+            let count = (count > 0).then_some(count);
+
+            reply.extend([
+                field("entries"),
+                entries_reply(stream.range(StreamId::MIN, StreamId::MAX, false, count)),
+                field("groups"),
+                Resp::Array(Array(
+                    stream
+                        .groups()
+                        .iter()
+                        .map(|(name, group)| full_group_reply(stream, name, group, count))
+                        .collect(),
+                )),
+            ]);
+        }
+    }
+
+    Ok(Resp::Array(Array(reply)))
+}
+
+fn full_group_reply(
+    stream: &Stream,
+    name: &str,
+    group: &ConsumerGroup,
+    count: Option<usize>,
+) -> Resp {
+    let count = count.unwrap_or(usize::MAX);
+
+    let pending = group
+        .pending()
+        .iter()
+        .take(count)
+        .map(|(id, entry)| {
+            Resp::Array(Array(vec![
+                bulk_string(&id.to_string()),
+                bulk_string(&entry.consumer),
+                integer(entry.delivery_time as i64),
+                integer(entry.delivery_count as i64),
+            ]))
+        })
+        .collect();
+
+    let consumers = group
+        .consumers()
+        .iter()
+        .map(|(name, consumer)| {
+            let pending = consumer
+                .pending()
+                .iter()
+                .take(count)
+                .map(|id| {
+                    let entry = &group.pending()[id];
+                    Resp::Array(Array(vec![
+                        bulk_string(&id.to_string()),
+                        integer(entry.delivery_time as i64),
+                        integer(entry.delivery_count as i64),
+                    ]))
+                })
+                .collect();
+
+            Resp::Array(Array(vec![
+                bulk_string("name"),
+                bulk_string(name),
+                bulk_string("seen-time"),
+                integer(consumer.seen_time as i64),
+                bulk_string("active-time"),
+                integer(consumer.active_time.map_or(-1, |time| time as i64)),
+                bulk_string("pel-count"),
+                integer(consumer.pending().len() as i64),
+                bulk_string("pending"),
+                Resp::Array(Array(pending)),
+            ]))
+        })
+        .collect();
+
+    Resp::Array(Array(vec![
+        bulk_string("name"),
+        bulk_string(name),
+        bulk_string("last-delivered-id"),
+        bulk_string(&group.last_delivered_id().to_string()),
+        bulk_string("entries-read"),
+        integer_or_nil(group.entries_read()),
+        bulk_string("lag"),
+        integer_or_nil(stream.lag(group)),
+        bulk_string("pel-count"),
+        integer(group.pending().len() as i64),
+        bulk_string("pending"),
+        Resp::Array(Array(pending)),
+        bulk_string("consumers"),
+        Resp::Array(Array(consumers)),
+    ]))
+}
+
+fn xinfo_groups(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<Resp> {
+    let key = args.pop_front().context("missing key")?;
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let storage = storage.read().unwrap();
+    let stream = storage.get_as::<Stream>(&key)?.context("no such key")?;
+
+    let groups = stream
+        .groups()
+        .iter()
+        .map(|(name, group)| {
+            Resp::Array(Array(vec![
+                bulk_string("name"),
+                bulk_string(name),
+                bulk_string("consumers"),
+                integer(group.consumers().len() as i64),
+                bulk_string("pending"),
+                integer(group.pending().len() as i64),
+                bulk_string("last-delivered-id"),
+                bulk_string(&group.last_delivered_id().to_string()),
+                bulk_string("entries-read"),
+                integer_or_nil(group.entries_read()),
+                bulk_string("lag"),
+                integer_or_nil(stream.lag(group)),
+            ]))
+        })
+        .collect();
+
+    Ok(Resp::Array(Array(groups)))
+}
+
+fn xinfo_consumers(mut args: VecDeque<Resp>, storage: &RwLock<Storage>) -> Result<Resp> {
+    let key = args.pop_front().context("missing key")?;
+    let group_name = args.pop_front().context("missing group")?;
+    let group_name = group_name.plain_string()?;
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let storage = storage.read().unwrap();
+    let group = storage
+        .get_as::<Stream>(&key)?
+        .and_then(|stream| stream.group(group_name))
+        .ok_or_else(|| no_group_error(&key, group_name))?;
+
+    let now = now_ms();
+    let consumers = group
+        .consumers()
+        .iter()
+        .map(|(name, consumer)| {
+            Resp::Array(Array(vec![
+                bulk_string("name"),
+                bulk_string(name),
+                bulk_string("pending"),
+                integer(consumer.pending().len() as i64),
+                bulk_string("idle"),
+                integer(now.saturating_sub(consumer.seen_time) as i64),
+                bulk_string("inactive"),
+                integer(
+                    consumer
+                        .active_time
+                        .map_or(-1, |time| now.saturating_sub(time) as i64),
+                ),
+            ]))
+        })
+        .collect();
+
+    Ok(Resp::Array(Array(consumers)))
+}
+
+fn bulk_string(s: &str) -> Resp {
+    Resp::BulkString(BulkString(Some(s.to_string())))
+}
+
+fn integer(i: i64) -> Resp {
+    Resp::Integer(Integer(i))
+}
+
+fn integer_or_nil(i: Option<u64>) -> Resp {
+    i.map_or(Resp::BulkString(BulkString(None)), |i| integer(i as i64))
+}
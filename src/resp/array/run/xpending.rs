@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::stream_ops::no_group_error;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::storage::{Storage, Stream, StreamId};
+use crate::utils::now_ms;
+
+pub async fn xpending(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let group_name = args.pop_front().context("missing group")?;
+    let group_name = group_name.plain_string()?;
+
+    let storage = storage.read().unwrap();
+    let group = storage
+        .get_as::<Stream>(&key)?
+        .and_then(|stream| stream.group(group_name))
+        .ok_or_else(|| no_group_error(&key, group_name))?;
+
+    let id = |id: &StreamId| Resp::BulkString(BulkString(Some(id.to_string())));
+
+    // the summary form
+    if args.is_empty() {
+        let pending = group.pending();
+
+        let reply = match (pending.first_key_value(), pending.last_key_value()) {
+            (Some((first, _)), Some((last, _))) => {
+                let mut per_consumer = BTreeMap::<&str, usize>::new();
+                for entry in pending.values() {
+                    *per_consumer.entry(&entry.consumer).or_default() += 1;
+                }
+
+                vec![
+                    Resp::Integer(Integer(pending.len() as i64)),
+                    id(first),
+                    id(last),
+                    Resp::Array(Array(
+                        per_consumer
+                            .into_iter()
+                            .map(|(consumer, count)| {
+                                Resp::Array(Array(vec![
+                                    Resp::BulkString(BulkString(Some(consumer.to_string()))),
+                                    Resp::BulkString(BulkString(Some(count.to_string()))),
+                                ]))
+                            })
+                            .collect(),
+                    )),
+                ]
+            }
+            _ => vec![
+                Resp::Integer(Integer(0)),
+                Resp::BulkString(BulkString(None)),
+                Resp::BulkString(BulkString(None)),
+                Resp::NullArray,
+            ],
+        };
+
+        return Ok(RespEffect {
+            run_result: RespRunResult::Owned(Resp::Array(Array(reply))),
+            post_run_cmd: None,
+        });
+    }
+
+    let mut min_idle_time = 0;
+    if args
+        .front()
+        .map(|option| option.plain_string().unwrap_or_default())
+        .is_some_and(|option| option.eq_ignore_ascii_case("IDLE"))
+    {
+        args.pop_front();
+        min_idle_time = args
+            .pop_front()
+            .context("missing IDLE value")?
+            .plain_i64()?;
+    }
+
+    let start = StreamId::parse_start(args.pop_front().context("missing start")?.plain_string()?)?;
+    let end = StreamId::parse_end(args.pop_front().context("missing end")?.plain_string()?)?;
+    let count = args.pop_front().context("missing count")?.plain_i64()?;
+    let consumer = args.pop_front();
+    let consumer = consumer.as_ref().map(Resp::plain_string).transpose()?;
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let now = now_ms();
+    let entries = if start > end {
+        vec![]
+    } else {
+        group
+            .pending()
+            .range(start..=end)
+            .filter(|(_, entry)| consumer.map_or(true, |consumer| entry.consumer == consumer))
+            .filter(|(_, entry)| now.saturating_sub(entry.delivery_time) as i64 >= min_idle_time)
+            .take(count.max(0) as usize)
+            .map(|(entry_id, entry)| {
+                Resp::Array(Array(vec![
+                    id(entry_id),
+                    Resp::BulkString(BulkString(Some(entry.consumer.clone()))),
+                    Resp::Integer(Integer(now.saturating_sub(entry.delivery_time) as i64)),
+                    Resp::Integer(Integer(entry.delivery_count as i64)),
+                ]))
+            })
+            .collect()
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(entries))),
+        post_run_cmd: None,
+    })
+}
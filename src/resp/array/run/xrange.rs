@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::entries_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::storage::{Storage, Stream, StreamId};
+
+pub async fn xrange(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let start = StreamId::parse_start(args.pop_front().context("missing start")?.plain_string()?)?;
+    let end = StreamId::parse_end(args.pop_front().context("missing end")?.plain_string()?)?;
+
+    let count = match args.pop_front() {
+        None => None,
+        Some(option) if option.plain_string()?.eq_ignore_ascii_case("COUNT") => {
+            let count = args
+                .pop_front()
+                .context("missing COUNT value")?
+                .plain_i64()?;
+            ensure!(count >= 0, "value is out of range, must be positive");
+            Some(count as usize)
+        }
+        Some(_) => bail!("syntax error"),
+    };
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let storage = storage.read().unwrap();
+    let reply = match storage.get_as::<Stream>(&key)? {
+        None => entries_reply([]),
+        Some(stream) => entries_reply(stream.range(start, end, false, count)),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::entries_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, Resp};
+use crate::storage::{block_on_keys, Storage, Stream, StreamId};
+
+pub async fn xread(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let mut count = None;
+    let mut block = None;
+
+    loop {
+        let option = args.pop_front().context("missing STREAMS")?;
+
+        match option.plain_string()?.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing COUNT value")?
+                    .plain_i64()?;
+                // a non-positive count means no limit
+                count = (value > 0).then_some(value as usize);
+            }
+            "BLOCK" => {
+                let value = args
+                    .pop_front()
+                    .context("missing BLOCK value")?
+                    .plain_i64()?;
+                ensure!(value >= 0, "timeout is negative");
+                block = Some(value as u64);
+            }
+            "STREAMS" => break,
+            _ => bail!("syntax error"),
+        }
+    }
+
+    ensure!(
+        !args.is_empty() && args.len() % 2 == 0,
+        "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+    );
+
+    let ids = args.split_off(args.len() / 2);
+    let keys = Vec::from(args);
+
+    // `$` is resolved once, up front, so that a blocked read only returns entries added later
+    let ids = {
+        let storage = storage.read().unwrap();
+
+        keys.iter()
+            .zip(ids)
+            .map(|(key, id)| match id.plain_string()? {
+                "$" => Ok(storage
+                    .get_as::<Stream>(key)?
+                    .map_or(StreamId::MIN, Stream::last_id)),
+                id => StreamId::parse(id, 0),
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let read = |storage: &mut Storage| -> Result<Option<Resp>> {
+        let mut replies = vec![];
+
+        for (key, id) in keys.iter().zip(&ids) {
+            let Some(stream) = storage.get_as::<Stream>(key)? else {
+                continue;
+            };
+
+            let entries = stream.entries_after(*id, count);
+            if !entries.is_empty() {
+                replies.push(Resp::Array(Array(vec![
+                    key.clone(),
+                    entries_reply(entries),
+                ])));
+            }
+        }
+
+        Ok((!replies.is_empty()).then_some(Resp::Array(Array(replies))))
+    };
+
+    let reply = match block {
+        None => read(&mut storage.write().unwrap())?,
+        // BLOCK 0 waits forever
+        Some(ms) => {
+            let timeout = (ms > 0).then(|| Duration::from_millis(ms));
+            block_on_keys(storage, &keys, timeout, read).await?
+        }
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply.unwrap_or(Resp::NullArray)),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::stream_ops::{entry_reply, no_group_error, pending_entry_reply};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, Resp};
+use crate::storage::{block_on_keys, Storage, Stream, StreamId};
+use crate::utils::now_ms;
+
+pub async fn xreadgroup(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let group_option = args.pop_front().context("missing GROUP")?;
+    ensure!(
+        group_option.plain_string()?.eq_ignore_ascii_case("GROUP"),
+        "syntax error"
+    );
+
+    let group = args.pop_front().context("missing group")?;
+    let group = group.plain_string()?.to_string();
+    let consumer = args.pop_front().context("missing consumer")?;
+    let consumer = consumer.plain_string()?.to_string();
+
+    let mut count = None;
+    let mut block = None;
+    let mut no_ack = false;
+
+    loop {
+        let option = args.pop_front().context("missing STREAMS")?;
+
+        match option.plain_string()?.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing COUNT value")?
+                    .plain_i64()?;
+                count = (value > 0).then_some(value as usize);
+            }
+            "BLOCK" => {
+                let value = args
+                    .pop_front()
+                    .context("missing BLOCK value")?
+                    .plain_i64()?;
+                ensure!(value >= 0, "timeout is negative");
+                block = Some(value as u64);
+            }
+            "NOACK" => no_ack = true,
+            "STREAMS" => break,
+            _ => bail!("syntax error"),
+        }
+    }
+
+    ensure!(
+        !args.is_empty() && args.len() % 2 == 0,
+        "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be \
+         specified."
+    );
+
+    let ids = args.split_off(args.len() / 2);
+    let keys = Vec::from(args);
+
+    // `None` stands for `>`, entries never delivered to the group
+    let ids = ids
+        .iter()
+        .map(|id| match id.plain_string()? {
+            ">" => Ok(None),
+            id => StreamId::parse(id, 0).map(Some),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let read = |storage: &mut Storage| -> Result<Option<Resp>> {
+        let mut replies = vec![];
+        let now = now_ms();
+
+        for (key, id) in keys.iter().zip(&ids) {
+            let stream = storage
+                .get_as_mut::<Stream>(key)?
+                .ok_or_else(|| no_group_error(key, &group))?;
+            // reading creates the consumer, which is a change even if nothing is read
+            let new_consumer = stream
+                .group(&group)
+                .is_some_and(|group| !group.consumers().contains_key(consumer.as_str()));
+
+            let entries: Vec<Resp> = match id {
+                None => {
+                    let entries = stream
+                        .read_group_new(&group, &consumer, count, no_ack, now)
+                        .ok_or_else(|| no_group_error(key, &group))?;
+
+                    if entries.is_empty() {
+                        if new_consumer {
+                            storage.touch(key);
+                        }
+                        continue;
+                    }
+
+                    entries
+                        .iter()
+                        .map(|(id, fields)| entry_reply(id, fields))
+                        .collect()
+                }
+                // history is replied to even if it is empty
+                Some(id) => stream
+                    .read_group_history(&group, &consumer, *id, count, now)
+                    .ok_or_else(|| no_group_error(key, &group))?
+                    .iter()
+                    .map(|(id, fields)| pending_entry_reply(id, fields.as_ref()))
+                    .collect(),
+            };
+            if new_consumer || !entries.is_empty() {
+                storage.touch(key);
+            }
+
+            replies.push(Resp::Array(Array(vec![
+                key.clone(),
+                Resp::Array(Array(entries)),
+            ])));
+        }
+
+        Ok((!replies.is_empty()).then_some(Resp::Array(Array(replies))))
+    };
+
+    // only reads of new entries can block, the history is available right away
+    let reply = match block {
+        Some(ms) if ids.iter().all(Option::is_none) => {
+            let timeout = (ms > 0).then(|| Duration::from_millis(ms));
+            block_on_keys(storage, &keys, timeout, read).await?
+        }
+        _ => read(&mut storage.write().unwrap())?,
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply.unwrap_or(Resp::NullArray)),
+        post_run_cmd: None,
+    })
+}
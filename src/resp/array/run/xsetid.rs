@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Resp, SimpleString};
+use crate::storage::{KeyspaceEvents, Storage, Stream, StreamId};
+
+pub async fn xsetid(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let last_id = args.pop_front().context("missing ID")?;
+    let last_id = StreamId::parse(last_id.plain_string()?, 0)?;
+
+    let mut entries_added = None;
+    let mut max_deleted_id = None;
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "ENTRIESADDED" => {
+                let value = args.pop_front().context("missing ENTRIESADDED value")?;
+                let value = value.plain_i64()?;
+                ensure!(value >= 0, "entries_added must be positive");
+                entries_added = Some(value as u64);
+            }
+            "MAXDELETEDID" => {
+                let id = args.pop_front().context("missing MAXDELETEDID value")?;
+                max_deleted_id = Some(StreamId::parse(id.plain_string()?, 0)?);
+            }
+            _ => bail!("syntax error"),
+        }
+    }
+
+    let mut storage = storage.write().unwrap();
+    storage
+        .get_as_mut::<Stream>(&key)?
+        .context("no such key")?
+        .set_id(last_id, entries_added, max_deleted_id)?;
+    storage.touch(&key);
+    storage.notify(KeyspaceEvents::STREAM, "xsetid", &key);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::SimpleString(SimpleString("OK".to_string()))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::stream_ops::parse_trim;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, Storage, Stream};
+
+pub async fn xtrim(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let strategy = args.pop_front().context("missing trim strategy")?;
+    let trim = parse_trim(&strategy.plain_string()?.to_uppercase(), &mut args)?;
+
+    if !args.is_empty() {
+        bail!("syntax error");
+    }
+
+    let mut storage = storage.write().unwrap();
+    let removed = match storage.get_as_mut::<Stream>(&key)? {
+        None => 0,
+        Some(stream) => stream.trim(trim),
+    };
+    if removed > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::STREAM, "xtrim", &key);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(removed as i64))),
+        post_run_cmd: None,
+    })
+}
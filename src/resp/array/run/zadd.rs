@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::zset_ops::score_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{BulkString, Integer, Resp};
+use crate::storage::{parse_score, KeyspaceEvents, SortedSet, Storage};
+
+pub async fn zadd(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let mut nx = false;
+    let mut xx = false;
+    let mut gt = false;
+    let mut lt = false;
+    let mut ch = false;
+    let mut incr = false;
+
+    while let Some(option) = args.front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            "CH" => ch = true,
+            "INCR" => incr = true,
+            _ => break,
+        }
+        args.pop_front();
+    }
+
+    ensure!(
+        !(nx && xx),
+        "XX and NX options at the same time are not compatible"
+    );
+    ensure!(
+        !((gt && lt) || (nx && (gt || lt))),
+        "GT, LT, and/or NX options at the same time are not compatible"
+    );
+
+    if args.is_empty() || args.len() % 2 != 0 {
+        bail!("syntax error");
+    }
+    ensure!(
+        !incr || args.len() == 2,
+        "INCR option supports a single increment-element pair"
+    );
+
+    let mut pairs = Vec::with_capacity(args.len() / 2);
+    while let (Some(score), Some(member)) = (args.pop_front(), args.pop_front()) {
+        pairs.push((
+            parse_score(score.plain_string()?)?,
+            member.plain_string()?.to_string(),
+        ));
+    }
+
+    let mut storage = storage.write().unwrap();
+    // XX only updates, so there is nothing to do without the key
+    if xx && storage.get_as::<SortedSet>(&key)?.is_none() {
+        let reply = if incr {
+            Resp::BulkString(BulkString(None))
+        } else {
+            Resp::Integer(Integer(0))
+        };
+        return Ok(RespEffect {
+            run_result: RespRunResult::Owned(reply),
+            post_run_cmd: None,
+        });
+    }
+    let set = storage.get_or_default_as_mut::<SortedSet>(&key)?;
+
+    let mut added = 0;
+    let mut changed = 0;
+    let mut incr_result = None;
+
+    for (score, member) in pairs {
+        let new_score = match set.score(&member) {
+            None if xx => continue,
+            None => score,
+            Some(_) if nx => continue,
+            Some(old_score) => {
+                let new_score = if incr { old_score + score } else { score };
+                ensure!(!new_score.is_nan(), "resulting score is not a number (NaN)");
+
+                if (gt && new_score <= old_score) || (lt && new_score >= old_score) {
+                    continue;
+                }
+                if new_score != old_score {
+                    changed += 1;
+                }
+                new_score
+            }
+        };
+
+        if set.insert(member, new_score) {
+            added += 1;
+        }
+        incr_result = Some(new_score);
+    }
+    if incr && incr_result.is_some() {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::ZSET, "zincr", &key);
+    } else if added + changed > 0 {
+        storage.touch(&key);
+        storage.notify(KeyspaceEvents::ZSET, "zadd", &key);
+    }
+
+    let reply = if incr {
+        // an increment that was skipped because of NX/XX/GT/LT replies with nil
+        incr_result.map_or(Resp::BulkString(BulkString(None)), score_reply)
+    } else if ch {
+        Resp::Integer(Integer(added + changed))
+    } else {
+        Resp::Integer(Integer(added))
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,27 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{SortedSet, Storage};
+
+pub async fn zcard(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let len = storage.get_as::<SortedSet>(&key)?.map_or(0, SortedSet::len);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,32 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{ScoreBound, SortedSet, Storage};
+
+pub async fn zcount(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let min = ScoreBound::parse(args.pop_front().context("missing min")?.plain_string()?)?;
+    let max = ScoreBound::parse(args.pop_front().context("missing max")?.plain_string()?)?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let count = storage.get_as::<SortedSet>(&key)?.map_or(0, |set| {
+        let (start, end) = set.score_rank_range(min, max);
+        end - start
+    });
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(count as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,31 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::zset_ops::ZSetOpArgs;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, Storage};
+
+pub async fn zdiffstore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let destination = args.pop_front().context("missing destination")?;
+    let op_args = ZSetOpArgs::parse(&mut args, false)?;
+
+    let mut storage = storage.write().unwrap();
+    let result = op_args.difference(&storage)?;
+    let len = result.len();
+
+    storage.insert_as(destination.clone(), result);
+    if len > 0 {
+        storage.notify(KeyspaceEvents::ZSET, "zdiffstore", &destination);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
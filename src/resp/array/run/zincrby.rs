@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::zset_ops::score_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::storage::{parse_score, KeyspaceEvents, SortedSet, Storage};
+
+pub async fn zincrby(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let increment = parse_score(
+        args.pop_front()
+            .context("missing increment")?
+            .plain_string()?,
+    )?;
+    let member = args.pop_front().context("missing member")?;
+    let member = member.plain_string()?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let mut storage = storage.write().unwrap();
+    let set = storage.get_or_default_as_mut::<SortedSet>(&key)?;
+
+    let score = set.score(member).unwrap_or(0.0) + increment;
+    ensure!(!score.is_nan(), "resulting score is not a number (NaN)");
+
+    set.insert(member.to_string(), score);
+    storage.touch(&key);
+    storage.notify(KeyspaceEvents::ZSET, "zincr", &key);
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(score_reply(score)),
+        post_run_cmd: None,
+    })
+}
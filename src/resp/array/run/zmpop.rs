@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::zset_ops::{parse_min_max, pop_first_non_empty, score_reply};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::Storage;
+
+pub async fn zmpop(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let num_keys = args.pop_front().context("missing numkeys")?.plain_i64()?;
+
+    ensure!(num_keys > 0, "numkeys should be greater than 0");
+    ensure!(num_keys as usize <= args.len(), "syntax error");
+
+    let keys = args.drain(..num_keys as usize).collect::<Vec<_>>();
+    let max = parse_min_max(&args.pop_front().context("missing MIN or MAX")?)?;
+
+    let mut count = 1;
+    while let Some(option) = args.pop_front() {
+        match option.plain_string()?.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = args
+                    .pop_front()
+                    .context("missing COUNT value")?
+                    .plain_i64()?;
+                ensure!(value > 0, "count should be greater than 0");
+                count = value as usize;
+            }
+            _ => bail!("syntax error"),
+        }
+    }
+
+    let mut storage = storage.write().unwrap();
+    let reply = match pop_first_non_empty(&mut storage, &keys, count, max)? {
+        None => Resp::NullArray,
+        Some((key, popped)) => Resp::Array(Array(vec![
+            key,
+            Resp::Array(Array(
+                popped
+                    .into_iter()
+                    .map(|(member, score)| {
+                        Resp::Array(Array(vec![
+                            Resp::BulkString(BulkString(Some(member))),
+                            score_reply(score),
+                        ]))
+                    })
+                    .collect(),
+            )),
+        ])),
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use super::zset_ops::score_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{SortedSet, Storage};
+
+pub async fn zmscore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    ensure!(!args.is_empty(), "missing member");
+
+    let storage = storage.read().unwrap();
+    let set = storage.get_as::<SortedSet>(&key)?;
+
+    let replies = args
+        .iter()
+        .map(|member| {
+            let score = match set {
+                None => None,
+                Some(set) => set.score(member.plain_string()?),
+            };
+
+            Ok(score.map_or(Resp::BulkString(BulkString(None)), score_reply))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Array(Array(replies))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use super::zset_ops::{members_reply, pop_first_non_empty};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::storage::Storage;
+
+pub async fn zpopmin(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    zpop(args, storage, false).await
+}
+
+pub async fn zpopmax(
+    args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    zpop(args, storage, true).await
+}
+
+async fn zpop(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+    max: bool,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+
+    let count = match args.pop_front() {
+        None => 1,
+        Some(count) => {
+            let count = count.plain_i64()?;
+            ensure!(count >= 0, "value is out of range, must be positive");
+            count as usize
+        }
+    };
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let mut storage = storage.write().unwrap();
+    let popped = pop_first_non_empty(&mut storage, &[key], count, max)?
+        .map(|(_, popped)| popped)
+        .unwrap_or_default();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(members_reply(
+            popped
+                .iter()
+                .map(|(member, score)| (member.as_str(), *score)),
+            true,
+        )),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,28 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::zset_ops::{members_reply, ZRange};
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::Resp;
+use crate::storage::{SortedSet, Storage};
+
+pub async fn zrange(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let range = ZRange::parse(&mut args)?;
+
+    let storage = storage.read().unwrap();
+    let members = storage
+        .get_as::<SortedSet>(&key)?
+        .map(|set| range.members(set))
+        .unwrap_or_default();
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(members_reply(members, range.with_scores)),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,39 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{ensure, Context, Result};
+
+use super::zset_ops::ZRange;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Integer, Resp};
+use crate::storage::{KeyspaceEvents, SortedSet, Storage};
+
+pub async fn zrangestore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let destination = args.pop_front().context("missing destination")?;
+    let source = args.pop_front().context("missing source")?;
+    let range = ZRange::parse(&mut args)?;
+
+    ensure!(!range.with_scores, "syntax error");
+
+    let mut storage = storage.write().unwrap();
+    let mut result = SortedSet::default();
+    if let Some(set) = storage.get_as::<SortedSet>(&source)? {
+        for (member, score) in range.members(set) {
+            result.insert(member.to_string(), score);
+        }
+    }
+    let len = result.len();
+
+    storage.insert_as(destination.clone(), result);
+    if len > 0 {
+        storage.notify(KeyspaceEvents::ZSET, "zrangestore", &destination);
+    }
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(Resp::Integer(Integer(len as i64))),
+        post_run_cmd: None,
+    })
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::zset_ops::score_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::storage::{SortedSet, Storage};
+
+pub async fn zrank(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let member = args.pop_front().context("missing member")?;
+    let member = member.plain_string()?;
+
+    let with_score = match args.pop_front() {
+        None => false,
+        Some(option) if option.plain_string()?.eq_ignore_ascii_case("WITHSCORE") => true,
+        Some(_) => bail!("syntax error"),
+    };
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let rank = storage
+        .get_as::<SortedSet>(&key)?
+        .and_then(|set| Some((set.rank(member)?, set.score(member)?)));
+
+    let reply = match rank {
+        None => Resp::BulkString(BulkString(None)),
+        Some((rank, score)) => {
+            let rank = Resp::Integer(Integer(rank as i64));
+
+            if with_score {
+                Resp::Array(Array(vec![rank, score_reply(score)]))
+            } else {
+                rank
+            }
+        }
+    };
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(reply),
+        post_run_cmd: None,
+    })
+}
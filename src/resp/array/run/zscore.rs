@@ -0,0 +1,34 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::zset_ops::score_reply;
+use crate::resp::resp_effect::{RespEffect, RespRunResult};
+use crate::resp::{BulkString, Resp};
+use crate::storage::{SortedSet, Storage};
+
+pub async fn zscore(
+    mut args: VecDeque<Resp>,
+    storage: &RwLock<Storage>,
+) -> Result<RespEffect<'static>> {
+    let key = args.pop_front().context("missing key")?;
+    let member = args.pop_front().context("missing member")?;
+    let member = member.plain_string()?;
+
+    if !args.is_empty() {
+        bail!("too many arguments");
+    }
+
+    let storage = storage.read().unwrap();
+    let score = storage
+        .get_as::<SortedSet>(&key)?
+        .and_then(|set| set.score(member));
+
+    Ok(RespEffect {
+        run_result: RespRunResult::Owned(
+            score.map_or(Resp::BulkString(BulkString(None)), score_reply),
+        ),
+        post_run_cmd: None,
+    })
+}
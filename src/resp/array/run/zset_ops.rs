@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{
+    format_score, KeyspaceEvents, LexBound, ScoreBound, SortedSet, Storage, Value, WRONGTYPE,
+};
+
+pub fn score_reply(score: f64) -> Resp {
+    Resp::BulkString(BulkString(Some(format_score(score))))
+}
+
+pub fn members_reply<'a>(
+    members: impl IntoIterator<Item = (&'a str, f64)>,
+    with_scores: bool,
+) -> Resp {
+    let mut replies = vec![];
+
+    for (member, score) in members {
+        replies.push(Resp::BulkString(BulkString(Some(member.to_string()))));
+        if with_scores {
+            replies.push(score_reply(score));
+        }
+    }
+
+    Resp::Array(Array(replies))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// The arguments shared by `ZRANGE` and `ZRANGESTORE`, using the unified Redis 6.2 syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZRange {
+    pub by: RangeBy,
+    pub rev: bool,
+    pub limit: Option<(usize, Option<usize>)>,
+    pub with_scores: bool,
+}
+
+impl ZRange {
+    /// Parses `start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`.
+    pub fn parse(args: &mut VecDeque<Resp>) -> Result<Self> {
+        let start = args.pop_front().context("missing start")?;
+        let stop = args.pop_front().context("missing stop")?;
+
+        let mut by_score = false;
+        let mut by_lex = false;
+        let mut rev = false;
+        let mut limit = None;
+        let mut with_scores = false;
+
+        while let Some(option) = args.pop_front() {
+            match option.plain_string()?.to_uppercase().as_str() {
+                "BYSCORE" => by_score = true,
+                "BYLEX" => by_lex = true,
+                "REV" => rev = true,
+                "WITHSCORES" => with_scores = true,
+                "LIMIT" => {
+                    let offset = args.pop_front().context("missing LIMIT offset")?;
+                    let count = args.pop_front().context("missing LIMIT count")?;
+                    let offset = offset.plain_i64()?;
+                    let count = count.plain_i64()?;
+
+                    // a negative offset returns nothing, a negative count returns everything
+                    limit = Some((
+                        usize::try_from(offset).unwrap_or(usize::MAX),
+                        usize::try_from(count).ok(),
+                    ));
+                }
+                _ => bail!("syntax error"),
+            }
+        }
+
+        ensure!(!(by_score && by_lex), "syntax error");
+        ensure!(
+            limit.is_none() || by_score || by_lex,
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+        );
+        ensure!(
+            !(with_scores && by_lex),
+            "syntax error, WITHSCORES not supported in combination with BYLEX"
+        );
+
+        // with REV the range is given from max to min
+        let (min, max) = if rev && (by_score || by_lex) {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+
+        let by = if by_score {
+            RangeBy::Score(
+                ScoreBound::parse(min.plain_string()?)?,
+                ScoreBound::parse(max.plain_string()?)?,
+            )
+        } else if by_lex {
+            RangeBy::Lex(
+                LexBound::parse(min.plain_string()?)?,
+                LexBound::parse(max.plain_string()?)?,
+            )
+        } else {
+            RangeBy::Rank(min.plain_i64()?, max.plain_i64()?)
+        };
+
+        Ok(ZRange {
+            by,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+
+    pub fn members<'a>(&self, set: &'a SortedSet) -> Vec<(&'a str, f64)> {
+        let len = set.len();
+
+        // the selected ranks as a half-open range in ascending order
+        let (start, end) = match &self.by {
+            RangeBy::Rank(start, stop) => {
+                let normalize = |index: i64| {
+                    if index < 0 {
+                        (len as i64 + index).max(0)
+                    } else {
+                        index
+                    }
+                };
+                let start = normalize(*start) as usize;
+                let stop = normalize(*stop).min(len as i64 - 1);
+
+                if stop < start as i64 {
+                    return vec![];
+                }
+
+                // rank indices count from the end with REV
+                if self.rev {
+                    (len - 1 - stop as usize, len - start)
+                } else {
+                    (start, stop as usize + 1)
+                }
+            }
+            RangeBy::Score(min, max) => set.score_rank_range(*min, *max),
+            RangeBy::Lex(min, max) => set.lex_rank_range(min, max),
+        };
+
+        let (offset, count) = self.limit.unwrap_or((0, None));
+        let count = count.unwrap_or(usize::MAX).min(end - start);
+
+        if start == end || offset >= end - start {
+            return vec![];
+        }
+
+        if self.rev {
+            set.iter_from(end - 1 - offset, true)
+                .take(count.min(end - start - offset))
+                .collect()
+        } else {
+            set.iter_from(start + offset, false)
+                .take(count.min(end - start - offset))
+                .collect()
+        }
+    }
+}
+
+/// How `ZUNIONSTORE` and `ZINTERSTORE` combine the scores of a member found in several inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        let result = match self {
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        };
+
+        // inf + -inf is defined as 0 in Redis
+        if result.is_nan() {
+            0.0
+        } else {
+            result
+        }
+    }
+}
+
+/// The arguments of `ZUNIONSTORE`, `ZINTERSTORE` and `ZDIFFSTORE` that follow the destination.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZSetOpArgs {
+    pub keys: Vec<Resp>,
+    pub weights: Vec<f64>,
+    pub aggregate: Aggregate,
+}
+
+impl ZSetOpArgs {
+    /// Parses `numkeys key [key ...]`, followed by `[WEIGHTS weight [weight ...]]
+    /// [AGGREGATE SUM|MIN|MAX]` if `with_options` is set.
+    pub fn parse(args: &mut VecDeque<Resp>, with_options: bool) -> Result<Self> {
+        let num_keys = args.pop_front().context("missing numkeys")?.plain_i64()?;
+
+        ensure!(num_keys > 0, "at least 1 input key is needed");
+        ensure!(num_keys as usize <= args.len(), "syntax error");
+
+        let keys = args.drain(..num_keys as usize).collect::<Vec<_>>();
+        let mut weights = vec![1.0; keys.len()];
+        let mut aggregate = Aggregate::Sum;
+
+        while let Some(option) = args.pop_front() {
+            match option.plain_string()?.to_uppercase().as_str() {
+                "WEIGHTS" if with_options => {
+                    ensure!(args.len() >= keys.len(), "syntax error");
+
+                    for weight in &mut weights {
+                        *weight = args
+                            .pop_front()
+                            .context("missing weight")?
+                            .plain_string()?
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|weight| !weight.is_nan())
+                            .context("weight value is not a float")?;
+                    }
+                }
+                "AGGREGATE" if with_options => {
+                    let value = args.pop_front().context("missing AGGREGATE value")?;
+                    aggregate = match value.plain_string()?.to_uppercase().as_str() {
+                        "SUM" => Aggregate::Sum,
+                        "MIN" => Aggregate::Min,
+                        "MAX" => Aggregate::Max,
+                        _ => bail!("syntax error"),
+                    };
+                }
+                _ => bail!("syntax error"),
+            }
+        }
+
+        Ok(ZSetOpArgs {
+            keys,
+            weights,
+            aggregate,
+        })
+    }
+
+    /// Reads every input key. Plain sets are accepted too, with a score of 1 for every member.
+    fn inputs<'a>(&self, storage: &'a Storage) -> Result<Vec<HashMap<&'a str, f64>>> {
+        self.keys
+            .iter()
+            .zip(&self.weights)
+            .map(|(key, weight)| {
+                let weighted = |score: f64| {
+                    let score = score * weight;
+                    // 0 * inf is defined as 0 in Redis
+                    if score.is_nan() {
+                        0.0
+                    } else {
+                        score
+                    }
+                };
+
+                Ok(match storage.get_value(key) {
+                    None => HashMap::new(),
+                    Some(Value::SortedSet(set)) => set
+                        .iter()
+                        .map(|(member, score)| (member, weighted(score)))
+                        .collect(),
+                    Some(Value::Set(set)) => set
+                        .iter()
+                        .map(|member| (member.as_str(), weighted(1.0)))
+                        .collect(),
+                    Some(_) => bail!(WRONGTYPE),
+                })
+            })
+            .collect()
+    }
+
+    pub fn union(&self, storage: &Storage) -> Result<SortedSet> {
+        let mut scores = HashMap::<&str, f64>::new();
+
+        for input in self.inputs(storage)? {
+            for (member, score) in input {
+                scores
+                    .entry(member)
+                    .and_modify(|current| *current = self.aggregate.apply(*current, score))
+                    .or_insert(score);
+            }
+        }
+
+        Ok(into_sorted_set(scores))
+    }
+
+    pub fn intersection(&self, storage: &Storage) -> Result<SortedSet> {
+        let mut inputs = self.inputs(storage)?;
+        inputs.sort_by_key(HashMap::len);
+
+        let Some((smallest, rest)) = inputs.split_first() else {
+            return Ok(SortedSet::default());
+        };
+
+        let scores = smallest.iter().filter_map(|(member, score)| {
+            rest.iter()
+                .try_fold(*score, |acc, input| {
+                    let score = input.get(member)?;
+                    Some(self.aggregate.apply(acc, *score))
+                })
+                .map(|score| (*member, score))
+        });
+
+        Ok(into_sorted_set(scores))
+    }
+
+    /// Returns the members of the first input that are in none of the others, with their
+    /// original scores.
+    pub fn difference(&self, storage: &Storage) -> Result<SortedSet> {
+        let inputs = self.inputs(storage)?;
+
+        let Some((first, rest)) = inputs.split_first() else {
+            return Ok(SortedSet::default());
+        };
+
+        let excluded = rest
+            .iter()
+            .flat_map(|input| input.keys())
+            .collect::<HashSet<_>>();
+
+        Ok(into_sorted_set(
+            first
+                .iter()
+                .filter(|(member, _)| !excluded.contains(member))
+                .map(|(member, score)| (*member, *score)),
+        ))
+    }
+}
+
+fn into_sorted_set<'a>(members: impl IntoIterator<Item = (&'a str, f64)>) -> SortedSet {
+    let mut set = SortedSet::default();
+
+    for (member, score) in members {
+        set.insert(member.to_string(), score);
+    }
+
+    set
+}
+
+/// Parses the `MIN | MAX` argument of the pop commands, returning whether it is `MAX`.
+pub fn parse_min_max(arg: &Resp) -> Result<bool> {
+    match arg.plain_string()?.to_uppercase().as_str() {
+        "MIN" => Ok(false),
+        "MAX" => Ok(true),
+        _ => bail!("syntax error"),
+    }
+}
+
+/// The key a pop command popped from, and the popped members with their scores.
+pub type Popped = (Resp, Vec<(String, f64)>);
+
+/// Pops from the first non-empty sorted set among `keys`, returning its key and the popped
+/// members.
+pub fn pop_first_non_empty(
+    storage: &mut Storage,
+    keys: &[Resp],
+    count: usize,
+    max: bool,
+) -> Result<Option<Popped>> {
+    for key in keys {
+        let Some(set) = storage.get_as_mut::<SortedSet>(key)? else {
+            continue;
+        };
+
+        let popped = set.pop(count, max);
+        if !popped.is_empty() {
+            let event = if max { "zpopmax" } else { "zpopmin" };
+            storage.touch(key);
+            storage.notify(KeyspaceEvents::ZSET, event, key);
+            storage.remove_if_empty::<SortedSet>(key);
+        }
+
+        return Ok(Some((key.clone(), popped)));
+    }
+
+    Ok(None)
+}
@@ -47,7 +47,7 @@ impl RespVariant for BulkString {
 }
 
 impl RespRunnable for BulkString {
-    async fn run(self, _storage: &RwLock<Storage>) -> Result<RespEffect> {
+    async fn run(self, _storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
         match self.0 {
             None => bail!("bulk string is null"),
             Some(s) => Ok(RespEffect {
@@ -1,25 +1,31 @@
 use std::fmt::{Display, Formatter};
 use std::sync::{Arc, RwLock};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 pub use array::Array;
 pub use bulk_string::BulkString;
 pub use integer::Integer;
+pub use simple_error::SimpleError;
 pub use simple_string::SimpleString;
 
+use crate::aof;
 use crate::resp::resp_effect::RespEffect;
+use crate::resp::simple_string::run_string_check;
 use crate::storage::Storage;
+use crate::utils::now_or_never;
 
 mod array;
 mod bulk_string;
 mod integer;
+mod simple_error;
 mod simple_string;
 
 mod resp_effect;
 
 trait RespVariant: Display {
+    #[allow(dead_code)]
     const MAX_BYTES: usize = 1_000_000;
     const PREFIX: char;
 
@@ -29,7 +35,7 @@ trait RespVariant: Display {
 }
 
 trait RespRunnable {
-    async fn run(self, storage: &RwLock<Storage>) -> Result<RespEffect>;
+    async fn run(self, storage: &RwLock<Storage>) -> Result<RespEffect<'_>>;
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -38,6 +44,9 @@ pub enum Resp {
     BulkString(BulkString),
     Array(Array),
     Integer(Integer),
+    SimpleError(SimpleError),
+    /// The RESP2 null array, `*-1\r\n`, e.g. the reply of a blocking command that timed out.
+    NullArray,
 }
 
 impl Display for Resp {
@@ -47,6 +56,8 @@ impl Display for Resp {
             Resp::BulkString(b) => write!(f, "{}", b),
             Resp::Array(a) => write!(f, "{}", a),
             Resp::Integer(i) => write!(f, "{}", i),
+            Resp::SimpleError(e) => write!(f, "{}", e),
+            Resp::NullArray => write!(f, "*-1\r\n"),
         }
     }
 }
@@ -71,7 +82,7 @@ impl Resp {
             };
         }
 
-        parse_body_types![SimpleString, BulkString, Array, Integer];
+        parse_body_types![SimpleString, BulkString, Array, Integer, SimpleError];
 
         bail!("unknown prefix: {:?}", prefix[0] as char);
     }
@@ -81,34 +92,19 @@ impl Resp {
         mut write: impl AsyncWrite + Send + Unpin,
         storage: Arc<RwLock<Storage>>,
     ) -> Result<()> {
-        async fn run_inner(resp: Resp, storage: &RwLock<Storage>) -> Result<RespEffect> {
-            macro_rules! run_types {
-                [$($tt:tt),*] => {
-                    $(
-                        if let Resp::$tt(inner) =
-                        resp {
-                            return inner.run(storage).await;
-                        }
-                    )*
-                };
-            }
-
-            run_types![SimpleString, BulkString, Array];
-
-            bail!("unknown resp type");
-        }
-
-        let (run_result, post_run_cmd) = {
-            let RespEffect {
+        let (run_result, post_run_cmd) = match self.execute(storage.as_ref()).await {
+            Ok(RespEffect {
                 run_result,
                 post_run_cmd,
-            } = run_inner(self, storage.as_ref()).await?;
-
-            let run_result = run_result.to_string();
-
-            (run_result, post_run_cmd)
+            }) => (run_result.to_string(), post_run_cmd),
+            // a failed command replies with its error, and the connection stays open
+            Err(e) => (
+                Resp::SimpleError(SimpleError::from_error(&e)).to_string(),
+                None,
+            ),
         };
 
+        storage.read().unwrap().write_aof();
         write.write_all(run_result.as_bytes()).await?;
 
         if let Some(post_run_cmd) = post_run_cmd {
@@ -118,6 +114,88 @@ impl Resp {
         Ok(())
     }
 
+    /// Runs the command right away and returns its reply, or an error reply if it fails.
+    ///
+    /// This is for commands that run while the caller holds the storage lock, such as the ones
+    /// queued in a transaction, so a command that would wait fails instead.
+    pub fn run_now(self, storage: &RwLock<Storage>) -> Resp {
+        let reply = now_or_never(self.execute(storage))
+            .unwrap_or_else(|| Err(anyhow!("command would block")))
+            .map(|effect| effect.run_result.clone());
+
+        reply.unwrap_or_else(|e| Resp::SimpleError(SimpleError::from_error(&e)))
+    }
+
+    async fn execute(self, storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
+        // FUNCTION isn't a write command for scripts, but its LOAD, DELETE, FLUSH and RESTORE
+        // change the libraries, which the AOF keeps too
+        let propagated =
+            self.is_write_command() || self.command_name().as_deref() == Some("FUNCTION");
+        if propagated && storage.read().unwrap().aof.is_some() {
+            return aof::propagating(self.clone(), self.dispatch(storage)).await;
+        }
+
+        self.dispatch(storage).await
+    }
+
+    async fn dispatch(self, storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
+        macro_rules! run_types {
+            [$($tt:tt),*] => {
+                $(
+                    if let Resp::$tt(inner) =
+                    self {
+                        return inner.run(storage).await;
+                    }
+                )*
+            };
+        }
+
+        run_types![SimpleString, BulkString, Array];
+
+        bail!("unknown resp type");
+    }
+
+    /// The uppercase name of the command, if this is one.
+    pub fn command_name(&self) -> Option<String> {
+        match self {
+            Resp::SimpleString(_) | Resp::BulkString(_) => {
+                self.plain_string().ok().map(str::to_uppercase)
+            }
+            Resp::Array(Array(elements)) => elements
+                .first()
+                .and_then(|name| name.plain_string().ok())
+                .map(str::to_uppercase),
+            _ => None,
+        }
+    }
+
+    /// Checks that the command exists and has a valid number of arguments, without running it.
+    pub fn check_command(&self) -> Result<()> {
+        match self {
+            Resp::SimpleString(SimpleString(s)) | Resp::BulkString(BulkString(Some(s))) => {
+                run_string_check(s)
+            }
+            Resp::Array(array) => array.check_command(),
+            _ => bail!("unknown resp type"),
+        }
+    }
+
+    /// Whether this is a command that may modify the storage.
+    pub fn is_write_command(&self) -> bool {
+        match self {
+            Resp::Array(array) => array.is_write_command(),
+            _ => false,
+        }
+    }
+
+    /// The keys a read-only command reads, for the clients tracking them.
+    pub fn read_keys(&self) -> Vec<Resp> {
+        match self {
+            Resp::Array(array) => array.read_keys(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn plain_string(&self) -> Result<&str> {
         match self {
             Resp::SimpleString(SimpleString(s)) => Ok(s),
@@ -125,6 +203,25 @@ impl Resp {
             _ => bail!("not a string"),
         }
     }
+
+    pub fn plain_i64(&self) -> Result<i64> {
+        match self {
+            Resp::Integer(Integer(i)) => Ok(*i),
+            _ => Ok(self.plain_string()?.parse()?),
+        }
+    }
+}
+
+/// A bulk string holding `s`, to write tests with.
+#[cfg(test)]
+pub fn bulk(s: &str) -> Resp {
+    Resp::BulkString(BulkString(Some(s.to_string())))
+}
+
+/// A command as clients send it, an array of bulk strings, to write tests with.
+#[cfg(test)]
+pub fn command(args: &[&str]) -> Resp {
+    Resp::Array(Array(args.iter().map(|arg| bulk(arg)).collect()))
 }
 
 trait AsyncCrlfReadExt: AsyncBufRead {
@@ -2,7 +2,7 @@ use std::ops::Deref;
 use std::ptr::NonNull;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::resp::Resp;
@@ -50,11 +50,7 @@ impl PostRespRunCommand {
     ) -> Result<()> {
         match self {
             PostRespRunCommand::FullResync => {
-                if !storage.read().unwrap().is_empty() {
-                    bail!("full resync currently only supported for empty storage");
-                }
-
-                let encoded = storage.read().unwrap().encode()?;
+                let encoded = storage.read().unwrap().encode();
 
                 write.write_all(&encoded).await?;
 
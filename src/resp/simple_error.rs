@@ -0,0 +1,88 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Result;
+use tokio::io::AsyncBufRead;
+
+use crate::resp::{AsyncCrlfReadExt, RespVariant};
+
+/// The error codes Redis replies with besides the generic `ERR`.
+const ERROR_CODES: &[&str] = &[
+    "BUSY",
+    "BUSYGROUP",
+    "EXECABORT",
+    "NOGROUP",
+    "NOSCRIPT",
+    "NOTBUSY",
+    "READONLY",
+    "UNKILLABLE",
+    "WRONGTYPE",
+];
+
+/// Represents a RESP simple error, such as `-ERR syntax error`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SimpleError(pub String);
+
+impl SimpleError {
+    /// Turns a command error into an error reply.
+    ///
+    /// Messages that start with an error code, such as `WRONGTYPE`, are kept as they are; others
+    /// get the generic `ERR` code.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let message = error.to_string().replace(['\r', '\n'], " ");
+
+        let code = message.split(' ').next().unwrap_or_default();
+
+        if ERROR_CODES.contains(&code) {
+            SimpleError(message)
+        } else {
+            SimpleError(format!("ERR {}", message))
+        }
+    }
+}
+
+impl Display for SimpleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "-{}\r\n", self.0)
+    }
+}
+
+impl RespVariant for SimpleError {
+    const PREFIX: char = '-';
+
+    async fn parse_body(read: &mut (impl AsyncBufRead + Unpin + Send)) -> Result<Self> {
+        let line = read.read_crlf_line().await?;
+
+        Ok(SimpleError(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use crate::resp::tests::assert_parse;
+    use crate::resp::Resp;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_parse_simple_error() -> Result<()> {
+        assert_parse(
+            "-ERR syntax error\r\n",
+            Resp::SimpleError(SimpleError("ERR syntax error".to_string())),
+        )
+        .await
+    }
+
+    #[test]
+    fn test_from_error() {
+        assert_eq!(
+            SimpleError::from_error(&anyhow!("syntax error")),
+            SimpleError("ERR syntax error".to_string())
+        );
+        assert_eq!(
+            SimpleError::from_error(&anyhow!("WRONGTYPE Operation against a key")),
+            SimpleError("WRONGTYPE Operation against a key".to_string())
+        );
+    }
+}
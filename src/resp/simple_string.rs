@@ -28,7 +28,7 @@ impl RespVariant for SimpleString {
 }
 
 impl RespRunnable for SimpleString {
-    async fn run(self, _storage: &RwLock<Storage>) -> Result<RespEffect> {
+    async fn run(self, _storage: &RwLock<Storage>) -> Result<RespEffect<'_>> {
         Ok(RespEffect {
             run_result: RespRunResult::Owned(run_string(self.0)?),
             post_run_cmd: None,
@@ -36,6 +36,13 @@ impl RespRunnable for SimpleString {
     }
 }
 
+pub(super) fn run_string_check(s: &str) -> Result<()> {
+    match s {
+        "PING" => Ok(()),
+        _ => bail!("unknown command '{}'", s),
+    }
+}
+
 pub(super) fn run_string(s: String) -> Result<Resp> {
     match s.as_str() {
         "PING" => Ok(Resp::SimpleString(SimpleString("PONG".to_string()))),
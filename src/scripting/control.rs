@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+const DEFAULT_BUSY_REPLY_THRESHOLD: Duration = Duration::from_millis(5000);
+
+/// Whether a script was run with `EVAL` or is a function, which are killed with different
+/// commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptKind {
+    Eval,
+    Function,
+}
+
+impl ScriptKind {
+    /// The command whose `KILL` subcommand stops this kind of script.
+    pub fn command(self) -> &'static str {
+        match self {
+            ScriptKind::Eval => "SCRIPT",
+            ScriptKind::Function => "FUNCTION",
+        }
+    }
+}
+
+/// What other clients need to know about the running script.
+///
+/// A script holds the storage lock while it runs, so this is shared outside of the storage: it
+/// is how clients find out that the server is busy, and how `SCRIPT KILL` reaches the script.
+#[derive(Debug)]
+pub struct ScriptControl {
+    busy_reply_threshold: Duration,
+    started: Mutex<Option<(Instant, ScriptKind)>>,
+    kill: AtomicBool,
+    /// Set once the running script modified the storage, after which it can't be killed.
+    wrote: AtomicBool,
+}
+
+impl Default for ScriptControl {
+    fn default() -> Self {
+        ScriptControl::new(DEFAULT_BUSY_REPLY_THRESHOLD)
+    }
+}
+
+/// Marks a script as running until it is dropped.
+pub struct Running<'a>(&'a ScriptControl);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        *self.0.started.lock().unwrap() = None;
+    }
+}
+
+impl ScriptControl {
+    pub fn new(busy_reply_threshold: Duration) -> Self {
+        ScriptControl {
+            busy_reply_threshold,
+            started: Mutex::new(None),
+            kill: AtomicBool::new(false),
+            wrote: AtomicBool::new(false),
+        }
+    }
+
+    pub fn start(&self, kind: ScriptKind) -> Running<'_> {
+        self.kill.store(false, Ordering::SeqCst);
+        self.wrote.store(false, Ordering::SeqCst);
+        *self.started.lock().unwrap() = Some((Instant::now(), kind));
+
+        Running(self)
+    }
+
+    /// The kind of the running script if it has been running for longer than the busy reply
+    /// threshold, in which case other clients get a `BUSY` error instead of waiting for it.
+    pub fn busy(&self) -> Option<ScriptKind> {
+        self.started
+            .lock()
+            .unwrap()
+            .filter(|(started, _)| started.elapsed() >= self.busy_reply_threshold)
+            .map(|(_, kind)| kind)
+    }
+
+    /// Asks the running script of the given kind to stop, unless it already wrote to the
+    /// storage.
+    pub fn kill(&self, kind: ScriptKind) -> Result<()> {
+        if self
+            .started
+            .lock()
+            .unwrap()
+            .map_or(true, |(_, running)| running != kind)
+        {
+            bail!("NOTBUSY No scripts in execution right now.");
+        }
+        if self.wrote.load(Ordering::SeqCst) {
+            bail!(
+                "UNKILLABLE Sorry the script already executed write commands against the \
+                 dataset. You can either wait the script termination or kill the server in a \
+                 hard way using the SHUTDOWN NOSAVE command."
+            );
+        }
+
+        self.kill.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    pub fn mark_write(&self) {
+        self.wrote.store(true, Ordering::SeqCst);
+    }
+
+    /// Checked by the script while it runs.
+    pub fn check_killed(&self) -> Result<(), String> {
+        if self.kill.load(Ordering::SeqCst) {
+            return Err("Script killed by user with SCRIPT KILL...".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill() {
+        let control = ScriptControl::default();
+        assert!(control.kill(ScriptKind::Eval).is_err());
+
+        let running = control.start(ScriptKind::Eval);
+        assert_eq!(control.busy(), None);
+        assert!(control.kill(ScriptKind::Function).is_err());
+        control.kill(ScriptKind::Eval).unwrap();
+        assert!(control.check_killed().is_err());
+        drop(running);
+
+        let _running = control.start(ScriptKind::Function);
+        assert!(control.check_killed().is_ok());
+        control.mark_write();
+        assert!(control
+            .kill(ScriptKind::Function)
+            .unwrap_err()
+            .to_string()
+            .starts_with("UNKILLABLE"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Conversions between replies and Lua values, following the rules Redis documents for
+//! `redis.call` and for the values scripts return.
+
+use crate::lua::{Table, TableRef, Value};
+use crate::resp::{Array, BulkString, Integer, Resp, SimpleError, SimpleString};
+
+/// Converts the reply of a command called from a script to a Lua value. Status and error
+/// replies become tables with a single `ok` or `err` field, and nil replies become `false`.
+pub fn resp_to_lua(resp: Resp) -> Value {
+    match resp {
+        Resp::Integer(Integer(i)) => Value::Number(i as f64),
+        Resp::BulkString(BulkString(Some(s))) => Value::string(s),
+        Resp::BulkString(BulkString(None)) | Resp::NullArray => Value::Boolean(false),
+        Resp::Array(Array(elements)) => {
+            TableRef::from_array(elements.into_iter().map(resp_to_lua).collect()).into()
+        }
+        Resp::SimpleString(SimpleString(s)) => single_field_table("ok", s),
+        Resp::SimpleError(SimpleError(s)) => single_field_table("err", s),
+    }
+}
+
+pub fn single_field_table(field: &str, s: String) -> Value {
+    let table = TableRef::new(Table::default());
+    table.set_str(field, Value::string(s));
+    table.into()
+}
+
+/// Converts the value a script returns to its reply. Numbers are truncated to integers, and
+/// arrays end at their first nil.
+pub fn lua_to_resp(value: &Value) -> Resp {
+    match value {
+        Value::String(s) => Resp::BulkString(BulkString(Some(s.to_string_lossy()))),
+        Value::Number(n) => Resp::Integer(Integer(*n as i64)),
+        Value::Boolean(true) => Resp::Integer(Integer(1)),
+        Value::Table(table) => {
+            if let Value::String(err) = table.get_str("err") {
+                return Resp::SimpleError(SimpleError(single_line(err.to_string_lossy())));
+            }
+            if let Value::String(ok) = table.get_str("ok") {
+                return Resp::SimpleString(SimpleString(single_line(ok.to_string_lossy())));
+            }
+
+            let elements = (1..)
+                .map(|i| table.get(&Value::Number(i as f64)))
+                .take_while(|element| !element.is_nil())
+                .map(|element| lua_to_resp(&element))
+                .collect();
+
+            Resp::Array(Array(elements))
+        }
+        Value::Boolean(false) | Value::Nil | Value::Function(_) => {
+            Resp::BulkString(BulkString(None))
+        }
+    }
+}
+
+/// Status and error replies can't span lines.
+fn single_line(s: String) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let reply = Resp::Array(Array(vec![
+            Resp::Integer(Integer(1)),
+            Resp::BulkString(BulkString(Some("a".to_string()))),
+            Resp::SimpleString(SimpleString("OK".to_string())),
+            Resp::SimpleError(SimpleError("ERR oops".to_string())),
+        ]));
+
+        assert_eq!(lua_to_resp(&resp_to_lua(reply.clone())), reply);
+
+        // the nil reply becomes false, which comes back as nil without ending an array
+        let nil = Resp::BulkString(BulkString(None));
+        assert_eq!(lua_to_resp(&resp_to_lua(nil.clone())), nil);
+        assert_eq!(
+            lua_to_resp(&resp_to_lua(Resp::Array(Array(vec![
+                Resp::Integer(Integer(1)),
+                nil
+            ])))),
+            Resp::Array(Array(vec![
+                Resp::Integer(Integer(1)),
+                Resp::BulkString(BulkString(None))
+            ]))
+        );
+
+        assert_eq!(lua_to_resp(&Value::Number(3.99)), Resp::Integer(Integer(3)));
+    }
+}
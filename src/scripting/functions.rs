@@ -0,0 +1,514 @@
+//! Function libraries, loaded with `FUNCTION LOAD` and called with `FCALL`.
+//!
+//! Values of an interpreter can't outlive it, so a library is kept as its compiled code and
+//! loaded again in the interpreter of every call. State a library keeps in its local variables
+//! therefore doesn't carry over from one call to the next.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::lua::{self, Function, FunctionProto, Host, Interpreter, LuaError, LuaResult, Value};
+use crate::rdb::{encode_string, Reader, RDB_MAX_VERSION, RDB_OPCODE_FUNCTION2, RDB_VERSION};
+use crate::scripting::{block_in_place, protect_globals, redis};
+use crate::utils::crc64;
+
+/// The chunk name of libraries, which error messages refer to.
+pub const CHUNK_NAME: &str = "user_function";
+
+/// The flags functions can be registered with, and scripts given in their shebang. Only
+/// `no-writes` changes anything here.
+pub const FUNCTION_FLAGS: &[&str] = &[
+    "allow-cross-slot-keys",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "no-writes",
+];
+
+/// How long a library may take to load.
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub description: Option<String>,
+    pub flags: Vec<String>,
+}
+
+impl FunctionInfo {
+    pub fn is_read_only(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "no-writes")
+    }
+}
+
+#[derive(Debug)]
+pub struct Library {
+    pub name: String,
+    pub code: String,
+    proto: Arc<FunctionProto>,
+    pub functions: BTreeMap<String, FunctionInfo>,
+}
+
+/// The functions registered by a library while it loads, with their callbacks.
+pub type Registered = BTreeMap<String, (Value, FunctionInfo)>;
+
+impl Library {
+    /// Compiles a library and loads it to find out which functions it registers.
+    pub fn load(code: &str) -> Result<Library> {
+        let (name, body) = parse_metadata(code)?;
+
+        let proto = lua::parse(CHUNK_NAME, body.as_bytes())
+            .map_err(|e| anyhow!("Error compiling function: {}", e))?;
+
+        let functions = block_in_place(|| {
+            lua::with_stack(|| {
+                let mut host = LoadHost {
+                    deadline: Instant::now() + LOAD_TIMEOUT,
+                };
+                let mut interpreter = Interpreter::new(&mut host, CHUNK_NAME);
+
+                let registered = register_functions(&mut interpreter, &proto).map_err(|e| {
+                    let message = match e {
+                        LuaError::Error(value) => interpreter
+                            .tostring(&value)
+                            .map(|message| message.to_string_lossy())
+                            .unwrap_or_else(|_| value.type_name().to_string()),
+                        LuaError::Interrupted(message) => message,
+                    };
+                    anyhow!("Error registering functions: {}", message)
+                })?;
+
+                Ok::<_, anyhow::Error>(
+                    registered
+                        .into_iter()
+                        .map(|(name, (_, info))| (name, info))
+                        .collect::<BTreeMap<_, _>>(),
+                )
+            })
+        })?;
+
+        ensure!(!functions.is_empty(), "No functions registered");
+
+        Ok(Library {
+            name,
+            code: code.to_string(),
+            proto,
+            functions,
+        })
+    }
+
+    pub fn proto(&self) -> &Arc<FunctionProto> {
+        &self.proto
+    }
+}
+
+/// Runs a library with the API for loading, returning the functions it registered.
+pub fn register_functions(
+    interpreter: &mut Interpreter<'_>,
+    proto: &Arc<FunctionProto>,
+) -> LuaResult<Registered> {
+    let registered = Rc::new(RefCell::new(Registered::new()));
+
+    let register_function = {
+        let registered = Rc::clone(&registered);
+        Function::native("register_function", move |interpreter, args| {
+            let (name, callback, info) = parse_registration(interpreter, args)?;
+
+            let mut registered = registered.borrow_mut();
+            if registered.contains_key(&name) {
+                return Err(interpreter.runtime_error("Function already exists in the library"));
+            }
+            registered.insert(name, (callback, info));
+
+            Ok(Vec::new())
+        })
+    };
+
+    interpreter.set_global("redis", redis::load_api(register_function));
+    protect_globals(interpreter);
+
+    let chunk = interpreter.load(Arc::clone(proto));
+    interpreter.call(&chunk, Vec::new())?;
+
+    let registered = registered.take();
+    Ok(registered)
+}
+
+/// Parses `redis.register_function(name, callback)` or the form with a table of named
+/// arguments, `redis.register_function{function_name=..., callback=..., flags=...,
+/// description=...}`.
+fn parse_registration(
+    interpreter: &mut Interpreter<'_>,
+    args: Vec<Value>,
+) -> LuaResult<(String, Value, FunctionInfo)> {
+    let mut info = FunctionInfo {
+        description: None,
+        flags: Vec::new(),
+    };
+
+    let (name, callback) =
+        match args.as_slice() {
+            [Value::Table(table)] => {
+                let mut name = Value::Nil;
+                let mut callback = Value::Nil;
+
+                let mut key = Value::Nil;
+                loop {
+                    let next = table.0.borrow().next(&key);
+                    let Ok(Some((next_key, value))) = next else {
+                        break;
+                    };
+                    let field = match &next_key {
+                        Value::String(field) => field.to_string_lossy(),
+                        _ => String::new(),
+                    };
+                    match field.as_str() {
+                        "function_name" => name = value,
+                        "callback" => callback = value,
+                        "description" => match value {
+                            Value::String(description) => {
+                                info.description = Some(description.to_string_lossy())
+                            }
+                            _ => return Err(interpreter.runtime_error(
+                                "description argument given to redis.register_function must be \
+                                 a string",
+                            )),
+                        },
+                        "flags" => info.flags = parse_flags(interpreter, &value)?,
+                        _ => {
+                            return Err(interpreter.runtime_error(
+                                "unknown argument given to redis.register_function",
+                            ))
+                        }
+                    }
+                    key = next_key;
+                }
+
+                (name, callback)
+            }
+            [name, callback] => (name.clone(), callback.clone()),
+            _ => {
+                return Err(interpreter
+                    .runtime_error("wrong number of arguments to redis.register_function"))
+            }
+        };
+
+    let Value::String(name) = name else {
+        return Err(interpreter.runtime_error(
+            "function_name argument given to redis.register_function must be a string",
+        ));
+    };
+    let name = name.to_string_lossy();
+    if !is_valid_name(&name) {
+        return Err(interpreter.runtime_error(
+            "Function names can only contain letters, numbers, or underscores(_) and must be at \
+             least one character long",
+        ));
+    }
+
+    if !matches!(callback, Value::Function(_)) {
+        return Err(interpreter.runtime_error(
+            "callback argument given to redis.register_function must be a function",
+        ));
+    }
+
+    Ok((name, callback, info))
+}
+
+fn parse_flags(interpreter: &mut Interpreter<'_>, value: &Value) -> LuaResult<Vec<String>> {
+    let Value::Table(table) = value else {
+        return Err(interpreter.runtime_error(
+            "flags argument to redis.register_function must be a table representing function \
+             flags",
+        ));
+    };
+
+    let mut flags = Vec::new();
+    for i in 1..=table.len() {
+        let flag = match table.get(&Value::Number(i as f64)) {
+            Value::String(flag) => flag.to_string_lossy(),
+            _ => return Err(interpreter.runtime_error("unknown flag given")),
+        };
+        if !FUNCTION_FLAGS.contains(&flag.as_str()) {
+            return Err(interpreter.runtime_error("unknown flag given"));
+        }
+        flags.push(flag);
+    }
+
+    Ok(flags)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses the first line of a library, such as `#!lua name=mylib`, returning the library name
+/// and the code with that line blanked out, so that line numbers stay the same.
+fn parse_metadata(code: &str) -> Result<(String, String)> {
+    let shebang_end = code.find('\n').unwrap_or(code.len());
+    let shebang = code[..shebang_end]
+        .strip_prefix("#!")
+        .context("Missing library metadata")?;
+
+    let mut parts = shebang.split(' ').filter(|part| !part.is_empty());
+
+    let engine = parts.next().context("Missing library metadata")?;
+    ensure!(
+        engine.eq_ignore_ascii_case("lua"),
+        "Engine '{}' not found",
+        engine
+    );
+
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => bail!("Invalid metadata value given: {}", part),
+        }
+    }
+
+    let name = name.context("Library name was not given")?;
+    ensure!(
+        is_valid_name(&name),
+        "Library names can only contain letters, numbers, or underscores(_) and must be at least \
+         one character long"
+    );
+
+    Ok((name, code[shebang_end..].to_string()))
+}
+
+/// Loading a library can't call commands, so all it needs from the host is a time limit.
+struct LoadHost {
+    deadline: Instant,
+}
+
+impl Host for LoadHost {
+    fn call(&mut self, name: &str, _: Vec<Value>) -> LuaResult<Vec<Value>> {
+        Err(LuaError::Error(Value::string(format!(
+            "redis.{} is not available while loading a library",
+            name
+        ))))
+    }
+
+    fn check_interrupt(&mut self) -> Result<(), String> {
+        if Instant::now() >= self.deadline {
+            return Err("FUNCTION LOAD timeout".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// What `FUNCTION RESTORE` does with the libraries that already exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePolicy {
+    /// Fails if a restored library already exists.
+    Append,
+    /// Deletes all libraries first.
+    Flush,
+    /// Replaces the libraries that already exist.
+    Replace,
+}
+
+/// The loaded libraries. Unlike keys, they survive `FLUSHALL`.
+#[derive(Debug, Default, Clone)]
+pub struct Functions {
+    libraries: BTreeMap<String, Arc<Library>>,
+}
+
+impl Functions {
+    pub fn libraries(&self) -> impl Iterator<Item = &Arc<Library>> {
+        self.libraries.values()
+    }
+
+    /// Finds the library that registered a function.
+    pub fn find(&self, function: &str) -> Option<&Arc<Library>> {
+        self.libraries
+            .values()
+            .find(|library| library.functions.contains_key(function))
+    }
+
+    /// Adds a library. Its name and the names of its functions must be new, unless `replace` is
+    /// set and they belong to the library with the same name.
+    pub fn insert(&mut self, library: Library, replace: bool) -> Result<()> {
+        ensure!(
+            replace || !self.libraries.contains_key(&library.name),
+            "Library '{}' already exists",
+            library.name
+        );
+
+        for function in library.functions.keys() {
+            if let Some(other) = self.find(function) {
+                ensure!(
+                    other.name == library.name,
+                    "Function {} already exists",
+                    function
+                );
+            }
+        }
+
+        self.libraries
+            .insert(library.name.clone(), Arc::new(library));
+
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<()> {
+        self.libraries
+            .remove(name)
+            .map(|_| ())
+            .context("Library not found")
+    }
+
+    pub fn flush(&mut self) {
+        self.libraries.clear();
+    }
+
+    /// Serializes the libraries like Redis does for `FUNCTION DUMP`: their code as RDB function
+    /// entries, followed by the RDB version and a CRC-64 of the whole payload.
+    pub fn dump(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        self.encode(&mut payload);
+
+        payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+        let checksum = crc64(&payload);
+        payload.extend_from_slice(&checksum.to_le_bytes());
+
+        payload
+    }
+
+    /// Writes the libraries as RDB function entries.
+    pub fn encode(&self, rdb: &mut Vec<u8>) {
+        for library in self.libraries.values() {
+            rdb.push(RDB_OPCODE_FUNCTION2);
+            encode_string(library.code.as_bytes(), rdb);
+        }
+    }
+
+    /// Loads the libraries of a `FUNCTION DUMP` payload. Nothing changes if one of them fails
+    /// to load.
+    pub fn restore(&mut self, payload: &[u8], policy: RestorePolicy) -> Result<()> {
+        const WRONG_PAYLOAD: &str = "payload version or checksum are wrong";
+
+        ensure!(payload.len() >= 10, WRONG_PAYLOAD);
+        let (body, footer) = payload.split_at(payload.len() - 10);
+        let version = u16::from_le_bytes([footer[0], footer[1]]);
+        let checksum = u64::from_le_bytes(footer[2..].try_into()?);
+        ensure!(
+            version <= RDB_MAX_VERSION && checksum == crc64(&payload[..payload.len() - 8]),
+            WRONG_PAYLOAD
+        );
+
+        let mut functions = match policy {
+            RestorePolicy::Flush => Functions::default(),
+            RestorePolicy::Append | RestorePolicy::Replace => self.clone(),
+        };
+
+        let mut reader = Reader::new(body);
+        while !reader.is_empty() {
+            ensure!(
+                reader.read_u8()? == RDB_OPCODE_FUNCTION2,
+                "given type is not a function"
+            );
+            let code = reader.read_string().context(WRONG_PAYLOAD)?;
+            let code = std::str::from_utf8(&code).context(WRONG_PAYLOAD)?;
+
+            functions.insert(Library::load(code)?, policy == RestorePolicy::Replace)?;
+        }
+
+        *self = functions;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "#!lua name=mylib
+        local function echo(keys, args) return args[1] end
+        redis.register_function('echo', echo)
+        redis.register_function{function_name = 'get', callback = function(keys)
+            return redis.call('GET', keys[1])
+        end, flags = {'no-writes'}, description = 'reads a key'}";
+
+    #[test]
+    fn test_load_library() {
+        let library = Library::load(LIBRARY).unwrap();
+
+        assert_eq!(library.name, "mylib");
+        assert_eq!(
+            library.functions.keys().collect::<Vec<_>>(),
+            vec!["echo", "get"]
+        );
+        assert!(!library.functions["echo"].is_read_only());
+        assert!(library.functions["get"].is_read_only());
+        assert_eq!(
+            library.functions["get"].description.as_deref(),
+            Some("reads a key")
+        );
+
+        let error = |code: &str| Library::load(code).unwrap_err().to_string();
+        assert_eq!(error("return 1"), "Missing library metadata");
+        assert_eq!(error("#!js name=lib\n"), "Engine 'js' not found");
+        assert_eq!(error("#!lua\n"), "Library name was not given");
+        assert_eq!(
+            error("#!lua name=lib\nlocal x = 1"),
+            "No functions registered"
+        );
+        assert_eq!(
+            error("#!lua name=lib\nredis.call('PING')"),
+            "Error registering functions: user_function:2: attempt to call field 'call' (a nil \
+             value)"
+        );
+        assert_eq!(
+            error("#!lua name=lib\nredis.register_function('f', function() end, 1)"),
+            "Error registering functions: user_function:2: wrong number of arguments to \
+             redis.register_function"
+        );
+        assert_eq!(
+            error("#!lua name=lib\nwhile true do end"),
+            "Error registering functions: FUNCTION LOAD timeout"
+        );
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut functions = Functions::default();
+        functions
+            .insert(Library::load(LIBRARY).unwrap(), false)
+            .unwrap();
+
+        let payload = functions.dump();
+
+        let mut restored = Functions::default();
+        restored.restore(&payload, RestorePolicy::Append).unwrap();
+        assert!(restored.find("echo").is_some());
+
+        assert_eq!(
+            restored
+                .restore(&payload, RestorePolicy::Append)
+                .unwrap_err()
+                .to_string(),
+            "Library 'mylib' already exists"
+        );
+        restored.restore(&payload, RestorePolicy::Replace).unwrap();
+        restored.restore(&payload, RestorePolicy::Flush).unwrap();
+
+        let mut corrupted = payload.clone();
+        corrupted[3] ^= 1;
+        assert_eq!(
+            restored
+                .restore(&corrupted, RestorePolicy::Flush)
+                .unwrap_err()
+                .to_string(),
+            "payload version or checksum are wrong"
+        );
+        assert_eq!(restored.libraries().count(), 1);
+    }
+}
@@ -0,0 +1,314 @@
+//! Lua scripts run by `EVAL` and `EVALSHA`, and the functions of libraries run by `FCALL`.
+//!
+//! A script has the storage to itself while it runs, so the commands it calls can't interleave
+//! with other clients. It runs in an interpreter of its own with the `redis` library and
+//! read-only globals. Scripts get their keys and arguments in the `KEYS` and `ARGV` tables,
+//! functions as their two parameters.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+pub use control::{ScriptControl, ScriptKind};
+pub use functions::{Functions, Library, RestorePolicy};
+
+use crate::lua::{self, FunctionProto, Interpreter, LuaError, TableRef, Value};
+use crate::resp::Resp;
+use crate::scripting::convert::lua_to_resp;
+use crate::scripting::redis::ScriptHost;
+use crate::storage::Storage;
+use crate::utils::sha1_hex;
+
+mod control;
+mod convert;
+mod functions;
+mod redis;
+
+/// The chunk name of scripts, which error messages refer to.
+const CHUNK_NAME: &str = "user_script";
+
+/// The scripts loaded with `EVAL` or `SCRIPT LOAD`, by their SHA1.
+#[derive(Debug, Default, Clone)]
+pub struct Scripts {
+    cache: HashMap<String, Arc<EvalScript>>,
+    pub control: Arc<ScriptControl>,
+}
+
+impl Scripts {
+    pub fn new(busy_reply_threshold: Duration) -> Self {
+        Scripts {
+            cache: HashMap::new(),
+            control: Arc::new(ScriptControl::new(busy_reply_threshold)),
+        }
+    }
+
+    /// Compiles a script and caches it, returning its SHA1 and the compiled script.
+    pub fn load(&mut self, source: &str) -> Result<(String, Arc<EvalScript>)> {
+        let sha = sha1_hex(source.as_bytes());
+
+        if let Some(script) = self.cache.get(&sha) {
+            return Ok((sha, Arc::clone(script)));
+        }
+
+        let read_only = parse_shebang(source)?;
+        // the lexer skips the shebang line
+        let proto = lua::parse(CHUNK_NAME, source.as_bytes())
+            .map_err(|e| anyhow!("Error compiling script (new function): {}", e))?;
+        let script = Arc::new(EvalScript { proto, read_only });
+        self.cache.insert(sha.clone(), Arc::clone(&script));
+
+        Ok((sha, script))
+    }
+
+    /// Looks up a cached script. SHA1s are accepted in either case.
+    pub fn get(&self, sha: &str) -> Option<Arc<EvalScript>> {
+        self.cache.get(&sha.to_lowercase()).cloned()
+    }
+
+    pub fn flush(&mut self) {
+        self.cache.clear();
+    }
+}
+
+/// A script loaded with `EVAL` or `SCRIPT LOAD`.
+#[derive(Debug)]
+pub struct EvalScript {
+    proto: Arc<FunctionProto>,
+    /// Set by the `no-writes` flag of the shebang.
+    pub read_only: bool,
+}
+
+/// Parses the optional first line of a script, such as `#!lua flags=no-writes`, returning
+/// whether the script is read-only.
+fn parse_shebang(source: &str) -> Result<bool> {
+    let Some(shebang) = source.strip_prefix("#!") else {
+        return Ok(false);
+    };
+    let shebang = shebang.lines().next().unwrap_or_default();
+
+    let mut parts = shebang.split(' ').filter(|part| !part.is_empty());
+
+    let engine = parts.next().unwrap_or_default();
+    ensure!(
+        engine == "lua",
+        "Unexpected engine in script shebang: #!{}",
+        engine
+    );
+
+    let mut read_only = false;
+    for part in parts {
+        let flags = part
+            .strip_prefix("flags=")
+            .with_context(|| format!("Unknown lua shebang option: {}", part))?;
+        for flag in flags.split(',').filter(|flag| !flag.is_empty()) {
+            ensure!(
+                functions::FUNCTION_FLAGS.contains(&flag),
+                "Unexpected flag in script shebang: {}",
+                flag
+            );
+            read_only |= flag == "no-writes";
+        }
+    }
+
+    Ok(read_only)
+}
+
+/// Splits the arguments of `EVAL`-like commands, `numkeys [key ...] [arg ...]`, into the keys
+/// and the other arguments.
+pub fn parse_keys_and_args(mut args: VecDeque<Resp>) -> Result<(Vec<String>, Vec<String>)> {
+    let num_keys = args.pop_front().context("missing number of keys")?;
+    let num_keys = num_keys
+        .plain_i64()
+        .context("value is not an integer or out of range")?;
+
+    ensure!(num_keys >= 0, "Number of keys can't be negative");
+    ensure!(
+        num_keys as usize <= args.len(),
+        "Number of keys can't be greater than number of args"
+    );
+
+    let args = args
+        .iter()
+        .map(|arg| arg.plain_string().map(str::to_string))
+        .collect::<Result<Vec<_>>>()?;
+    let (keys, args) = args.split_at(num_keys as usize);
+
+    Ok((keys.to_vec(), args.to_vec()))
+}
+
+/// What `scripting::run` runs: a script passed to `EVAL`, or a function of a library.
+pub enum Script {
+    Eval {
+        sha: String,
+        script: Arc<EvalScript>,
+    },
+    Function {
+        library: Arc<Library>,
+        name: String,
+    },
+}
+
+impl Script {
+    fn kind(&self) -> ScriptKind {
+        match self {
+            Script::Eval { .. } => ScriptKind::Eval,
+            Script::Function { .. } => ScriptKind::Function,
+        }
+    }
+
+    fn chunk_name(&self) -> &'static str {
+        match self {
+            Script::Eval { .. } => CHUNK_NAME,
+            Script::Function { .. } => functions::CHUNK_NAME,
+        }
+    }
+
+    /// How error messages refer to the script.
+    fn name(&self) -> &str {
+        match self {
+            Script::Eval { sha, .. } => sha,
+            Script::Function { name, .. } => name,
+        }
+    }
+}
+
+/// Runs a script with the storage to itself and returns its reply. Read-only scripts fail on
+/// the first write command they call.
+pub fn run(
+    storage: &mut Storage,
+    script: Script,
+    keys: Vec<String>,
+    args: Vec<String>,
+    read_only: bool,
+) -> Result<Resp> {
+    let control = Arc::clone(&storage.scripts.control);
+
+    storage.run_exclusive(|storage| {
+        block_in_place(|| {
+            lua::with_stack(|| {
+                let _running = control.start(script.kind());
+                let mut host = ScriptHost {
+                    storage,
+                    control: &control,
+                    read_only,
+                };
+                run_in_interpreter(&mut host, &script, keys, args)
+            })
+        })
+    })
+}
+
+fn run_in_interpreter(
+    host: &mut ScriptHost<'_>,
+    script: &Script,
+    keys: Vec<String>,
+    args: Vec<String>,
+) -> Result<Resp> {
+    let mut interpreter = Interpreter::new(host, script.chunk_name());
+
+    let to_table = |strings: Vec<String>| {
+        TableRef::from_array(strings.into_iter().map(Value::string).collect())
+    };
+
+    let result = match script {
+        Script::Eval { script, .. } => {
+            interpreter.set_global("redis", redis::api());
+            interpreter.set_global("KEYS", to_table(keys));
+            interpreter.set_global("ARGV", to_table(args));
+            protect_globals(&interpreter);
+
+            let function = interpreter.load(Arc::clone(&script.proto));
+            interpreter.call(&function, Vec::new())
+        }
+        // the library is loaded again to get the function, which then gets the full library
+        Script::Function { library, name } => {
+            functions::register_functions(&mut interpreter, library.proto()).and_then(
+                |mut registered| {
+                    interpreter.set_global("redis", redis::api());
+                    let (function, _) = registered
+                        .remove(name)
+                        .expect("libraries register the same functions every time");
+                    interpreter.call(
+                        &function,
+                        vec![to_table(keys).into(), to_table(args).into()],
+                    )
+                },
+            )
+        }
+    };
+
+    match result {
+        Ok(values) => Ok(lua_to_resp(&values.into_iter().next().unwrap_or_default())),
+        Err(e) => Err(script_error(&mut interpreter, e, script)),
+    }
+}
+
+/// Makes the globals read-only, and reading an undefined one an error, so that scripts can't
+/// leak state into each other or mistype a variable unnoticed.
+fn protect_globals(interpreter: &Interpreter<'_>) {
+    let metatable = lua::library(&[("__index", |interpreter, args| {
+        let name = args
+            .get(1)
+            .and_then(Value::to_lua_string)
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        Err(interpreter.runtime_error(format!(
+            "Script attempted to access nonexistent global variable '{}'",
+            name
+        )))
+    })]);
+
+    let mut globals = interpreter.globals.0.borrow_mut();
+    globals.metatable = Some(metatable);
+    globals.readonly = true;
+}
+
+/// Describes an error the script didn't catch, with where it happened.
+fn script_error(
+    interpreter: &mut Interpreter<'_>,
+    error: LuaError,
+    script: &Script,
+) -> anyhow::Error {
+    let message = match error {
+        LuaError::Error(value) => {
+            // errors raised by `redis.call` are tables like the ones of `redis.error_reply`
+            let value = match &value {
+                Value::Table(table) => match table.get_str("err") {
+                    Value::String(err) => Value::String(err),
+                    _ => value,
+                },
+                _ => value,
+            };
+            match interpreter.tostring(&value) {
+                Ok(message) => message.to_string_lossy(),
+                Err(_) => value.type_name().to_string(),
+            }
+        }
+        LuaError::Interrupted(message) => message,
+    };
+
+    // the reply gets the generic error code back unless it has another one
+    let message = message.strip_prefix("ERR ").unwrap_or(&message);
+
+    anyhow!(
+        "{} script: {}, on @{}:{}.",
+        message,
+        script.name(),
+        script.chunk_name(),
+        interpreter.line()
+    )
+}
+
+/// The storage stays locked while a script runs, so on the multi-threaded runtime the worker
+/// hands its other tasks over to another thread meanwhile.
+fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
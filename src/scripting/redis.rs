@@ -0,0 +1,203 @@
+//! The `redis` library scripts use to call commands.
+
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::lua::{
+    check_integer, check_string, library, Function, Host, Interpreter, LuaError, LuaResult,
+    TableRef, Value,
+};
+use crate::resp::{Array, BulkString, Resp, SimpleError};
+use crate::scripting::convert::{resp_to_lua, single_field_table};
+use crate::scripting::ScriptControl;
+use crate::storage::Storage;
+use crate::utils::sha1_hex;
+
+/// Commands that make no sense inside a script, since they are about the connection or run
+/// scripts themselves.
+const NO_SCRIPT_COMMANDS: &[&str] = &[
+    "BGSAVE",
+    "CLIENT",
+    "DISCARD",
+    "EVAL",
+    "EVALSHA",
+    "EXEC",
+    "FCALL",
+    "FCALL_RO",
+    "FUNCTION",
+    "MULTI",
+    "PSUBSCRIBE",
+    "PSYNC",
+    "PUNSUBSCRIBE",
+    "REPLCONF",
+    "SAVE",
+    "SCRIPT",
+    "SHUTDOWN",
+    "SSUBSCRIBE",
+    "SUBSCRIBE",
+    "SUNSUBSCRIBE",
+    "UNSUBSCRIBE",
+    "UNWATCH",
+    "WATCH",
+];
+
+const LOG_LEVELS: &[&str] = &["debug", "verbose", "notice", "warning"];
+
+/// The library scripts and functions use.
+pub fn api() -> TableRef {
+    let redis = library(&[
+        ("call", |interpreter, args| {
+            interpreter.host().call("call", args)
+        }),
+        ("error_reply", error_reply),
+        ("log", log),
+        ("pcall", |interpreter, args| {
+            interpreter.host().call("pcall", args)
+        }),
+        ("register_function", |interpreter, _| {
+            Err(interpreter.runtime_error(
+                "redis.register_function can only be called on FUNCTION LOAD command",
+            ))
+        }),
+        // commands are always replicated as their effects, so there is nothing to choose
+        ("replicate_commands", |_, _| Ok(vec![Value::Boolean(true)])),
+        ("set_repl", |_, _| Ok(Vec::new())),
+        ("sha1hex", sha1hex),
+        ("status_reply", status_reply),
+    ]);
+    set_constants(&redis);
+
+    redis
+}
+
+/// The library a function library sees while it loads, which can register functions but not
+/// call commands.
+pub fn load_api(register_function: Function) -> TableRef {
+    let redis = library(&[("log", log)]);
+    redis.set_str("register_function", Value::Function(register_function));
+    set_constants(&redis);
+
+    redis
+}
+
+fn set_constants(redis: &TableRef) {
+    for (level, name) in LOG_LEVELS.iter().enumerate() {
+        redis.set_str(&format!("LOG_{}", name.to_uppercase()), level as f64);
+    }
+    for (name, flags) in [
+        ("NONE", 0),
+        ("AOF", 1),
+        ("SLAVE", 2),
+        ("REPLICA", 2),
+        ("ALL", 3),
+    ] {
+        redis.set_str(&format!("REPL_{}", name), flags as f64);
+    }
+}
+
+fn error_reply(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let message = check_string(interpreter, &args, 0, "error_reply")?.to_string_lossy();
+    // the leading dash of the reply is optional
+    let message = message.strip_prefix('-').unwrap_or(&message).to_string();
+    Ok(vec![single_field_table("err", message)])
+}
+
+fn status_reply(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let message = check_string(interpreter, &args, 0, "status_reply")?;
+    Ok(vec![single_field_table("ok", message.to_string_lossy())])
+}
+
+fn sha1hex(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    let s = check_string(interpreter, &args, 0, "sha1hex")?;
+    Ok(vec![Value::string(sha1_hex(s.as_bytes()))])
+}
+
+fn log(interpreter: &mut Interpreter<'_>, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+    if args.len() < 2 {
+        return Err(interpreter.runtime_error("redis.log() requires two arguments or more."));
+    }
+
+    let level = check_integer(interpreter, &args, 0, "log")?;
+    let Some(level) = usize::try_from(level)
+        .ok()
+        .and_then(|level| LOG_LEVELS.get(level))
+    else {
+        return Err(interpreter.runtime_error("Invalid debug level."));
+    };
+
+    let mut message = Vec::new();
+    for value in &args[1..] {
+        message.push(interpreter.tostring(value)?.to_string_lossy());
+    }
+    println!("script log ({}): {}", level, message.join(" "));
+
+    Ok(Vec::new())
+}
+
+/// Runs the commands a script calls, against the storage the script has to itself.
+pub struct ScriptHost<'a> {
+    pub storage: &'a RwLock<Storage>,
+    pub control: &'a ScriptControl,
+    /// Set for scripts and functions with the `no-writes` flag.
+    pub read_only: bool,
+}
+
+impl Host for ScriptHost<'_> {
+    /// `redis.call` raises error replies, while `redis.pcall` returns them.
+    fn call(&mut self, name: &str, args: Vec<Value>) -> LuaResult<Vec<Value>> {
+        let reply = self
+            .run_command(args)
+            .unwrap_or_else(|e| Resp::SimpleError(SimpleError::from_error(&e)));
+
+        let raise = name == "call" && matches!(reply, Resp::SimpleError(_));
+        let value = resp_to_lua(reply);
+
+        if raise {
+            Err(LuaError::Error(value))
+        } else {
+            Ok(vec![value])
+        }
+    }
+
+    fn check_interrupt(&mut self) -> Result<(), String> {
+        self.control.check_killed()
+    }
+}
+
+impl ScriptHost<'_> {
+    fn run_command(&self, args: Vec<Value>) -> Result<Resp> {
+        ensure!(
+            !args.is_empty(),
+            "Please specify at least one argument for this redis lib call"
+        );
+
+        let mut elements = Vec::with_capacity(args.len());
+        for arg in args {
+            let arg = match arg {
+                Value::String(_) | Value::Number(_) => arg.to_lua_string().unwrap(),
+                _ => bail!("Lua redis lib command arguments must be strings or integers"),
+            };
+            elements.push(Resp::BulkString(BulkString(Some(arg.to_string_lossy()))));
+        }
+
+        let command = Resp::Array(Array(elements));
+        command.check_command()?;
+
+        let name = command.command_name().unwrap_or_default();
+        ensure!(
+            !NO_SCRIPT_COMMANDS.contains(&name.as_str()),
+            "This Redis command is not allowed from script"
+        );
+
+        if command.is_write_command() {
+            ensure!(
+                !self.read_only,
+                "Write commands are not allowed from read-only scripts."
+            );
+            self.control.mark_write();
+        }
+
+        Ok(command.run_now(self.storage))
+    }
+}
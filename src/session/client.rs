@@ -0,0 +1,107 @@
+use std::sync::RwLock;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::resp::{Array, Integer, Resp};
+use crate::session::{error_reply, ok_reply, Session};
+use crate::storage::{parse_tracking_options, Storage};
+
+impl Session {
+    pub(super) fn client(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Resp {
+        self.run_client(resp, storage).unwrap_or_else(error_reply)
+    }
+
+    fn run_client(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Result<Resp> {
+        resp.check_command()?;
+
+        let Resp::Array(Array(elements)) = resp else {
+            bail!("invalid command");
+        };
+        let subcommand = elements[1].plain_string()?.to_uppercase();
+        let args = &elements[2..];
+
+        match subcommand.as_str() {
+            "CACHING" => {
+                ensure!(args.len() == 1, "syntax error");
+                let storage = storage.read().unwrap();
+                let options = storage.tracking.options(self.id);
+
+                match args[0].plain_string()?.to_uppercase().as_str() {
+                    "YES" if options.is_some_and(|options| options.optin) => {
+                        self.caching = Some(true)
+                    }
+                    "NO" if options.is_some_and(|options| options.optout) => {
+                        self.caching = Some(false)
+                    }
+                    "YES" | "NO" => bail!(
+                        "CLIENT CACHING can be called only when the client is in tracking mode \
+                         with OPTIN or OPTOUT mode enabled"
+                    ),
+                    _ => bail!("syntax error"),
+                }
+
+                Ok(ok_reply())
+            }
+            // -1 when tracking is off, 0 when it isn't redirected
+            "GETREDIR" => {
+                ensure!(args.is_empty(), "syntax error");
+                let storage = storage.read().unwrap();
+                let redirect = match storage.tracking.options(self.id) {
+                    None => -1,
+                    Some(options) => options.redirect.map_or(0, |id| id as i64),
+                };
+
+                Ok(Resp::Integer(Integer(redirect)))
+            }
+            "ID" => {
+                ensure!(args.is_empty(), "syntax error");
+                Ok(Resp::Integer(Integer(self.id as i64)))
+            }
+            "TRACKING" => {
+                ensure!(!args.is_empty(), "syntax error");
+                let mut storage = storage.write().unwrap();
+
+                match args[0].plain_string()?.to_uppercase().as_str() {
+                    "ON" => {
+                        let options = parse_tracking_options(&args[1..])?;
+                        storage.tracking.enable(self.id, options)?;
+                    }
+                    "OFF" => storage.tracking.disable(self.id),
+                    _ => bail!("syntax error"),
+                }
+                self.caching = None;
+
+                Ok(ok_reply())
+            }
+            _ => bail!("unknown subcommand {}", subcommand),
+        }
+    }
+
+    /// Remembers the keys a command is about to read, if the client tracks them. In `OPTIN`
+    /// mode only the command right after `CLIENT CACHING YES` counts, and in `OPTOUT` mode the
+    /// one right after `CLIENT CACHING NO` doesn't.
+    pub(super) fn track_reads(&mut self, resp: &Resp, storage: &RwLock<Storage>) {
+        let caching = self.caching.take();
+
+        let keys = resp.read_keys();
+        if keys.is_empty() {
+            return;
+        }
+
+        let mut storage = storage.write().unwrap();
+        let Some(options) = storage.tracking.options(self.id) else {
+            return;
+        };
+        let track = if options.optin {
+            caching == Some(true)
+        } else if options.optout {
+            caching != Some(false)
+        } else {
+            true
+        };
+
+        if track {
+            storage.tracking.remember(self.id, keys);
+        }
+    }
+}
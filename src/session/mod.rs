@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, TryLockError};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use pubsub::Subscriptions;
+use transaction::Transaction;
+
+use crate::resp::{Array, Resp, SimpleError, SimpleString};
+use crate::scripting::{ScriptControl, ScriptKind};
+use crate::storage::{Storage, Watch, CURRENT_CLIENT};
+use crate::utils::exit_server;
+
+mod client;
+mod pubsub;
+mod transaction;
+mod watch;
+
+/// How often a command waiting for the storage lock checks whether it is free.
+const STORAGE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The ID of the last client that connected.
+static LAST_CLIENT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The state of a client connection, for the commands that depend on previous ones.
+#[derive(Debug, Default)]
+pub struct Session {
+    transaction: Option<Transaction>,
+    watches: Vec<Watch>,
+    /// Kept outside of the storage lock, which a running script holds.
+    script_control: Arc<ScriptControl>,
+    subscriptions: Subscriptions,
+    /// The ID of the client, unique among the connections the server accepted.
+    id: u64,
+    /// Whether the client was registered with the tracking of the storage yet.
+    connected: bool,
+    /// Set by `CLIENT CACHING` for the next command.
+    caching: Option<bool>,
+}
+
+impl Session {
+    /// Doesn't lock the storage, so that a client can connect to kill a script that holds it.
+    pub fn new(script_control: Arc<ScriptControl>) -> Self {
+        Session {
+            script_control,
+            id: LAST_CLIENT_ID.fetch_add(1, Ordering::Relaxed) + 1,
+            ..Default::default()
+        }
+    }
+
+    /// Runs a command sent by the client and writes its reply.
+    pub async fn run(
+        &mut self,
+        resp: Resp,
+        write: impl AsyncWrite + Send + Unpin,
+        storage: Arc<RwLock<Storage>>,
+    ) -> Result<()> {
+        // so that the keys the client modifies itself are known
+        CURRENT_CLIENT
+            .scope(self.id, self.run_command(resp, write, storage))
+            .await
+    }
+
+    async fn run_command(
+        &mut self,
+        resp: Resp,
+        mut write: impl AsyncWrite + Send + Unpin,
+        storage: Arc<RwLock<Storage>>,
+    ) -> Result<()> {
+        if let Some(kind) = self.wait_for_storage(&storage).await {
+            let reply = self.run_busy(&resp, kind);
+            write.write_all(reply.to_string().as_bytes()).await?;
+            return Ok(());
+        }
+
+        if !self.connected {
+            let mut storage = storage.write().unwrap();
+            storage
+                .tracking
+                .connect(self.id, self.subscriptions.outbox());
+            self.connected = true;
+        }
+
+        if let Some(replies) = self.run_pubsub(&resp, &storage) {
+            for reply in replies {
+                write.write_all(reply.to_string().as_bytes()).await?;
+            }
+            return Ok(());
+        }
+
+        let reply = match resp.command_name().as_deref() {
+            Some("CLIENT") => self.client(&resp, &storage),
+            Some("MULTI") => self.multi(&resp),
+            Some("EXEC") => self.exec(&resp, &storage),
+            Some("DISCARD") => self.discard(&resp, &storage),
+            Some("WATCH") => self.watch(&resp, &storage),
+            Some("UNWATCH") if self.transaction.is_none() => self.unwatch(&resp, &storage),
+            _ => match &mut self.transaction {
+                Some(transaction) => transaction.queue(resp),
+                None => {
+                    self.track_reads(&resp, &storage);
+                    return resp.run(write, storage).await;
+                }
+            },
+        };
+
+        storage.read().unwrap().write_aof();
+        write.write_all(reply.to_string().as_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Waits for the storage lock to be free, returning the kind of the running script instead
+    /// once it makes the server busy. The lock is only tried, so that a script holding it blocks
+    /// no thread and the client can still get a `BUSY` error, or kill the script.
+    async fn wait_for_storage(&self, storage: &RwLock<Storage>) -> Option<ScriptKind> {
+        loop {
+            if let Some(kind) = self.script_control.busy() {
+                return Some(kind);
+            }
+            if !matches!(storage.try_read(), Err(TryLockError::WouldBlock)) {
+                return None;
+            }
+            tokio::time::sleep(STORAGE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// While a script runs for too long, the only commands that do something are `SCRIPT KILL`,
+    /// or `FUNCTION KILL` for a function, and `SHUTDOWN NOSAVE`, which exits without waiting for
+    /// the storage.
+    fn run_busy(&self, resp: &Resp, kind: ScriptKind) -> Resp {
+        let args = match resp {
+            Resp::Array(Array(elements)) => elements
+                .iter()
+                .map(|arg| arg.plain_string().unwrap_or_default().to_uppercase())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        if args.first().is_some_and(|name| name == "SHUTDOWN")
+            && args.iter().any(|arg| arg == "NOSAVE")
+        {
+            println!("User requested shutdown...");
+            exit_server();
+        }
+
+        let is_kill = args.len() == 2 && args[0] == kind.command() && args[1] == "KILL";
+        if !is_kill {
+            return error_reply(anyhow!(
+                "BUSY Redis is busy running a script. You can only call {} KILL or SHUTDOWN \
+                 NOSAVE.",
+                kind.command()
+            ));
+        }
+
+        match self.script_control.kill(kind) {
+            Ok(()) => ok_reply(),
+            Err(e) => error_reply(e),
+        }
+    }
+
+    /// Releases what the session holds in the storage once the client disconnects.
+    pub fn close(&mut self, storage: &RwLock<Storage>) {
+        if self.connected {
+            storage.write().unwrap().tracking.disconnect(self.id);
+        }
+        if !self.watches.is_empty() {
+            self.unwatch_all(&mut storage.write().unwrap());
+        }
+        if self.subscriptions.is_subscribed() {
+            self.unsubscribe_all(&mut storage.write().unwrap());
+        }
+    }
+}
+
+fn ok_reply() -> Resp {
+    Resp::SimpleString(SimpleString("OK".to_string()))
+}
+
+fn error_reply(e: anyhow::Error) -> Resp {
+    Resp::SimpleError(SimpleError::from_error(&e))
+}
+
+#[cfg(test)]
+mod tests;
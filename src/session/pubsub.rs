@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+
+use anyhow::anyhow;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::resp::{Array, BulkString, Integer, Resp};
+use crate::session::{error_reply, Session};
+use crate::storage::{Outbox, Storage};
+
+/// The commands a client can still send once it subscribed to a channel.
+const SUBSCRIBED_COMMANDS: &[&str] = &[
+    "PING",
+    "PSUBSCRIBE",
+    "PUNSUBSCRIBE",
+    "QUIT",
+    "RESET",
+    "SSUBSCRIBE",
+    "SUBSCRIBE",
+    "SUNSUBSCRIBE",
+    "UNSUBSCRIBE",
+];
+
+/// What a client subscribes to: channels by name, channels matching a pattern, or shard
+/// channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+impl Kind {
+    /// The first element of the frames confirming a change.
+    fn frame_kind(self, subscribe: bool) -> &'static str {
+        match (self, subscribe) {
+            (Kind::Channel, true) => "subscribe",
+            (Kind::Channel, false) => "unsubscribe",
+            (Kind::Pattern, true) => "psubscribe",
+            (Kind::Pattern, false) => "punsubscribe",
+            (Kind::Shard, true) => "ssubscribe",
+            (Kind::Shard, false) => "sunsubscribe",
+        }
+    }
+}
+
+/// The channels, patterns and shard channels a client subscribed to, and the messages published to them that
+/// it hasn't been sent yet.
+#[derive(Debug)]
+pub struct Subscriptions {
+    outbox: Outbox,
+    messages: UnboundedReceiver<Resp>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    shard_channels: BTreeSet<String>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        let (outbox, messages) = mpsc::unbounded_channel();
+        Subscriptions {
+            outbox,
+            messages,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            shard_channels: BTreeSet::new(),
+        }
+    }
+}
+
+impl Subscriptions {
+    /// Where messages for the client go, messages about keys it caches included.
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty() || !self.shard_channels.is_empty()
+    }
+
+    /// The number of subscriptions confirmations report: shard channels are counted apart from
+    /// the others.
+    fn count(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Channel | Kind::Pattern => self.channels.len() + self.patterns.len(),
+            Kind::Shard => self.shard_channels.len(),
+        }
+    }
+
+    fn names(&mut self, kind: Kind) -> &mut BTreeSet<String> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+}
+
+impl Session {
+    /// Runs the commands that are about subscriptions, or that a subscribed client can't run,
+    /// returning their replies. Other commands are left to the caller.
+    pub(super) fn run_pubsub(
+        &mut self,
+        resp: &Resp,
+        storage: &RwLock<Storage>,
+    ) -> Option<Vec<Resp>> {
+        let name = resp.command_name().unwrap_or_default();
+
+        let replies = match name.as_str() {
+            "SUBSCRIBE" | "UNSUBSCRIBE" => self.run_subscription(resp, Kind::Channel, storage),
+            "PSUBSCRIBE" | "PUNSUBSCRIBE" => self.run_subscription(resp, Kind::Pattern, storage),
+            "SSUBSCRIBE" | "SUNSUBSCRIBE" => self.run_subscription(resp, Kind::Shard, storage),
+            // a subscribed client gets PONG in the same shape as messages
+            "PING" if self.subscriptions.is_subscribed() => {
+                if let Err(e) = resp.check_command() {
+                    return Some(vec![error_reply(e)]);
+                }
+                let message = command_args(resp).into_iter().next().unwrap_or_default();
+                vec![frame(
+                    "pong",
+                    Resp::BulkString(BulkString(Some(message))),
+                    None,
+                )]
+            }
+            _ if self.subscriptions.is_subscribed()
+                && !SUBSCRIBED_COMMANDS.contains(&name.as_str()) =>
+            {
+                vec![error_reply(anyhow!(
+                    "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / \
+                     RESET are allowed in this context",
+                    name.to_lowercase()
+                ))]
+            }
+            _ => return None,
+        };
+
+        Some(replies)
+    }
+
+    fn run_subscription(
+        &mut self,
+        resp: &Resp,
+        kind: Kind,
+        storage: &RwLock<Storage>,
+    ) -> Vec<Resp> {
+        let name = resp.command_name().unwrap_or_default();
+
+        if let Err(e) = resp.check_command() {
+            return vec![error_reply(e)];
+        }
+        if self.transaction.is_some() {
+            return vec![error_reply(anyhow!("{} inside MULTI is not allowed", name))];
+        }
+
+        let names = command_args(resp);
+        let mut storage = storage.write().unwrap();
+        if name.ends_with("UNSUBSCRIBE") {
+            self.unsubscribe(kind, names, &mut storage)
+        } else {
+            self.subscribe(kind, names, &mut storage)
+        }
+    }
+
+    fn subscribe(&mut self, kind: Kind, names: Vec<String>, storage: &mut Storage) -> Vec<Resp> {
+        names
+            .into_iter()
+            .map(|name| {
+                let outbox = &self.subscriptions.outbox;
+                match kind {
+                    Kind::Channel => storage.pubsub.subscribe(&name, outbox),
+                    Kind::Pattern => storage.pubsub.psubscribe(&name, outbox),
+                    Kind::Shard => storage.pubsub.ssubscribe(&name, outbox),
+                }
+                self.subscriptions.names(kind).insert(name.clone());
+                self.subscription_frame(kind, true, Some(name))
+            })
+            .collect()
+    }
+
+    /// Unsubscribes from the given names of one kind, or from all of them if none are given.
+    fn unsubscribe(&mut self, kind: Kind, names: Vec<String>, storage: &mut Storage) -> Vec<Resp> {
+        let names = if names.is_empty() {
+            self.subscriptions.names(kind).iter().cloned().collect()
+        } else {
+            names
+        };
+
+        if names.is_empty() {
+            return vec![self.subscription_frame(kind, false, None)];
+        }
+
+        names
+            .into_iter()
+            .map(|name| {
+                let outbox = &self.subscriptions.outbox;
+                match kind {
+                    Kind::Channel => storage.pubsub.unsubscribe(&name, outbox),
+                    Kind::Pattern => storage.pubsub.punsubscribe(&name, outbox),
+                    Kind::Shard => storage.pubsub.sunsubscribe(&name, outbox),
+                }
+                self.subscriptions.names(kind).remove(&name);
+                self.subscription_frame(kind, false, Some(name))
+            })
+            .collect()
+    }
+
+    /// Confirms a change to the subscriptions, with how many are left.
+    fn subscription_frame(&self, kind: Kind, subscribe: bool, name: Option<String>) -> Resp {
+        let count = self.subscriptions.count(kind) as i64;
+        frame(
+            kind.frame_kind(subscribe),
+            Resp::BulkString(BulkString(name)),
+            Some(count),
+        )
+    }
+
+    /// Waits for the next message published to a channel the client subscribed to.
+    pub async fn next_message(&mut self) -> Resp {
+        // the session holds a sender itself, so the queue never closes
+        self.subscriptions
+            .messages
+            .recv()
+            .await
+            .expect("subscriptions keep their queue open")
+    }
+
+    pub(super) fn unsubscribe_all(&mut self, storage: &mut Storage) {
+        self.unsubscribe(Kind::Channel, Vec::new(), storage);
+        self.unsubscribe(Kind::Pattern, Vec::new(), storage);
+        self.unsubscribe(Kind::Shard, Vec::new(), storage);
+    }
+}
+
+fn command_args(resp: &Resp) -> Vec<String> {
+    match resp {
+        Resp::Array(Array(elements)) => elements[1..]
+            .iter()
+            .map(|arg| arg.plain_string().unwrap_or_default().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn frame(kind: &str, subject: Resp, count: Option<i64>) -> Resp {
+    let mut parts = vec![
+        Resp::BulkString(BulkString(Some(kind.to_string()))),
+        subject,
+    ];
+    parts.extend(count.map(|count| Resp::Integer(Integer(count))));
+    Resp::Array(Array(parts))
+}
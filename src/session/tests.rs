@@ -0,0 +1,369 @@
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::resp::{command, Array, BulkString, Integer, Resp, SimpleError};
+use crate::scripting::Scripts;
+use crate::session::Session;
+use crate::storage::Storage;
+use crate::utils::sha1_hex;
+
+/// A session for a new connection.
+pub fn new_session(storage: &RwLock<Storage>) -> Session {
+    Session::new(Arc::clone(&storage.read().unwrap().scripts.control))
+}
+
+/// Runs a command in `session`, returning what it wrote to the client.
+pub async fn run(
+    session: &mut Session,
+    args: &[&str],
+    storage: &Arc<RwLock<Storage>>,
+) -> Result<String> {
+    let mut buf = Vec::new();
+    session
+        .run(command(args), &mut buf, Arc::clone(storage))
+        .await?;
+
+    Ok(String::from_utf8(buf)?)
+}
+
+#[tokio::test]
+async fn test_busy_script() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    storage.write().unwrap().scripts = Scripts::new(Duration::from_millis(10));
+
+    let mut session = new_session(&storage);
+
+    let script = {
+        let storage = Arc::clone(&storage);
+        thread::spawn(move || command(&["EVAL", "while true do end", "0"]).run_now(&storage))
+    };
+
+    while session.script_control.busy().is_none() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(
+        run(&mut session, &["GET", "k"], &storage).await?,
+        "-BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN \
+         NOSAVE.\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["SCRIPT", "KILL"], &storage).await?,
+        "+OK\r\n"
+    );
+
+    assert_eq!(
+        script.join().unwrap(),
+        Resp::SimpleError(SimpleError(format!(
+            "ERR Script killed by user with SCRIPT KILL... script: {}, on @user_script:1.",
+            sha1_hex(b"while true do end")
+        )))
+    );
+    assert_eq!(run(&mut session, &["GET", "k"], &storage).await?, "$-1\r\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_kill_script_from_new_connection() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    storage.write().unwrap().scripts = Scripts::new(Duration::from_millis(10));
+    let script_control = Arc::clone(&storage.read().unwrap().scripts.control);
+
+    let script = {
+        let storage = Arc::clone(&storage);
+        thread::spawn(move || command(&["EVAL", "while true do end", "0"]).run_now(&storage))
+    };
+
+    while script_control.busy().is_none() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // connecting doesn't wait for the storage lock the script holds
+    let mut session = Session::new(script_control);
+    assert_eq!(
+        run(&mut session, &["SCRIPT", "KILL"], &storage).await?,
+        "+OK\r\n"
+    );
+    assert!(matches!(script.join().unwrap(), Resp::SimpleError(_)));
+    assert_eq!(run(&mut session, &["GET", "k"], &storage).await?, "$-1\r\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wait_for_busy_script() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    storage.write().unwrap().scripts = Scripts::new(Duration::from_millis(100));
+    let mut session = new_session(&storage);
+
+    let script = {
+        let storage = Arc::clone(&storage);
+        thread::spawn(move || command(&["EVAL", "while true do end", "0"]).run_now(&storage))
+    };
+
+    while storage.try_write().is_ok() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // sent before the script is busy, the command waits for it to be rather than for the lock
+    assert_eq!(
+        run(&mut session, &["GET", "k"], &storage).await?,
+        "-BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN \
+         NOSAVE.\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["SCRIPT", "KILL"], &storage).await?,
+        "+OK\r\n"
+    );
+    assert!(matches!(script.join().unwrap(), Resp::SimpleError(_)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribe() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    let mut session = new_session(&storage);
+
+    assert_eq!(
+        run(&mut session, &["SUBSCRIBE", "news", "weather"], &storage).await?,
+        "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+         *3\r\n$9\r\nsubscribe\r\n$7\r\nweather\r\n:2\r\n"
+    );
+
+    assert_eq!(
+        command(&["PUBLISH", "news", "hello"]).run_now(&storage),
+        Resp::Integer(Integer(1))
+    );
+    assert_eq!(
+        session.next_message().await.to_string(),
+        "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+    );
+
+    assert_eq!(
+        run(&mut session, &["PING"], &storage).await?,
+        "*2\r\n$4\r\npong\r\n$0\r\n\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["GET", "key"], &storage).await?,
+        "-ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET \
+         are allowed in this context\r\n"
+    );
+
+    assert_eq!(
+        run(&mut session, &["UNSUBSCRIBE"], &storage).await?,
+        "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:1\r\n\
+         *3\r\n$11\r\nunsubscribe\r\n$7\r\nweather\r\n:0\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["UNSUBSCRIBE"], &storage).await?,
+        "*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n"
+    );
+    assert_eq!(
+        command(&["PUBLISH", "news", "hello"]).run_now(&storage),
+        Resp::Integer(Integer(0))
+    );
+    assert_eq!(run(&mut session, &["PING"], &storage).await?, "+PONG\r\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_psubscribe() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    let mut session = new_session(&storage);
+
+    run(&mut session, &["SUBSCRIBE", "orders.created"], &storage).await?;
+    assert_eq!(
+        run(&mut session, &["PSUBSCRIBE", "orders.*"], &storage).await?,
+        "*3\r\n$10\r\npsubscribe\r\n$8\r\norders.*\r\n:2\r\n"
+    );
+
+    assert_eq!(
+        command(&["PUBLISH", "orders.created", "42"]).run_now(&storage),
+        Resp::Integer(Integer(2))
+    );
+    assert_eq!(
+        session.next_message().await.to_string(),
+        "*3\r\n$7\r\nmessage\r\n$14\r\norders.created\r\n$2\r\n42\r\n"
+    );
+    assert_eq!(
+        session.next_message().await.to_string(),
+        "*4\r\n$8\r\npmessage\r\n$8\r\norders.*\r\n$14\r\norders.created\r\n$2\r\n42\r\n"
+    );
+
+    assert_eq!(
+        command(&["PUBSUB", "NUMSUB", "orders.created", "other"]).run_now(&storage),
+        Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("orders.created".to_string()))),
+            Resp::Integer(Integer(1)),
+            Resp::BulkString(BulkString(Some("other".to_string()))),
+            Resp::Integer(Integer(0)),
+        ]))
+    );
+    assert_eq!(
+        command(&["PUBSUB", "NUMPAT"]).run_now(&storage),
+        Resp::Integer(Integer(1))
+    );
+    assert_eq!(
+        command(&["PUBSUB", "CHANNELS", "orders.*"]).run_now(&storage),
+        Resp::Array(Array(vec![Resp::BulkString(BulkString(Some(
+            "orders.created".to_string()
+        )))]))
+    );
+
+    // disconnecting drops every subscription
+    session.close(&storage);
+    assert_eq!(
+        command(&["PUBSUB", "NUMPAT"]).run_now(&storage),
+        Resp::Integer(Integer(0))
+    );
+    assert_eq!(
+        command(&["PUBSUB", "CHANNELS"]).run_now(&storage),
+        Resp::Array(Array(Vec::new()))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ssubscribe() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    let mut session = new_session(&storage);
+
+    run(&mut session, &["SUBSCRIBE", "orders"], &storage).await?;
+    // shard subscriptions are counted apart from the others
+    assert_eq!(
+        run(&mut session, &["SSUBSCRIBE", "orders"], &storage).await?,
+        "*3\r\n$10\r\nssubscribe\r\n$6\r\norders\r\n:1\r\n"
+    );
+
+    assert_eq!(
+        command(&["SPUBLISH", "orders", "42"]).run_now(&storage),
+        Resp::Integer(Integer(1))
+    );
+    assert_eq!(
+        session.next_message().await.to_string(),
+        "*3\r\n$8\r\nsmessage\r\n$6\r\norders\r\n$2\r\n42\r\n"
+    );
+
+    assert_eq!(
+        command(&["PUBSUB", "SHARDNUMSUB", "orders"]).run_now(&storage),
+        Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("orders".to_string()))),
+            Resp::Integer(Integer(1)),
+        ]))
+    );
+    assert_eq!(
+        command(&["PUBSUB", "SHARDCHANNELS", "ord*"]).run_now(&storage),
+        Resp::Array(Array(vec![Resp::BulkString(BulkString(Some(
+            "orders".to_string()
+        )))]))
+    );
+
+    assert_eq!(
+        run(&mut session, &["SUNSUBSCRIBE"], &storage).await?,
+        "*3\r\n$12\r\nsunsubscribe\r\n$6\r\norders\r\n:0\r\n"
+    );
+    assert_eq!(
+        command(&["SPUBLISH", "orders", "43"]).run_now(&storage),
+        Resp::Integer(Integer(0))
+    );
+    // still subscribed to the plain channel
+    assert!(run(&mut session, &["GET", "key"], &storage)
+        .await?
+        .starts_with("-ERR Can't execute 'get'"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_tracking() -> Result<()> {
+    let storage: Arc<RwLock<Storage>> = Default::default();
+    let mut session = new_session(&storage);
+    let mut invalidations = new_session(&storage);
+
+    let id = run(&mut invalidations, &["CLIENT", "ID"], &storage).await?;
+    let id = id.trim_start_matches(':').trim_end();
+    run(
+        &mut invalidations,
+        &["SUBSCRIBE", "__redis__:invalidate"],
+        &storage,
+    )
+    .await?;
+
+    assert_eq!(
+        run(
+            &mut session,
+            &["CLIENT", "TRACKING", "ON", "REDIRECT", "42"],
+            &storage
+        )
+        .await?,
+        "-ERR The client ID you want redirect to does not exist\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["CLIENT", "CACHING", "YES"], &storage).await?,
+        "-ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN \
+         or OPTOUT mode enabled\r\n"
+    );
+    assert_eq!(
+        run(
+            &mut session,
+            &["CLIENT", "TRACKING", "ON", "REDIRECT", id],
+            &storage
+        )
+        .await?,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["CLIENT", "GETREDIR"], &storage).await?,
+        format!(":{}\r\n", id)
+    );
+
+    run(&mut session, &["GET", "cached"], &storage).await?;
+    command(&["SET", "other", "1"]).run_now(&storage);
+    command(&["SET", "cached", "1"]).run_now(&storage);
+    assert_eq!(
+        invalidations.next_message().await.to_string(),
+        "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$6\r\ncached\r\n"
+    );
+
+    // flushing invalidates everything at once
+    command(&["FLUSHALL"]).run_now(&storage);
+    assert_eq!(
+        invalidations.next_message().await.to_string(),
+        "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n$-1\r\n"
+    );
+
+    // in OPTIN mode, only reads right after CLIENT CACHING YES are tracked
+    run(
+        &mut session,
+        &["CLIENT", "TRACKING", "ON", "REDIRECT", id, "OPTIN"],
+        &storage,
+    )
+    .await?;
+    run(&mut session, &["GET", "skipped"], &storage).await?;
+    run(&mut session, &["CLIENT", "CACHING", "YES"], &storage).await?;
+    run(&mut session, &["SMEMBERS", "opted"], &storage).await?;
+    command(&["SET", "skipped", "1"]).run_now(&storage);
+    command(&["SADD", "opted", "a"]).run_now(&storage);
+    assert_eq!(
+        invalidations.next_message().await.to_string(),
+        "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$5\r\nopted\r\n"
+    );
+
+    assert_eq!(
+        run(&mut session, &["CLIENT", "TRACKING", "OFF"], &storage).await?,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        run(&mut session, &["CLIENT", "GETREDIR"], &storage).await?,
+        ":-1\r\n"
+    );
+
+    Ok(())
+}
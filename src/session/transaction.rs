@@ -0,0 +1,212 @@
+use std::sync::RwLock;
+
+use anyhow::{anyhow, ensure, Result};
+
+use crate::resp::{Array, Resp, SimpleString};
+use crate::session::{error_reply, ok_reply, Session};
+use crate::storage::Storage;
+
+/// Commands that can't be queued, since they take over the connection or stop the server.
+const NO_MULTI_COMMANDS: &[&str] = &["PSYNC", "REPLCONF", "SHUTDOWN"];
+
+/// The commands queued since `MULTI`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    commands: Vec<Resp>,
+    /// Set when a command failed to queue, so that `EXEC` discards the transaction.
+    aborted: bool,
+}
+
+impl Transaction {
+    /// Queues a command after checking that it could run, replying with `QUEUED`.
+    pub fn queue(&mut self, resp: Resp) -> Resp {
+        let checked = resp.check_command().and_then(|()| {
+            let name = resp.command_name().unwrap_or_default();
+            ensure!(
+                !NO_MULTI_COMMANDS.contains(&name.as_str()),
+                "Command not allowed inside a transaction"
+            );
+            Ok(())
+        });
+
+        match checked {
+            Ok(()) => {
+                self.commands.push(resp);
+                Resp::SimpleString(SimpleString("QUEUED".to_string()))
+            }
+            Err(e) => {
+                self.aborted = true;
+                error_reply(e)
+            }
+        }
+    }
+
+    /// Makes `EXEC` discard the transaction, for a command that failed instead of queuing.
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+}
+
+impl Session {
+    pub(super) fn multi(&mut self, resp: &Resp) -> Resp {
+        if let Err(e) = self.check_no_arguments(resp) {
+            return error_reply(e);
+        }
+        if self.transaction.is_some() {
+            return error_reply(anyhow!("MULTI calls can not be nested"));
+        }
+
+        self.transaction = Some(Transaction::default());
+
+        ok_reply()
+    }
+
+    pub(super) fn discard(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Resp {
+        if let Err(e) = self.check_no_arguments(resp) {
+            return error_reply(e);
+        }
+        if self.transaction.take().is_none() {
+            return error_reply(anyhow!("DISCARD without MULTI"));
+        }
+
+        self.unwatch_all(&mut storage.write().unwrap());
+
+        ok_reply()
+    }
+
+    /// Runs the queued commands under a single write lock, so that no other client sees the
+    /// storage in between them, and replies with an array of their replies. If a watched key was
+    /// modified, nothing runs and the reply is null.
+    pub(super) fn exec(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Resp {
+        if let Err(e) = self.check_no_arguments(resp) {
+            return error_reply(e);
+        }
+        let Some(transaction) = self.transaction.take() else {
+            return error_reply(anyhow!("EXEC without MULTI"));
+        };
+
+        let mut guard = storage.write().unwrap();
+
+        let modified = self.is_watched_key_modified(&guard);
+        self.unwatch_all(&mut guard);
+
+        if transaction.aborted {
+            return error_reply(anyhow!(
+                "EXECABORT Transaction discarded because of previous errors."
+            ));
+        }
+        if modified {
+            return Resp::NullArray;
+        }
+
+        let replies = guard.run_exclusive(|storage| {
+            transaction
+                .commands
+                .into_iter()
+                .map(|command| command.run_now(storage))
+                .collect()
+        });
+
+        Resp::Array(Array(replies))
+    }
+
+    /// MULTI, EXEC and DISCARD take no arguments. Like other invalid commands, they abort the
+    /// transaction if there is one.
+    fn check_no_arguments(&mut self, resp: &Resp) -> Result<()> {
+        let result = resp.check_command();
+
+        if let (Err(_), Some(transaction)) = (&result, &mut self.transaction) {
+            transaction.abort();
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::resp::{Integer, SimpleError};
+    use crate::session::tests::run;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exec() -> Result<()> {
+        let storage: Arc<RwLock<Storage>> = Default::default();
+        let mut session = Session::default();
+
+        let queued = Resp::SimpleString(SimpleString("QUEUED".to_string())).to_string();
+
+        assert_eq!(
+            run(&mut session, &["MULTI"], &storage).await?,
+            ok_reply().to_string()
+        );
+        assert_eq!(
+            run(&mut session, &["SADD", "s", "a", "b"], &storage).await?,
+            queued
+        );
+        assert_eq!(run(&mut session, &["GET", "s"], &storage).await?, queued);
+        assert_eq!(run(&mut session, &["SCARD", "s"], &storage).await?, queued);
+
+        // nothing runs before EXEC
+        assert!(storage.read().unwrap().is_empty());
+
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::Array(Array(vec![
+                Resp::Integer(Integer(2)),
+                Resp::SimpleError(SimpleError(
+                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+                )),
+                Resp::Integer(Integer(2)),
+            ]))
+            .to_string()
+        );
+
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            "-ERR EXEC without MULTI\r\n"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exec_abort_and_discard() -> Result<()> {
+        let storage: Arc<RwLock<Storage>> = Default::default();
+        let mut session = Session::default();
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["SADD", "s", "a"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["SCARD"], &storage).await?,
+            "-ERR wrong number of arguments for 'scard' command\r\n"
+        );
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            "-EXECABORT Transaction discarded because of previous errors.\r\n"
+        );
+        assert!(storage.read().unwrap().is_empty());
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["SADD", "s", "a"], &storage).await?;
+        // blocking commands don't wait inside a transaction
+        run(&mut session, &["BZPOPMIN", "z", "0"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["DISCARD"], &storage).await?,
+            ok_reply().to_string()
+        );
+        assert!(storage.read().unwrap().is_empty());
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["BZPOPMIN", "z", "0"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::Array(Array(vec![Resp::NullArray])).to_string()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::RwLock;
+
+use anyhow::{anyhow, ensure};
+
+use crate::resp::{Array, Resp};
+use crate::session::{error_reply, ok_reply, Session};
+use crate::storage::Storage;
+
+impl Session {
+    pub(super) fn watch(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Resp {
+        let checked = resp.check_command().and_then(|()| {
+            ensure!(
+                self.transaction.is_none(),
+                "WATCH inside MULTI is not allowed"
+            );
+            Ok(())
+        });
+        if let Err(e) = checked {
+            // like the commands that fail to queue, this discards the transaction
+            if let Some(transaction) = &mut self.transaction {
+                transaction.abort();
+            }
+            return error_reply(e);
+        }
+
+        let Resp::Array(Array(elements)) = resp else {
+            return error_reply(anyhow!("invalid command"));
+        };
+
+        let mut storage = storage.write().unwrap();
+        for key in &elements[1..] {
+            if !self.watches.iter().any(|watch| &watch.key == key) {
+                self.watches.push(storage.watch(key.clone()));
+            }
+        }
+
+        ok_reply()
+    }
+
+    pub(super) fn unwatch(&mut self, resp: &Resp, storage: &RwLock<Storage>) -> Resp {
+        if let Err(e) = resp.check_command() {
+            return error_reply(e);
+        }
+
+        self.unwatch_all(&mut storage.write().unwrap());
+
+        ok_reply()
+    }
+
+    /// Whether any watched key was modified since it was watched.
+    pub(super) fn is_watched_key_modified(&self, storage: &Storage) -> bool {
+        self.watches
+            .iter()
+            .any(|watch| storage.is_modified_since(watch))
+    }
+
+    /// Forgets every watched key, as `UNWATCH`, `EXEC` and `DISCARD` do.
+    pub(super) fn unwatch_all(&mut self, storage: &mut Storage) {
+        for watch in self.watches.drain(..) {
+            storage.unwatch(&watch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::Result;
+
+    use crate::resp::{BulkString, Integer, SimpleString};
+    use crate::session::tests::run;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watch() -> Result<()> {
+        let storage: Arc<RwLock<Storage>> = Default::default();
+        let mut session = Session::default();
+        let mut other = Session::default();
+
+        run(&mut session, &["WATCH", "s"], &storage).await?;
+        run(&mut other, &["SADD", "s", "a"], &storage).await?;
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["SADD", "s", "b"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::NullArray.to_string()
+        );
+
+        // EXEC forgot the watched key
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["SADD", "s", "b"], &storage).await?;
+        run(&mut other, &["SADD", "s", "c"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::Array(Array(vec![Resp::Integer(Integer(1))])).to_string()
+        );
+
+        run(&mut session, &["WATCH", "s"], &storage).await?;
+        run(&mut session, &["UNWATCH"], &storage).await?;
+        run(&mut other, &["FLUSHALL"], &storage).await?;
+        run(&mut session, &["MULTI"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::Array(Array(vec![])).to_string()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_inside_multi() -> Result<()> {
+        let storage: Arc<RwLock<Storage>> = Default::default();
+        let mut session = Session::default();
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["SADD", "s", "a"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["WATCH", "s"], &storage).await?,
+            "-ERR WATCH inside MULTI is not allowed\r\n"
+        );
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            "-EXECABORT Transaction discarded because of previous errors.\r\n"
+        );
+        assert!(storage.read().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_flush_and_expiry() -> Result<()> {
+        let storage: Arc<RwLock<Storage>> = Default::default();
+        let mut session = Session::default();
+        let mut other = Session::default();
+
+        run(&mut other, &["SADD", "s", "a"], &storage).await?;
+        run(&mut session, &["WATCH", "s"], &storage).await?;
+        run(&mut other, &["FLUSHDB"], &storage).await?;
+        run(&mut session, &["MULTI"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::NullArray.to_string()
+        );
+
+        run(&mut other, &["SET", "k", "v", "PX", "20"], &storage).await?;
+        run(&mut session, &["WATCH", "k"], &storage).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        run(&mut session, &["MULTI"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["GET", "k"], &storage).await?,
+            Resp::SimpleString(SimpleString("QUEUED".to_string())).to_string()
+        );
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::NullArray.to_string()
+        );
+
+        // a key that doesn't exist can be watched too
+        run(&mut session, &["WATCH", "missing"], &storage).await?;
+        run(&mut session, &["MULTI"], &storage).await?;
+        run(&mut session, &["GET", "missing"], &storage).await?;
+        assert_eq!(
+            run(&mut session, &["EXEC"], &storage).await?,
+            Resp::Array(Array(vec![Resp::BulkString(BulkString(None))])).to_string()
+        );
+
+        Ok(())
+    }
+}
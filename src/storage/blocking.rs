@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::resp::Resp;
+use crate::storage::Storage;
+
+/// Clients blocked on keys, woken up when one of those keys is written to.
+#[derive(Debug, Default, Clone)]
+pub struct Waiters(HashMap<Resp, Vec<Weak<Notify>>>);
+
+impl Waiters {
+    fn register(&mut self, keys: &[Resp], notify: &Arc<Notify>) {
+        for key in keys {
+            let waiters = self.0.entry(key.clone()).or_default();
+            // drop clients that gave up waiting on this key
+            waiters.retain(|waiter| waiter.strong_count() > 0);
+            waiters.push(Arc::downgrade(notify));
+        }
+    }
+
+    /// Forgets a client that stopped waiting without being woken up, because it timed out or
+    /// disconnected, so that keys nobody writes to don't keep entries forever.
+    fn unregister(&mut self, keys: &[Resp], notify: &Arc<Notify>) {
+        for key in keys {
+            let Some(waiters) = self.0.get_mut(key) else {
+                continue;
+            };
+            waiters.retain(|waiter| {
+                waiter.strong_count() > 0 && !std::ptr::eq(waiter.as_ptr(), Arc::as_ptr(notify))
+            });
+            if waiters.is_empty() {
+                self.0.remove(key);
+            }
+        }
+    }
+
+    pub fn wake(&mut self, key: &Resp) {
+        for waiter in self.0.remove(key).into_iter().flatten() {
+            if let Some(notify) = waiter.upgrade() {
+                // `notify_one` stores a permit, so a client that is about to wait is not missed
+                notify.notify_one();
+            }
+        }
+    }
+}
+
+/// Runs `attempt` until it produces a value, waiting for a write to one of `keys` in between.
+///
+/// The storage lock is only held while `attempt` runs, never while waiting. Returns `None` once
+/// `timeout` elapses; a `None` timeout waits forever. Inside a transaction, it never waits.
+pub async fn block_on_keys<T>(
+    storage: &RwLock<Storage>,
+    keys: &[Resp],
+    timeout: Option<Duration>,
+    mut attempt: impl FnMut(&mut Storage) -> Result<Option<T>>,
+) -> Result<Option<T>> {
+    // a deadline too far to represent is as good as none
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let notify = Arc::new(Notify::new());
+    // however this returns, or if the client disconnects and this is dropped
+    let _registration = Registration {
+        storage,
+        keys,
+        notify: &notify,
+    };
+
+    loop {
+        {
+            let mut storage = storage.write().unwrap();
+
+            if let Some(result) = attempt(&mut storage)? {
+                return Ok(Some(result));
+            }
+            if storage.deny_blocking {
+                return Ok(None);
+            }
+
+            storage.waiters.register(keys, &notify);
+        }
+
+        match deadline {
+            None => notify.notified().await,
+            Some(deadline) => {
+                if tokio::time::timeout_at(deadline, notify.notified())
+                    .await
+                    .is_err()
+                {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Unregisters a blocked client from the keys it waits on once dropped.
+struct Registration<'a> {
+    storage: &'a RwLock<Storage>,
+    keys: &'a [Resp],
+    notify: &'a Arc<Notify>,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut storage) = self.storage.write() {
+            storage.waiters.unregister(self.keys, self.notify);
+        }
+    }
+}
+
+/// Parses a blocking command's timeout in seconds, where 0 means no timeout.
+pub fn parse_timeout(timeout: &Resp) -> Result<Option<Duration>> {
+    let seconds = timeout
+        .plain_string()?
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| anyhow!("timeout is not a float or out of range"))?;
+
+    ensure!(seconds >= 0.0, "timeout is negative");
+
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    let timeout = Duration::try_from_secs_f64(seconds).context("timeout is out of range")?;
+
+    Ok(Some(timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::bulk;
+
+    #[tokio::test]
+    async fn test_waiters_removed() {
+        let storage = RwLock::new(Storage::default());
+        let keys = [bulk("never")];
+        let never = |_: &mut Storage| Ok(None::<()>);
+
+        let timeout = Some(Duration::from_millis(1));
+        let result = block_on_keys(&storage, &keys, timeout, never).await;
+        assert!(result.unwrap().is_none());
+        assert!(storage.read().unwrap().waiters.0.is_empty());
+
+        // a client that disconnects drops the command while it waits
+        let blocked = block_on_keys(&storage, &keys, None, never);
+        let timeout = tokio::time::timeout(Duration::from_millis(1), blocked).await;
+        assert!(timeout.is_err());
+        assert!(storage.read().unwrap().waiters.0.is_empty());
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let parse = |s: &str| parse_timeout(&bulk(s));
+
+        assert_eq!(parse("0").unwrap(), None);
+        assert_eq!(parse("0.5").unwrap(), Some(Duration::from_millis(500)));
+        assert_eq!(parse("-1").unwrap_err().to_string(), "timeout is negative");
+        assert_eq!(
+            parse("1e300").unwrap_err().to_string(),
+            "timeout is out of range"
+        );
+        assert_eq!(
+            parse("inf").unwrap_err().to_string(),
+            "timeout is not a float or out of range"
+        );
+    }
+}
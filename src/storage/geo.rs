@@ -0,0 +1,538 @@
+//! Geo indexing on top of sorted sets, a port of Redis' `geohash.c` and `geohash_helper.c`.
+//!
+//! Positions are stored as sorted set scores: a 52-bit geohash made of 26 bits of latitude and 26
+//! bits of longitude, interleaved. Searches scan the score ranges of the geohash box around the
+//! center and its 8 neighbors, then filter out the points outside the shape.
+
+use std::f64::consts::PI;
+
+use crate::storage::{ScoreBound, SortedSet};
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+/// The latitude limits of the Web Mercator projection, which Redis uses instead of +-90.
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+const STEP_MAX: u8 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+const D_R: f64 = PI / 180.0;
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct GeoHash {
+    bits: u64,
+    step: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    longitude: Range,
+    latitude: Range,
+}
+
+const LONGITUDE_RANGE: Range = Range {
+    min: LONGITUDE_MIN,
+    max: LONGITUDE_MAX,
+};
+const LATITUDE_RANGE: Range = Range {
+    min: LATITUDE_MIN,
+    max: LATITUDE_MAX,
+};
+
+pub fn is_valid_position(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+        && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+/// Encodes a position as the score of a sorted set member, or `None` if it's out of range.
+pub fn encode(longitude: f64, latitude: f64) -> Option<f64> {
+    let hash = encode_hash(
+        LONGITUDE_RANGE,
+        LATITUDE_RANGE,
+        longitude,
+        latitude,
+        STEP_MAX,
+    )?;
+
+    Some(hash.bits as f64)
+}
+
+/// Decodes the score of a sorted set member into its `(longitude, latitude)`, the center of its
+/// geohash box.
+pub fn decode(score: f64) -> (f64, f64) {
+    let hash = GeoHash {
+        bits: score as u64,
+        step: STEP_MAX,
+    };
+    let area = decode_hash(LONGITUDE_RANGE, LATITUDE_RANGE, hash);
+
+    let longitude =
+        ((area.longitude.min + area.longitude.max) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude =
+        ((area.latitude.min + area.latitude.max) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+
+    (longitude, latitude)
+}
+
+/// The standard 11 character geohash string of a score.
+///
+/// Scores are re-encoded with the standard latitude range of +-90 first, and since they only have
+/// 52 bits, the last character always stands for zero bits.
+pub fn hash_string(score: f64) -> Option<String> {
+    let (longitude, latitude) = decode(score);
+    let standard_latitude = Range {
+        min: -90.0,
+        max: 90.0,
+    };
+    let hash = encode_hash(
+        LONGITUDE_RANGE,
+        standard_latitude,
+        longitude,
+        latitude,
+        STEP_MAX,
+    )?;
+
+    let string = (0..11)
+        .map(|i| {
+            let index = if i == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            BASE32[index as usize] as char
+        })
+        .collect();
+
+    Some(string)
+}
+
+/// The distance in meters between two positions on the earth, using the haversine formula.
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let longitude1 = longitude1 * D_R;
+    let longitude2 = longitude2 * D_R;
+    let v = ((longitude2 - longitude1) / 2.0).sin();
+
+    // cheaper when the longitudes are practically the same
+    if v == 0.0 {
+        return latitude_distance(latitude1, latitude2);
+    }
+
+    let latitude1 = latitude1 * D_R;
+    let latitude2 = latitude2 * D_R;
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let a = u * u + latitude1.cos() * latitude2.cos() * v * v;
+
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+fn latitude_distance(latitude1: f64, latitude2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (latitude2 * D_R - latitude1 * D_R).abs()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShapeKind {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+/// The area of a search, in the unit given by the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoShape {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub kind: GeoShapeKind,
+    /// How many meters make one unit.
+    pub conversion: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoPoint {
+    pub member: String,
+    pub score: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+    /// The distance in meters from the center of the search.
+    pub distance: f64,
+}
+
+impl GeoShape {
+    /// The distance in meters from the center to a position, or `None` if it's outside.
+    fn distance_if_within(&self, longitude: f64, latitude: f64) -> Option<f64> {
+        match self.kind {
+            GeoShapeKind::Radius(radius) => {
+                let distance = distance(self.longitude, self.latitude, longitude, latitude);
+                (distance <= radius * self.conversion).then_some(distance)
+            }
+            GeoShapeKind::Box { width, height } => {
+                // the latitude distance is cheaper, so it's checked first
+                if latitude_distance(latitude, self.latitude) > height * self.conversion / 2.0 {
+                    return None;
+                }
+                if distance(longitude, latitude, self.longitude, latitude)
+                    > width * self.conversion / 2.0
+                {
+                    return None;
+                }
+
+                Some(distance(self.longitude, self.latitude, longitude, latitude))
+            }
+        }
+    }
+
+    /// `(min_longitude, min_latitude, max_longitude, max_latitude)` of the shape.
+    fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        let (height, width) = match self.kind {
+            GeoShapeKind::Radius(radius) => (radius, radius),
+            GeoShapeKind::Box { width, height } => (height / 2.0, width / 2.0),
+        };
+        let height = self.conversion * height;
+        let width = self.conversion * width;
+
+        let latitude_delta = height / EARTH_RADIUS_IN_METERS / D_R;
+        let longitude_delta_top =
+            width / EARTH_RADIUS_IN_METERS / ((self.latitude + latitude_delta) * D_R).cos() / D_R;
+        let longitude_delta_bottom =
+            width / EARTH_RADIUS_IN_METERS / ((self.latitude - latitude_delta) * D_R).cos() / D_R;
+
+        // the edge nearer to the pole spans the most longitude, and it's a different one in each
+        // hemisphere
+        let longitude_delta = if self.latitude < 0.0 {
+            longitude_delta_bottom
+        } else {
+            longitude_delta_top
+        };
+
+        (
+            self.longitude - longitude_delta,
+            self.latitude - latitude_delta,
+            self.longitude + longitude_delta,
+            self.latitude + latitude_delta,
+        )
+    }
+
+    /// The geohash box containing the center and its neighbors, with a step large enough for
+    /// them to cover the whole shape. Neighbors that don't overlap the shape are left out.
+    fn covering_hashes(&self) -> [Option<GeoHash>; 9] {
+        let (min_longitude, min_latitude, max_longitude, max_latitude) = self.bounding_box();
+
+        // for boxes, the distance from the center to a corner
+        let radius = match self.kind {
+            GeoShapeKind::Radius(radius) => radius,
+            GeoShapeKind::Box { width, height } => {
+                ((width / 2.0) * (width / 2.0) + (height / 2.0) * (height / 2.0)).sqrt()
+            }
+        } * self.conversion;
+
+        let mut step = estimate_step(radius, self.latitude);
+        let encode = |step| {
+            // the center was validated, so it can always be encoded
+            encode_hash(
+                LONGITUDE_RANGE,
+                LATITUDE_RANGE,
+                self.longitude,
+                self.latitude,
+                step,
+            )
+            .unwrap_or_default()
+        };
+
+        let mut hash = encode(step);
+        let mut neighbors = Neighbors::of(hash);
+
+        // near the edges of its box, the estimated step may be too large for the neighbors to
+        // cover everything
+        let decode = |hash| decode_hash(LONGITUDE_RANGE, LATITUDE_RANGE, hash);
+        let too_large = decode(neighbors.north).latitude.max < max_latitude
+            || decode(neighbors.south).latitude.min > min_latitude
+            || decode(neighbors.east).longitude.max < max_longitude
+            || decode(neighbors.west).longitude.min > min_longitude;
+
+        if step > 1 && too_large {
+            step -= 1;
+            hash = encode(step);
+            neighbors = Neighbors::of(hash);
+        }
+
+        let area = decode(hash);
+        let mut hashes = [
+            Some(hash),
+            Some(neighbors.north),
+            Some(neighbors.south),
+            Some(neighbors.east),
+            Some(neighbors.west),
+            Some(neighbors.north_east),
+            Some(neighbors.north_west),
+            Some(neighbors.south_east),
+            Some(neighbors.south_west),
+        ];
+
+        if step >= 2 {
+            let mut exclude = |indices: [usize; 3]| {
+                for index in indices {
+                    hashes[index] = None;
+                }
+            };
+
+            if area.latitude.min < min_latitude {
+                exclude([2, 8, 7]);
+            }
+            if area.latitude.max > max_latitude {
+                exclude([1, 5, 6]);
+            }
+            if area.longitude.min < min_longitude {
+                exclude([4, 8, 6]);
+            }
+            if area.longitude.max > max_longitude {
+                exclude([3, 7, 5]);
+            }
+        }
+
+        hashes
+    }
+}
+
+/// Finds the members of `set` within `shape`, in the order Redis finds them, stopping after
+/// `limit` points if given.
+pub fn search(set: &SortedSet, shape: &GeoShape, limit: Option<usize>) -> Vec<GeoPoint> {
+    let mut points = vec![];
+    let mut last_searched: Option<GeoHash> = None;
+
+    for (index, hash) in shape.covering_hashes().into_iter().enumerate() {
+        let Some(hash) = hash else {
+            continue;
+        };
+
+        // with huge radiuses, adjacent neighbors can be the same box. Like Redis, only neighbors
+        // are compared, never the center box.
+        if last_searched == Some(hash) {
+            continue;
+        }
+        if limit.is_some_and(|limit| points.len() >= limit) {
+            break;
+        }
+
+        let min = align_52_bits(hash) as f64;
+        let max = align_52_bits(GeoHash {
+            bits: hash.bits + 1,
+            step: hash.step,
+        }) as f64;
+
+        let (start, end) =
+            set.score_rank_range(ScoreBound::Inclusive(min), ScoreBound::Exclusive(max));
+
+        for (member, score) in set.iter_from(start, false).take(end - start) {
+            let (longitude, latitude) = decode(score);
+
+            if let Some(distance) = shape.distance_if_within(longitude, latitude) {
+                points.push(GeoPoint {
+                    member: member.to_string(),
+                    score,
+                    longitude,
+                    latitude,
+                    distance,
+                });
+            }
+
+            if limit.is_some_and(|limit| points.len() >= limit) {
+                break;
+            }
+        }
+
+        if index > 0 {
+            last_searched = Some(hash);
+        }
+    }
+
+    points
+}
+
+fn estimate_step(mut range: f64, latitude: f64) -> u8 {
+    if range == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut step: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // make sure the range is included in most of the base cases
+    step -= 2;
+
+    // boxes are narrower towards the poles
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+
+    step.clamp(1, STEP_MAX as i32) as u8
+}
+
+fn encode_hash(
+    longitude_range: Range,
+    latitude_range: Range,
+    longitude: f64,
+    latitude: f64,
+    step: u8,
+) -> Option<GeoHash> {
+    if !is_valid_position(longitude, latitude)
+        || latitude < latitude_range.min
+        || latitude > latitude_range.max
+        || longitude < longitude_range.min
+        || longitude > longitude_range.max
+    {
+        return None;
+    }
+
+    let scale = (1u64 << step) as f64;
+    let latitude_offset =
+        (latitude - latitude_range.min) / (latitude_range.max - latitude_range.min);
+    let longitude_offset =
+        (longitude - longitude_range.min) / (longitude_range.max - longitude_range.min);
+
+    Some(GeoHash {
+        bits: interleave(
+            (latitude_offset * scale) as u32,
+            (longitude_offset * scale) as u32,
+        ),
+        step,
+    })
+}
+
+fn decode_hash(longitude_range: Range, latitude_range: Range, hash: GeoHash) -> Area {
+    let (latitude_bits, longitude_bits) = deinterleave(hash.bits);
+    let scale = (1u64 << hash.step) as f64;
+
+    let latitude_scale = latitude_range.max - latitude_range.min;
+    let longitude_scale = longitude_range.max - longitude_range.min;
+
+    Area {
+        latitude: Range {
+            min: latitude_range.min + (latitude_bits as f64 / scale) * latitude_scale,
+            max: latitude_range.min + ((latitude_bits as f64 + 1.0) / scale) * latitude_scale,
+        },
+        longitude: Range {
+            min: longitude_range.min + (longitude_bits as f64 / scale) * longitude_scale,
+            max: longitude_range.min + ((longitude_bits as f64 + 1.0) / scale) * longitude_scale,
+        },
+    }
+}
+
+fn align_52_bits(hash: GeoHash) -> u64 {
+    hash.bits << (52 - hash.step as u32 * 2)
+}
+
+/// Interleaves the bits of `even` and `odd`, `even` taking the least significant bit.
+fn interleave(even: u32, odd: u32) -> u64 {
+    (0..32).fold(0, |bits, i| {
+        bits | ((even as u64 >> i) & 1) << (2 * i) | ((odd as u64 >> i) & 1) << (2 * i + 1)
+    })
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    (0..32).fold((0, 0), |(even, odd), i| {
+        (
+            even | (((bits >> (2 * i)) & 1) as u32) << i,
+            odd | (((bits >> (2 * i + 1)) & 1) as u32) << i,
+        )
+    })
+}
+
+struct Neighbors {
+    north: GeoHash,
+    south: GeoHash,
+    east: GeoHash,
+    west: GeoHash,
+    north_east: GeoHash,
+    north_west: GeoHash,
+    south_east: GeoHash,
+    south_west: GeoHash,
+}
+
+impl Neighbors {
+    fn of(hash: GeoHash) -> Self {
+        let moved = |dx, dy| move_y(move_x(hash, dx), dy);
+
+        Neighbors {
+            north: moved(0, 1),
+            south: moved(0, -1),
+            east: moved(1, 0),
+            west: moved(-1, 0),
+            north_east: moved(1, 1),
+            north_west: moved(-1, 1),
+            south_east: moved(1, -1),
+            south_west: moved(-1, -1),
+        }
+    }
+}
+
+/// Moves a hash one box east or west, wrapping around, by adding to its longitude bits.
+fn move_x(hash: GeoHash, d: i8) -> GeoHash {
+    move_bits(hash, d, 0xaaaaaaaaaaaaaaaa)
+}
+
+/// Moves a hash one box north or south, wrapping around, by adding to its latitude bits.
+fn move_y(hash: GeoHash, d: i8) -> GeoHash {
+    move_bits(hash, d, 0x5555555555555555)
+}
+
+fn move_bits(hash: GeoHash, d: i8, mask: u64) -> GeoHash {
+    if d == 0 {
+        return hash;
+    }
+
+    let shift = 64 - hash.step as u32 * 2;
+    let moved = hash.bits & mask;
+    let kept = hash.bits & !mask;
+    // all the bits of the other coordinate, so that carries go through them
+    let others = !mask >> shift;
+
+    let moved = if d > 0 {
+        moved.wrapping_add(others + 1)
+    } else {
+        (moved | others).wrapping_sub(others + 1)
+    };
+
+    GeoHash {
+        bits: (moved & (mask >> shift)) | kept,
+        step: hash.step,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        // Palermo, as in the Redis documentation
+        let score = encode(13.361389, 38.115556).unwrap();
+        assert_eq!(score, 3479099956230698.0);
+
+        let (longitude, latitude) = decode(score);
+        assert_eq!(format!("{:.17}", longitude), "13.36138933897018433");
+        assert_eq!(format!("{:.17}", latitude), "38.11555639549629859");
+
+        assert_eq!(hash_string(score).unwrap(), "sqc8b49rny0");
+        assert_eq!(encode(0.0, 86.0), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        let palermo = decode(encode(13.361389, 38.115556).unwrap());
+        let catania = decode(encode(15.087269, 37.502669).unwrap());
+
+        let distance = distance(palermo.0, palermo.1, catania.0, catania.1);
+        assert_eq!(format!("{:.4}", distance), "166274.1516");
+    }
+}
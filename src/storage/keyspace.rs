@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::resp::Resp;
+use crate::storage::Value;
+
+/// How many parts the keyspace is split into. A write after a snapshot copies the part its key
+/// is in, so the more there are, the less a single write copies.
+const SHARDS: usize = 1024;
+
+/// The keys with their values and expiries, which can be copied in constant time.
+///
+/// The keyspace is split into shards shared with its copies until they are written to, and
+/// values are shared until they are modified, the same way a forked process shares memory
+/// pages with its parent. This is what lets a snapshot be saved while the storage changes.
+///
+/// Copying a shard only counts references, as its keys and values are shared too. Copying a
+/// value is cheap as well: sets, sorted sets and streams are persistent trees, so a write to
+/// one that a snapshot shares only copies the O(log n) nodes it touches, much like a forked
+/// process only copies the pages a write touches. Lists and hashes are copied whole, but they
+/// have no commands that write to them.
+#[derive(Debug, Default, Clone)]
+pub struct Keyspace {
+    /// Empty until the first key is added, so that empty keyspaces cost nothing to create.
+    shards: Vec<Arc<Shard>>,
+}
+
+type Shard = HashMap<Arc<Resp>, Entry>;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Arc<Value>,
+    expiry: Option<SystemTime>,
+}
+
+impl Keyspace {
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+
+    pub fn contains_key(&self, key: &Resp) -> bool {
+        self.shard(key).is_some_and(|shard| shard.contains_key(key))
+    }
+
+    pub fn get(&self, key: &Resp) -> Option<(&Value, Option<SystemTime>)> {
+        let entry = self.shard(key)?.get(key)?;
+
+        Some((&entry.value, entry.expiry))
+    }
+
+    /// Copies the value first if a snapshot shares it, which for collections only copies the
+    /// root of their tree.
+    pub fn get_mut(&mut self, key: &Resp) -> Option<&mut Value> {
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let entry = self.shard_mut(key).get_mut(key)?;
+        Some(Arc::make_mut(&mut entry.value))
+    }
+
+    pub fn get_or_insert_with(&mut self, key: &Resp, f: impl FnOnce() -> Value) -> &mut Value {
+        let entry = self
+            .shard_mut(key)
+            .entry(Arc::new(key.clone()))
+            .or_insert_with(|| Entry {
+                value: Arc::new(f()),
+                expiry: None,
+            });
+
+        Arc::make_mut(&mut entry.value)
+    }
+
+    pub fn insert(&mut self, key: Resp, value: Value, expiry: Option<SystemTime>) {
+        let entry = Entry {
+            value: Arc::new(value),
+            expiry,
+        };
+        self.shard_mut(&key).insert(Arc::new(key), entry);
+    }
+
+    /// Removes `key`, returning whether it existed. A value a snapshot shares is left to it
+    /// rather than copied.
+    pub fn remove(&mut self, key: &Resp) -> bool {
+        if !self.contains_key(key) {
+            return false;
+        }
+
+        self.shard_mut(key).remove(key).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.shards.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Resp, &Value, Option<SystemTime>)> {
+        self.shards.iter().flat_map(|shard| {
+            shard
+                .iter()
+                .map(|(key, entry)| (&**key, &*entry.value, entry.expiry))
+        })
+    }
+
+    fn shard(&self, key: &Resp) -> Option<&Shard> {
+        self.shards.get(shard_index(key)).map(|shard| &**shard)
+    }
+
+    /// Copies the shard first if a snapshot shares it.
+    fn shard_mut(&mut self, key: &Resp) -> &mut Shard {
+        if self.shards.is_empty() {
+            self.shards = (0..SHARDS).map(|_| Arc::default()).collect();
+        }
+
+        Arc::make_mut(&mut self.shards[shard_index(key)])
+    }
+}
+
+fn shard_index(key: &Resp) -> usize {
+    BuildHasherDefault::<DefaultHasher>::default().hash_one(key) as usize % SHARDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::bulk;
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut keyspace = Keyspace::default();
+        for i in 0..100 {
+            keyspace.insert(bulk(&i.to_string()), Value::String(bulk("old")), None);
+        }
+
+        let snapshot = keyspace.clone();
+        *keyspace.get_mut(&bulk("1")).unwrap() = Value::String(bulk("new"));
+        keyspace.remove(&bulk("2"));
+        keyspace.insert(bulk("new"), Value::String(bulk("new")), None);
+
+        let string = |keyspace: &Keyspace, key| match keyspace.get(&bulk(key)) {
+            Some((Value::String(s), _)) => Some(s.clone()),
+            _ => None,
+        };
+        assert_eq!(string(&snapshot, "1"), Some(bulk("old")));
+        assert_eq!(string(&keyspace, "1"), Some(bulk("new")));
+        assert_eq!(string(&snapshot, "2"), Some(bulk("old")));
+        assert_eq!(string(&keyspace, "2"), None);
+        assert_eq!((snapshot.len(), keyspace.len()), (100, 100));
+
+        // only the shards written to were copied
+        let copied = (keyspace.shards.iter().zip(&snapshot.shards))
+            .filter(|(a, b)| !Arc::ptr_eq(a, b))
+            .count();
+        assert_eq!(copied, 3);
+
+        // a copied shard still shares its keys with the snapshot
+        let shard = shard_index(&bulk("1"));
+        let key = |keyspace: &Keyspace| {
+            let (key, _) = keyspace.shards[shard].get_key_value(&bulk("1")).unwrap();
+            Arc::clone(key)
+        };
+        assert!(Arc::ptr_eq(&key(&keyspace), &key(&snapshot)));
+
+        keyspace.clear();
+        assert!(keyspace.is_empty());
+        assert_eq!(snapshot.len(), 100);
+    }
+}
@@ -0,0 +1,462 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use blocking::Waiters;
+use keyspace::Keyspace;
+use watch::WatchedKeys;
+
+pub use blocking::{block_on_keys, parse_timeout};
+pub use notify::KeyspaceEvents;
+pub use persistent::{PersistentMap, PersistentSet};
+pub use pubsub::{Outbox, PubSub};
+pub use sorted_set::{format_score, parse_score, LexBound, ScoreBound, SortedSet};
+pub use stream::{
+    ClaimOptions, Claimed, Consumer, ConsumerGroup, Fields, NewStreamId, PendingEntry, Stream,
+    StreamId, Trim, TrimStrategy, STREAM_NODE_MAX_ENTRIES,
+};
+pub use tracking::{parse_tracking_options, Tracking, CURRENT_CLIENT};
+pub use value::{Value, ValueKind};
+pub use watch::Watch;
+
+use crate::aof::{self, Aof};
+use crate::config::{Config, Role};
+use crate::rdb::{self, SaveState};
+use crate::resp::Resp;
+use crate::scripting::{Functions, Scripts};
+
+mod blocking;
+pub mod geo;
+mod keyspace;
+mod notify;
+mod persistent;
+mod pubsub;
+mod sorted_set;
+mod stream;
+mod tracking;
+mod value;
+mod watch;
+
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+const RANDOM_REPLID: &str = "random_replid";
+
+fn random_repl_id() -> String {
+    RANDOM_REPLID.to_string()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Storage {
+    data: Keyspace,
+    pub replication: Replication,
+    waiters: Waiters,
+    watched_keys: WatchedKeys,
+    /// Set while commands run inside a transaction or a script, where blocking commands time
+    /// out right away instead of waiting.
+    deny_blocking: bool,
+    pub scripts: Scripts,
+    pub functions: Functions,
+    pub pubsub: PubSub,
+    pub keyspace_events: KeyspaceEvents,
+    pub tracking: Tracking,
+    pub saves: Arc<SaveState>,
+    /// How many writes there were since the server started, which tells when `save` rules
+    /// are met.
+    dirty: u64,
+    /// Where writes are logged, if `appendonly` is on.
+    pub aof: Option<Arc<Aof>>,
+    /// Set while the AOF is replayed at startup, when keys don't expire.
+    pub loading: bool,
+    /// Whether `SHUTDOWN` saves the dataset unless told not to, which it does when `save` rules
+    /// are set.
+    pub save_on_shutdown: bool,
+}
+
+/// A copy of the dataset taken in constant time, which is saved without holding the storage
+/// lock. The storage copies the parts of the keyspace it writes to while a snapshot shares them.
+#[derive(Debug)]
+pub struct Snapshot {
+    keyspace: Keyspace,
+    pub functions: Functions,
+}
+
+impl Snapshot {
+    /// Every key that hasn't expired, with its value and expiry.
+    pub fn entries(&self) -> impl Iterator<Item = (&Resp, &Value, Option<SystemTime>)> {
+        self.keyspace
+            .iter()
+            .filter(|(_, _, expiry)| !is_expired(expiry))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Replication {
+    Master {
+        replid: String,
+        offset: u64,
+    },
+    Slave {
+        master_host: String,
+        master_port: u16,
+    },
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Replication::Master {
+            replid: random_repl_id(),
+            offset: 0,
+        }
+    }
+}
+
+impl Replication {
+    pub fn info(&self) -> String {
+        match self {
+            Replication::Master { replid, offset } => [
+                "role:master".to_string(),
+                format!("master_replid:{}", replid),
+                format!("master_repl_offset:{}", offset),
+            ]
+            .join("\n"),
+            Replication::Slave {
+                master_host,
+                master_port,
+            } => [
+                "role:slave".to_string(),
+                format!("master_host:{}", master_host),
+                format!("master_port:{}", master_port),
+            ]
+            .join("\n"),
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        matches!(self, Replication::Slave { .. })
+    }
+
+    pub fn info_psync(&self) -> Option<String> {
+        match self {
+            Replication::Master { replid, offset } => Some(format!("{} {}", replid, offset)),
+            Replication::Slave { .. } => None,
+        }
+    }
+}
+
+impl Storage {
+    pub fn new(config: &Config) -> Self {
+        let data = Keyspace::default();
+        let replication = match config.role {
+            Role::Master => Replication::default(),
+            Role::Slave {
+                ref master_host,
+                master_port,
+            } => Replication::Slave {
+                master_host: master_host.clone(),
+                master_port,
+            },
+        };
+
+        Storage {
+            data,
+            replication,
+            waiters: Waiters::default(),
+            watched_keys: WatchedKeys::default(),
+            deny_blocking: false,
+            scripts: Scripts::new(config.busy_reply_threshold),
+            functions: Functions::default(),
+            pubsub: PubSub::default(),
+            keyspace_events: config.notify_keyspace_events,
+            tracking: Tracking::default(),
+            saves: Arc::new(SaveState::new(config.rdb_path())),
+            dirty: 0,
+            aof: None,
+            loading: false,
+            save_on_shutdown: !config.save_rules.is_empty(),
+        }
+    }
+}
+
+impl Storage {
+    /// Runs `f` with this storage moved behind a lock of its own, where blocking commands don't
+    /// wait. Commands lock the storage themselves, so this is how several of them run as one
+    /// while the caller holds the shared lock.
+    pub fn run_exclusive<R>(&mut self, f: impl FnOnce(&RwLock<Storage>) -> R) -> R {
+        let deny_blocking = self.deny_blocking;
+        // nested in another transaction or script, the outer one is already logged as a whole
+        let aof_start = match &self.aof {
+            Some(aof) if !deny_blocking => Some(aof.position()),
+            _ => None,
+        };
+
+        let exclusive = RwLock::new(std::mem::take(self));
+        exclusive.write().unwrap().deny_blocking = true;
+
+        let result = f(&exclusive);
+
+        *self = exclusive.into_inner().unwrap();
+        self.deny_blocking = deny_blocking;
+
+        if let (Some(aof), Some(start)) = (&self.aof, aof_start) {
+            aof.wrap_in_transaction(start);
+        }
+
+        result
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, key: &Resp) -> Result<Option<&Resp>> {
+        match self.get_value(key) {
+            None => Ok(None),
+            Some(Value::String(resp)) => Ok(Some(resp)),
+            Some(_) => bail!(WRONGTYPE),
+        }
+    }
+
+    pub fn set(&mut self, key: Resp, value: Resp, expiry: Option<Duration>) {
+        self.remove_if_expired(&key);
+        self.touch(&key);
+
+        if !self.data.contains_key(&key) {
+            self.notify(KeyspaceEvents::NEW, "new", &key);
+        }
+        self.notify(KeyspaceEvents::STRING, "set", &key);
+        if expiry.is_some() {
+            self.notify(KeyspaceEvents::GENERIC, "expire", &key);
+        }
+
+        self.data.insert(
+            key,
+            Value::String(value),
+            expiry.map(|d| SystemTime::now() + d),
+        );
+    }
+
+    /// Stores a value loaded from a snapshot, with its expiry.
+    pub fn restore(&mut self, key: Resp, value: Value, expiry: Option<SystemTime>) {
+        self.data.insert(key, value, expiry);
+    }
+
+    pub fn get_value(&self, key: &Resp) -> Option<&Value> {
+        match self.data.get(key) {
+            Some((value, expiry)) if self.loading || !is_expired(&expiry) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of `key` as `T`, or bails with `WRONGTYPE` if it holds another type.
+    pub fn get_as<T: ValueKind>(&self, key: &Resp) -> Result<Option<&T>> {
+        match self.get_value(key) {
+            None => Ok(None),
+            Some(value) => T::from_value(value)
+                .map(Some)
+                .ok_or_else(|| anyhow!(WRONGTYPE)),
+        }
+    }
+
+    /// Mutable access alone isn't a modification: callers [`Storage::touch`] `key` once they
+    /// changed the value.
+    pub fn get_as_mut<T: ValueKind>(&mut self, key: &Resp) -> Result<Option<&mut T>> {
+        self.remove_if_expired(key);
+
+        // checked first, so that a value of another type isn't copied for nothing
+        match self.data.get(key) {
+            None => return Ok(None),
+            Some((value, _)) => ensure!(T::from_value(value).is_some(), WRONGTYPE),
+        }
+
+        Ok(self.data.get_mut(key).and_then(T::from_value_mut))
+    }
+
+    /// Like [`Storage::get_as_mut`], but creates an empty `T` if `key` does not exist. Callers
+    /// only get here once they know they will add to it, since the key is announced as new.
+    ///
+    /// This is how values are added to, so it also wakes up clients blocked on `key`.
+    pub fn get_or_default_as_mut<T: ValueKind>(&mut self, key: &Resp) -> Result<&mut T> {
+        self.remove_if_expired(key);
+
+        match self.data.get(key) {
+            Some((value, _)) => ensure!(T::from_value(value).is_some(), WRONGTYPE),
+            None => self.notify(KeyspaceEvents::NEW, "new", key),
+        }
+        self.waiters.wake(key);
+
+        let value = self
+            .data
+            .get_or_insert_with(key, || T::default().into_value());
+
+        T::from_value_mut(value).ok_or_else(|| anyhow!(WRONGTYPE))
+    }
+
+    /// Removes `key` if it holds an empty `T`, so that empty collections never stay visible.
+    pub fn remove_if_empty<T: ValueKind>(&mut self, key: &Resp) {
+        let is_empty = matches!(
+            self.data.get(key).and_then(|(value, _)| T::from_value(value)),
+            Some(t) if t.is_empty()
+        );
+
+        if is_empty {
+            self.data.remove(key);
+            self.notify(KeyspaceEvents::GENERIC, "del", key);
+        }
+    }
+
+    /// Stores `value` under `key`, replacing any previous value and its expiry. An empty `value`
+    /// removes the key instead.
+    ///
+    /// Callers publish the event of the command that produced `value`, unless it was empty.
+    pub fn insert_as<T: ValueKind>(&mut self, key: Resp, value: T) {
+        self.remove_if_expired(&key);
+
+        if value.is_empty() {
+            if self.data.remove(&key) {
+                self.touch(&key);
+                self.notify(KeyspaceEvents::GENERIC, "del", &key);
+            }
+        } else {
+            self.touch(&key);
+            self.waiters.wake(&key);
+            if !self.data.contains_key(&key) {
+                self.notify(KeyspaceEvents::NEW, "new", &key);
+            }
+            self.data.insert(key, value.into_value(), None);
+        }
+    }
+
+    /// Removes every key.
+    pub fn flush(&mut self) {
+        self.propagate();
+        self.dirty += self.data.len() as u64;
+        self.touch_all_watched();
+        self.tracking.invalidate_all(&self.pubsub);
+        self.data.clear();
+    }
+
+    pub fn dirty(&self) -> u64 {
+        self.dirty
+    }
+
+    /// Logs the write command that is running to the AOF, if it wasn't already. Modifying the
+    /// storage does this, so only writes that don't modify a key need to.
+    pub fn propagate(&self) {
+        if let Some(aof) = &self.aof {
+            aof::log_current(aof);
+        }
+    }
+
+    /// Changes what the running write command is logged as, for commands that depend on the
+    /// time or on randomness, so that replaying the log does the same as they did.
+    pub fn rewrite_propagated(&self, f: impl FnOnce(&mut Vec<Resp>)) {
+        if let Some(aof) = &self.aof {
+            aof::rewrite_current(aof, f);
+        }
+    }
+
+    /// Writes the commands logged so far to the AOF. This happens before their replies are
+    /// sent, so that a client never hears about a write that isn't in the log.
+    pub fn write_aof(&self) {
+        let Some(aof) = &self.aof else {
+            return;
+        };
+
+        if let Err(e) = aof.write() {
+            if aof.fsync_policy() == aof::Fsync::Always {
+                eprintln!(
+                    "Can't recover from AOF write error when the AOF fsync policy is 'always'. \
+                     Exiting...; error = {:?}",
+                    e
+                );
+                std::process::exit(1);
+            }
+            eprintln!("writing the AOF failed; error = {:?}", e);
+        }
+    }
+
+    /// The dataset as it is now, which later writes don't change.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            keyspace: self.data.clone(),
+            functions: self.functions.clone(),
+        }
+    }
+
+    /// Removes `key`, returning whether it existed.
+    pub fn remove(&mut self, key: &Resp) -> bool {
+        self.remove_if_expired(key);
+
+        if !self.data.remove(key) {
+            return false;
+        }
+        self.touch(key);
+
+        true
+    }
+
+    /// Records that the running command modified `key`: the command is logged to the AOF, and
+    /// the clients watching `key` or caching it are told.
+    pub fn touch(&mut self, key: &Resp) {
+        self.propagate();
+        self.invalidate(key);
+    }
+
+    /// Counts a modification of `key` and tells the clients watching it or caching it, without
+    /// logging the running command, which didn't make it.
+    fn invalidate(&mut self, key: &Resp) {
+        self.dirty += 1;
+        self.watched_keys.touch(key);
+        self.tracking.invalidate(key, &self.pubsub);
+    }
+
+    fn remove_if_expired(&mut self, key: &Resp) {
+        if self.loading {
+            return;
+        }
+
+        if matches!(self.data.get(key), Some((_, expiry)) if is_expired(&expiry)) {
+            // logged before the command that found the key expired
+            if let (Some(aof), Ok(key)) = (&self.aof, key.plain_string()) {
+                aof::log(aof, ["DEL", key]);
+            }
+            self.invalidate(key);
+            self.data.remove(key);
+            self.notify(KeyspaceEvents::EXPIRED, "expired", key);
+        }
+    }
+
+    /// Removes every expired key. Reads leave expired keys in place, so without this a key that
+    /// is only read would stay around, and its `expired` event would never be published.
+    pub fn remove_expired(&mut self) {
+        let expired = self
+            .data
+            .iter()
+            .filter(|(_, _, expiry)| is_expired(expiry))
+            .map(|(key, _, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in expired {
+            self.remove_if_expired(&key);
+        }
+    }
+
+    /// The dataset as an RDB file, framed like a bulk string without the trailing CRLF, the way
+    /// replicas are sent it on a full resynchronization.
+    pub fn encode(&self) -> Vec<u8> {
+        let bytes = rdb::encode(&self.snapshot());
+
+        let mut result = Vec::new();
+        result.extend_from_slice("$".as_bytes());
+        result.extend(format!("{}\r\n", bytes.len()).as_bytes());
+        result.extend_from_slice(&bytes);
+
+        result
+    }
+}
+
+fn is_expired(expiry: &Option<SystemTime>) -> bool {
+    matches!(expiry, Some(expiry) if *expiry < SystemTime::now())
+}
@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+
+use crate::resp::Resp;
+use crate::storage::Storage;
+
+/// The classes of keyspace events to publish, selected by the `notify-keyspace-events` flags.
+///
+/// `K` and `E` choose the channels, `__keyspace@0__:<key>` and `__keyevent@0__:<event>`, and the
+/// other flags the events. Nothing is published unless one of each is given.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    pub const GENERIC: KeyspaceEvents = KeyspaceEvents(1 << 0);
+    pub const STRING: KeyspaceEvents = KeyspaceEvents(1 << 1);
+    pub const LIST: KeyspaceEvents = KeyspaceEvents(1 << 2);
+    pub const SET: KeyspaceEvents = KeyspaceEvents(1 << 3);
+    pub const HASH: KeyspaceEvents = KeyspaceEvents(1 << 4);
+    pub const ZSET: KeyspaceEvents = KeyspaceEvents(1 << 5);
+    pub const EXPIRED: KeyspaceEvents = KeyspaceEvents(1 << 6);
+    /// There is no memory limit here, so keys are never evicted, but the flag is accepted.
+    pub const EVICTED: KeyspaceEvents = KeyspaceEvents(1 << 7);
+    pub const STREAM: KeyspaceEvents = KeyspaceEvents(1 << 8);
+    pub const KEY_MISS: KeyspaceEvents = KeyspaceEvents(1 << 9);
+    pub const MODULE: KeyspaceEvents = KeyspaceEvents(1 << 10);
+    pub const NEW: KeyspaceEvents = KeyspaceEvents(1 << 11);
+    const KEYSPACE: KeyspaceEvents = KeyspaceEvents(1 << 12);
+    const KEYEVENT: KeyspaceEvents = KeyspaceEvents(1 << 13);
+
+    /// Parses flags such as `KEA` or `Ex`, like Redis does.
+    pub fn parse(flags: &str) -> Result<Self> {
+        let mut events = KeyspaceEvents::default();
+
+        for flag in flags.chars() {
+            events.0 |= match flag {
+                'g' => KeyspaceEvents::GENERIC.0,
+                '$' => KeyspaceEvents::STRING.0,
+                'l' => KeyspaceEvents::LIST.0,
+                's' => KeyspaceEvents::SET.0,
+                'h' => KeyspaceEvents::HASH.0,
+                'z' => KeyspaceEvents::ZSET.0,
+                'x' => KeyspaceEvents::EXPIRED.0,
+                'e' => KeyspaceEvents::EVICTED.0,
+                't' => KeyspaceEvents::STREAM.0,
+                'm' => KeyspaceEvents::KEY_MISS.0,
+                'd' => KeyspaceEvents::MODULE.0,
+                'n' => KeyspaceEvents::NEW.0,
+                'K' => KeyspaceEvents::KEYSPACE.0,
+                'E' => KeyspaceEvents::KEYEVENT.0,
+                // every class but key misses and new keys, which are noisy
+                'A' => {
+                    KeyspaceEvents::GENERIC.0
+                        | KeyspaceEvents::STRING.0
+                        | KeyspaceEvents::LIST.0
+                        | KeyspaceEvents::SET.0
+                        | KeyspaceEvents::HASH.0
+                        | KeyspaceEvents::ZSET.0
+                        | KeyspaceEvents::EXPIRED.0
+                        | KeyspaceEvents::EVICTED.0
+                        | KeyspaceEvents::STREAM.0
+                        | KeyspaceEvents::MODULE.0
+                }
+                _ => bail!("Invalid event class character. Use 'Ag$lshzxeKEtmdn'."),
+            };
+        }
+
+        Ok(events)
+    }
+
+    fn contains(self, other: KeyspaceEvents) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Storage {
+    /// Publishes that `event` happened to `key`, if its class is enabled.
+    pub fn notify(&self, class: KeyspaceEvents, event: &str, key: &Resp) {
+        let events = self.keyspace_events;
+        if !events.contains(class) {
+            return;
+        }
+
+        let key = key.plain_string().unwrap_or_default();
+        if events.contains(KeyspaceEvents::KEYSPACE) {
+            self.pubsub
+                .publish(&format!("__keyspace@0__:{}", key), event);
+        }
+        if events.contains(KeyspaceEvents::KEYEVENT) {
+            self.pubsub
+                .publish(&format!("__keyevent@0__:{}", event), key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let events = KeyspaceEvents::parse("KEA").unwrap();
+        assert!(events.contains(KeyspaceEvents::KEYSPACE));
+        assert!(events.contains(KeyspaceEvents::STREAM));
+        assert!(!events.contains(KeyspaceEvents::NEW));
+
+        let events = KeyspaceEvents::parse("Ex").unwrap();
+        assert!(events.contains(KeyspaceEvents::EXPIRED));
+        assert!(!events.contains(KeyspaceEvents::KEYSPACE));
+        assert!(!events.contains(KeyspaceEvents::GENERIC));
+
+        assert_eq!(
+            KeyspaceEvents::parse("").unwrap(),
+            KeyspaceEvents::default()
+        );
+        assert!(KeyspaceEvents::parse("KEq").is_err());
+    }
+}
@@ -0,0 +1,752 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Bound, Index, Range, RangeBounds};
+use std::sync::Arc;
+
+use crate::utils::random_u64;
+
+/// An ordered map whose copies share their nodes until they are written to, so that copying
+/// it takes constant time, and a write to a copy only copies the O(log n) nodes on the path to
+/// the entry it changes. This is what collections are made of, so that a snapshot sharing one
+/// doesn't make the next write to it copy every member.
+///
+/// It is a treap: a binary search tree by key that is also a heap by random priorities, which
+/// keeps it balanced in expectation. Every node counts the entries below it, so entries can be
+/// found by rank in O(log n) too. Ranks are 0-based.
+pub struct PersistentMap<K, V> {
+    root: Link<K, V>,
+}
+
+type Link<K, V> = Option<Arc<Node<K, V>>>;
+
+#[derive(Clone)]
+struct Node<K, V> {
+    key: K,
+    value: V,
+    priority: u64,
+    /// Number of entries in the subtree rooted at this node.
+    len: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    fn update_len(&mut self) {
+        self.len = len(&self.left) + 1 + len(&self.right);
+    }
+}
+
+fn len<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.len)
+}
+
+/// Splits the entries into those with keys before `key` and the others.
+fn split<K, V, Q>(link: Link<K, V>, key: &Q) -> (Link<K, V>, Link<K, V>)
+where
+    K: Borrow<Q> + Clone,
+    V: Clone,
+    Q: Ord + ?Sized,
+{
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+
+    let inner = Arc::make_mut(&mut node);
+    if inner.key.borrow() < key {
+        let (left, right) = split(inner.right.take(), key);
+        inner.right = left;
+        inner.update_len();
+        (Some(node), right)
+    } else {
+        let (left, right) = split(inner.left.take(), key);
+        inner.left = right;
+        inner.update_len();
+        (left, Some(node))
+    }
+}
+
+/// Joins two treaps, where every key of `left` is before every key of `right`.
+fn merge<K: Clone, V: Clone>(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                let inner = Arc::make_mut(&mut left);
+                inner.right = merge(inner.right.take(), Some(right));
+                inner.update_len();
+                Some(left)
+            } else {
+                let inner = Arc::make_mut(&mut right);
+                inner.left = merge(Some(left), inner.left.take());
+                inner.update_len();
+                Some(right)
+            }
+        }
+    }
+}
+
+/// Removes `key`, which must be in the treap.
+fn remove<K, V, Q>(link: &mut Link<K, V>, key: &Q) -> Option<V>
+where
+    K: Borrow<Q> + Clone,
+    V: Clone,
+    Q: Ord + ?Sized,
+{
+    let ordering = key.cmp(link.as_ref()?.key.borrow());
+    if ordering == Ordering::Equal {
+        let node = Arc::unwrap_or_clone(link.take()?);
+        *link = merge(node.left, node.right);
+        return Some(node.value);
+    }
+
+    let node = Arc::make_mut(link.as_mut()?);
+    let value = match ordering {
+        Ordering::Less => remove(&mut node.left, key),
+        _ => remove(&mut node.right, key),
+    };
+    node.update_len();
+
+    value
+}
+
+impl<K, V> PersistentMap<K, V> {
+    pub fn new() -> Self {
+        PersistentMap { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        len(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the entry at `rank`.
+    pub fn get_index(&self, mut rank: usize) -> Option<(&K, &V)> {
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let left = len(&node.left);
+            match rank.cmp(&left) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    rank -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the number of leading entries for which `in_prefix` holds, in O(log n).
+    /// `in_prefix` must hold for a (possibly empty) prefix of the entries and not afterwards.
+    pub fn prefix_len(&self, in_prefix: impl Fn(&K, &V) -> bool) -> usize {
+        let mut rank = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            if in_prefix(&node.key, &node.value) {
+                rank += len(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+
+        rank
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.get_index(self.len().checked_sub(1)?)
+    }
+
+    /// Iterates over the entries with ranks in `ranks`.
+    pub fn rank_range(&self, ranks: Range<usize>) -> Iter<'_, K, V> {
+        let end = ranks.end.min(self.len());
+        let start = ranks.start.min(end);
+
+        if start == end {
+            return Iter {
+                front: vec![],
+                back: vec![],
+                len: 0,
+            };
+        }
+
+        Iter {
+            front: self.path_to(start, false),
+            back: self.path_to(end - 1, true),
+            len: end - start,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.rank_range(0..self.len())
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns the nodes an in-order walk from `rank` has left to visit, the next one last.
+    /// The walk goes towards higher ranks, or towards lower ones if `rev` is set.
+    fn path_to(&self, mut rank: usize, rev: bool) -> Vec<&Node<K, V>> {
+        let mut path = vec![];
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            let left = len(&node.left);
+            match rank.cmp(&left) {
+                Ordering::Less => {
+                    if !rev {
+                        path.push(&**node);
+                    }
+                    link = &node.left;
+                }
+                Ordering::Equal => {
+                    path.push(&**node);
+                    break;
+                }
+                Ordering::Greater => {
+                    if rev {
+                        path.push(&**node);
+                    }
+                    rank -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+
+        path
+    }
+}
+
+impl<K: Ord, V> PersistentMap<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => link = &node.left,
+                Ordering::Greater => link = &node.right,
+                Ordering::Equal => return Some(&node.value),
+            }
+        }
+
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Returns the rank of `key`.
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut rank = 0;
+        let mut link = &self.root;
+
+        while let Some(node) = link {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => link = &node.left,
+                Ordering::Greater => {
+                    rank += len(&node.left) + 1;
+                    link = &node.right;
+                }
+                Ordering::Equal => return Some(rank + len(&node.left)),
+            }
+        }
+
+        None
+    }
+
+    /// Iterates over the entries with keys in `range`. Unlike `BTreeMap::range`, a range that
+    /// starts after it ends is empty rather than a panic.
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Unbounded => 0,
+            Bound::Included(start) => self.prefix_len(|key, _| key.borrow() < start),
+            Bound::Excluded(start) => self.prefix_len(|key, _| key.borrow() <= start),
+        };
+        let end = match range.end_bound() {
+            Bound::Unbounded => self.len(),
+            Bound::Included(end) => self.prefix_len(|key, _| key.borrow() <= end),
+            Bound::Excluded(end) => self.prefix_len(|key, _| key.borrow() < end),
+        };
+
+        self.rank_range(start..end)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> PersistentMap<K, V> {
+    /// Copies the nodes on the path to `key` first if a copy of the map shares them.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // so that looking for a missing key copies nothing
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let mut link = &mut self.root;
+        loop {
+            let node = Arc::make_mut(link.as_mut()?);
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => link = &mut node.left,
+                Ordering::Greater => link = &mut node.right,
+                Ordering::Equal => return Some(&mut node.value),
+            }
+        }
+    }
+
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        if !self.contains_key(&key) {
+            self.insert(key.clone(), default());
+        }
+
+        self.get_mut(&key).expect("the key was just inserted")
+    }
+
+    /// Inserts an entry, returning the previous value of `key` if it had one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(previous) = self.get_mut(&key) {
+            return Some(std::mem::replace(previous, value));
+        }
+
+        let (left, right) = split(self.root.take(), &key);
+        let node = Node {
+            key,
+            value,
+            priority: random_u64(),
+            len: 1,
+            left: None,
+            right: None,
+        };
+        self.root = merge(merge(left, Some(Arc::new(node))), right);
+
+        None
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        // so that removing a missing key copies nothing
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        remove(&mut self.root, key)
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let key = self.first_key_value()?.0.clone();
+        let value = self.remove(&key)?;
+
+        Some((key, value))
+    }
+}
+
+impl<K, V> Clone for PersistentMap<K, V> {
+    /// Takes constant time, as the copy shares every node.
+    fn clone(&self) -> Self {
+        PersistentMap {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for PersistentMap<K, V> {
+    fn default() -> Self {
+        PersistentMap::new()
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for PersistentMap<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, Q> Index<&Q> for PersistentMap<K, V>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> FromIterator<(K, V)> for PersistentMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = PersistentMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a PersistentMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An in-order walk over a range of ranks, from both ends.
+pub struct Iter<'a, K, V> {
+    front: Vec<&'a Node<K, V>>,
+    back: Vec<&'a Node<K, V>>,
+    /// Entries left between the two ends, so that they stop when they meet.
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        let mut link = &node.right;
+        while let Some(next) = link {
+            self.front.push(next);
+            link = &next.left;
+        }
+        self.len -= 1;
+
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        let mut link = &node.left;
+        while let Some(next) = link {
+            self.back.push(next);
+            link = &next.right;
+        }
+        self.len -= 1;
+
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+/// An ordered set made of a [`PersistentMap`], so copying it takes constant time and a write
+/// to a copy only copies O(log n) nodes.
+pub struct PersistentSet<T> {
+    map: PersistentMap<T, ()>,
+}
+
+impl<T> PersistentSet<T> {
+    pub fn new() -> Self {
+        PersistentSet {
+            map: PersistentMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the member at `rank`.
+    pub fn get_index(&self, rank: usize) -> Option<&T> {
+        self.map.get_index(rank).map(|(member, _)| member)
+    }
+
+    /// Returns the number of leading members for which `in_prefix` holds, in O(log n).
+    /// `in_prefix` must hold for a (possibly empty) prefix of the members and not afterwards.
+    pub fn prefix_len(&self, in_prefix: impl Fn(&T) -> bool) -> usize {
+        self.map.prefix_len(|member, _| in_prefix(member))
+    }
+
+    /// Iterates over the members with ranks in `ranks`.
+    pub fn rank_range(&self, ranks: Range<usize>) -> SetIter<'_, T> {
+        SetIter(self.map.rank_range(ranks))
+    }
+
+    pub fn iter(&self) -> SetIter<'_, T> {
+        SetIter(self.map.iter())
+    }
+}
+
+impl<T: Ord> PersistentSet<T> {
+    pub fn contains<Q>(&self, member: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(member)
+    }
+
+    /// Returns the rank of `member`.
+    pub fn rank<Q>(&self, member: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.rank(member)
+    }
+
+    /// Iterates over the members in `range`, see [`PersistentMap::range`].
+    pub fn range<Q, R>(&self, range: R) -> SetIter<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        SetIter(self.map.range(range))
+    }
+}
+
+impl<T: Ord + Clone> PersistentSet<T> {
+    /// Adds `member`, returning whether it wasn't in the set yet.
+    pub fn insert(&mut self, member: T) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+
+        self.map.insert(member, ());
+
+        true
+    }
+
+    /// Removes `member`, returning whether it was in the set.
+    pub fn remove<Q>(&mut self, member: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(member).is_some()
+    }
+}
+
+impl<T> Clone for PersistentSet<T> {
+    /// Takes constant time, as the copy shares every node.
+    fn clone(&self) -> Self {
+        PersistentSet {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<T> Default for PersistentSet<T> {
+    fn default() -> Self {
+        PersistentSet::new()
+    }
+}
+
+impl<T: Debug> Debug for PersistentSet<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Ord + Clone> Extend<T> for PersistentSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for member in iter {
+            self.insert(member);
+        }
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for PersistentSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = PersistentSet::new();
+        set.extend(iter);
+
+        set
+    }
+}
+
+impl<'a, T> IntoIterator for &'a PersistentSet<T> {
+    type Item = &'a T;
+    type IntoIter = SetIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct SetIter<'a, T>(Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for SetIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(member, _)| member)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for SetIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(member, _)| member)
+    }
+}
+
+impl<T> ExactSizeIterator for SetIter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// The nodes of `map` that `other` doesn't share.
+    fn unshared<K, V>(map: &PersistentMap<K, V>, other: &PersistentMap<K, V>) -> usize {
+        fn nodes<K, V>(link: &Link<K, V>, found: &mut HashSet<*const Node<K, V>>) {
+            if let Some(node) = link {
+                found.insert(Arc::as_ptr(node));
+                nodes(&node.left, found);
+                nodes(&node.right, found);
+            }
+        }
+
+        let (mut ours, mut theirs) = (HashSet::new(), HashSet::new());
+        nodes(&map.root, &mut ours);
+        nodes(&other.root, &mut theirs);
+
+        ours.difference(&theirs).count()
+    }
+
+    #[test]
+    fn test_insert_remove_rank() {
+        let mut map = PersistentMap::new();
+
+        for i in (0..200).rev() {
+            assert_eq!(map.insert(i, format!("m{}", i)), None);
+        }
+        assert_eq!(map.insert(7, "seven".to_string()), Some("m7".to_string()));
+
+        assert_eq!(map.len(), 200);
+        for i in 0..200 {
+            assert_eq!(map.rank(&i), Some(i));
+        }
+
+        for i in (0..200).step_by(2) {
+            assert!(map.remove(&i).is_some());
+        }
+        assert_eq!(map.remove(&0), None);
+
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.rank(&51), Some(25));
+        assert_eq!(map.get_index(25), Some((&51, &"m51".to_string())));
+        assert_eq!(map.get(&7).map(String::as_str), Some("seven"));
+
+        let keys = map
+            .rank_range(98..1000)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![197, 199]);
+
+        let keys = map
+            .rank_range(0..2)
+            .rev()
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![3, 1]);
+
+        let keys = map.range(10..=15).map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(keys, vec![11, 13, 15]);
+
+        let mut range = map.range((Bound::Excluded(11), Bound::Unbounded));
+        assert_eq!(range.len(), 94);
+        assert_eq!(range.next().map(|(key, _)| *key), Some(13));
+        assert_eq!(range.next_back().map(|(key, _)| *key), Some(199));
+        assert_eq!(range.len(), 92);
+
+        assert_eq!(map.prefix_len(|key, _| *key < 50), 25);
+        assert_eq!(map.pop_first().map(|(key, _)| key), Some(1));
+        assert_eq!(map.first_key_value().map(|(key, _)| *key), Some(3));
+        assert_eq!(map.last_key_value().map(|(key, _)| *key), Some(199));
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut set = (0..10_000).collect::<PersistentSet<u32>>();
+        let snapshot = set.clone();
+
+        assert!(set.insert(10_000));
+        assert!(set.remove(&5000));
+        assert!(!set.remove(&20_000));
+
+        assert_eq!((set.len(), snapshot.len()), (10_000, 10_000));
+        assert!(set.contains(&10_000) && !snapshot.contains(&10_000));
+        assert!(!set.contains(&5000) && snapshot.contains(&5000));
+
+        // only the paths to the members written to were copied
+        assert!(unshared(&set.map, &snapshot.map) < 200);
+
+        let mut map = (0..10_000)
+            .map(|i| (i, i))
+            .collect::<PersistentMap<u32, u32>>();
+        let snapshot = map.clone();
+
+        assert_eq!(map.get_mut(&20_000), None);
+        assert_eq!(unshared(&map, &snapshot), 0);
+
+        *map.get_mut(&42).unwrap() = 0;
+        assert_eq!((map[&42], snapshot[&42]), (0, 42));
+        assert!(unshared(&map, &snapshot) < 100);
+    }
+}
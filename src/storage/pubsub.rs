@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::resp::{Array, BulkString, Resp};
+use crate::utils::glob_match;
+
+/// Where the messages for a client go: its connection writes them out between commands.
+pub type Outbox = UnboundedSender<Resp>;
+
+type Subscribers = HashMap<String, Vec<Outbox>>;
+
+/// The clients subscribed to each channel, and to each glob-style pattern of channels.
+///
+/// Shard channels are a namespace of their own, which neither `PUBLISH` nor patterns reach. In
+/// a cluster their messages stay on the node owning the channel's slot; a single node owns
+/// every slot, so here they only differ from the other channels by that separation.
+#[derive(Debug, Default, Clone)]
+pub struct PubSub {
+    channels: Subscribers,
+    patterns: Subscribers,
+    shard_channels: Subscribers,
+}
+
+impl PubSub {
+    pub fn subscribe(&mut self, channel: &str, outbox: &Outbox) {
+        add(&mut self.channels, channel, outbox);
+    }
+
+    pub fn unsubscribe(&mut self, channel: &str, outbox: &Outbox) {
+        remove(&mut self.channels, channel, outbox);
+    }
+
+    pub fn psubscribe(&mut self, pattern: &str, outbox: &Outbox) {
+        add(&mut self.patterns, pattern, outbox);
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &str, outbox: &Outbox) {
+        remove(&mut self.patterns, pattern, outbox);
+    }
+
+    pub fn ssubscribe(&mut self, channel: &str, outbox: &Outbox) {
+        add(&mut self.shard_channels, channel, outbox);
+    }
+
+    pub fn sunsubscribe(&mut self, channel: &str, outbox: &Outbox) {
+        remove(&mut self.shard_channels, channel, outbox);
+    }
+
+    /// Sends a message to the subscribers of a channel and of the patterns matching it,
+    /// returning how many messages were sent. A client subscribed in several ways gets one for
+    /// each.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut receivers = 0;
+
+        if let Some(subscribers) = self.channels.get(channel) {
+            let frame = message_frame(&["message", channel, message]);
+            receivers += send(subscribers, &frame);
+        }
+
+        for (pattern, subscribers) in &self.patterns {
+            if glob_match(pattern, channel) {
+                let frame = message_frame(&["pmessage", pattern, channel, message]);
+                receivers += send(subscribers, &frame);
+            }
+        }
+
+        receivers
+    }
+
+    /// Sends a message to the subscribers of a shard channel, returning how many got it.
+    pub fn spublish(&self, channel: &str, message: &str) -> usize {
+        self.shard_channels.get(channel).map_or(0, |subscribers| {
+            send(subscribers, &message_frame(&["smessage", channel, message]))
+        })
+    }
+
+    /// The channels with at least one subscriber that match `pattern`, or all of them.
+    pub fn channels(&self, pattern: Option<&str>) -> Vec<&str> {
+        matching_names(&self.channels, pattern)
+    }
+
+    pub fn shard_channels(&self, pattern: Option<&str>) -> Vec<&str> {
+        matching_names(&self.shard_channels, pattern)
+    }
+
+    pub fn num_subscribers(&self, channel: &str) -> usize {
+        self.channels.get(channel).map_or(0, Vec::len)
+    }
+
+    pub fn shard_num_subscribers(&self, channel: &str) -> usize {
+        self.shard_channels.get(channel).map_or(0, Vec::len)
+    }
+
+    pub fn is_subscribed(&self, channel: &str, outbox: &Outbox) -> bool {
+        self.channels
+            .get(channel)
+            .is_some_and(|outboxes| outboxes.iter().any(|other| other.same_channel(outbox)))
+    }
+
+    /// The number of distinct patterns clients subscribed to.
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+fn matching_names<'a>(subscribers: &'a Subscribers, pattern: Option<&str>) -> Vec<&'a str> {
+    let mut names = subscribers
+        .keys()
+        .filter(|name| pattern.map_or(true, |pattern| glob_match(pattern, name)))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+
+    names
+}
+
+fn add(subscribers: &mut Subscribers, name: &str, outbox: &Outbox) {
+    let outboxes = subscribers.entry(name.to_string()).or_default();
+    if !outboxes.iter().any(|other| other.same_channel(outbox)) {
+        outboxes.push(outbox.clone());
+    }
+}
+
+fn remove(subscribers: &mut Subscribers, name: &str, outbox: &Outbox) {
+    if let Some(outboxes) = subscribers.get_mut(name) {
+        outboxes.retain(|other| !other.same_channel(outbox));
+        if outboxes.is_empty() {
+            subscribers.remove(name);
+        }
+    }
+}
+
+/// Clients unsubscribe when they disconnect, so every outbox is still open, but a client may be
+/// gone by the time a message is sent, in which case it doesn't count.
+fn send(outboxes: &[Outbox], frame: &Resp) -> usize {
+    outboxes
+        .iter()
+        .filter(|outbox| outbox.send(frame.clone()).is_ok())
+        .count()
+}
+
+fn message_frame(parts: &[&str]) -> Resp {
+    Resp::Array(Array(
+        parts
+            .iter()
+            .map(|part| Resp::BulkString(BulkString(Some(part.to_string()))))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_publish() {
+        let mut pubsub = PubSub::default();
+        let (first, mut first_messages) = mpsc::unbounded_channel();
+        let (second, second_messages) = mpsc::unbounded_channel();
+
+        pubsub.subscribe("news", &first);
+        pubsub.subscribe("news", &first);
+        pubsub.subscribe("news", &second);
+        assert_eq!(pubsub.publish("news", "hello"), 2);
+        assert_eq!(pubsub.publish("weather", "sunny"), 0);
+
+        assert_eq!(
+            first_messages.try_recv().unwrap(),
+            message_frame(&["message", "news", "hello"])
+        );
+        assert!(first_messages.try_recv().is_err());
+
+        drop(second_messages);
+        assert_eq!(pubsub.publish("news", "again"), 1);
+        first_messages.try_recv().unwrap();
+
+        pubsub.unsubscribe("news", &first);
+        pubsub.unsubscribe("news", &second);
+        assert_eq!(pubsub.publish("news", "gone"), 0);
+        assert!(pubsub.channels.is_empty());
+    }
+
+    #[test]
+    fn test_patterns() {
+        let mut pubsub = PubSub::default();
+        let (outbox, mut messages) = mpsc::unbounded_channel();
+
+        pubsub.subscribe("orders.created", &outbox);
+        pubsub.psubscribe("orders.*", &outbox);
+        pubsub.psubscribe("user:[0-9]*", &outbox);
+
+        assert_eq!(pubsub.publish("orders.created", "1"), 2);
+        assert_eq!(
+            messages.try_recv().unwrap(),
+            message_frame(&["message", "orders.created", "1"])
+        );
+        assert_eq!(
+            messages.try_recv().unwrap(),
+            message_frame(&["pmessage", "orders.*", "orders.created", "1"])
+        );
+
+        assert_eq!(pubsub.publish("user:42", "hi"), 1);
+        assert_eq!(pubsub.publish("user:x", "hi"), 0);
+
+        assert_eq!(pubsub.channels(None), vec!["orders.created"]);
+        assert_eq!(pubsub.channels(Some("user*")), Vec::<&str>::new());
+        assert_eq!(pubsub.num_subscribers("orders.created"), 1);
+        assert_eq!(pubsub.num_patterns(), 2);
+
+        pubsub.punsubscribe("orders.*", &outbox);
+        assert_eq!(pubsub.num_patterns(), 1);
+    }
+
+    #[test]
+    fn test_shard_channels() {
+        let mut pubsub = PubSub::default();
+        let (outbox, mut messages) = mpsc::unbounded_channel();
+
+        pubsub.ssubscribe("orders", &outbox);
+        pubsub.psubscribe("*", &outbox);
+
+        assert_eq!(pubsub.publish("orders", "1"), 1);
+        messages.try_recv().unwrap();
+
+        assert_eq!(pubsub.spublish("orders", "2"), 1);
+        assert_eq!(
+            messages.try_recv().unwrap(),
+            message_frame(&["smessage", "orders", "2"])
+        );
+        assert!(messages.try_recv().is_err());
+
+        assert_eq!(pubsub.channels(None), Vec::<&str>::new());
+        assert_eq!(pubsub.shard_channels(None), vec!["orders"]);
+        assert_eq!(pubsub.shard_num_subscribers("orders"), 1);
+
+        pubsub.sunsubscribe("orders", &outbox);
+        assert_eq!(pubsub.spublish("orders", "3"), 0);
+    }
+}
@@ -0,0 +1,316 @@
+use std::cmp::Ordering;
+
+use anyhow::{bail, Context, Result};
+
+use crate::storage::persistent::{PersistentMap, PersistentSet, SetIter};
+
+/// A Redis sorted set: a member to score map for O(log n) score lookups, plus a set ordered by
+/// `(score, member)` for O(log n) rank and range queries. Both are persistent, so a copy shares
+/// its members with the original.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: PersistentMap<String, f64>,
+    ranked: PersistentSet<Ranked>,
+}
+
+/// A member ordered by score first, then lexicographically.
+#[derive(Debug, Clone)]
+struct Ranked {
+    score: f64,
+    member: String,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| self.member.cmp(&other.member))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.ranked.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning whether it was newly added.
+    pub fn insert(&mut self, member: String, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            None => {
+                self.ranked.insert(Ranked { score, member });
+                true
+            }
+            Some(old_score) => {
+                if old_score != score {
+                    self.ranked.remove(&Ranked {
+                        score: old_score,
+                        member: member.clone(),
+                    });
+                    self.ranked.insert(Ranked { score, member });
+                }
+                false
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &str) -> bool {
+        match self.scores.remove(member) {
+            None => false,
+            Some(score) => self.ranked.remove(&Ranked {
+                score,
+                member: member.to_string(),
+            }),
+        }
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores, or the highest ones if
+    /// `max` is set.
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(String, f64)> {
+        let popped = if max {
+            self.iter_from(self.len().saturating_sub(1), true)
+                .take(count)
+                .map(|(member, score)| (member.to_string(), score))
+                .collect::<Vec<_>>()
+        } else {
+            self.iter()
+                .take(count)
+                .map(|(member, score)| (member.to_string(), score))
+                .collect::<Vec<_>>()
+        };
+
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+
+        popped
+    }
+
+    /// Returns the 0-based rank of `member` in ascending score order.
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+
+        self.ranked.rank(&Ranked {
+            score,
+            member: member.to_string(),
+        })
+    }
+
+    /// Returns the ranks of the members within the score range as a half-open range.
+    pub fn score_rank_range(&self, min: ScoreBound, max: ScoreBound) -> (usize, usize) {
+        let start = self
+            .ranked
+            .prefix_len(|ranked| !min.admits_as_min(ranked.score));
+        let end = self
+            .ranked
+            .prefix_len(|ranked| max.admits_as_max(ranked.score));
+
+        (start, end.max(start))
+    }
+
+    /// Returns the ranks of the members within the lexicographical range as a half-open range.
+    ///
+    /// Like in Redis, the result is only meaningful if all members have the same score.
+    pub fn lex_rank_range(&self, min: &LexBound, max: &LexBound) -> (usize, usize) {
+        let start = self
+            .ranked
+            .prefix_len(|ranked| !min.admits_as_min(&ranked.member));
+        let end = self
+            .ranked
+            .prefix_len(|ranked| max.admits_as_max(&ranked.member));
+
+        (start, end.max(start))
+    }
+
+    /// Iterates from the member at `rank`, towards higher ranks or towards lower ones if `rev`
+    /// is set.
+    pub fn iter_from(&self, rank: usize, rev: bool) -> Iter<'_> {
+        let ranks = match rev {
+            false => rank..self.len(),
+            true if rank < self.len() => 0..rank + 1,
+            true => 0..0,
+        };
+
+        Iter {
+            ranked: self.ranked.rank_range(ranks),
+            rev,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0, false)
+    }
+}
+
+pub struct Iter<'a> {
+    ranked: SetIter<'a, Ranked>,
+    rev: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ranked = if self.rev {
+            self.ranked.next_back()
+        } else {
+            self.ranked.next()
+        }?;
+
+        Some((&ranked.member, ranked.score))
+    }
+}
+
+/// A `min` or `max` argument of the `BYSCORE` commands, such as `1.5`, `(1.5` or `-inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (bound, score): (fn(f64) -> Self, _) = match s.strip_prefix('(') {
+            Some(score) => (ScoreBound::Exclusive, score),
+            None => (ScoreBound::Inclusive, s),
+        };
+
+        Ok(bound(
+            parse_score(score).context("min or max is not a float")?,
+        ))
+    }
+
+    pub fn admits_as_min(&self, score: f64) -> bool {
+        match *self {
+            ScoreBound::Inclusive(min) => score >= min,
+            ScoreBound::Exclusive(min) => score > min,
+        }
+    }
+
+    pub fn admits_as_max(&self, score: f64) -> bool {
+        match *self {
+            ScoreBound::Inclusive(max) => score <= max,
+            ScoreBound::Exclusive(max) => score < max,
+        }
+    }
+}
+
+/// A `min` or `max` argument of the `BYLEX` commands, such as `[a`, `(a`, `-` or `+`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    NegativeInfinity,
+    PositiveInfinity,
+    Inclusive(String),
+    Exclusive(String),
+}
+
+impl LexBound {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "-" => Ok(LexBound::NegativeInfinity),
+            "+" => Ok(LexBound::PositiveInfinity),
+            _ => {
+                if let Some(member) = s.strip_prefix('[') {
+                    Ok(LexBound::Inclusive(member.to_string()))
+                } else if let Some(member) = s.strip_prefix('(') {
+                    Ok(LexBound::Exclusive(member.to_string()))
+                } else {
+                    bail!("min or max not valid string range item")
+                }
+            }
+        }
+    }
+
+    pub fn admits_as_min(&self, member: &str) -> bool {
+        match self {
+            LexBound::NegativeInfinity => true,
+            LexBound::PositiveInfinity => false,
+            LexBound::Inclusive(min) => member >= min.as_str(),
+            LexBound::Exclusive(min) => member > min.as_str(),
+        }
+    }
+
+    pub fn admits_as_max(&self, member: &str) -> bool {
+        match self {
+            LexBound::NegativeInfinity => false,
+            LexBound::PositiveInfinity => true,
+            LexBound::Inclusive(max) => member <= max.as_str(),
+            LexBound::Exclusive(max) => member < max.as_str(),
+        }
+    }
+}
+
+/// Parses a score the way Redis does, accepting `inf`, `+inf` and `-inf` but rejecting NaN.
+pub fn parse_score(s: &str) -> Result<f64> {
+    let score = s
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+        .context("value is not a valid float")?;
+
+    Ok(score)
+}
+
+/// Formats a score the way Redis replies with it, e.g. `1.5`, `3` or `-inf`.
+pub fn format_score(score: f64) -> String {
+    score.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_rank_range() {
+        let mut set = SortedSet::default();
+        for (i, member) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            set.insert(member.to_string(), i as f64);
+        }
+
+        let range = |min: &str, max: &str| {
+            set.score_rank_range(
+                ScoreBound::parse(min).unwrap(),
+                ScoreBound::parse(max).unwrap(),
+            )
+        };
+
+        assert_eq!(range("-inf", "+inf"), (0, 4));
+        assert_eq!(range("(0", "2"), (1, 3));
+        assert_eq!(range("(1", "(2"), (2, 2));
+        assert_eq!(range("5", "10"), (4, 4));
+    }
+
+    #[test]
+    fn test_update_score() {
+        let mut set = SortedSet::default();
+        assert!(set.insert("a".to_string(), 1.0));
+        assert!(set.insert("b".to_string(), 2.0));
+        assert!(!set.insert("a".to_string(), 3.0));
+
+        assert_eq!(set.rank("a"), Some(1));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![("b", 2.0), ("a", 3.0)]);
+        assert_eq!(set.iter_from(0, true).collect::<Vec<_>>(), vec![("b", 2.0)]);
+        assert_eq!(set.iter_from(2, true).next(), None);
+    }
+}
@@ -0,0 +1,511 @@
+use std::ops::Bound;
+
+use crate::storage::stream::{Fields, Stream, StreamId};
+use crate::storage::{PersistentMap, PersistentSet};
+
+/// A consumer group: the last delivered ID and the pending entries list (PEL) of messages that
+/// were delivered but not acknowledged yet.
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerGroup {
+    last_delivered_id: StreamId,
+    /// Number of entries the group has read, or `None` if it can't be known, e.g. after
+    /// `XGROUP SETID` to an arbitrary ID in a stream with deleted entries.
+    entries_read: Option<u64>,
+    pending: PersistentMap<StreamId, PendingEntry>,
+    consumers: PersistentMap<String, Consumer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Consumer {
+    /// Unix time in milliseconds of the last interaction of any kind.
+    pub seen_time: u64,
+    /// Unix time in milliseconds of the last successful read or claim.
+    pub active_time: Option<u64>,
+    pending: PersistentSet<StreamId>,
+}
+
+impl Consumer {
+    fn new(now: u64) -> Self {
+        Consumer {
+            seen_time: now,
+            active_time: None,
+            pending: PersistentSet::new(),
+        }
+    }
+
+    /// Rebuilds a consumer saved in a snapshot.
+    pub fn restore(
+        seen_time: u64,
+        active_time: Option<u64>,
+        pending: PersistentSet<StreamId>,
+    ) -> Self {
+        Consumer {
+            seen_time,
+            active_time,
+            pending,
+        }
+    }
+
+    pub fn pending(&self) -> &PersistentSet<StreamId> {
+        &self.pending
+    }
+}
+
+/// Options of `XCLAIM` and `XAUTOCLAIM` that control how a claimed entry is updated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClaimOptions {
+    /// Only claim entries idle for at least this many milliseconds.
+    pub min_idle_time: u64,
+    /// The new delivery time, instead of now.
+    pub delivery_time: Option<u64>,
+    /// The new delivery count, instead of incrementing it.
+    pub retry_count: Option<u64>,
+    /// Create a pending entry for IDs that exist in the stream but are not pending.
+    pub force: bool,
+    /// Don't increment the delivery count, since the entry isn't delivered.
+    pub just_id: bool,
+}
+
+/// The result of claiming a single ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claimed {
+    Entry(StreamId, Fields),
+    /// The entry was pending but has been deleted from the stream, so it was dropped from the
+    /// PEL instead of claimed.
+    Deleted(StreamId),
+    Skipped,
+}
+
+impl ConsumerGroup {
+    /// Rebuilds a group saved in a snapshot, where every pending entry belongs to one of the
+    /// consumers.
+    pub fn restore(
+        last_delivered_id: StreamId,
+        entries_read: Option<u64>,
+        pending: PersistentMap<StreamId, PendingEntry>,
+        consumers: PersistentMap<String, Consumer>,
+    ) -> Self {
+        ConsumerGroup {
+            last_delivered_id,
+            entries_read,
+            pending,
+            consumers,
+        }
+    }
+
+    pub fn last_delivered_id(&self) -> StreamId {
+        self.last_delivered_id
+    }
+
+    pub fn entries_read(&self) -> Option<u64> {
+        self.entries_read
+    }
+
+    pub fn pending(&self) -> &PersistentMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &PersistentMap<String, Consumer> {
+        &self.consumers
+    }
+
+    /// Creates a consumer, returning whether it didn't exist yet.
+    pub fn create_consumer(&mut self, name: &str, now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+
+        self.consumers.insert(name.to_string(), Consumer::new(now));
+
+        true
+    }
+
+    fn consumer_mut(&mut self, name: &str, now: u64) -> &mut Consumer {
+        self.consumers
+            .get_or_insert_with(name.to_string(), || Consumer::new(now))
+    }
+
+    /// Deletes a consumer along with its pending entries, returning how many it had.
+    pub fn delete_consumer(&mut self, name: &str) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+
+        Some(consumer.pending.len())
+    }
+
+    /// Acknowledges a pending entry, returning whether it was pending.
+    pub fn ack(&mut self, id: &StreamId) -> bool {
+        let Some(entry) = self.pending.remove(id) else {
+            return false;
+        };
+
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(id);
+        }
+
+        true
+    }
+
+    /// Adds `id` to the PEL as delivered to `consumer`, taking it over from another consumer
+    /// if necessary.
+    fn deliver(&mut self, id: StreamId, consumer: &str, now: u64) {
+        let previous = self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time: now,
+                delivery_count: 1,
+            },
+        );
+
+        if let Some(previous) = previous {
+            if let Some(previous) = self.consumers.get_mut(&previous.consumer) {
+                previous.pending.remove(&id);
+            }
+        }
+
+        self.consumer_mut(consumer, now).pending.insert(id);
+    }
+
+    /// Makes `consumer` the owner of the pending entry `id`.
+    fn transfer(&mut self, id: StreamId, consumer: &str, now: u64) {
+        let Some(entry) = self.pending.get_mut(&id) else {
+            return;
+        };
+
+        if entry.consumer != consumer {
+            let previous = std::mem::replace(&mut entry.consumer, consumer.to_string());
+
+            if let Some(previous) = self.consumers.get_mut(&previous) {
+                previous.pending.remove(&id);
+            }
+        }
+
+        self.consumer_mut(consumer, now).pending.insert(id);
+    }
+}
+
+impl Stream {
+    pub fn groups(&self) -> &PersistentMap<String, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Creates a group whose last delivered ID is `id`, returning whether it didn't exist yet.
+    pub fn create_group(&mut self, name: &str, id: StreamId, entries_read: Option<u64>) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+
+        let entries_read = entries_read.or_else(|| self.estimate_entries_read(id));
+        self.groups.insert(
+            name.to_string(),
+            ConsumerGroup {
+                last_delivered_id: id,
+                entries_read,
+                ..Default::default()
+            },
+        );
+
+        true
+    }
+
+    /// Moves the last delivered ID of a group, returning whether the group exists.
+    pub fn set_group_id(&mut self, name: &str, id: StreamId, entries_read: Option<u64>) -> bool {
+        let entries_read = entries_read.or_else(|| self.estimate_entries_read(id));
+
+        let Some(group) = self.groups.get_mut(name) else {
+            return false;
+        };
+
+        group.last_delivered_id = id;
+        group.entries_read = entries_read;
+
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Delivers entries that were never delivered to the group before, the `>` ID of
+    /// `XREADGROUP`. Returns `None` if the group doesn't exist.
+    pub fn read_group_new(
+        &mut self,
+        group_name: &str,
+        consumer: &str,
+        count: Option<usize>,
+        no_ack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let last_delivered_id = self.groups.get(group_name)?.last_delivered_id;
+
+        let entries = self
+            .entries_after(last_delivered_id, count)
+            .into_iter()
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect::<Vec<_>>();
+
+        let mut entries_read = self.groups[group_name].entries_read;
+        for (id, _) in &entries {
+            entries_read = match entries_read {
+                Some(entries_read) if !self.has_tombstones_from(*id) => Some(entries_read + 1),
+                _ => self.estimate_entries_read(*id),
+            };
+        }
+
+        let group = self.groups.get_mut(group_name)?;
+        let consumer_entry = group.consumer_mut(consumer, now);
+        consumer_entry.seen_time = now;
+
+        if let Some((last_id, _)) = entries.last() {
+            consumer_entry.active_time = Some(now);
+            group.last_delivered_id = *last_id;
+            group.entries_read = entries_read;
+        }
+
+        if !no_ack {
+            for (id, _) in &entries {
+                group.deliver(*id, consumer, now);
+            }
+        }
+
+        Some(entries)
+    }
+
+    /// Re-delivers entries already pending for `consumer` with IDs greater than `after`, the
+    /// explicit ID form of `XREADGROUP`. Entries deleted from the stream come back as `None`.
+    /// Returns `None` if the group doesn't exist.
+    #[allow(clippy::type_complexity)]
+    pub fn read_group_history(
+        &mut self,
+        group_name: &str,
+        consumer: &str,
+        after: StreamId,
+        count: Option<usize>,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let group = self.groups.get_mut(group_name)?;
+        let consumer = group.consumer_mut(consumer, now);
+        consumer.seen_time = now;
+
+        let ids = consumer
+            .pending
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut entries = Vec::with_capacity(ids.len());
+        for id in ids {
+            let fields = self.entries.get(&id).cloned();
+
+            if fields.is_some() {
+                if let Some(entry) = group.pending.get_mut(&id) {
+                    entry.delivery_time = now;
+                    entry.delivery_count += 1;
+                }
+            }
+
+            entries.push((id, fields));
+        }
+
+        Some(entries)
+    }
+
+    /// Claims the entry `id` for `consumer` as `XCLAIM` and `XAUTOCLAIM` do. Returns `None` if
+    /// the group doesn't exist.
+    pub fn claim(
+        &mut self,
+        group_name: &str,
+        consumer: &str,
+        id: StreamId,
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<Claimed> {
+        let group = self.groups.get_mut(group_name)?;
+        let fields = self.entries.get(&id);
+
+        if !group.pending.contains_key(&id) {
+            if !(options.force && fields.is_some()) {
+                return Some(Claimed::Skipped);
+            }
+
+            group.deliver(id, consumer, now);
+            // the delivery below counts as the first one
+            group.pending.get_mut(&id)?.delivery_count = 0;
+        }
+
+        let Some(fields) = fields else {
+            group.ack(&id);
+            return Some(Claimed::Deleted(id));
+        };
+
+        let entry = group.pending.get(&id)?;
+        if now.saturating_sub(entry.delivery_time) < options.min_idle_time {
+            return Some(Claimed::Skipped);
+        }
+
+        group.transfer(id, consumer, now);
+
+        let entry = group.pending.get_mut(&id)?;
+        entry.delivery_time = options.delivery_time.unwrap_or(now);
+        match options.retry_count {
+            Some(retry_count) => entry.delivery_count = retry_count,
+            None if !options.just_id => entry.delivery_count += 1,
+            None => {}
+        }
+
+        let consumer = group.consumer_mut(consumer, now);
+        consumer.seen_time = now;
+        consumer.active_time = Some(now);
+
+        Some(Claimed::Entry(id, fields.clone()))
+    }
+
+    /// Returns how many entries the group still has to read, if that can be known.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+
+        let entries_read = match group.entries_read {
+            Some(entries_read) if !self.has_tombstones_from(group.last_delivered_id) => {
+                Some(entries_read)
+            }
+            _ => self.estimate_entries_read(group.last_delivered_id),
+        };
+
+        entries_read.map(|entries_read| self.entries_added.saturating_sub(entries_read))
+    }
+
+    /// Returns whether entries were deleted at or after `id`, which makes counting entries by
+    /// ID unreliable.
+    fn has_tombstones_from(&self, id: StreamId) -> bool {
+        self.max_deleted_id != StreamId::MIN && self.max_deleted_id >= id
+    }
+
+    /// Estimates how many entries were ever added up to and including `id`, the same way
+    /// `streamEstimateDistanceFromFirstEverEntry` does in Redis.
+    fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+
+        if self.len() == 0 && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+
+        if id == self.last_id {
+            return Some(self.entries_added);
+        } else if id > self.last_id {
+            return None;
+        }
+
+        let first_id = self
+            .first_entry()
+            .map_or(StreamId::MIN, |(first_id, _)| *first_id);
+
+        // without deletions after the first entry, everything before it was trimmed
+        if (self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id) && id < first_id
+        {
+            return Some(self.entries_added - self.len() as u64);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::stream::NewStreamId;
+
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn stream_with_group() -> Stream {
+        let mut stream = Stream::default();
+        for ms in 1..=3 {
+            stream
+                .add(NewStreamId::Explicit(id(ms, 0)), vec![])
+                .unwrap();
+        }
+        assert!(stream.create_group("g", StreamId::MIN, None));
+
+        stream
+    }
+
+    #[test]
+    fn test_read_ack_and_lag() {
+        let mut stream = stream_with_group();
+
+        let group = stream.group("g").unwrap();
+        assert_eq!(stream.lag(group), Some(3));
+
+        let entries = stream.read_group_new("g", "alice", Some(2), false, 100);
+        assert_eq!(entries.map(|entries| entries.len()), Some(2));
+
+        let group = stream.group("g").unwrap();
+        assert_eq!(group.last_delivered_id(), id(2, 0));
+        assert_eq!(group.pending().len(), 2);
+        assert_eq!(stream.lag(group), Some(1));
+
+        let group = stream.group_mut("g").unwrap();
+        assert!(group.ack(&id(1, 0)));
+        assert!(!group.ack(&id(1, 0)));
+        assert_eq!(group.consumers()["alice"].pending().len(), 1);
+    }
+
+    #[test]
+    fn test_claim_transfers_ownership() {
+        let mut stream = stream_with_group();
+        stream.read_group_new("g", "alice", None, false, 100);
+
+        let options = ClaimOptions {
+            min_idle_time: 50,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            stream.claim("g", "bob", id(1, 0), &options, 120),
+            Some(Claimed::Skipped)
+        );
+        assert_eq!(
+            stream.claim("g", "bob", id(1, 0), &options, 200),
+            Some(Claimed::Entry(id(1, 0), vec![]))
+        );
+
+        stream.delete(&id(2, 0));
+        assert_eq!(
+            stream.claim("g", "bob", id(2, 0), &options, 200),
+            Some(Claimed::Deleted(id(2, 0)))
+        );
+
+        let group = stream.group("g").unwrap();
+        assert_eq!(group.pending()[&id(1, 0)].consumer, "bob");
+        assert_eq!(group.pending()[&id(1, 0)].delivery_count, 2);
+        assert_eq!(group.consumers()["alice"].pending().len(), 1);
+        assert!(!group.pending().contains_key(&id(2, 0)));
+    }
+}
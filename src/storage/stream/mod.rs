@@ -0,0 +1,413 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Bound;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::storage::PersistentMap;
+use crate::utils::now_ms;
+
+pub use group::{ClaimOptions, Claimed, Consumer, ConsumerGroup, PendingEntry};
+
+mod group;
+
+/// Number of entries Redis packs into one listpack node of a stream's radix tree. Approximate
+/// trimming only ever removes whole nodes, so it removes entries in multiples of this.
+pub const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// Parses `ms-seq`, or `ms` alone, which gets `missing_seq` as its sequence number.
+    pub fn parse(s: &str, missing_seq: u64) -> Result<Self> {
+        let (ms, seq) = match s.split_once('-') {
+            Some((ms, seq)) => (ms, Some(seq)),
+            None => (s, None),
+        };
+
+        let ms = ms
+            .parse()
+            .context("Invalid stream ID specified as stream command argument")?;
+        let seq = match seq {
+            None => missing_seq,
+            Some(seq) => seq
+                .parse()
+                .context("Invalid stream ID specified as stream command argument")?,
+        };
+
+        Ok(StreamId { ms, seq })
+    }
+
+    /// Parses the start of an `XRANGE`-style range: `-`, an ID, or an exclusive `(ID`.
+    pub fn parse_start(s: &str) -> Result<Self> {
+        match s {
+            "-" => Ok(StreamId::MIN),
+            "+" => Ok(StreamId::MAX),
+            _ => match s.strip_prefix('(') {
+                None => StreamId::parse(s, 0),
+                Some(s) => StreamId::parse(s, 0)?
+                    .next()
+                    .context("invalid start ID for the interval"),
+            },
+        }
+    }
+
+    /// Parses the end of an `XRANGE`-style range: `+`, an ID, or an exclusive `(ID`.
+    pub fn parse_end(s: &str) -> Result<Self> {
+        match s {
+            "-" => Ok(StreamId::MIN),
+            "+" => Ok(StreamId::MAX),
+            _ => match s.strip_prefix('(') {
+                None => StreamId::parse(s, u64::MAX),
+                Some(s) => StreamId::parse(s, u64::MAX)?
+                    .prev()
+                    .context("invalid end ID for the interval"),
+            },
+        }
+    }
+
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    pub fn prev(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+/// The ID argument of `XADD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewStreamId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSeq(u64),
+    /// `ms-seq`, or `ms` alone for `ms-0`
+    Explicit(StreamId),
+}
+
+impl NewStreamId {
+    pub fn parse(s: &str) -> Result<Self> {
+        if s == "*" {
+            return Ok(NewStreamId::Auto);
+        }
+
+        match s.strip_suffix("-*") {
+            Some(ms) => Ok(NewStreamId::AutoSeq(
+                ms.parse()
+                    .context("Invalid stream ID specified as stream command argument")?,
+            )),
+            None => Ok(NewStreamId::Explicit(StreamId::parse(s, 0)?)),
+        }
+    }
+}
+
+/// The trimming strategy of `XADD` and `XTRIM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    /// Set by `~`: only whole radix tree nodes are removed, so a few more entries than asked
+    /// for may be kept.
+    pub approximate: bool,
+    /// The most entries to remove, from `LIMIT`.
+    pub limit: Option<usize>,
+}
+
+pub type Fields = Vec<(String, String)>;
+
+/// A Redis stream. Entries are kept in a persistent tree keyed by ID, so range queries are
+/// O(log n) plus the size of the range.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: PersistentMap<StreamId, Fields>,
+    last_id: StreamId,
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    groups: PersistentMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    /// Rebuilds a stream saved in a snapshot.
+    pub fn restore(
+        entries: PersistentMap<StreamId, Fields>,
+        last_id: StreamId,
+        max_deleted_id: StreamId,
+        entries_added: u64,
+        groups: PersistentMap<String, ConsumerGroup>,
+    ) -> Self {
+        Stream {
+            entries,
+            last_id,
+            max_deleted_id,
+            entries_added,
+            groups,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// Sets the ID of the last entry added, and optionally what is known of the entries that
+    /// were, as `XSETID` does.
+    pub fn set_id(
+        &mut self,
+        last_id: StreamId,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamId>,
+    ) -> Result<()> {
+        if let Some((&top, _)) = self.last_entry() {
+            ensure!(
+                last_id >= top,
+                "The ID specified in XSETID is smaller than the target stream top item"
+            );
+        }
+        if let Some(entries_added) = entries_added {
+            ensure!(
+                entries_added >= self.len() as u64,
+                "The entries_added specified in XSETID is smaller than the target stream length"
+            );
+        }
+        if let Some(max_deleted_id) = max_deleted_id {
+            ensure!(
+                last_id >= max_deleted_id,
+                "The ID specified in XSETID is smaller than the provided max_deleted_entry_id"
+            );
+        }
+
+        self.last_id = last_id;
+        if let Some(entries_added) = entries_added {
+            self.entries_added = entries_added;
+        }
+        if let Some(max_deleted_id) = max_deleted_id {
+            self.max_deleted_id = max_deleted_id;
+        }
+
+        Ok(())
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.last_key_value()
+    }
+
+    /// Appends an entry, returning its ID. IDs must always increase.
+    pub fn add(&mut self, id: NewStreamId, fields: Fields) -> Result<StreamId> {
+        let id = match id {
+            NewStreamId::Auto => {
+                let now = now_ms();
+
+                if now > self.last_id.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else {
+                    self.last_id.next().context(
+                        "The stream has exhausted the last possible ID, unable to add more items",
+                    )?
+                }
+            }
+            NewStreamId::AutoSeq(ms) => {
+                if ms == self.last_id.ms {
+                    self.last_id.next().context(
+                        "The stream has exhausted the last possible ID, unable to add more items",
+                    )?
+                } else {
+                    StreamId { ms, seq: 0 }
+                }
+            }
+            NewStreamId::Explicit(id) => id,
+        };
+
+        ensure!(
+            id > StreamId::MIN,
+            "The ID specified in XADD must be greater than 0-0"
+        );
+        ensure!(
+            id > self.last_id,
+            "The ID specified in XADD is equal or smaller than the target stream top item"
+        );
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+
+        Ok(id)
+    }
+
+    /// Returns entries with IDs in `start..=end`, in descending order if `rev` is set.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        rev: bool,
+        count: Option<usize>,
+    ) -> Vec<(&StreamId, &Fields)> {
+        if start > end {
+            return vec![];
+        }
+
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+
+        if rev {
+            range.rev().take(count).collect()
+        } else {
+            range.take(count).collect()
+        }
+    }
+
+    /// Returns entries with IDs greater than `id`.
+    pub fn entries_after(&self, id: StreamId, count: Option<usize>) -> Vec<(&StreamId, &Fields)> {
+        self.entries
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    pub fn delete(&mut self, id: &StreamId) -> bool {
+        if self.entries.remove(id).is_none() {
+            return false;
+        }
+
+        self.max_deleted_id = self.max_deleted_id.max(*id);
+
+        true
+    }
+
+    /// Trims the stream, returning the number of removed entries.
+    pub fn trim(&mut self, trim: Trim) -> usize {
+        let mut to_remove = match trim.strategy {
+            TrimStrategy::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).len(),
+        };
+
+        if trim.approximate {
+            to_remove -= to_remove % STREAM_NODE_MAX_ENTRIES;
+        }
+        if let Some(limit) = trim.limit {
+            to_remove = to_remove.min(limit);
+        }
+
+        for _ in 0..to_remove {
+            if let Some((id, _)) = self.entries.pop_first() {
+                self.max_deleted_id = self.max_deleted_id.max(id);
+            }
+        }
+
+        to_remove
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    #[test]
+    fn test_add_ids_must_increase() -> Result<()> {
+        let mut stream = Stream::default();
+
+        assert_eq!(stream.add(NewStreamId::AutoSeq(5), vec![])?, id(5, 0));
+        assert_eq!(stream.add(NewStreamId::AutoSeq(5), vec![])?, id(5, 1));
+        assert_eq!(
+            stream.add(NewStreamId::Explicit(id(6, 3)), vec![])?,
+            id(6, 3)
+        );
+
+        stream
+            .add(NewStreamId::Explicit(id(6, 3)), vec![])
+            .unwrap_err();
+        Stream::default()
+            .add(NewStreamId::Explicit(id(0, 0)), vec![])
+            .unwrap_err();
+
+        assert!(stream.add(NewStreamId::Auto, vec![])? > id(6, 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_approximate_trim_removes_whole_nodes() -> Result<()> {
+        let mut stream = Stream::default();
+        for ms in 1..=250 {
+            stream.add(NewStreamId::Explicit(id(ms, 0)), vec![])?;
+        }
+
+        let removed = stream.trim(Trim {
+            strategy: TrimStrategy::MaxLen(120),
+            approximate: true,
+            limit: None,
+        });
+        assert_eq!(removed, 100);
+
+        let removed = stream.trim(Trim {
+            strategy: TrimStrategy::MinId(id(200, 0)),
+            approximate: false,
+            limit: Some(10),
+        });
+        assert_eq!(removed, 10);
+        assert_eq!(stream.first_entry().map(|(id, _)| *id), Some(id(111, 0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_range_bounds() -> Result<()> {
+        assert_eq!(StreamId::parse_start("5")?, id(5, 0));
+        assert_eq!(StreamId::parse_end("5")?, id(5, u64::MAX));
+        assert_eq!(StreamId::parse_start("(5-1")?, id(5, 2));
+        assert_eq!(StreamId::parse_end("(5-0")?, id(4, u64::MAX));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{ensure, Context, Result};
+
+use crate::resp::{Array, BulkString, Resp};
+use crate::storage::{Outbox, PubSub};
+
+/// The channel invalidation messages are published on, as clients only speak RESP2 here.
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+tokio::task_local! {
+    /// The client whose command is running, so that `NOLOOP` clients aren't told about their
+    /// own writes.
+    pub static CURRENT_CLIENT: u64;
+}
+
+/// How a client wants to hear about the keys it caches, from `CLIENT TRACKING ON`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrackingOptions {
+    /// The client that gets the invalidation messages instead of this one.
+    pub redirect: Option<u64>,
+    /// Set in broadcasting mode, where the client hears about every key with one of these
+    /// prefixes instead of the keys it read.
+    pub prefixes: Option<Vec<String>>,
+    /// Only the keys read right after `CLIENT CACHING YES` are tracked.
+    pub optin: bool,
+    /// The keys read right after `CLIENT CACHING NO` aren't tracked.
+    pub optout: bool,
+    /// The client isn't told about the keys it modifies itself.
+    pub noloop: bool,
+}
+
+/// The connected clients, and what the ones with tracking enabled cache.
+#[derive(Debug, Default, Clone)]
+pub struct Tracking {
+    clients: HashMap<u64, Outbox>,
+    trackers: HashMap<u64, TrackingOptions>,
+    /// The clients that read each key, each told once when the key is next modified.
+    keys: HashMap<Resp, HashSet<u64>>,
+    /// The clients broadcast to for each prefix.
+    prefixes: BTreeMap<String, HashSet<u64>>,
+}
+
+impl Tracking {
+    /// Registers a connection, which can then be redirected to.
+    pub fn connect(&mut self, id: u64, outbox: &Outbox) {
+        self.clients.insert(id, outbox.clone());
+    }
+
+    pub fn disconnect(&mut self, id: u64) {
+        self.disable(id);
+        self.clients.remove(&id);
+    }
+
+    pub fn options(&self, id: u64) -> Option<&TrackingOptions> {
+        self.trackers.get(&id)
+    }
+
+    pub fn enable(&mut self, id: u64, options: TrackingOptions) -> Result<()> {
+        if let Some(redirect) = options.redirect {
+            ensure!(
+                self.clients.contains_key(&redirect),
+                "The client ID you want redirect to does not exist"
+            );
+        }
+
+        self.disable(id);
+        for prefix in options.prefixes.iter().flatten() {
+            self.prefixes.entry(prefix.clone()).or_default().insert(id);
+        }
+        self.trackers.insert(id, options);
+
+        Ok(())
+    }
+
+    /// Turns tracking off. The keys the client read are forgotten as they get modified.
+    pub fn disable(&mut self, id: u64) {
+        if self.trackers.remove(&id).is_none() {
+            return;
+        }
+
+        self.prefixes.retain(|_, clients| {
+            clients.remove(&id);
+            !clients.is_empty()
+        });
+    }
+
+    /// Remembers that a client read some keys, if it tracks the keys it reads.
+    pub fn remember(&mut self, id: u64, keys: Vec<Resp>) {
+        let tracks_reads = self
+            .trackers
+            .get(&id)
+            .is_some_and(|options| options.prefixes.is_none());
+        if !tracks_reads {
+            return;
+        }
+
+        for key in keys {
+            self.keys.entry(key).or_default().insert(id);
+        }
+    }
+
+    /// Tells the clients caching `key` that it was modified.
+    pub fn invalidate(&mut self, key: &Resp, pubsub: &PubSub) {
+        let mut clients = self.keys.remove(key).unwrap_or_default();
+
+        let name = key.plain_string().unwrap_or_default();
+        for (prefix, prefix_clients) in &self.prefixes {
+            if name.starts_with(prefix.as_str()) {
+                clients.extend(prefix_clients);
+            }
+        }
+
+        let current = CURRENT_CLIENT.try_with(|id| *id).ok();
+        for id in clients {
+            if self
+                .trackers
+                .get(&id)
+                .is_some_and(|options| !(options.noloop && Some(id) == current))
+            {
+                self.send(id, Resp::Array(Array(vec![key.clone()])), pubsub);
+            }
+        }
+    }
+
+    /// Tells every tracking client to drop its whole cache, with a nil instead of keys.
+    pub fn invalidate_all(&mut self, pubsub: &PubSub) {
+        self.keys.clear();
+
+        for &id in self.trackers.keys() {
+            self.send(id, Resp::BulkString(BulkString(None)), pubsub);
+        }
+    }
+
+    /// Clients get invalidation messages like the ones published on the invalidation channel,
+    /// but only when they are subscribed to it.
+    fn send(&self, id: u64, keys: Resp, pubsub: &PubSub) {
+        let target = self.trackers[&id].redirect.unwrap_or(id);
+        let Some(outbox) = self.clients.get(&target) else {
+            return;
+        };
+        if !pubsub.is_subscribed(INVALIDATE_CHANNEL, outbox) {
+            return;
+        }
+
+        let message = Resp::Array(Array(vec![
+            Resp::BulkString(BulkString(Some("message".to_string()))),
+            Resp::BulkString(BulkString(Some(INVALIDATE_CHANNEL.to_string()))),
+            keys,
+        ]));
+        // a client that is going away unregisters itself
+        let _ = outbox.send(message);
+    }
+}
+
+/// Parses the options of `CLIENT TRACKING ON`.
+pub fn parse_tracking_options(args: &[Resp]) -> Result<TrackingOptions> {
+    let mut options = TrackingOptions::default();
+    let mut bcast = false;
+    let mut prefixes = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.plain_string()?.to_uppercase().as_str() {
+            "BCAST" => bcast = true,
+            "NOLOOP" => options.noloop = true,
+            "OPTIN" => options.optin = true,
+            "OPTOUT" => options.optout = true,
+            "PREFIX" => {
+                let prefix = args.next().context("syntax error")?;
+                prefixes.push(prefix.plain_string()?.to_string());
+            }
+            "REDIRECT" => {
+                let id = args.next().context("syntax error")?;
+                let id = id
+                    .plain_i64()
+                    .ok()
+                    .and_then(|id| u64::try_from(id).ok())
+                    .context("Invalid client ID")?;
+                options.redirect = Some(id);
+            }
+            _ => anyhow::bail!("syntax error"),
+        }
+    }
+
+    ensure!(
+        bcast || prefixes.is_empty(),
+        "PREFIX option requires BCAST mode to be enabled"
+    );
+    ensure!(
+        !(bcast && (options.optin || options.optout)),
+        "OPTIN and OPTOUT are not compatible with BCAST"
+    );
+    ensure!(
+        !(options.optin && options.optout),
+        "You can't use both OPTIN and OPTOUT"
+    );
+
+    if bcast {
+        // no prefix at all broadcasts every key
+        if prefixes.is_empty() {
+            prefixes.push(String::new());
+        }
+        options.prefixes = Some(prefixes);
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::resp::bulk;
+
+    #[tokio::test]
+    async fn test_invalidate() {
+        let mut tracking = Tracking::default();
+        let mut pubsub = PubSub::default();
+
+        let (outbox, mut messages) = mpsc::unbounded_channel();
+        let id = 1;
+        tracking.connect(id, &outbox);
+        pubsub.subscribe(INVALIDATE_CHANNEL, &outbox);
+
+        tracking.enable(id, TrackingOptions::default()).unwrap();
+        tracking.remember(id, vec![bulk("a")]);
+
+        tracking.invalidate(&bulk("b"), &pubsub);
+        assert!(messages.try_recv().is_err());
+
+        // a key is invalidated once, until it is read again
+        tracking.invalidate(&bulk("a"), &pubsub);
+        tracking.invalidate(&bulk("a"), &pubsub);
+        let Resp::Array(Array(message)) = messages.try_recv().unwrap() else {
+            panic!("not a message");
+        };
+        assert_eq!(message[2], Resp::Array(Array(vec![bulk("a")])));
+        assert!(messages.try_recv().is_err());
+
+        // clients with NOLOOP don't hear about their own writes
+        let options =
+            parse_tracking_options(&[bulk("BCAST"), bulk("PREFIX"), bulk("user:"), bulk("NOLOOP")])
+                .unwrap();
+        tracking.enable(id, options).unwrap();
+        CURRENT_CLIENT
+            .scope(id, async { tracking.invalidate(&bulk("user:1"), &pubsub) })
+            .await;
+        assert!(messages.try_recv().is_err());
+        tracking.invalidate(&bulk("user:1"), &pubsub);
+        tracking.invalidate(&bulk("order:1"), &pubsub);
+        assert!(messages.try_recv().is_ok());
+        assert!(messages.try_recv().is_err());
+
+        tracking.disconnect(id);
+        tracking.invalidate(&bulk("user:1"), &pubsub);
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_parse_tracking_options() {
+        let error = |args: &[&str]| {
+            let args = args.iter().map(|arg| bulk(arg)).collect::<Vec<_>>();
+            parse_tracking_options(&args).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error(&["PREFIX", "a"]),
+            "PREFIX option requires BCAST mode to be enabled"
+        );
+        assert_eq!(
+            error(&["BCAST", "OPTIN"]),
+            "OPTIN and OPTOUT are not compatible with BCAST"
+        );
+        assert_eq!(
+            error(&["OPTIN", "OPTOUT"]),
+            "You can't use both OPTIN and OPTOUT"
+        );
+        assert_eq!(error(&["REDIRECT", "x"]), "Invalid client ID");
+
+        let options = parse_tracking_options(&[bulk("BCAST")]).unwrap();
+        assert_eq!(options.prefixes, Some(vec![String::new()]));
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::resp::Resp;
+use crate::storage::{PersistentSet, SortedSet, Stream};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Resp),
+    /// Lists and hashes have no commands yet. They are kept so that keys loaded from a Redis
+    /// snapshot are saved again instead of lost.
+    List(VecDeque<String>),
+    Hash(HashMap<String, String>),
+    Set(PersistentSet<String>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The name `TYPE` replies with.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+}
+
+/// A concrete type that can be stored as a [`Value`] variant.
+///
+/// Collection types are created empty on first write and removed from the keyspace once they
+/// become empty again, the same as Redis does.
+pub trait ValueKind: Default + Sized {
+    fn from_value(value: &Value) -> Option<&Self>;
+
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self>;
+
+    fn into_value(self) -> Value;
+
+    fn is_empty(&self) -> bool;
+}
+
+impl ValueKind for PersistentSet<String> {
+    fn from_value(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Set(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        PersistentSet::is_empty(self)
+    }
+}
+
+impl ValueKind for SortedSet {
+    fn from_value(value: &Value) -> Option<&Self> {
+        match value {
+            Value::SortedSet(sorted_set) => Some(sorted_set),
+            _ => None,
+        }
+    }
+
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::SortedSet(sorted_set) => Some(sorted_set),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::SortedSet(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        SortedSet::is_empty(self)
+    }
+}
+
+impl ValueKind for Stream {
+    fn from_value(value: &Value) -> Option<&Self> {
+        match value {
+            Value::Stream(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+        match value {
+            Value::Stream(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Stream(self)
+    }
+
+    /// Unlike other collections, streams stay in the keyspace when all their entries are
+    /// deleted, so they never count as empty.
+    fn is_empty(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use crate::resp::Resp;
+use crate::storage::Storage;
+
+/// Keys watched by clients with `WATCH`, each with a version bumped whenever the key is
+/// modified. Only watched keys are tracked, so that the versions don't outlive their watchers.
+#[derive(Debug, Default, Clone)]
+pub struct WatchedKeys(HashMap<Resp, WatchedKey>);
+
+#[derive(Debug, Default, Clone)]
+struct WatchedKey {
+    watchers: usize,
+    version: u64,
+}
+
+/// What a client saw of a key when it started watching it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    pub key: Resp,
+    version: u64,
+    existed: bool,
+}
+
+impl WatchedKeys {
+    pub fn touch(&mut self, key: &Resp) {
+        if let Some(watched) = self.0.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+
+    fn keys(&self) -> Vec<Resp> {
+        self.0.keys().cloned().collect()
+    }
+}
+
+impl Storage {
+    pub fn watch(&mut self, key: Resp) -> Watch {
+        let watched = self.watched_keys.0.entry(key.clone()).or_default();
+        watched.watchers += 1;
+
+        Watch {
+            version: watched.version,
+            existed: self.get_value(&key).is_some(),
+            key,
+        }
+    }
+
+    pub fn unwatch(&mut self, watch: &Watch) {
+        if let Some(watched) = self.watched_keys.0.get_mut(&watch.key) {
+            watched.watchers -= 1;
+            if watched.watchers == 0 {
+                self.watched_keys.0.remove(&watch.key);
+            }
+        }
+    }
+
+    /// Whether the key was modified since `watch`, including by expiring.
+    pub fn is_modified_since(&self, watch: &Watch) -> bool {
+        let version = self
+            .watched_keys
+            .0
+            .get(&watch.key)
+            .map(|watched| watched.version);
+
+        version != Some(watch.version) || (watch.existed && self.get_value(&watch.key).is_none())
+    }
+
+    /// Touches every watched key that exists, before the whole keyspace is emptied.
+    pub(super) fn touch_all_watched(&mut self) {
+        for key in self.watched_keys.keys() {
+            if self.data.contains_key(&key) {
+                self.watched_keys.touch(&key);
+            }
+        }
+    }
+}
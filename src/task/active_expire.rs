@@ -0,0 +1,26 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::storage::Storage;
+
+/// How often expired keys are looked for, like the 10 Hz cron of Redis.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Removes expired keys in the background.
+pub async fn run(storage: Arc<RwLock<Storage>>) -> Result<()> {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+        // a busy storage, such as while a script runs, is left for the next tick instead of
+        // blocking the worker thread that clients may need to kill the script
+        let Ok(mut storage) = storage.try_write() else {
+            continue;
+        };
+        storage.remove_expired();
+        // the deletions aren't written with the reply of a command
+        storage.write_aof();
+    }
+}
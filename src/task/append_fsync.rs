@@ -0,0 +1,22 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::aof::Aof;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Flushes the AOF to the disk every second, for `appendfsync everysec`.
+pub async fn run(aof: Arc<Aof>) -> Result<()> {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let aof = Arc::clone(&aof);
+        if let Err(e) = tokio::task::spawn_blocking(move || aof.fsync()).await? {
+            eprintln!("fsync of the AOF failed; error = {:?}", e);
+        }
+    }
+}
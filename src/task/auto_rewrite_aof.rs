@@ -0,0 +1,33 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::aof;
+use crate::storage::Storage;
+
+/// How often the size of the AOF is checked, like the 10 Hz cron of Redis.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Starts rewriting the AOF whenever it grew by `percentage` percent since it was last
+/// rewritten, and is over `min_size` bytes.
+pub async fn run(storage: Arc<RwLock<Storage>>, percentage: u64, min_size: u64) -> Result<()> {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // checked again on the next tick while a script holds the storage
+        let Ok(storage) = storage.try_read() else {
+            continue;
+        };
+        let Some(aof) = &storage.aof else {
+            continue;
+        };
+        if aof.should_rewrite(percentage, min_size) {
+            if let Err(e) = aof::start_rewrite(&storage) {
+                eprintln!("automatic AOF rewrite failed; error = {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::rdb::{self, SaveRule};
+use crate::storage::Storage;
+
+/// How often the rules are checked, like the 10 Hz cron of Redis.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Starts a background save whenever one of `rules` is met.
+pub async fn run(storage: Arc<RwLock<Storage>>, rules: Vec<SaveRule>) -> Result<()> {
+    let mut interval = tokio::time::interval(INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // checked again on the next tick while a script holds the storage
+        let Ok(storage) = storage.try_read() else {
+            continue;
+        };
+        if storage.saves.should_save(&rules, storage.dirty()) {
+            rdb::start_background_save(&storage);
+        }
+    }
+}
@@ -1,2 +1,6 @@
+pub mod active_expire;
+pub mod append_fsync;
+pub mod auto_rewrite_aof;
+pub mod auto_save;
 pub mod replication;
 pub mod serve_client;
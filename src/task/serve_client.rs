@@ -1,12 +1,14 @@
 use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::{anyhow, Result};
-use tokio::io::{AsyncBufRead, AsyncWrite, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener;
 use tokio::sync::watch;
 use tokio::task::JoinSet;
 
 use crate::resp::Resp;
+use crate::scripting::ScriptControl;
+use crate::session::Session;
 use crate::storage::Storage;
 
 pub async fn run(listener: TcpListener, storage: Arc<RwLock<Storage>>) -> Result<()> {
@@ -19,7 +21,15 @@ pub async fn run(listener: TcpListener, storage: Arc<RwLock<Storage>>) -> Result
     let shutdown_rx_task = shutdown_rx_for_listener.changed();
     tokio::pin!(shutdown_rx_task);
 
-    let listener_loop_task = listener_loop(listener, storage, Arc::clone(&join_set), shutdown_rx);
+    // taken once, as the storage lock is held while a script runs
+    let script_control = Arc::clone(&storage.read().unwrap().scripts.control);
+    let listener_loop_task = listener_loop(
+        listener,
+        storage,
+        script_control,
+        Arc::clone(&join_set),
+        shutdown_rx,
+    );
 
     tokio::select! {
         _ = &mut shutdown_rx_task => {}
@@ -56,6 +66,7 @@ pub async fn run(listener: TcpListener, storage: Arc<RwLock<Storage>>) -> Result
 async fn listener_loop(
     listener: TcpListener,
     storage: Arc<RwLock<Storage>>,
+    script_control: Arc<ScriptControl>,
     join_set: Arc<Mutex<JoinSet<Result<()>>>>,
     shutdown_rx: watch::Receiver<()>,
 ) -> Result<()> {
@@ -63,6 +74,7 @@ async fn listener_loop(
         let (mut stream, _address) = listener.accept().await?;
 
         let storage = Arc::clone(&storage);
+        let script_control = Arc::clone(&script_control);
         let mut join_set = join_set
             .lock()
             .map_err(|_| anyhow!("unable to lock join set"))?;
@@ -72,7 +84,7 @@ async fn listener_loop(
             let (read, write) = stream.split();
             let mut read = BufReader::new(read);
 
-            let resp_loop = run_resp_loop(&mut read, write, storage);
+            let resp_loop = run_resp_loop(&mut read, write, storage, script_control);
 
             tokio::select! {
                 _ = shutdown_rx.changed() => {}
@@ -90,12 +102,93 @@ async fn listener_loop(
 
 async fn run_resp_loop(
     read: &mut (impl AsyncBufRead + Unpin + Send),
-    mut write: impl AsyncWrite + Unpin + Send,
+    write: impl AsyncWrite + Unpin + Send,
     storage: Arc<RwLock<Storage>>,
+    script_control: Arc<ScriptControl>,
+) -> Result<()> {
+    let mut session = Session::new(script_control);
+
+    let result = run_commands(read, write, &storage, &mut session).await;
+    // waits for the storage lock, which a running script may hold for a while
+    tokio::task::spawn_blocking(move || session.close(&storage));
+
+    result
+}
+
+async fn run_commands(
+    read: &mut (impl AsyncBufRead + Unpin + Send),
+    mut write: impl AsyncWrite + Unpin + Send,
+    storage: &Arc<RwLock<Storage>>,
+    session: &mut Session,
 ) -> Result<()> {
     loop {
+        // Messages published to the channels the client subscribed to are written out whenever
+        // no command is running, including while waiting for the next one.
+        tokio::select! {
+            closed = is_closed(read) => {
+                if closed {
+                    return Ok(());
+                }
+            }
+            message = session.next_message() => {
+                write.write_all(message.to_string().as_bytes()).await?;
+                continue;
+            }
+        }
+
         let resp = Resp::parse(read).await?;
 
-        resp.run(&mut write, Arc::clone(&storage)).await?;
+        // Blocking commands such as XREAD BLOCK may wait for a long time without holding the
+        // storage lock. Watch the connection meanwhile, so that a client that disconnects stops
+        // waiting. Pipelined commands are left in the buffer until the running one completes.
+        let run = session.run(resp, &mut write, Arc::clone(storage));
+        tokio::pin!(run);
+
+        tokio::select! {
+            result = &mut run => result?,
+            closed = is_closed(read) => {
+                if closed {
+                    return Ok(());
+                }
+                run.await?;
+            }
+        }
+    }
+}
+
+async fn is_closed(read: &mut (impl AsyncBufRead + Unpin + Send)) -> bool {
+    read.fill_buf().await.map_or(true, |buf| buf.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_reply_keeps_connection() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(run(listener, Arc::default()));
+
+        let mut stream = TcpStream::connect(address).await?;
+        stream
+            .write_all(
+                b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n\
+                  *3\r\n$4\r\nSADD\r\n$1\r\nk\r\n$1\r\nx\r\n\
+                  *2\r\n$3\r\nGET\r\n$1\r\nk\r\n",
+            )
+            .await?;
+
+        let expected = "+OK\r\n\
+                        -WRONGTYPE Operation against a key holding the wrong kind of value\r\n\
+                        $1\r\nv\r\n";
+        let mut replies = vec![0; expected.len()];
+        stream.read_exact(&mut replies).await?;
+        assert_eq!(String::from_utf8(replies)?, expected);
+
+        Ok(())
     }
 }
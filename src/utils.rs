@@ -1,18 +1,344 @@
-use anyhow::{bail, Result};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn unhex(s: &str) -> Result<Vec<u8>> {
-    if s.len() % 2 != 0 {
-        bail!("invalid hex string length {}", s.len());
+/// Returns the current Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// Polls `future` once, returning its output if it completed without waiting.
+pub fn now_or_never<F: Future>(future: F) -> Option<F::Output> {
+    // nothing to wake, since the future isn't polled again
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(Noop));
+    let mut context = Context::from_waker(&waker);
+
+    match pin!(future).poll(&mut context) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
+
+/// Exits the server once `SHUTDOWN` is done with what it had to save.
+pub fn exit_server() -> ! {
+    println!("Redis is now ready to exit, bye bye...");
+    std::process::exit(0)
+}
+
+/// Returns a pseudo-random `u64`, seeded from the std hasher's random keys.
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Returns a pseudo-random index in `0..len`. `len` must not be zero.
+pub fn random_index(len: usize) -> usize {
+    (random_u64() % len as u64) as usize
+}
+
+/// Moves a uniformly chosen random sample of `count` items to the front of `items` with a
+/// partial Fisher-Yates shuffle.
+pub fn shuffle_prefix<T>(items: &mut [T], count: usize) {
+    for i in 0..count.min(items.len()) {
+        let j = i + random_index(items.len() - i);
+        items.swap(i, j);
+    }
+}
+
+/// 64-bit FNV-1a. Unlike the std hasher it is stable across runs, which makes it usable for
+/// SCAN cursors.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The SHA-1 digest of `bytes` as lowercase hex, which is how scripts are identified.
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((bytes.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
     }
 
-    let mut bytes = Vec::with_capacity(s.len() / 2);
+    h.iter().map(|word| format!("{:08x}", word)).collect()
+}
+
+/// The CRC-64 variant Redis uses to check RDB files and `DUMP` payloads (Jones polynomial,
+/// reflected, no final XOR).
+pub fn crc64(bytes: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac9329ac4bc9b5;
+
+    bytes.iter().fold(0, |mut crc, &byte| {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+        crc
+    })
+}
 
-    for i in 0..s.len() / 2 {
-        let first_byte = u8::from_str_radix(&s[i * 2..i * 2 + 1], 16)?;
-        let second_byte = u8::from_str_radix(&s[i * 2 + 1..i * 2 + 2], 16)?;
+/// Runs one SCAN step over `items`.
+///
+/// Items are visited in order of their [`fnv1a`] hash and the cursor is the hash of the next
+/// item to return, so every item that is present for the whole iteration is returned at least
+/// once even if the collection changes between calls. Returns the next cursor, which is 0 once
+/// the iteration is complete.
+pub fn scan<'a>(
+    items: impl Iterator<Item = &'a str>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<&'a str>) {
+    let mut candidates = items
+        .map(|item| (fnv1a(item.as_bytes()), item))
+        .filter(|(hash, _)| *hash >= cursor)
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
 
-        bytes.push(first_byte << 4 | second_byte);
+    let mut end = count.max(1).min(candidates.len());
+    // items that share a hash must be returned in the same batch, the cursor can't split them
+    while end < candidates.len() && candidates[end].0 == candidates[end - 1].0 {
+        end += 1;
     }
 
-    Ok(bytes)
+    let next_cursor = candidates.get(end).map_or(0, |(hash, _)| *hash);
+
+    (
+        next_cursor,
+        candidates[..end].iter().map(|(_, item)| *item).collect(),
+    )
+}
+
+/// Matches `string` against a Redis glob-style `pattern`, supporting `*`, `?`, `[...]` classes
+/// with `^` negation and ranges, and `\` escapes.
+///
+/// On a mismatch, only the last `*` is retried one character further, which is enough since an
+/// earlier one taking more could only leave less for the later ones. This bounds the work by
+/// the product of both lengths, where retrying every `*` was exponential in their count.
+pub fn glob_match(pattern: &str, string: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let string = string.chars().collect::<Vec<_>>();
+
+    let (mut p, mut s) = (0, 0);
+    // where to resume after the last `*`, in the pattern and in the string
+    let mut backtrack = None;
+
+    while s < string.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            backtrack = Some((p, s));
+            continue;
+        }
+
+        match match_char(&pattern[p..], string[s]) {
+            Some((true, len)) => {
+                p += len;
+                s += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_s)) => {
+                    // the `*` takes one more character
+                    backtrack = Some((star_p, star_s + 1));
+                    (p, s) = (star_p, star_s + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the element `pattern` starts with, other than `*`, returning whether it
+/// matched and the length of the element. `None` if the pattern is over.
+fn match_char(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let (&first, rest) = pattern.split_first()?;
+
+    let matched = match first {
+        '?' => (true, 1),
+        '[' => {
+            let (negate, mut class) = match rest.split_first() {
+                Some(('^', class)) => (true, class),
+                _ => (false, rest),
+            };
+
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => break,
+                    [']', ..] => {
+                        class = &class[1..];
+                        break;
+                    }
+                    ['\\', escaped, ..] => {
+                        matched |= *escaped == c;
+                        class = &class[2..];
+                    }
+                    [start, '-', end, ..] if *end != ']' => {
+                        let (start, end) = if start <= end {
+                            (*start, *end)
+                        } else {
+                            (*end, *start)
+                        };
+                        matched |= start <= c && c <= end;
+                        class = &class[3..];
+                    }
+                    [other, ..] => {
+                        matched |= *other == c;
+                        class = &class[1..];
+                    }
+                }
+            }
+
+            (matched != negate, pattern.len() - class.len())
+        }
+        '\\' if !rest.is_empty() => (rest[0] == c, 2),
+        _ => (first == c, 1),
+    };
+
+    Some(matched)
+}
+
+/// `s` as a JSON string, quoted and escaped.
+pub fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("orders.*", "orders.created"));
+        assert!(!glob_match("orders.*", "order.created"));
+        assert!(glob_match("user:[0-9]*", "user:42"));
+        assert!(!glob_match("user:[0-9]*", "user:x"));
+        assert!(glob_match("h?llo", "hallo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match(r"h\*llo", "h*llo"));
+        assert!(!glob_match(r"h\*llo", "hello"));
+        assert!(glob_match("a*b*c", "aXbYc"));
+        assert!(glob_match("*.created", "orders.x.created"));
+        assert!(!glob_match("a*", ""));
+        assert!(glob_match("**", ""));
+
+        // many stars don't make the match exponential
+        let pattern = format!("{}b", "*a".repeat(20));
+        assert!(!glob_match(&pattern, &"a".repeat(100)));
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("key"), r#""key""#);
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+    }
+
+    #[test]
+    fn test_sha1_hex() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            sha1_hex(b"return 1"),
+            "e0e1f9fabfc9d4800c877a703b823ac0578ff8db"
+        );
+        assert_eq!(
+            sha1_hex(&[b'a'; 1000]),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_scan_visits_every_item() {
+        let items = (0..100).map(|i| i.to_string()).collect::<Vec<_>>();
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next_cursor, batch) = scan(items.iter().map(String::as_str), cursor, 7);
+            seen.extend(batch);
+            if next_cursor == 0 {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        assert_eq!(seen.len(), items.len());
+    }
 }